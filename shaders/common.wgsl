@@ -1,9 +1,56 @@
 struct Camera {
     view_position: vec4<f32>,
     view_projection: mat4x4<f32>,
+    view: mat4x4<f32>,
+    inverse_view_projection: mat4x4<f32>,
+    exposure: f32,
 }
 
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_SPOT: u32 = 1u;
+
 struct Light {
     position: vec3<f32>,
+    intensity: f32,
     color: vec3<f32>,
+    // Fades out to nothing at this distance, 0 reaching everywhere
+    range: f32,
+    // Where spot lights shine, along with the cosines of the angles their
+    // cone fades out between
+    direction: vec3<f32>,
+    light_type: u32,
+    cos_inner: f32,
+    cos_outer: f32,
+}
+
+struct Lights {
+    count: u32,
+    lights: array<Light>,
+}
+
+// Blended from the ground color facing down to the sky color facing up
+struct AmbientLight {
+    sky_color: vec3<f32>,
+    intensity: f32,
+    ground_color: vec3<f32>,
+}
+
+// Reaches everywhere from the same direction, unattenuated
+struct DirectionalLight {
+    // The way the light travels
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    // Into the shadow map's clip space
+    view_projection: mat4x4<f32>,
+}
+
+struct ShadowSettings {
+    // Off the depth compared, in the map's 0 to 1
+    depth_bias: f32,
+    // World units along the normal positions are moved before the lookup
+    normal_bias: f32,
+    // Texels either side averaged in
+    pcf_radius: i32,
+    enabled: u32,
 }
@@ -0,0 +1,42 @@
+struct DepthRange {
+    near: f32,
+    far: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Read as a float texture, GL can only sample depth ones with comparisons
+@group(0) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var depth_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> range: DepthRange;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vi: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.uv = vec2<f32>(
+        f32((vi << 1u) & 2u),
+        f32(vi & 2u),
+    );
+    out.clip_position = vec4<f32>(out.uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv.y = 1.0 - out.uv.y;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth = textureSample(depth_texture, depth_sampler, in.uv).x;
+    // Undoes the perspective divide, near maps to 0 and far to 1
+    let distance = range.near * range.far / (range.far - depth * (range.far - range.near));
+    let gray = (distance - range.near) / (range.far - range.near);
+
+    return vec4<f32>(vec3<f32>(gray), 1.0);
+}
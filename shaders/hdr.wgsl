@@ -6,7 +6,7 @@ struct VertexOutput {
 @group(0) @binding(0)
 var hdr_image: texture_2d<f32>;
 
-@group(0) @binding(0)
+@group(0) @binding(1)
 var hdr_sampler: sampler;
 
 @vertex
@@ -0,0 +1,70 @@
+// Spins each instance about its own y axis, writing the instance data the
+// render pipelines read as vertices
+
+// Matches InstanceSource, the rotation is a quaternion
+struct Source {
+    rotation: vec4<f32>,
+    position: vec3<f32>,
+    texture_index: u32,
+}
+
+// Matches RawInstance, the normal matrix is packed into an array since a
+// mat3x3 would have its columns padded
+struct Instance {
+    model: mat4x4<f32>,
+    normal: array<f32, 9>,
+    texture_index: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> angle: f32;
+@group(0) @binding(1)
+var<storage, read> sources: array<Source>;
+@group(0) @binding(2)
+var<storage, read_write> instances: array<Instance>;
+
+fn rotation_matrix(q: vec4<f32>) -> mat3x3<f32> {
+    let x = q.x;
+    let y = q.y;
+    let z = q.z;
+    let w = q.w;
+
+    return mat3x3<f32>(
+        vec3<f32>(1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w)),
+        vec3<f32>(2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w)),
+        vec3<f32>(2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y)),
+    );
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&sources) {
+        return;
+    }
+    let source = sources[index];
+
+    let spin = mat3x3<f32>(
+        vec3<f32>(cos(angle), 0.0, -sin(angle)),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(sin(angle), 0.0, cos(angle)),
+    );
+    // A var since a let can only be indexed by constants
+    var rotation = rotation_matrix(source.rotation) * spin;
+
+    var instance: Instance;
+    instance.model = mat4x4<f32>(
+        vec4<f32>(rotation[0], 0.0),
+        vec4<f32>(rotation[1], 0.0),
+        vec4<f32>(rotation[2], 0.0),
+        vec4<f32>(source.position, 1.0),
+    );
+    // Rotations are their own normal matrix
+    for (var column = 0; column < 3; column++) {
+        for (var row = 0; row < 3; row++) {
+            instance.normal[column * 3 + row] = rotation[column][row];
+        }
+    }
+    instance.texture_index = source.texture_index;
+    instances[index] = instance;
+}
@@ -1,11 +1,9 @@
-struct Camera {
-    view_position: vec4<f32>,
-    view_projection: mat4x4<f32>,
-}
+//!include "common.wgsl"
 
-struct Light {
-    position: vec3<f32>,
-    color: vec3<f32>,
+// Where a light's marker is drawn and its color, see DrawConstants
+struct Draw {
+    transform: mat4x4<f32>,
+    color: vec4<f32>,
 }
 
 struct VertexInput {
@@ -20,18 +18,18 @@ struct VertexOutput {
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+//!ifdef PUSH_CONSTANTS
+var<push_constant> draw: Draw;
+//!else
 @group(1) @binding(0)
-var<uniform> light: Light;
+var<uniform> draw: Draw;
+//!endif
 
 @vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
+fn vs_main(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    
-    let scale = 0.25;
-    out.clip_position = camera.view_projection * vec4<f32>(model.position * scale + light.position, 1.0);
-    out.color = light.color;
+    out.clip_position = camera.view_projection * draw.transform * vec4<f32>(model.position, 1.0);
+    out.color = draw.color.rgb;
 
     return out;
 }
@@ -0,0 +1,277 @@
+// Metallic-roughness shading, Cook-Torrance with the GGX distribution,
+// Smith's shadowing and Schlick's Fresnel. Bound exactly like standard.wgsl
+
+const PI: f32 = 3.14159265;
+// Reflectance of dielectrics seen head on
+const DIELECTRIC_F0: vec3<f32> = vec3<f32>(0.04);
+// Below this GGX's highlight shrinks to nothing on point lights
+const MIN_ROUGHNESS: f32 = 0.045;
+
+//!include "common.wgsl"
+
+// The Phong properties are unused here, the diffuse color is the base color
+struct Material {
+    diffuse: vec4<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+    specular_strength: f32,
+    metallic: f32,
+    roughness: f32,
+    // Added after lighting, glowing in the dark
+    emissive: vec3<f32>,
+}
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) texture_coordinates: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+struct InstanceInput {
+    @location(6) model_matrix_0: vec4<f32>,
+    @location(7) model_matrix_1: vec4<f32>,
+    @location(8) model_matrix_2: vec4<f32>,
+    @location(9) model_matrix_3: vec4<f32>,
+    @location(10) normal_matrix_0: vec3<f32>,
+    @location(11) normal_matrix_1: vec3<f32>,
+    @location(12) normal_matrix_2: vec3<f32>,
+}
+
+struct VertexOutput {
+    // Invariant to match the depth pre-pass, see standard.wgsl
+    @builtin(position) @invariant clip_position: vec4<f32>,
+    @location(0) texture_coordinates: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var texture_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var sampler_diffuse: sampler;
+@group(0) @binding(2)
+var texture_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var sampler_normal: sampler;
+@group(0) @binding(4)
+var<uniform> material: Material;
+// Roughness in green, metalness in blue
+@group(0) @binding(5)
+var texture_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(6)
+var sampler_metallic_roughness: sampler;
+@group(0) @binding(7)
+var texture_occlusion: texture_2d<f32>;
+@group(0) @binding(8)
+var sampler_occlusion: sampler;
+
+@group(1) @binding(0) 
+var<uniform> camera: Camera;
+
+@group(2) @binding(0)
+var<storage, read> lights: Lights;
+@group(2) @binding(1)
+var<uniform> ambient: AmbientLight;
+
+@group(3) @binding(0)
+var<uniform> sun: DirectionalLight;
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+@group(3) @binding(3)
+var<uniform> shadow_settings: ShadowSettings;
+
+// A smooth window reaching 0 at the radius, so lights beyond it can be
+// skipped without a visible edge
+fn attenuation(distance: f32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return 1.0;
+    }
+    let falloff = saturate(1.0 - pow(distance / radius, 4.0));
+    return falloff * falloff;
+}
+
+// 1 inside a spot light's inner cone, fading to 0 at the outer one. Point
+// lights shine everywhere
+fn cone(light: Light, light_direction: vec3<f32>) -> f32 {
+    if light.light_type != LIGHT_SPOT {
+        return 1.0;
+    }
+    let cos_angle = dot(-light_direction, light.direction);
+    if light.cos_inner <= light.cos_outer {
+        return select(0.0, 1.0, cos_angle >= light.cos_outer);
+    }
+    return smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+}
+
+// How much of the sun reaches `world_position` on a surface facing
+// `normal`, the comparisons around it averaged to soften the edges.
+// Anything outside the map is lit, as is everything with shadows disabled
+fn shadow(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if shadow_settings.enabled == 0u {
+        return 1.0;
+    }
+    let biased_position = world_position + normal * shadow_settings.normal_bias;
+    let clip = sun.view_projection * vec4<f32>(biased_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let depth = ndc.z - shadow_settings.depth_bias;
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    let radius = shadow_settings.pcf_radius;
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, depth);
+        }
+    }
+    let width = f32(radius * 2 + 1);
+    return lit / (width * width);
+}
+
+struct Surface {
+    normal: vec3<f32>,
+    view_direction: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    // Squared roughness, as GGX uses it
+    alpha: f32,
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Schlick-GGX for both the light and view directions, with the remapping
+// for analytic lights
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let roughness = sqrt(alpha);
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let view = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let light = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return view * light;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Light reflected towards the camera from a light in `light_direction`,
+// `visibility` of which isn't shadowed
+fn shade(surface: Surface, light_direction: vec3<f32>, radiance: vec3<f32>, visibility: f32) -> vec3<f32> {
+    let half_direction = normalize(surface.view_direction + light_direction);
+    let n_dot_l = max(dot(surface.normal, light_direction), 0.0);
+    let n_dot_v = max(dot(surface.normal, surface.view_direction), 1e-4);
+    let n_dot_h = max(dot(surface.normal, half_direction), 0.0);
+
+    let f0 = mix(DIELECTRIC_F0, surface.albedo, surface.metallic);
+    let fresnel = fresnel_schlick(max(dot(half_direction, surface.view_direction), 0.0), f0);
+    let specular = distribution_ggx(n_dot_h, surface.alpha) * geometry_smith(n_dot_v, n_dot_l, surface.alpha) * fresnel / (4.0 * n_dot_v * n_dot_l + 1e-4);
+    // Metals have no diffuse, and what the surface reflects can't also
+    // scatter into it
+    let diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * surface.albedo / PI;
+
+    // The lights' intensities were picked for the Phong shader's diffuse,
+    // which isn't divided by pi, so they're scaled up to match it
+    return (diffuse + specular) * radiance * PI * n_dot_l * visibility;
+}
+
+// Exposes the lit color and Reinhard tone maps it, so bright lights roll off
+// rather than clipping to white
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    let exposed = color * camera.exposure;
+    return exposed / (1.0 + exposed);
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {    
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    
+    let world_normal = normalize(normal_matrix * model.normal);
+    let world_tangent = normalize(normal_matrix * model.tangent);
+    let world_bitangent = normalize(normal_matrix * model.bitangent);
+    
+    var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
+    
+    var out: VertexOutput;
+    // out.clip_position = camera.view_projection * model_matrix * vec4<f32>(model.position, 1.0);
+    out.texture_coordinates = model.texture_coordinates;
+    out.color = model.color;
+
+    
+    out.clip_position = camera.view_projection * world_position;
+
+    out.world_position = world_position.xyz;
+    out.world_normal = world_normal;
+    out.world_tangent = world_tangent;
+    out.world_bitangent = world_bitangent;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let object_color: vec4<f32> = textureSample(texture_diffuse, sampler_diffuse, in.texture_coordinates) * material.diffuse * in.color;
+    let object_normal: vec4<f32> = textureSample(texture_normal, sampler_normal, in.texture_coordinates); 
+    
+    // Only x and y are read, which also covers two channel BC5 maps. z is
+    // rebuilt from them, renormalizing normals that mip filtering shortened
+    let normal_xy = object_normal.xy * 2.0 - 1.0;
+    let tangent_normal = vec3<f32>(normal_xy, sqrt(max(1.0 - dot(normal_xy, normal_xy), 0.0)));
+    // Lit in world space, tangent space would need every light moved into
+    // it in the vertex shader
+    let tangent_to_world = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    let normal = normalize(tangent_to_world * tangent_normal);
+    let metallic_roughness = textureSample(texture_metallic_roughness, sampler_metallic_roughness, in.texture_coordinates);
+    let occlusion = textureSample(texture_occlusion, sampler_occlusion, in.texture_coordinates).r;
+    let roughness = clamp(material.roughness * metallic_roughness.g, MIN_ROUGHNESS, 1.0);
+
+    var surface: Surface;
+    surface.normal = normal;
+    surface.view_direction = normalize(camera.view_position.xyz - in.world_position);
+    surface.albedo = object_color.rgb;
+    surface.metallic = saturate(material.metallic * metallic_roughness.b);
+    surface.alpha = roughness * roughness;
+
+    let sky_amount = normal.y * 0.5 + 0.5;
+    var color = mix(ambient.ground_color, ambient.sky_color, sky_amount) * ambient.intensity * surface.albedo * occlusion;
+    color += shade(surface, -sun.direction, sun.color * sun.intensity, shadow(in.world_position, normalize(in.world_normal)));
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = lights.lights[i];
+        let to_light = light.position - in.world_position;
+        let light_direction = normalize(to_light);
+        let radiance = light.color * light.intensity * attenuation(length(to_light), light.range) * cone(light, light_direction);
+        color += shade(surface, light_direction, radiance, 1.0);
+    }
+
+    return vec4<f32>(tone_map(color + material.emissive), object_color.a);
+}
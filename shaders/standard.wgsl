@@ -1,11 +1,17 @@
-struct Camera {
-    view_position: vec4<f32>,
-    view_projection: mat4x4<f32>,
-}
+// With TEXTURE_ARRAY defined the diffuse map is a texture array, each
+// instance picking its layer.
+
+//!include "common.wgsl"
 
-struct Light {
-    position: vec3<f32>,
-    color: vec3<f32>,
+struct Material {
+    diffuse: vec4<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+    specular_strength: f32,
+    metallic: f32,
+    roughness: f32,
+    // Added after lighting, glowing in the dark
+    emissive: vec3<f32>,
 }
 
 struct VertexInput {
@@ -14,40 +20,137 @@ struct VertexInput {
     @location(2) normal: vec3<f32>,
     @location(3) tangent: vec3<f32>,
     @location(4) bitangent: vec3<f32>,
+    @location(5) color: vec4<f32>,
 }
 
 struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-    @location(9) normal_matrix_0: vec3<f32>,
-    @location(10) normal_matrix_1: vec3<f32>,
-    @location(11) normal_matrix_2: vec3<f32>,
+    @location(6) model_matrix_0: vec4<f32>,
+    @location(7) model_matrix_1: vec4<f32>,
+    @location(8) model_matrix_2: vec4<f32>,
+    @location(9) model_matrix_3: vec4<f32>,
+    @location(10) normal_matrix_0: vec3<f32>,
+    @location(11) normal_matrix_1: vec3<f32>,
+    @location(12) normal_matrix_2: vec3<f32>,
+//!ifdef TEXTURE_ARRAY
+    @location(13) texture_index: u32,
+//!endif
 }
 
 struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
+    // Invariant so the depth pre-pass, which runs only vs_main, lands on
+    // exactly the depth the shading pass tests equal against
+    @builtin(position) @invariant clip_position: vec4<f32>,
     @location(0) texture_coordinates: vec2<f32>,
-    @location(1) tangent_position: vec3<f32>,
-    @location(2) tangent_light_position: vec3<f32>,
-    @location(3) tangent_view_position: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+    @location(5) color: vec4<f32>,
+//!ifdef TEXTURE_ARRAY
+    @location(6) @interpolate(flat) texture_index: u32,
+//!endif
 }
 
 @group(0) @binding(0)
+//!ifdef TEXTURE_ARRAY
+var texture_diffuse: texture_2d_array<f32>;
+//!else
 var texture_diffuse: texture_2d<f32>;
+//!endif
 @group(0) @binding(1)
 var sampler_diffuse: sampler;
 @group(0) @binding(2)
 var texture_normal: texture_2d<f32>;
 @group(0) @binding(3)
 var sampler_normal: sampler;
+@group(0) @binding(4)
+var<uniform> material: Material;
 
 @group(1) @binding(0) 
 var<uniform> camera: Camera;
 
 @group(2) @binding(0)
-var<uniform> light: Light;
+var<storage, read> lights: Lights;
+@group(2) @binding(1)
+var<uniform> ambient: AmbientLight;
+
+@group(3) @binding(0)
+var<uniform> sun: DirectionalLight;
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+@group(3) @binding(3)
+var<uniform> shadow_settings: ShadowSettings;
+
+// A smooth window reaching 0 at the radius, so lights beyond it can be
+// skipped without a visible edge
+fn attenuation(distance: f32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return 1.0;
+    }
+    let falloff = saturate(1.0 - pow(distance / radius, 4.0));
+    return falloff * falloff;
+}
+
+// 1 inside a spot light's inner cone, fading to 0 at the outer one. Point
+// lights shine everywhere
+fn cone(light: Light, light_direction: vec3<f32>) -> f32 {
+    if light.light_type != LIGHT_SPOT {
+        return 1.0;
+    }
+    let cos_angle = dot(-light_direction, light.direction);
+    if light.cos_inner <= light.cos_outer {
+        return select(0.0, 1.0, cos_angle >= light.cos_outer);
+    }
+    return smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+}
+
+// How much of the sun reaches `world_position` on a surface facing
+// `normal`, the comparisons around it averaged to soften the edges.
+// Anything outside the map is lit, as is everything with shadows disabled
+fn shadow(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if shadow_settings.enabled == 0u {
+        return 1.0;
+    }
+    let biased_position = world_position + normal * shadow_settings.normal_bias;
+    let clip = sun.view_projection * vec4<f32>(biased_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let depth = ndc.z - shadow_settings.depth_bias;
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    let radius = shadow_settings.pcf_radius;
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, depth);
+        }
+    }
+    let width = f32(radius * 2 + 1);
+    return lit / (width * width);
+}
+
+// Blinn-Phong lighting from a light in `light_direction`, `visibility` of
+// which isn't shadowed
+fn shade(normal: vec3<f32>, view_direction: vec3<f32>, light_direction: vec3<f32>, radiance: vec3<f32>, visibility: f32) -> vec3<f32> {
+    let half_direction = normalize(view_direction + light_direction);
+
+    let diffuse_strength = max(dot(normal, light_direction), 0.0);
+    let highlight = pow(max(dot(normal, half_direction), 0.0), material.shininess);
+    return radiance * visibility * (diffuse_strength + highlight * material.specular * material.specular_strength);
+}
+
+// Exposes the lit color and Reinhard tone maps it, so bright lights roll off
+// rather than clipping to white
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    let exposed = color * camera.exposure;
+    return exposed / (1.0 + exposed);
+}
 
 @vertex
 fn vs_main(
@@ -69,50 +172,60 @@ fn vs_main(
     let world_normal = normalize(normal_matrix * model.normal);
     let world_tangent = normalize(normal_matrix * model.tangent);
     let world_bitangent = normalize(normal_matrix * model.bitangent);
-    let tangent_matrix = transpose(mat3x3<f32>(
-        world_tangent,
-        world_bitangent,
-        world_normal,
-    ));
     
     var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
     
     var out: VertexOutput;
     // out.clip_position = camera.view_projection * model_matrix * vec4<f32>(model.position, 1.0);
     out.texture_coordinates = model.texture_coordinates;
+    out.color = model.color;
+//!ifdef TEXTURE_ARRAY
+    out.texture_index = instance.texture_index;
+//!endif
 
     
-    // out.world_normal = normal_matrix * model.normal;
-    // out.world_position = world_position.xyz;
     out.clip_position = camera.view_projection * world_position;
 
-    out.tangent_position = tangent_matrix * world_position.xyz;
-    out.tangent_light_position = tangent_matrix * light.position;
-    out.tangent_view_position = tangent_matrix * camera.view_position.xyz;
+    out.world_position = world_position.xyz;
+    out.world_normal = world_normal;
+    out.world_tangent = world_tangent;
+    out.world_bitangent = world_bitangent;
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let object_color: vec4<f32> = textureSample(texture_diffuse, sampler_diffuse, in.texture_coordinates); 
+//!ifdef TEXTURE_ARRAY
+    let object_color: vec4<f32> = textureSample(texture_diffuse, sampler_diffuse, in.texture_coordinates, in.texture_index) * material.diffuse * in.color;
+//!else
+    let object_color: vec4<f32> = textureSample(texture_diffuse, sampler_diffuse, in.texture_coordinates) * material.diffuse * in.color;
+//!endif
     let object_normal: vec4<f32> = textureSample(texture_normal, sampler_normal, in.texture_coordinates); 
     
-    let ambient_strength = 0.1;
-    let ambient_color = light.color * ambient_strength;
-    
-    let tangent_normal = object_normal.xyz * 2.0 - 1.0;
-    // let light_direction = normalize(light.position - in.world_position);
-    // let view_direction = normalize(camera.view_position.xyz - in.world_position);
-    let light_direction = normalize(in.tangent_light_position - in.tangent_position);
-    let view_direction = normalize(in.tangent_view_position.xyz - in.tangent_position);
-    let half_direction = normalize(view_direction + light_direction);
+    // Only x and y are read, which also covers two channel BC5 maps. z is
+    // rebuilt from them, renormalizing normals that mip filtering shortened
+    let normal_xy = object_normal.xy * 2.0 - 1.0;
+    let tangent_normal = vec3<f32>(normal_xy, sqrt(max(1.0 - dot(normal_xy, normal_xy), 0.0)));
+    // Lit in world space, tangent space would need every light moved into
+    // it in the vertex shader
+    let tangent_to_world = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    let normal = normalize(tangent_to_world * tangent_normal);
+    let view_direction = normalize(camera.view_position.xyz - in.world_position);
 
-    let diffuse_strength = max(dot(tangent_normal, light_direction), 0.0);
-    let diffuse_color = light.color * diffuse_strength;
-    
-    let specular_strength = pow(max(dot(tangent_normal, half_direction), 0.0), 32.0);
-    let specular_color = specular_strength * light.color;
-    
-    return vec4<f32>((ambient_color + diffuse_color + specular_color) * object_color.xyz, object_color.a);
-    // return vec4<f32>(specular_color, object_color.a);
+    let sky_amount = normal.y * 0.5 + 0.5;
+    var color = mix(ambient.ground_color, ambient.sky_color, sky_amount) * ambient.intensity;
+    color += shade(normal, view_direction, -sun.direction, sun.color * sun.intensity, shadow(in.world_position, normalize(in.world_normal)));
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = lights.lights[i];
+        let to_light = light.position - in.world_position;
+        let light_direction = normalize(to_light);
+        let radiance = light.color * light.intensity * attenuation(length(to_light), light.range) * cone(light, light_direction);
+        color += shade(normal, view_direction, light_direction, radiance, 1.0);
+    }
+
+    return vec4<f32>(tone_map(color * object_color.xyz + material.emissive), object_color.a);
 }
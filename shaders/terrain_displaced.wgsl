@@ -0,0 +1,180 @@
+// The terrain shader lifting a flat patch into place from the heights,
+// instanced once for every chunk.
+
+//!include "common.wgsl"
+
+// How the heights are laid out in the world
+struct TerrainUniform {
+    origin: vec3<f32>,
+    spacing: f32,
+    height_scale: f32,
+    uv_tiling: f32,
+    // Samples along x and z
+    size: vec2<u32>,
+}
+
+struct PatchInput {
+    // Samples from the chunk's first
+    @location(0) grid: vec2<u32>,
+    // Non-zero for the skirt, hanging below the border
+    @location(1) skirt: u32,
+}
+
+struct ChunkInput {
+    @location(2) offset: vec2<u32>,
+    @location(3) skirt_bottom: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var<storage, read> lights: Lights;
+@group(1) @binding(1)
+var<uniform> ambient: AmbientLight;
+
+@group(2) @binding(0)
+var<uniform> sun: DirectionalLight;
+@group(2) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(2) @binding(2)
+var shadow_sampler: sampler_comparison;
+@group(2) @binding(3)
+var<uniform> shadow_settings: ShadowSettings;
+
+@group(3) @binding(0)
+var height_map: texture_2d<f32>;
+@group(3) @binding(1)
+var<uniform> terrain: TerrainUniform;
+
+const GRASS_COLOR: vec3<f32> = vec3<f32>(0.25, 0.45, 0.15);
+const ROCK_COLOR: vec3<f32> = vec3<f32>(0.45, 0.42, 0.38);
+
+// A smooth window reaching 0 at the radius, so lights beyond it can be
+// skipped without a visible edge
+fn attenuation(distance: f32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return 1.0;
+    }
+    let falloff = saturate(1.0 - pow(distance / radius, 4.0));
+    return falloff * falloff;
+}
+
+// 1 inside a spot light's inner cone, fading to 0 at the outer one. Point
+// lights shine everywhere
+fn cone(light: Light, light_direction: vec3<f32>) -> f32 {
+    if light.light_type != LIGHT_SPOT {
+        return 1.0;
+    }
+    let cos_angle = dot(-light_direction, light.direction);
+    if light.cos_inner <= light.cos_outer {
+        return select(0.0, 1.0, cos_angle >= light.cos_outer);
+    }
+    return smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+}
+
+// How much of the sun reaches `world_position` on a surface facing
+// `normal`, the comparisons around it averaged to soften the edges.
+// Anything outside the map is lit, as is everything with shadows disabled
+fn shadow(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if shadow_settings.enabled == 0u {
+        return 1.0;
+    }
+    let biased_position = world_position + normal * shadow_settings.normal_bias;
+    let clip = sun.view_projection * vec4<f32>(biased_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let depth = ndc.z - shadow_settings.depth_bias;
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    let radius = shadow_settings.pcf_radius;
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, depth);
+        }
+    }
+    let width = f32(radius * 2 + 1);
+    return lit / (width * width);
+}
+
+// Diffuse only, the ground has no highlights
+fn shade(normal: vec3<f32>, light_direction: vec3<f32>, radiance: vec3<f32>, visibility: f32) -> vec3<f32> {
+    return radiance * visibility * max(dot(normal, light_direction), 0.0);
+}
+
+// Exposes the lit color and Reinhard tone maps it, so bright lights roll off
+// rather than clipping to white
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    let exposed = color * camera.exposure;
+    return exposed / (1.0 + exposed);
+}
+
+// The scaled height of a sample of the map
+fn height(sample: vec2<u32>) -> f32 {
+    return textureLoad(height_map, sample, 0).r * terrain.height_scale;
+}
+
+// Rise over run between two samples, flat when they're the same one
+fn slope(low: f32, high: f32, steps: u32) -> f32 {
+    if steps == 0u {
+        return 0.0;
+    }
+    return (high - low) / (f32(steps) * terrain.spacing);
+}
+
+@vertex
+fn vs_main(grid: PatchInput, chunk: ChunkInput) -> VertexOutput {
+    // Past the map's far edges the patch folds onto its last samples
+    let last = max(terrain.size, vec2<u32>(1u)) - 1u;
+    let sample = min(grid.grid + chunk.offset, last);
+
+    // Central differences, one-sided along the borders, as on the CPU
+    let low = max(sample, vec2<u32>(1u)) - 1u;
+    let high = min(sample + 1u, last);
+    let dx = slope(height(vec2<u32>(low.x, sample.y)), height(vec2<u32>(high.x, sample.y)), high.x - low.x);
+    let dz = slope(height(vec2<u32>(sample.x, low.y)), height(vec2<u32>(sample.x, high.y)), high.y - low.y);
+
+    var position = terrain.origin + vec3<f32>(f32(sample.x) * terrain.spacing, height(sample), f32(sample.y) * terrain.spacing);
+    if grid.skirt != 0u {
+        position.y = chunk.skirt_bottom;
+    }
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_projection * vec4<f32>(position, 1.0);
+    out.world_position = position;
+    out.world_normal = normalize(vec3<f32>(-dx, 1.0, -dz));
+    out.uv = vec2<f32>(sample) * terrain.uv_tiling / vec2<f32>(max(last, vec2<u32>(1u)));
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(in.world_normal);
+    // Grass on the flat, giving way to rock on the slopes
+    let albedo = mix(ROCK_COLOR, GRASS_COLOR, smoothstep(0.6, 0.85, normal.y));
+
+    let sky_amount = normal.y * 0.5 + 0.5;
+    var color = mix(ambient.ground_color, ambient.sky_color, sky_amount) * ambient.intensity;
+    color += shade(normal, -sun.direction, sun.color * sun.intensity, shadow(in.world_position, normal));
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = lights.lights[i];
+        let to_light = light.position - in.world_position;
+        let light_direction = normalize(to_light);
+        let radiance = light.color * light.intensity * attenuation(length(to_light), light.range) * cone(light, light_direction);
+        color += shade(normal, light_direction, radiance, 1.0);
+    }
+
+    return vec4<f32>(tone_map(color * albedo), 1.0);
+}
@@ -0,0 +1,195 @@
+//! Copies a texture over a whole render target with a fullscreen triangle,
+//! scaling it with the texture's sampler.
+
+use crate::texture::Texture;
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, CommandEncoder,
+    Device, LoadOp, Operations, PipelineLayoutDescriptor, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPassTimestampWrites, RenderPipeline, SamplerBindingType,
+    ShaderStages, StoreOp, TextureFormat, TextureSampleType, TextureView, TextureViewDimension,
+};
+
+pub struct Blit {
+    bind_group_layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+}
+
+impl Blit {
+    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Blit bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/blit.wgsl"));
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Blit pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Binds `source` for [`Blit::draw`], rebuild it whenever the texture is
+    /// recreated.
+    pub fn bind_group(&self, device: &Device, source: &Texture) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Blit bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&source.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&source.sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn draw(
+        &self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        source: &BindGroup,
+        timestamp_writes: Option<RenderPassTimestampWrites>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Blit pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    // Every pixel gets overwritten
+                    load: LoadOp::Clear(wgpu::Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, source, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// The scene rendered at a fraction or multiple of the window's resolution,
+/// scaled onto the screen afterwards.
+pub struct ScaledTarget {
+    pub scale: f32,
+    pub color: Texture,
+    pub depth: Texture,
+    pub bind_group: BindGroup,
+}
+
+impl ScaledTarget {
+    pub fn new(
+        device: &Device,
+        blit: &Blit,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        scale: f32,
+    ) -> Self {
+        let max = device.limits().max_texture_dimension_2d;
+        let (width, height) = (
+            ((width as f32 * scale).round() as u32).min(max),
+            ((height as f32 * scale).round() as u32).min(max),
+        );
+        let color = Texture::create_render_target(device, width, height, format, 1);
+        let depth = Texture::create_depth_target(device, width, height, 1);
+        let bind_group = blit.bind_group(device, &color);
+
+        Self {
+            scale,
+            color,
+            depth,
+            bind_group,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use super::Blit;
+    use crate::texture::{test_device, SamplerOptions, Texture, TextureKind};
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use std::iter;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn same_size_blit_is_exact() {
+        let (device, queue) = test_device();
+        let image = RgbaImage::from_fn(8, 4, |x, y| Rgba([x as u8 * 30, y as u8 * 60, 7, 255]));
+        let source = Texture::from_image(
+            &device,
+            &queue,
+            &DynamicImage::ImageRgba8(image.clone()),
+            None,
+            TextureKind::Color,
+            SamplerOptions::default(),
+        );
+        let target = Texture::create_render_target(&device, 8, 4, TextureFormat::Rgba8UnormSrgb, 1);
+
+        let blit = Blit::new(&device, TextureFormat::Rgba8UnormSrgb);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        blit.draw(
+            &mut encoder,
+            &target.view,
+            &blit.bind_group(&device, &source),
+            None,
+        );
+        queue.submit(iter::once(encoder.finish()));
+
+        assert_eq!(target.read_to_image(&device, &queue).unwrap(), image);
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use winit::keyboard::KeyCode;
+
+/// Exits the renderer, so it can't be bound to a camera action.
+const RESERVED: KeyCode = KeyCode::Escape;
+
+/// What a held key does to the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CameraAction {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Moves faster while held.
+    SpeedBoost,
+    /// Banks the fly camera anticlockwise.
+    RollLeft,
+    RollRight,
+}
+
+/// Which keys drive the camera controllers. Serialized as a map from key to
+/// action, e.g. `{ArrowUp: Forward}` in RON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    from = "HashMap<KeyCode, CameraAction>",
+    into = "HashMap<KeyCode, CameraAction>"
+)]
+pub struct KeyBindings {
+    keys: HashMap<KeyCode, CameraAction>,
+}
+
+impl Default for KeyBindings {
+    /// WASD to move, space and left shift for up and down, either control
+    /// key to go faster and Q and E to roll.
+    fn default() -> Self {
+        Self::empty()
+            .bind(KeyCode::KeyW, CameraAction::Forward)
+            .bind(KeyCode::KeyS, CameraAction::Backward)
+            .bind(KeyCode::KeyA, CameraAction::Left)
+            .bind(KeyCode::KeyD, CameraAction::Right)
+            .bind(KeyCode::Space, CameraAction::Up)
+            .bind(KeyCode::ShiftLeft, CameraAction::Down)
+            .bind(KeyCode::ControlLeft, CameraAction::SpeedBoost)
+            .bind(KeyCode::ControlRight, CameraAction::SpeedBoost)
+            .bind(KeyCode::KeyQ, CameraAction::RollLeft)
+            .bind(KeyCode::KeyE, CameraAction::RollRight)
+    }
+}
+
+impl KeyBindings {
+    pub fn empty() -> Self {
+        Self {
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Makes `key` trigger `action` too, in place of whatever it did before.
+    /// Escape is left alone.
+    pub fn bind(mut self, key: KeyCode, action: CameraAction) -> Self {
+        if key != RESERVED {
+            self.keys.insert(key, action);
+        }
+
+        self
+    }
+
+    /// Makes `key` the only one triggering `action`.
+    pub fn rebind(mut self, action: CameraAction, key: KeyCode) -> Self {
+        self.keys.retain(|_, bound| *bound != action);
+
+        self.bind(key, action)
+    }
+
+    pub fn unbind(mut self, key: KeyCode) -> Self {
+        self.keys.remove(&key);
+
+        self
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<CameraAction> {
+        self.keys.get(&key).copied()
+    }
+}
+
+impl From<HashMap<KeyCode, CameraAction>> for KeyBindings {
+    fn from(keys: HashMap<KeyCode, CameraAction>) -> Self {
+        keys.into_iter()
+            .fold(Self::empty(), |bindings, (key, action)| {
+                bindings.bind(key, action)
+            })
+    }
+}
+
+impl From<KeyBindings> for HashMap<KeyCode, CameraAction> {
+    fn from(bindings: KeyBindings) -> Self {
+        bindings.keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CameraAction, KeyBindings};
+    use winit::keyboard::KeyCode;
+
+    #[test]
+    fn loads_from_ron() {
+        let bindings: KeyBindings =
+            ron::from_str("{ArrowUp: Forward, KeyW: Backward, Escape: Up}").unwrap();
+
+        assert_eq!(
+            bindings.action(KeyCode::ArrowUp),
+            Some(CameraAction::Forward)
+        );
+        assert_eq!(bindings.action(KeyCode::KeyW), Some(CameraAction::Backward));
+        assert_eq!(bindings.action(KeyCode::KeyA), None);
+        // Escape always exits
+        assert_eq!(bindings.action(KeyCode::Escape), None);
+
+        let text = ron::to_string(&bindings).unwrap();
+        assert_eq!(ron::from_str::<KeyBindings>(&text).unwrap(), bindings);
+    }
+}
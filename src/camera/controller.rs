@@ -1,13 +1,238 @@
 use std::time::Duration;
 
-use cgmath::{InnerSpace, Rad, Vector3};
+use cgmath::{InnerSpace, Point3, Rad, Vector2, Vector3};
 use winit::{
     dpi::PhysicalPosition,
-    event::{ElementState, MouseScrollDelta},
+    event::{ElementState, MouseButton, MouseScrollDelta},
     keyboard::KeyCode,
 };
 
-use super::{Camera, SAFE_FRAC_PI_2};
+use super::{
+    approach_factor, Camera, CameraAction, KeyBindings, OrbitController, SAFE_FRAC_PI_2,
+    SPEED_BOOST,
+};
+
+/// Distance in front of the camera the orbit target is put at when
+/// switching from flying.
+const ORBIT_DISTANCE: f32 = 10.0;
+/// Radians per second Q and E roll the camera by.
+const ROLL_SPEED: f32 = std::f32::consts::FRAC_PI_2;
+
+/// Where the fly camera may go, applied after every update. Clamping to the
+/// exact bound each frame lets the camera slide along it without jitter.
+pub struct CameraConstraints {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    /// The ground height at an `(x, z)` position, e.g. from the terrain.
+    pub min_height_fn: Option<Box<dyn Fn(f32, f32) -> f32>>,
+    /// How far above the ground the camera is kept.
+    pub eye_height: f32,
+}
+
+impl std::fmt::Debug for CameraConstraints {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CameraConstraints {{ min: {:?}, max: {:?}, eye_height: {} }}",
+            self.min, self.max, self.eye_height
+        )
+    }
+}
+
+impl CameraConstraints {
+    pub fn new<P: Into<Point3<f32>>>(min: P, max: P) -> Self {
+        Self {
+            min: min.into(),
+            max: max.into(),
+            min_height_fn: None,
+            eye_height: 0.0,
+        }
+    }
+
+    /// Keeps the camera `eye_height` above the ground `height` reports.
+    pub fn with_ground<F: Fn(f32, f32) -> f32 + 'static>(
+        mut self,
+        height: F,
+        eye_height: f32,
+    ) -> Self {
+        self.min_height_fn = Some(Box::new(height));
+        self.eye_height = eye_height;
+
+        self
+    }
+
+    /// Moves `position` into bounds. Above the box's top the box wins over
+    /// the ground.
+    pub fn apply(&self, position: Point3<f32>) -> Point3<f32> {
+        let mut clamped = Point3::new(
+            position.x.clamp(self.min.x, self.max.x.max(self.min.x)),
+            position.y.max(self.min.y),
+            position.z.clamp(self.min.z, self.max.z.max(self.min.z)),
+        );
+        if let Some(height) = &self.min_height_fn {
+            clamped.y = clamped
+                .y
+                .max(height(clamped.x, clamped.z) + self.eye_height);
+        }
+        clamped.y = clamped.y.min(self.max.y.max(self.min.y));
+
+        clamped
+    }
+}
+
+/// What scrolling does while flying.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoomMode {
+    /// Moves the camera along its view direction.
+    Dolly,
+    /// Narrows the projection's field of view, see
+    /// [`CameraController::take_fov_zoom`].
+    Fov,
+}
+
+/// The control scheme driving the camera, switched with [`Controller::toggle`].
+#[derive(Debug)]
+pub enum Controller {
+    Fly(CameraController),
+    /// Keeps the fly controller to switch back to with its settings.
+    Orbit {
+        orbit: OrbitController,
+        fly: CameraController,
+    },
+}
+
+impl Controller {
+    /// Switches between flying and orbiting. The new controller starts from
+    /// the camera's pose, so the view doesn't jump.
+    pub fn toggle(&mut self, camera: &Camera) {
+        let placeholder = Self::Fly(CameraController::new(0.0, 0.0));
+        *self = match std::mem::replace(self, placeholder) {
+            Self::Fly(mut fly) => {
+                // Keys released while orbiting would never reach it
+                fly.stop();
+                let mut orbit = OrbitController::from_camera(
+                    camera,
+                    ORBIT_DISTANCE,
+                    fly.speed,
+                    fly.sensitivity,
+                );
+                orbit.set_bindings(fly.bindings.clone());
+
+                Self::Orbit { orbit, fly }
+            }
+            Self::Orbit { fly, .. } => Self::Fly(fly),
+        };
+    }
+
+    /// Takes over another camera's pose, e.g. after switching cameras, and
+    /// drops any momentum.
+    pub fn reset_to(&mut self, camera: &Camera) {
+        match self {
+            Self::Fly(fly) => fly.stop(),
+            Self::Orbit { orbit, .. } => orbit.look_from(camera),
+        }
+    }
+
+    /// The base speed, before boosting.
+    pub fn speed(&self) -> f32 {
+        match self {
+            Self::Fly(fly) | Self::Orbit { fly, .. } => fly.speed,
+        }
+    }
+
+    /// Sets both control schemes' base speed.
+    pub fn set_speed(&mut self, speed: f32) {
+        if let Self::Orbit { orbit, .. } = self {
+            orbit.set_speed(speed);
+        }
+        self.fly_mut().set_speed(speed);
+    }
+
+    /// Rebinds both control schemes.
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        if let Self::Orbit { orbit, .. } = self {
+            orbit.set_bindings(bindings.clone());
+        }
+        self.fly_mut().set_bindings(bindings);
+    }
+
+    /// How far in front of the camera what's being looked at is, the orbit
+    /// target or a fixed distance when flying.
+    pub fn focus_distance(&self) -> f32 {
+        match self {
+            Self::Fly(_) => ORBIT_DISTANCE,
+            Self::Orbit { orbit, .. } => orbit.distance,
+        }
+    }
+
+    /// The fly controller, also while it's kept aside for orbiting.
+    pub fn fly_mut(&mut self) -> &mut CameraController {
+        match self {
+            Self::Fly(fly) | Self::Orbit { fly, .. } => fly,
+        }
+    }
+
+    /// See [`CameraController::take_fov_zoom`], orbiting zooms by distance
+    /// instead.
+    pub fn take_fov_zoom(&mut self) -> f32 {
+        match self {
+            Self::Fly(fly) => fly.take_fov_zoom(),
+            Self::Orbit { .. } => 0.0,
+        }
+    }
+
+    /// Lets go of every key in both control schemes, e.g. when the window
+    /// loses focus and their releases would go elsewhere.
+    pub fn release_keys(&mut self) {
+        if let Self::Orbit { orbit, .. } = self {
+            orbit.release_keys();
+        }
+        self.fly_mut().release_keys();
+    }
+
+    pub fn handle_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
+        match self {
+            Self::Fly(fly) => fly.handle_keyboard(key, state),
+            Self::Orbit { orbit, .. } => orbit.handle_keyboard(key, state),
+        }
+    }
+
+    /// Only orbiting uses other buttons than the left one, see
+    /// [`OrbitController::handle_mouse_button`].
+    pub fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState) -> bool {
+        match self {
+            Self::Fly(_) => false,
+            Self::Orbit { orbit, .. } => orbit.handle_mouse_button(button, state),
+        }
+    }
+
+    /// Whether mouse motion should reach the controller without the left
+    /// button held.
+    pub fn is_panning(&self) -> bool {
+        matches!(self, Self::Orbit { orbit, .. } if orbit.is_panning())
+    }
+
+    pub fn handle_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
+        match self {
+            Self::Fly(fly) => fly.handle_mouse(mouse_dx, mouse_dy),
+            Self::Orbit { orbit, .. } => orbit.handle_mouse(mouse_dx, mouse_dy),
+        }
+    }
+
+    pub fn handle_scroll(&mut self, delta: &MouseScrollDelta) {
+        match self {
+            Self::Fly(fly) => fly.handle_scroll(delta),
+            Self::Orbit { orbit, .. } => orbit.handle_scroll(delta),
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: Duration) {
+        match self {
+            Self::Fly(fly) => fly.update(camera, dt),
+            Self::Orbit { orbit, .. } => orbit.update(camera, dt),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct CameraController {
@@ -17,11 +242,25 @@ pub struct CameraController {
     amount_backward: f32,
     amount_up: f32,
     amount_down: f32,
+    amount_roll_left: f32,
+    amount_roll_right: f32,
+    boost: bool,
     rotate_horizontal: f32,
     rotate_vertical: f32,
     scroll: f32,
     speed: f32,
     sensitivity: f32,
+    /// Smoothed movement along right, up and forward.
+    velocity: Vector3<f32>,
+    /// Smoothed turning in radians per second, yaw then pitch.
+    angular_velocity: Vector2<f32>,
+    translation_smoothing: f32,
+    rotation_smoothing: f32,
+    zoom_mode: ZoomMode,
+    /// Lets the pitch go past straight up or down, for flight style loops.
+    free_rotation: bool,
+    bindings: KeyBindings,
+    constraints: Option<CameraConstraints>,
 }
 
 impl CameraController {
@@ -33,28 +272,116 @@ impl CameraController {
             amount_backward: 0.0,
             amount_up: 0.0,
             amount_down: 0.0,
+            amount_roll_left: 0.0,
+            amount_roll_right: 0.0,
+            boost: false,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
             scroll: 0.0,
             speed,
             sensitivity,
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            angular_velocity: Vector2::new(0.0, 0.0),
+            translation_smoothing: 0.0,
+            rotation_smoothing: 0.0,
+            zoom_mode: ZoomMode::Dolly,
+            free_rotation: false,
+            bindings: KeyBindings::default(),
+            constraints: None,
+        }
+    }
+
+    /// Units per second moved, and scrolled per line when dollying.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(f32::EPSILON);
+    }
+
+    pub fn set_free_rotation(&mut self, free_rotation: bool) {
+        self.free_rotation = free_rotation;
+    }
+
+    pub fn set_constraints(&mut self, constraints: Option<CameraConstraints>) {
+        self.constraints = constraints;
+    }
+
+    pub fn bindings(&self) -> &KeyBindings {
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    pub fn zoom_mode(&self) -> ZoomMode {
+        self.zoom_mode
+    }
+
+    pub fn set_zoom_mode(&mut self, zoom_mode: ZoomMode) {
+        self.zoom_mode = zoom_mode;
+        self.scroll = 0.0;
+    }
+
+    /// Scrolling since the last call in [`ZoomMode::Fov`], positive to widen
+    /// the field of view. The controller can't reach the projection itself.
+    pub fn take_fov_zoom(&mut self) -> f32 {
+        match self.zoom_mode {
+            ZoomMode::Dolly => 0.0,
+            ZoomMode::Fov => std::mem::take(&mut self.scroll),
         }
     }
 
+    /// Roughly the seconds movement and mouse look take to settle within 1%
+    /// of what the input asks for, 0 responding instantly.
+    pub fn set_smoothing(&mut self, translation: f32, rotation: f32) {
+        self.translation_smoothing = translation.max(0.0);
+        self.rotation_smoothing = rotation.max(0.0);
+    }
+
+    /// Lets go of any held keys, the camera still eases to a stop.
+    pub fn release_keys(&mut self) {
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+        self.amount_roll_left = 0.0;
+        self.amount_roll_right = 0.0;
+        self.boost = false;
+    }
+
+    /// Drops any held keys and momentum.
+    fn stop(&mut self) {
+        *self = Self {
+            translation_smoothing: self.translation_smoothing,
+            rotation_smoothing: self.rotation_smoothing,
+            zoom_mode: self.zoom_mode,
+            free_rotation: self.free_rotation,
+            bindings: std::mem::take(&mut self.bindings),
+            constraints: self.constraints.take(),
+            ..Self::new(self.speed, self.sensitivity)
+        };
+    }
+
     pub fn handle_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
         let amount = match state {
             ElementState::Pressed => 1.0,
             ElementState::Released => 0.0,
         };
 
-        match key {
-            KeyCode::KeyW => self.amount_forward = amount,
-            KeyCode::KeyS => self.amount_backward = amount,
-            KeyCode::KeyA => self.amount_left = amount,
-            KeyCode::KeyD => self.amount_right = amount,
-            KeyCode::Space => self.amount_up = amount,
-            KeyCode::ShiftLeft => self.amount_down = amount,
-            _ => return false,
+        let Some(action) = self.bindings.action(key) else {
+            return false;
+        };
+        match action {
+            CameraAction::Forward => self.amount_forward = amount,
+            CameraAction::Backward => self.amount_backward = amount,
+            CameraAction::Left => self.amount_left = amount,
+            CameraAction::Right => self.amount_right = amount,
+            CameraAction::Up => self.amount_up = amount,
+            CameraAction::Down => self.amount_down = amount,
+            CameraAction::SpeedBoost => self.boost = state.is_pressed(),
+            CameraAction::RollLeft => self.amount_roll_left = amount,
+            CameraAction::RollRight => self.amount_roll_right = amount,
         }
 
         true
@@ -78,32 +405,163 @@ impl CameraController {
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        let target_velocity = Vector3::new(
+            self.amount_right - self.amount_left,
+            self.amount_up - self.amount_down,
+            self.amount_forward - self.amount_backward,
+        ) * self.speed
+            * match self.boost {
+                true => SPEED_BOOST,
+                false => 1.0,
+            };
+        self.velocity +=
+            (target_velocity - self.velocity) * approach_factor(self.translation_smoothing, dt);
+        camera.position += forward * self.velocity.z * dt;
+        camera.position += right * self.velocity.x * dt;
 
         let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
         let scrollward =
             Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
-        camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
-        self.scroll = 0.0;
+        if self.zoom_mode == ZoomMode::Dolly {
+            camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
+            self.scroll = 0.0;
+        }
 
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        camera.position.y += self.velocity.y * dt;
 
-        camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
-        camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
+        let target_angular_velocity =
+            Vector2::new(self.rotate_horizontal, -self.rotate_vertical) * self.sensitivity;
+        self.angular_velocity += (target_angular_velocity - self.angular_velocity)
+            * approach_factor(self.rotation_smoothing, dt);
+        camera.yaw += Rad(self.angular_velocity.x) * dt;
+        camera.pitch += Rad(self.angular_velocity.y) * dt;
+        camera.roll += Rad(self.amount_roll_right - self.amount_roll_left) * ROLL_SPEED * dt;
 
         self.rotate_horizontal = 0.0;
         self.rotate_vertical = 0.0;
 
-        let max_angle = Rad(SAFE_FRAC_PI_2);
-        if camera.pitch < -max_angle {
-            camera.pitch = -max_angle;
+        if !self.free_rotation {
+            camera.pitch = Rad(camera.pitch.0.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+        }
+
+        if let Some(constraints) = &self.constraints {
+            camera.position = constraints.apply(camera.position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CameraConstraints, CameraController};
+    use crate::camera::{Camera, CameraAction, KeyBindings};
+    use cgmath::{assert_abs_diff_eq, Deg};
+    use std::time::Duration;
+    use winit::{event::ElementState, keyboard::KeyCode};
+
+    const FRAME: Duration = Duration::from_micros(8333);
+
+    #[test]
+    fn smoothed_movement_settles() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(8.0, 1.0);
+        controller.set_smoothing(0.25, 0.0);
+
+        controller.handle_keyboard(KeyCode::KeyW, ElementState::Pressed);
+        controller.update(&mut camera, FRAME);
+        assert!(controller.velocity.z < 8.0);
+        for _ in 0..120 {
+            controller.update(&mut camera, FRAME);
+        }
+        assert_abs_diff_eq!(controller.velocity.z, 8.0, epsilon = 0.08);
+
+        // A quarter of a second after letting go it's all but stopped
+        controller.handle_keyboard(KeyCode::KeyW, ElementState::Released);
+        for _ in 0..30 {
+            controller.update(&mut camera, FRAME);
+        }
+        assert!(controller.velocity.z < 0.08);
+    }
+
+    #[test]
+    fn no_smoothing_is_instant() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(8.0, 1.0);
+
+        controller.handle_keyboard(KeyCode::KeyW, ElementState::Pressed);
+        controller.update(&mut camera, Duration::from_millis(500));
+        assert_abs_diff_eq!(camera.position.x, 4.0, epsilon = 1e-5);
+
+        controller.handle_keyboard(KeyCode::KeyW, ElementState::Released);
+        controller.update(&mut camera, Duration::from_millis(500));
+        assert_abs_diff_eq!(camera.position.x, 4.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn released_keys_stop_moving() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(8.0, 1.0);
+
+        controller.handle_keyboard(KeyCode::KeyW, ElementState::Pressed);
+        controller.handle_keyboard(KeyCode::ControlLeft, ElementState::Pressed);
+        controller.release_keys();
+        controller.update(&mut camera, Duration::from_millis(500));
+        assert_abs_diff_eq!(camera.position.x, 0.0);
+    }
+
+    #[test]
+    fn arrow_key_bindings() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(8.0, 1.0);
+        controller.set_bindings(
+            KeyBindings::default()
+                .rebind(CameraAction::Forward, KeyCode::ArrowUp)
+                .rebind(CameraAction::Backward, KeyCode::ArrowDown)
+                .bind(KeyCode::Escape, CameraAction::Up),
+        );
+
+        // Unbound keys fall through to the window, where Escape exits
+        for key in [KeyCode::KeyW, KeyCode::Escape] {
+            assert!(!controller.handle_keyboard(key, ElementState::Pressed));
+        }
+        assert!(controller.handle_keyboard(KeyCode::ArrowUp, ElementState::Pressed));
+        controller.update(&mut camera, Duration::from_millis(500));
+        assert_abs_diff_eq!(camera.position, (4.0, 0.0, 0.0).into(), epsilon = 1e-5);
+
+        // Boosting keeps its default key
+        controller.handle_keyboard(KeyCode::ControlLeft, ElementState::Pressed);
+        controller.update(&mut camera, Duration::from_millis(500));
+        assert_abs_diff_eq!(camera.position.x, 20.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn constraints_clamp_to_the_bounds() {
+        let mut camera = Camera::new((0.0, 1.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(8.0, 1.0);
+        controller.set_smoothing(0.2, 0.0);
+        controller.set_constraints(Some(
+            CameraConstraints::new((-5.0, 0.0, -5.0), (5.0, 10.0, 5.0))
+                .with_ground(|x, _| x * 0.1, 1.5),
+        ));
 
-            return;
+        // Pushing diagonally into the wall at x = 5 slides along it
+        controller.handle_keyboard(KeyCode::KeyW, ElementState::Pressed);
+        controller.handle_keyboard(KeyCode::KeyD, ElementState::Pressed);
+        let mut previous_z = camera.position.z;
+        for _ in 0..120 {
+            controller.update(&mut camera, FRAME);
+            assert!(camera.position.x <= 5.0);
+            assert!(camera.position.z >= previous_z);
+            previous_z = camera.position.z;
         }
+        assert_eq!(camera.position.x, 5.0);
+        assert_eq!(camera.position.z, 5.0);
+        // Kept above the ground, which is higher than where it started
+        assert_eq!(camera.position.y, 5.0 * 0.1 + 1.5);
 
-        if camera.pitch > max_angle {
-            camera.pitch = max_angle;
+        controller.handle_keyboard(KeyCode::ShiftLeft, ElementState::Pressed);
+        for _ in 0..60 {
+            controller.update(&mut camera, FRAME);
         }
+        assert_eq!(camera.position.y, 5.0 * 0.1 + 1.5);
     }
 }
@@ -0,0 +1,122 @@
+use crate::model::Aabb;
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+
+/// The six planes bounding what a view projection matrix can see, each
+/// `(normal, distance)` with the normal pointing inwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a matrix mapping to wgpu's clip space, where
+    /// depth runs from 0 to 1. Ordered left, right, bottom, top, near, far.
+    pub fn from_matrix(view_projection: &Matrix4<f32>) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_projection.row(row));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+                let length = plane.truncate().magnitude();
+                match length > 0.0 {
+                    true => plane / length,
+                    false => plane,
+                }
+            }),
+        }
+    }
+
+    /// Whether any of the box may be visible. Boxes near the corners can
+    /// pass without being inside, which is fine for culling.
+    pub fn intersects_aabb(&self, bounds: &Aabb) -> bool {
+        if bounds.is_empty() {
+            return false;
+        }
+
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 {
+                    bounds.max.x
+                } else {
+                    bounds.min.x
+                },
+                if plane.y >= 0.0 {
+                    bounds.max.y
+                } else {
+                    bounds.min.y
+                },
+                if plane.z >= 0.0 {
+                    bounds.max.z
+                } else {
+                    bounds.min.z
+                },
+            );
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    /// Whether any of the sphere may be visible.
+    pub fn contains_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Frustum;
+    use crate::{camera::OPENGL_TO_WGPU_MATRIX, model::Aabb};
+    use cgmath::{assert_abs_diff_eq, Deg, Matrix4, Point3, Vector3, Vector4};
+
+    #[test]
+    fn orthographic_planes() {
+        let matrix = OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-2.0, 2.0, -1.0, 1.0, 1.0, 11.0);
+        let frustum = Frustum::from_matrix(&matrix);
+
+        // Looking down -z, the box spans x in [-2, 2], y in [-1, 1] and z in
+        // [-11, -1]
+        let expected = [
+            Vector4::new(1.0, 0.0, 0.0, 2.0),
+            Vector4::new(-1.0, 0.0, 0.0, 2.0),
+            Vector4::new(0.0, 1.0, 0.0, 1.0),
+            Vector4::new(0.0, -1.0, 0.0, 1.0),
+            Vector4::new(0.0, 0.0, -1.0, -1.0),
+            Vector4::new(0.0, 0.0, 1.0, 11.0),
+        ];
+        for (plane, expected) in frustum.planes.iter().zip(expected) {
+            assert_abs_diff_eq!(*plane, expected, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn culls_outside_the_view() {
+        let projection = OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(90.0), 1.0, 0.1, 100.0);
+        let view = Matrix4::look_at_rh(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vector3::unit_y(),
+        );
+        let frustum = Frustum::from_matrix(&(projection * view));
+
+        let unit_box = |x: f32, y: f32, z: f32| {
+            Aabb::new(
+                Vector3::new(x - 0.5, y - 0.5, z - 0.5),
+                Vector3::new(x + 0.5, y + 0.5, z + 0.5),
+            )
+        };
+        assert!(frustum.intersects_aabb(&unit_box(0.0, 0.0, -10.0)));
+        // Behind the camera, past the far plane and off to the side
+        assert!(!frustum.intersects_aabb(&unit_box(0.0, 0.0, 10.0)));
+        assert!(!frustum.intersects_aabb(&unit_box(0.0, 0.0, -200.0)));
+        assert!(!frustum.intersects_aabb(&unit_box(20.0, 0.0, -10.0)));
+        // Straddling the right edge of the 90° view
+        assert!(frustum.intersects_aabb(&unit_box(10.4, 0.0, -10.0)));
+        assert!(!frustum.intersects_aabb(&Aabb::EMPTY));
+
+        assert!(frustum.contains_sphere(Vector3::new(0.0, 0.0, -10.0), 1.0));
+        assert!(frustum.contains_sphere(Vector3::new(10.5, 0.0, -10.0), 1.0));
+        assert!(!frustum.contains_sphere(Vector3::new(0.0, 0.0, 5.0), 1.0));
+    }
+}
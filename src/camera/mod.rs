@@ -1,29 +1,63 @@
-use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3};
-use std::f32::consts::FRAC_PI_2;
+use crate::{math::Ray, model::Aabb};
+use cgmath::{
+    Angle, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector2, Vector3,
+    Vector4, VectorSpace,
+};
+use std::{f32::consts::FRAC_PI_2, time::Duration};
 
+mod bindings;
 mod controller;
+mod frustum;
+mod orbit;
+mod path;
+mod pose;
 mod projection;
 mod uniform;
 
-pub use controller::CameraController;
-pub use projection::Projection;
+pub use bindings::{CameraAction, KeyBindings};
+pub use controller::{CameraConstraints, CameraController, Controller, ZoomMode};
+pub use frustum::Frustum;
+pub use orbit::OrbitController;
+pub use path::{CameraPath, Keyframe};
+pub use pose::{CameraPose, CameraResult};
+pub use projection::{Projection, ProjectionKind};
 pub use uniform::CameraUniform;
 
+/// Maps OpenGL's depth range of -1 to 1 onto wgpu's 0 to 1. Written a
+/// column per line.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
         1.0, 0.0, 0.0, 0.0,
         0.0, 1.0, 0.0, 0.0,
-        0.0, 0.0, 0.5, 0.5,
-        0.0, 0.0, 0.0, 1.0,
+        0.0, 0.0, 0.5, 0.0,
+        0.0, 0.0, 0.5, 1.0,
     );
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
+/// How many times faster the controllers move while
+/// [`CameraAction::SpeedBoost`] is held.
+const SPEED_BOOST: f32 = 4.0;
+/// How much room [`Camera::frame_aabb`] leaves around what it frames.
+const FRAME_MARGIN: f32 = 1.1;
 
-#[derive(Debug)]
+/// How much of the way to its target an exponentially smoothed value moves
+/// in `dt`. Five time constants leave under 1% of a change, so `settle_time`
+/// is five of them.
+fn approach_factor(settle_time: f32, dt: f32) -> f32 {
+    match settle_time > 0.0 {
+        true => 1.0 - (-5.0 * dt / settle_time).exp(),
+        false => 1.0,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct Camera {
     pub position: Point3<f32>,
     yaw: Rad<f32>,
     pitch: Rad<f32>,
+    /// Banking around the view direction, positive tilting the top of the
+    /// view to the right.
+    roll: Rad<f32>,
 }
 
 impl Camera {
@@ -36,17 +70,249 @@ impl Camera {
             position: position.into(),
             yaw: yaw.into(),
             pitch: pitch.into(),
+            roll: Rad(0.0),
         }
     }
 
-    pub fn matrix(&self) -> Matrix4<f32> {
+    pub fn with_roll<R: Into<Rad<f32>>>(mut self, roll: R) -> Self {
+        self.roll = roll.into();
+
+        self
+    }
+
+    /// Unit vector the camera looks along.
+    pub fn forward(&self) -> Vector3<f32> {
         let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
 
-        Matrix4::look_to_rh(
-            self.position,
-            Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize(),
-            Vector3::unit_y(),
-        )
+        Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize()
+    }
+
+    /// Turns the camera towards `target`. Looking straight up or down keeps
+    /// the current yaw, and the pitch stops just short of the poles.
+    pub fn look_at(&mut self, target: Point3<f32>) {
+        let direction = target - self.position;
+        let distance = direction.magnitude();
+        if distance <= f32::EPSILON {
+            return;
+        }
+
+        let horizontal = direction.x.hypot(direction.z);
+        if horizontal > distance * 1e-6 {
+            self.yaw = Rad(direction.z.atan2(direction.x));
+        }
+        self.pitch = Rad((direction.y / distance)
+            .asin()
+            .clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+    }
+
+    /// Backs the camera away from the box's center along its view direction
+    /// until the whole box fits in the perspective view, with a margin.
+    pub fn frame_aabb(&mut self, aabb: &Aabb, projection: &Projection) {
+        if aabb.is_empty() {
+            return;
+        }
+
+        // Fitting the bounding sphere works whichever way the camera faces
+        let radius = (aabb.size().magnitude() / 2.0).max(f32::EPSILON);
+        let half_fovy = projection.fovy().0 / 2.0;
+        let half_fovx = (half_fovy.tan() * projection.aspect()).atan();
+        let distance = radius / half_fovy.min(half_fovx).sin() * FRAME_MARGIN;
+
+        self.position = Point3::from_vec(aabb.center() - self.forward() * distance);
+    }
+
+    /// The world space ray through `screen`, in pixels from the top left of
+    /// a `viewport` sized view, starting on the near plane.
+    pub fn screen_ray(
+        &self,
+        projection: &Projection,
+        screen: Vector2<f32>,
+        viewport: (u32, u32),
+    ) -> Ray {
+        let Some(inverse) = (projection.matrix() * self.matrix()).invert() else {
+            return Ray::new(self.position, self.forward());
+        };
+
+        let x = 2.0 * screen.x / viewport.0.max(1) as f32 - 1.0;
+        let y = 1.0 - 2.0 * screen.y / viewport.1.max(1) as f32;
+        // wgpu's depth runs from 0 on the near plane to 1 on the far one
+        let unproject = |depth| Point3::from_homogeneous(inverse * Vector4::new(x, y, depth, 1.0));
+        let near = unproject(0.0);
+
+        Ray::new(near, unproject(1.0) - near)
+    }
+
+    /// Unit vector out of the top of the view, square to
+    /// [`Camera::forward`]. It stays meaningful past the poles, which the
+    /// world's up isn't.
+    pub fn up(&self) -> Vector3<f32> {
+        let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
+        let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+        let up = Vector3::new(-sin_pitch * cos_yaw, cos_pitch, -sin_pitch * sin_yaw);
+
+        let (sin_roll, cos_roll) = self.roll.0.sin_cos();
+        let right = self.forward().cross(up);
+        (up * cos_roll + right * sin_roll).normalize()
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.forward(), self.up())
+    }
+
+    /// The pose `t` of the way from this camera to `other`, turning the
+    /// short way round.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: Point3::from_vec(self.position.to_vec().lerp(other.position.to_vec(), t)),
+            yaw: self.yaw + (other.yaw - self.yaw).normalize_signed() * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+            roll: self.roll + (other.roll - self.roll).normalize_signed() * t,
+        }
+    }
+}
+
+/// Eases the view from one camera's pose into another's after switching.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraTransition {
+    from: Camera,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl CameraTransition {
+    pub fn new(from: Camera, duration: Duration) -> Self {
+        Self {
+            from,
+            elapsed: Duration::ZERO,
+            duration,
+        }
+    }
+
+    /// Returns whether the transition has finished.
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        self.elapsed += dt;
+
+        self.elapsed >= self.duration
+    }
+
+    /// The pose to draw with while heading to `to`, eased in and out.
+    pub fn blend(&self, to: &Camera) -> Camera {
+        let t = (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+
+        self.from.lerp(to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Camera, Projection, ProjectionKind, FRAME_MARGIN, SAFE_FRAC_PI_2};
+    use crate::model::Aabb;
+    use cgmath::{
+        assert_abs_diff_eq, Deg, InnerSpace, Matrix, Matrix4, Point3, Rad, Vector2, Vector3,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn look_at_axis_targets() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+
+        let targets = [
+            ((0.0, 0.0, -5.0), Deg(-90.0), Deg(0.0)),
+            ((-5.0, 0.0, 0.0), Deg(180.0), Deg(0.0)),
+            ((0.0, 0.0, 5.0), Deg(90.0), Deg(0.0)),
+            ((5.0, 5.0, 0.0), Deg(0.0), Deg(45.0)),
+        ];
+        for (target, yaw, pitch) in targets {
+            camera.look_at(target.into());
+            assert_abs_diff_eq!(camera.yaw, Rad::from(yaw), epsilon = 1e-5);
+            assert_abs_diff_eq!(camera.pitch, Rad::from(pitch), epsilon = 1e-5);
+        }
+
+        // Straight down keeps facing +x, just short of the pole
+        camera.look_at(Point3::new(0.0, -5.0, 0.0));
+        assert_abs_diff_eq!(camera.yaw, Rad(0.0), epsilon = 1e-5);
+        assert_eq!(camera.pitch, Rad(-SAFE_FRAC_PI_2));
+    }
+
+    #[test]
+    fn center_ray_looks_forward() {
+        let camera = Camera::new((1.0, 5.0, 10.0), Deg(-60.0), Deg(-20.0));
+        let mut projection = Projection::new(1600, 900, Deg(60.0), 0.1, 100.0);
+
+        let ray = camera.screen_ray(&projection, Vector2::new(800.0, 450.0), (1600, 900));
+        assert_abs_diff_eq!(ray.direction, camera.forward(), epsilon = 1e-4);
+        assert_abs_diff_eq!(
+            ray.origin,
+            camera.position + camera.forward() * 0.1,
+            epsilon = 1e-4
+        );
+
+        // The top left corner is up and to the left of the view
+        let corner = camera.screen_ray(&projection, Vector2::new(0.0, 0.0), (1600, 900));
+        let right = camera.forward().cross(Vector3::unit_y()).normalize();
+        assert!(corner.direction.y > ray.direction.y);
+        assert!(corner.direction.dot(right) < 0.0);
+
+        projection.set_kind(ProjectionKind::Orthographic, 10.0);
+        projection.update(Duration::from_secs(1));
+        let parallel = camera.screen_ray(&projection, Vector2::new(0.0, 0.0), (1600, 900));
+        assert_abs_diff_eq!(parallel.direction, camera.forward(), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn frames_a_unit_cube() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(-90.0), Deg(0.0));
+        let projection = Projection::new(800, 800, Deg(45.0), 0.1, 100.0);
+        let cube = Aabb::new(Vector3::new(2.0, 0.0, 0.0), Vector3::new(3.0, 1.0, 1.0));
+
+        camera.frame_aabb(&cube, &projection);
+        let distance = 3f32.sqrt() / 2.0 / 22.5f32.to_radians().sin() * FRAME_MARGIN;
+        let center = Point3::new(2.5, 0.5, 0.5);
+        assert_abs_diff_eq!(
+            (center - camera.position).magnitude(),
+            distance,
+            epsilon = 1e-4
+        );
+        assert_abs_diff_eq!(
+            camera.position,
+            Point3::new(2.5, 0.5, 0.5 + distance),
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn roll_swaps_up_and_right() {
+        let level = Camera::new((0.0, 0.0, 0.0), Deg(30.0), Deg(-20.0));
+        let rolled = level.with_roll(Deg(90.0));
+        let (level_view, rolled_view) = (level.matrix(), rolled.matrix());
+
+        // The rows of a view matrix are right, up and backward
+        assert_abs_diff_eq!(rolled_view.row(2), level_view.row(2), epsilon = 1e-5);
+        assert_abs_diff_eq!(rolled_view.row(1), level_view.row(0), epsilon = 1e-5);
+        assert_abs_diff_eq!(rolled_view.row(0), -level_view.row(1), epsilon = 1e-5);
+        assert_abs_diff_eq!(rolled.forward(), level.forward());
+
+        // Rolled the other way the top points left
+        let right = level.forward().cross(level.up());
+        assert_abs_diff_eq!(level.with_roll(Deg(-90.0)).up(), -right, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn up_matches_the_world_when_level() {
+        let camera = Camera::new((0.0, 0.0, 0.0), Deg(70.0), Deg(-35.0));
+        let world_up = Matrix4::look_to_rh(camera.position, camera.forward(), Vector3::unit_y());
+        assert_abs_diff_eq!(camera.matrix(), world_up, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn lerp_turns_the_short_way() {
+        let from = Camera::new((0.0, 0.0, 0.0), Deg(170.0), Deg(0.0));
+        let to = Camera::new((10.0, 0.0, 0.0), Deg(-170.0), Deg(40.0));
+
+        let halfway = from.lerp(&to, 0.5);
+        assert_abs_diff_eq!(halfway.position.x, 5.0);
+        assert_abs_diff_eq!(halfway.yaw, Rad::from(Deg(180.0)), epsilon = 1e-5);
+        assert_abs_diff_eq!(halfway.pitch, Rad::from(Deg(20.0)), epsilon = 1e-5);
     }
 }
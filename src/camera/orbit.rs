@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Rad, Vector3};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, MouseButton, MouseScrollDelta},
+    keyboard::KeyCode,
+};
+
+use super::{Camera, CameraAction, KeyBindings, SAFE_FRAC_PI_2, SPEED_BOOST};
+
+const MIN_DISTANCE: f32 = 0.1;
+/// Factor the distance is scaled by per scrolled line.
+const ZOOM_STEP: f32 = 0.9;
+
+/// Circles the camera around a target point, for inspecting a single model.
+/// Dragging rotates, middle-dragging pans the target and scrolling zooms,
+/// while the fly controls' keys move the target.
+#[derive(Debug)]
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+    amount_left: f32,
+    amount_right: f32,
+    amount_forward: f32,
+    amount_backward: f32,
+    amount_up: f32,
+    amount_down: f32,
+    boost: bool,
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+    pan_horizontal: f32,
+    pan_vertical: f32,
+    panning: bool,
+    scroll: f32,
+    speed: f32,
+    sensitivity: f32,
+    bindings: KeyBindings,
+}
+
+impl OrbitController {
+    pub fn new<V: Into<Point3<f32>>, Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(
+        target: V,
+        distance: f32,
+        yaw: Y,
+        pitch: P,
+        speed: f32,
+        sensitivity: f32,
+    ) -> Self {
+        Self {
+            target: target.into(),
+            distance: distance.max(MIN_DISTANCE),
+            yaw: yaw.into(),
+            pitch: pitch.into(),
+            amount_left: 0.0,
+            amount_right: 0.0,
+            amount_forward: 0.0,
+            amount_backward: 0.0,
+            amount_up: 0.0,
+            amount_down: 0.0,
+            boost: false,
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+            pan_horizontal: 0.0,
+            pan_vertical: 0.0,
+            panning: false,
+            scroll: 0.0,
+            speed,
+            sensitivity,
+            bindings: KeyBindings::default(),
+        }
+    }
+
+    /// Orbits the point `distance` in front of the camera, keeping its pose.
+    pub fn from_camera(camera: &Camera, distance: f32, speed: f32, sensitivity: f32) -> Self {
+        Self::new(
+            camera.position + camera.forward() * distance,
+            distance,
+            camera.yaw,
+            camera.pitch,
+            speed,
+            sensitivity,
+        )
+    }
+
+    /// Orbits in front of `camera` at the current distance, taking over its
+    /// pose.
+    pub fn look_from(&mut self, camera: &Camera) {
+        self.target = camera.position + camera.forward() * self.distance;
+        self.yaw = camera.yaw;
+        self.pitch = camera.pitch;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(f32::EPSILON);
+    }
+
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    /// Lets go of any held keys and the pan button.
+    pub fn release_keys(&mut self) {
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+        self.boost = false;
+        self.panning = false;
+    }
+
+    pub fn is_panning(&self) -> bool {
+        self.panning
+    }
+
+    pub fn handle_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
+        let amount = match state {
+            ElementState::Pressed => 1.0,
+            ElementState::Released => 0.0,
+        };
+
+        let Some(action) = self.bindings.action(key) else {
+            return false;
+        };
+        match action {
+            CameraAction::Forward => self.amount_forward = amount,
+            CameraAction::Backward => self.amount_backward = amount,
+            CameraAction::Left => self.amount_left = amount,
+            CameraAction::Right => self.amount_right = amount,
+            CameraAction::Up => self.amount_up = amount,
+            CameraAction::Down => self.amount_down = amount,
+            CameraAction::SpeedBoost => self.boost = state.is_pressed(),
+            // Orbiting keeps the camera level
+            CameraAction::RollLeft | CameraAction::RollRight => return false,
+        }
+
+        true
+    }
+
+    /// Holding the middle button turns mouse motion into panning.
+    pub fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState) -> bool {
+        if button != MouseButton::Middle {
+            return false;
+        }
+        self.panning = state.is_pressed();
+
+        true
+    }
+
+    pub fn handle_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
+        match self.panning {
+            true => {
+                self.pan_horizontal = mouse_dx as f32;
+                self.pan_vertical = mouse_dy as f32;
+            }
+            false => {
+                self.rotate_horizontal = mouse_dx as f32;
+                self.rotate_vertical = mouse_dy as f32;
+            }
+        }
+    }
+
+    /// Scrolling up zooms in.
+    pub fn handle_scroll(&mut self, delta: &MouseScrollDelta) {
+        self.scroll = match delta {
+            MouseScrollDelta::LineDelta(_, scroll) => *scroll,
+            MouseScrollDelta::PixelDelta(PhysicalPosition { y: scroll, .. }) => {
+                *scroll as f32 / 20.0
+            }
+        };
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        self.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
+        self.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
+        self.pitch = Rad(self.pitch.0.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+
+        self.distance = (self.distance * ZOOM_STEP.powf(self.scroll)).max(MIN_DISTANCE);
+        self.scroll = 0.0;
+
+        let (yaw_sin, yaw_cos) = self.yaw.0.sin_cos();
+        let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
+        let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
+        let speed = match self.boost {
+            true => self.speed * SPEED_BOOST,
+            false => self.speed,
+        };
+        self.target += forward * (self.amount_forward - self.amount_backward) * speed * dt;
+        self.target += right * (self.amount_right - self.amount_left) * speed * dt;
+        self.target.y += (self.amount_up - self.amount_down) * speed * dt;
+
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+        camera.roll = Rad(0.0);
+        let view = camera.forward();
+
+        // Panning drags the target along with the cursor, further away
+        // targets move faster so it keeps up on screen
+        let up = right.cross(view).normalize();
+        let pan_speed = self.distance * self.sensitivity * dt * 0.1;
+        self.target += (-right * self.pan_horizontal + up * self.pan_vertical) * pan_speed;
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
+
+        camera.position = Point3::from_vec(self.target.to_vec() - view * self.distance);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OrbitController;
+    use crate::camera::{Camera, SAFE_FRAC_PI_2};
+    use cgmath::{assert_abs_diff_eq, Deg, InnerSpace};
+    use std::time::Duration;
+
+    #[test]
+    fn switching_keeps_the_pose() {
+        let mut camera = Camera::new((0.0, 5.0, 10.0), Deg(-90.0), Deg(-20.0));
+        let (position, forward) = (camera.position, camera.forward());
+
+        let mut orbit = OrbitController::from_camera(&camera, 10.0, 4.0, 0.4);
+        orbit.update(&mut camera, Duration::from_millis(16));
+        assert_abs_diff_eq!(camera.position, position, epsilon = 1e-4);
+        assert_abs_diff_eq!(camera.forward(), forward, epsilon = 1e-4);
+        assert_abs_diff_eq!(
+            (orbit.target - camera.position).magnitude(),
+            10.0,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn pitch_clamps_at_the_poles() {
+        let mut camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let mut orbit = OrbitController::from_camera(&camera, 5.0, 4.0, 1.0);
+
+        orbit.handle_mouse(0.0, -1e6);
+        orbit.update(&mut camera, Duration::from_secs(1));
+        assert_eq!(camera.pitch.0, SAFE_FRAC_PI_2);
+
+        orbit.handle_mouse(0.0, 1e6);
+        orbit.update(&mut camera, Duration::from_secs(1));
+        assert_eq!(camera.pitch.0, -SAFE_FRAC_PI_2);
+        // Still looking at the target from its distance
+        assert_abs_diff_eq!(
+            (orbit.target - camera.position).magnitude(),
+            5.0,
+            epsilon = 1e-4
+        );
+    }
+}
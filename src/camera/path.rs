@@ -0,0 +1,217 @@
+//! Scripted camera paths, for captures and demos.
+
+use super::{Camera, CameraResult};
+use cgmath::{Angle, EuclideanSpace, Point3, Rad, Vector3};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// A pose the path passes through `time` seconds in, angles in radians.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub time: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Self {
+            position: camera.position.into(),
+            yaw: camera.yaw.0,
+            pitch: camera.pitch.0,
+            time,
+        }
+    }
+}
+
+/// Keyframes the camera is moved through, smoothly along a Catmull-Rom
+/// spline and turning the short way between them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    /// Starts over once the last keyframe is reached, rather than stopping.
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<Keyframe>, looping: bool) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Self { keyframes, looping }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Adds a keyframe, keeping them in order.
+    pub fn push(&mut self, keyframe: Keyframe) {
+        let index = self
+            .keyframes
+            .partition_point(|other| other.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// When the last keyframe is reached.
+    pub fn end(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Whether playing for `time` seconds has run off the end of the path.
+    pub fn is_finished(&self, time: f32) -> bool {
+        !self.looping && time >= self.end()
+    }
+
+    /// The pose `time` seconds in, held at either end unless looping.
+    /// Empty paths sit at the origin.
+    pub fn sample(&self, time: f32) -> (Point3<f32>, Rad<f32>, Rad<f32>) {
+        let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) else {
+            return (Point3::origin(), Rad(0.0), Rad(0.0));
+        };
+
+        let span = last.time - first.time;
+        let time = match self.looping && span > 0.0 {
+            true => first.time + (time - first.time).rem_euclid(span),
+            false => time.clamp(first.time, last.time),
+        };
+
+        // The segment between keyframes `index` and `index + 1`
+        let last_index = self.keyframes.len() - 1;
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time)
+            .saturating_sub(1)
+            .min(last_index.saturating_sub(1));
+        let at = |offset: isize| {
+            let index = (index as isize + offset).clamp(0, last_index as isize) as usize;
+            &self.keyframes[index]
+        };
+        let (from, to) = (at(0), at(1));
+        let duration = to.time - from.time;
+        let t = match duration > 0.0 {
+            true => ((time - from.time) / duration).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+
+        let [p1, p2] = [from, to].map(|keyframe| Vector3::from(keyframe.position));
+        // Past either end the spline heads on in a straight line
+        let p0 = match index > 0 {
+            true => Vector3::from(at(-1).position),
+            false => p1 * 2.0 - p2,
+        };
+        let p3 = match index + 2 <= last_index {
+            true => Vector3::from(at(2).position),
+            false => p2 * 2.0 - p1,
+        };
+        let position = (p1 * 2.0
+            + (p2 - p0) * t
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (t * t)
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * (t * t * t))
+            * 0.5;
+        let turn = |from: f32, to: f32| Rad(from) + (Rad(to) - Rad(from)).normalize_signed() * t;
+
+        (
+            Point3::from_vec(position),
+            turn(from.yaw, to.yaw),
+            turn(from.pitch, to.pitch),
+        )
+    }
+
+    pub fn camera_at(&self, time: f32) -> Camera {
+        let (position, yaw, pitch) = self.sample(time);
+
+        Camera::new(position, yaw, pitch)
+    }
+
+    pub fn to_ron(&self) -> CameraResult<String> {
+        Ok(ron::ser::to_string_pretty(self, Default::default())?)
+    }
+
+    /// Keyframes don't need to be in order in the file.
+    pub fn from_ron(text: &str) -> CameraResult<Self> {
+        let path: Self = ron::from_str(text)?;
+
+        Ok(Self::new(path.keyframes, path.looping))
+    }
+
+    pub fn save(&self, path: &Path) -> CameraResult<()> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: &Path) -> CameraResult<Self> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CameraPath, Keyframe};
+    use cgmath::{assert_abs_diff_eq, Deg, Point3, Rad};
+
+    fn keyframe(x: f32, yaw: Deg<f32>, time: f32) -> Keyframe {
+        Keyframe {
+            position: [x, 0.0, 0.0],
+            yaw: Rad::from(yaw).0,
+            pitch: 0.0,
+            time,
+        }
+    }
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let path = CameraPath::new(
+            vec![
+                keyframe(20.0, Deg(-170.0), 4.0),
+                keyframe(0.0, Deg(0.0), 0.0),
+                keyframe(10.0, Deg(170.0), 2.0),
+            ],
+            false,
+        );
+
+        // Passing through every keyframe and holding at the ends
+        for (time, x) in [
+            (-1.0, 0.0),
+            (0.0, 0.0),
+            (2.0, 10.0),
+            (4.0, 20.0),
+            (9.0, 20.0),
+        ] {
+            assert_abs_diff_eq!(
+                path.sample(time).0,
+                Point3::new(x, 0.0, 0.0),
+                epsilon = 1e-5
+            );
+        }
+        assert!(path.is_finished(4.0));
+
+        // Evenly spaced points on a line stay on it, halfway in between
+        let (position, yaw, _) = path.sample(1.0);
+        assert_abs_diff_eq!(position, Point3::new(5.0, 0.0, 0.0), epsilon = 1e-5);
+        assert_abs_diff_eq!(yaw, Rad::from(Deg(85.0)), epsilon = 1e-5);
+        // From 170° to -170° turns through 180° rather than back past 0°
+        let (_, yaw, _) = path.sample(3.0);
+        assert_abs_diff_eq!(yaw, Rad::from(Deg(180.0)), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn loops_and_loads_from_ron() {
+        let path = CameraPath::from_ron(
+            "(keyframes: [
+                (position: (10.0, 0.0, 0.0), yaw: 0.0, pitch: 0.0, time: 2.0),
+                (position: (0.0, 0.0, 0.0), yaw: 0.0, pitch: 0.0, time: 0.0),
+            ], looping: true)",
+        )
+        .unwrap();
+
+        assert_eq!(path.keyframes()[0].time, 0.0);
+        assert!(!path.is_finished(100.0));
+        assert_abs_diff_eq!(path.sample(5.0).0, path.sample(1.0).0, epsilon = 1e-5);
+        assert_eq!(CameraPath::from_ron(&path.to_ron().unwrap()).unwrap(), path);
+    }
+}
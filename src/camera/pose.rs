@@ -0,0 +1,128 @@
+//! Camera poses saved to disk, so a framed viewpoint survives a restart.
+
+use super::{Camera, Projection};
+use cgmath::{Deg, Point3, Rad};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+use thiserror::Error;
+
+/// Where a camera is, what its projection sees and how fast it moves, angles
+/// in radians. Fields missing from a file are taken from the default startup
+/// pose.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub speed: f32,
+}
+
+impl Default for CameraPose {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 5.0, 10.0],
+            yaw: Rad::from(Deg(-90.0)).0,
+            pitch: Rad::from(Deg(-20.0)).0,
+            roll: 0.0,
+            fovy: Rad::from(Deg(45.0)).0,
+            znear: 0.1,
+            zfar: 100.0,
+            speed: 8.0,
+        }
+    }
+}
+
+impl CameraPose {
+    pub fn capture(camera: &Camera, projection: &Projection, speed: f32) -> Self {
+        Self {
+            position: camera.position.into(),
+            yaw: camera.yaw.0,
+            pitch: camera.pitch.0,
+            roll: camera.roll.0,
+            fovy: projection.target_fovy().0,
+            znear: projection.znear(),
+            zfar: projection.zfar(),
+            speed,
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        Camera::new(Point3::from(self.position), Rad(self.yaw), Rad(self.pitch))
+            .with_roll(Rad(self.roll))
+    }
+
+    /// Moves the camera to the pose and sets the projection's parameters,
+    /// keeping its size.
+    pub fn apply(&self, camera: &mut Camera, projection: &mut Projection) {
+        *camera = self.camera();
+        projection.set_parameters(Rad(self.fovy), self.znear, self.zfar);
+    }
+
+    pub fn to_ron(self) -> CameraResult<String> {
+        Ok(ron::ser::to_string_pretty(&self, Default::default())?)
+    }
+
+    pub fn from_ron(text: &str) -> CameraResult<Self> {
+        Ok(ron::from_str(text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> CameraResult<()> {
+        Ok(fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: &Path) -> CameraResult<Self> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+pub type CameraResult<T> = Result<T, CameraError>;
+
+#[derive(Debug, Error)]
+pub enum CameraError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Failed to parse camera pose: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("Failed to write camera pose: {0}")]
+    Write(#[from] ron::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::CameraPose;
+
+    #[test]
+    fn round_trip() {
+        let pose = CameraPose {
+            position: [1.0, -2.5, 30.0],
+            yaw: 3.0,
+            pitch: -1.2,
+            roll: 0.3,
+            fovy: 0.5,
+            znear: 0.5,
+            zfar: 250.0,
+            speed: 20.0,
+        };
+
+        let text = pose.to_ron().unwrap();
+        assert!(text.contains("yaw: 3.0"), "{text}");
+        assert_eq!(CameraPose::from_ron(&text).unwrap(), pose);
+    }
+
+    #[test]
+    fn missing_fields_default() {
+        let pose = CameraPose::from_ron("(position: (1.0, 2.0, 3.0), pitch: 0.25)").unwrap();
+
+        assert_eq!(pose.position, [1.0, 2.0, 3.0]);
+        assert_eq!(pose.pitch, 0.25);
+        assert_eq!(pose.yaw, CameraPose::default().yaw);
+        assert_eq!(pose.zfar, CameraPose::default().zfar);
+        assert_eq!(pose.speed, CameraPose::default().speed);
+        assert!(CameraPose::from_ron("(yaw: \"left\")").is_err());
+    }
+}
@@ -1,28 +1,230 @@
-use super::OPENGL_TO_WGPU_MATRIX;
-use cgmath::{Matrix4, Rad};
+use super::{approach_factor, OPENGL_TO_WGPU_MATRIX};
+use cgmath::{Deg, Matrix4, Rad};
+use std::time::Duration;
+
+/// How long [`Projection::set_kind`] takes to blend into the other matrix.
+const TRANSITION: Duration = Duration::from_millis(250);
+/// Roughly how long [`Projection::set_fovy`] takes to settle.
+const FOV_TRANSITION: Duration = Duration::from_millis(150);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectionKind {
+    Perspective,
+    /// Parallel lines stay parallel, for CAD style inspection.
+    Orthographic,
+}
 
 pub struct Projection {
-    aspect: f32,
+    width: u32,
+    height: u32,
+    /// The field of view drawn with, easing towards `target_fovy`.
     fovy: Rad<f32>,
+    target_fovy: Rad<f32>,
+    fovy_limits: (Rad<f32>, Rad<f32>),
     znear: f32,
     zfar: f32,
+    kind: ProjectionKind,
+    /// World units a pixel covers orthographically, kept on resize so the
+    /// scene doesn't scale with the window.
+    units_per_pixel: f32,
+    /// How far along the blend into the orthographic matrix is, 0 being
+    /// fully perspective.
+    blend: f32,
 }
 
 impl Projection {
     pub fn new<F: Into<Rad<f32>>>(width: u32, height: u32, fovy: F, znear: f32, zfar: f32) -> Self {
+        let fovy = fovy.into();
+
         Self {
-            aspect: width as f32 / height as f32,
-            fovy: fovy.into(),
+            width,
+            height,
+            fovy,
+            target_fovy: fovy,
+            fovy_limits: (Deg(5.0).into(), Deg(120.0).into()),
             znear,
             zfar,
+            kind: ProjectionKind::Perspective,
+            units_per_pixel: 0.01,
+            blend: 0.0,
         }
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.aspect = width as f32 / height as f32;
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    pub fn zfar(&self) -> f32 {
+        self.zfar
+    }
+
+    pub fn fovy(&self) -> Rad<f32> {
+        self.fovy
+    }
+
+    /// Where the field of view is easing towards.
+    pub fn target_fovy(&self) -> Rad<f32> {
+        self.target_fovy
+    }
+
+    /// Eases the field of view towards `fovy`, clamped to the limits, over
+    /// the next updates.
+    pub fn set_fovy<F: Into<Rad<f32>>>(&mut self, fovy: F) {
+        let (min, max) = self.fovy_limits;
+        self.target_fovy = Rad(fovy.into().0.clamp(min.0, max.0));
+    }
+
+    /// Sets the field of view straight away, unlike [`Projection::set_fovy`],
+    /// and the clipping planes.
+    pub fn set_parameters<F: Into<Rad<f32>>>(&mut self, fovy: F, znear: f32, zfar: f32) {
+        self.set_fovy(fovy);
+        self.fovy = self.target_fovy;
+        self.znear = znear;
+        self.zfar = zfar;
+    }
+
+    /// Bounds for [`Projection::set_fovy`], 5° to 120° by default.
+    pub fn set_fovy_limits<F: Into<Rad<f32>>>(&mut self, min: F, max: F) {
+        let (min, max) = (min.into(), max.into());
+        self.fovy_limits = (min, Rad(max.0.max(min.0)));
+        self.set_fovy(self.target_fovy);
+    }
+
+    pub fn kind(&self) -> ProjectionKind {
+        self.kind
+    }
+
+    /// Switches projection, blending over a short transition driven by
+    /// [`Projection::update`]. Going orthographic frames what the perspective
+    /// view shows at `focus_distance`, so the switch doesn't jump there.
+    pub fn set_kind(&mut self, kind: ProjectionKind, focus_distance: f32) {
+        if kind == ProjectionKind::Orthographic && self.kind == ProjectionKind::Perspective {
+            let visible_height = 2.0 * focus_distance * (self.fovy.0 / 2.0).tan();
+            self.units_per_pixel = visible_height / self.height.max(1) as f32;
+        }
+        self.kind = kind;
+    }
+
+    /// Advances the field of view and the blend between the two matrices.
+    pub fn update(&mut self, dt: Duration) {
+        self.fovy += (self.target_fovy - self.fovy)
+            * approach_factor(FOV_TRANSITION.as_secs_f32(), dt.as_secs_f32());
+
+        let step = dt.as_secs_f32() / TRANSITION.as_secs_f32();
+        self.blend = match self.kind {
+            ProjectionKind::Perspective => (self.blend - step).max(0.0),
+            ProjectionKind::Orthographic => (self.blend + step).min(1.0),
+        };
+    }
+
+    pub fn perspective_matrix(&self) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(self.fovy, self.aspect(), self.znear, self.zfar)
+    }
+
+    pub fn orthographic_matrix(&self) -> Matrix4<f32> {
+        let half_width = self.width as f32 * self.units_per_pixel / 2.0;
+        let half_height = self.height as f32 * self.units_per_pixel / 2.0;
+
+        OPENGL_TO_WGPU_MATRIX
+            * cgmath::ortho(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                self.znear,
+                self.zfar,
+            )
     }
 
     pub fn matrix(&self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(self.fovy, self.aspect, self.znear, self.zfar)
+        if self.blend <= 0.0 {
+            return self.perspective_matrix();
+        }
+        if self.blend >= 1.0 {
+            return self.orthographic_matrix();
+        }
+
+        self.perspective_matrix() * (1.0 - self.blend) + self.orthographic_matrix() * self.blend
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Projection, ProjectionKind};
+    use cgmath::{assert_abs_diff_eq, Deg};
+    use std::time::Duration;
+
+    #[test]
+    fn resize_changes_the_aspect() {
+        let mut projection = Projection::new(800, 800, Deg(90.0), 0.1, 100.0);
+        assert_abs_diff_eq!(projection.matrix().x.x, 1.0, epsilon = 1e-6);
+
+        // A view twice as wide squeezes x by half, y is left alone
+        projection.resize(1600, 800);
+        assert_abs_diff_eq!(projection.aspect(), 2.0);
+        assert_abs_diff_eq!(projection.matrix().x.x, 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(projection.matrix().y.y, 1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn fovy_clamps_and_eases() {
+        let mut projection = Projection::new(800, 800, Deg(45.0), 0.1, 100.0);
+        projection.set_fovy_limits(Deg(10.0), Deg(90.0));
+        projection.set_fovy(Deg(1.0));
+        assert_abs_diff_eq!(projection.target_fovy(), Deg(10.0).into(), epsilon = 1e-6);
+
+        projection.update(Duration::from_millis(16));
+        let fovy = projection.fovy();
+        assert!(fovy < Deg(45.0).into() && fovy > Deg(10.0).into());
+
+        projection.update(Duration::from_millis(150));
+        assert_abs_diff_eq!(projection.fovy(), Deg(10.0).into(), epsilon = 0.01);
+    }
+
+    #[test]
+    fn orthographic_keeps_units_per_pixel() {
+        let mut projection = Projection::new(800, 600, Deg(45.0), 0.1, 100.0);
+        projection.set_kind(ProjectionKind::Orthographic, 10.0);
+        projection.update(Duration::from_secs(1));
+        let before = projection.matrix();
+
+        // Twice the pixels show twice the world, at the same scale
+        projection.resize(1600, 1200);
+        let after = projection.matrix();
+        assert_abs_diff_eq!(after.x.x, before.x.x / 2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(after.y.y, before.y.y / 2.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn switching_blends_between_matrices() {
+        let mut projection = Projection::new(800, 600, Deg(45.0), 0.1, 100.0);
+        let perspective = projection.perspective_matrix();
+        projection.set_kind(ProjectionKind::Orthographic, 10.0);
+        assert_eq!(projection.matrix(), perspective);
+
+        projection.update(Duration::from_millis(125));
+        let halfway = (perspective + projection.orthographic_matrix()) * 0.5;
+        assert_abs_diff_eq!(projection.matrix(), halfway, epsilon = 1e-6);
+
+        projection.update(Duration::from_secs(1));
+        assert_eq!(projection.matrix(), projection.orthographic_matrix());
+
+        // The orthographic view is as tall as the perspective one at the
+        // focus distance
+        let visible_height = 2.0 * 10.0 * (22.5f32).to_radians().tan();
+        assert_abs_diff_eq!(
+            projection.matrix().y.y,
+            2.0 / visible_height,
+            epsilon = 1e-5
+        );
     }
 }
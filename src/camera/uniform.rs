@@ -1,25 +1,45 @@
 use super::{Camera, Projection};
 use crate::vec4;
-use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
+use cgmath::{Matrix4, SquareMatrix, Vector4};
 
+/// Laid out to match `Camera` in the shaders, every field is 16 byte aligned.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_position: Vector4<f32>,
     view_projection: Matrix4<f32>,
+    view: Matrix4<f32>,
+    /// For reconstructing world positions from clip space, e.g. for skyboxes.
+    inverse_view_projection: Matrix4<f32>,
+    /// Scales the lit color before it's tone mapped, higher is brighter.
+    exposure: f32,
+    _padding: [f32; 3],
 }
 
 impl CameraUniform {
     pub fn new(camera: &Camera, projection: &Projection) -> Self {
-        Self {
-            view_position: camera.position.to_homogeneous().into(),
-            view_projection: (projection.matrix() * camera.matrix()).into(),
-        }
+        let mut uniform = Self::default();
+        uniform.update(camera, projection);
+
+        uniform
     }
 
     pub fn update(&mut self, camera: &Camera, projection: &Projection) {
-        self.view_position = camera.position.to_homogeneous().into();
-        self.view_projection = (projection.matrix() * camera.matrix()).into();
+        let view = camera.matrix();
+        let view_projection = projection.matrix() * view;
+
+        self.view_position = camera.position.to_homogeneous();
+        self.view_projection = view_projection;
+        self.view = view;
+        self.inverse_view_projection = view_projection.invert().unwrap_or(Matrix4::identity());
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
     }
 }
 
@@ -27,7 +47,46 @@ impl Default for CameraUniform {
     fn default() -> Self {
         Self {
             view_position: vec4!(0.0, 0.0, 0.0, 0.0),
-            view_projection: Matrix4::identity().into(),
+            view_projection: Matrix4::identity(),
+            view: Matrix4::identity(),
+            inverse_view_projection: Matrix4::identity(),
+            exposure: 1.0,
+            _padding: [0.0; 3],
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::CameraUniform;
+    use crate::camera::{Camera, Projection};
+    use cgmath::{assert_abs_diff_eq, Deg, Matrix4, SquareMatrix};
+    use std::ptr;
+
+    #[test]
+    fn aligned() {
+        let size = std::mem::size_of::<CameraUniform>();
+        println!("Size of [CameraUniform] {size} bytes");
+        assert_eq!(size, 224);
+
+        let camera = Camera::new((0.0, 5.0, 10.0), Deg(-90.0), Deg(-20.0));
+        let projection = Projection::new(800, 600, Deg(45.0), 0.1, 100.0);
+        let uniform = CameraUniform::new(&camera, &projection);
+        let base = ptr::addr_of!(uniform).cast::<u8>();
+        let offsets = [
+            ptr::addr_of!(uniform.view_position).cast::<u8>(),
+            ptr::addr_of!(uniform.view_projection).cast::<u8>(),
+            ptr::addr_of!(uniform.view).cast::<u8>(),
+            ptr::addr_of!(uniform.inverse_view_projection).cast::<u8>(),
+            ptr::addr_of!(uniform.exposure).cast::<u8>(),
+        ]
+        .map(|field| unsafe { field.offset_from(base) });
+        assert_eq!(offsets, [0, 16, 80, 144, 208]);
+
+        assert_abs_diff_eq!(
+            uniform.inverse_view_projection * uniform.view_projection,
+            Matrix4::identity(),
+            epsilon = 1e-4
+        );
+    }
+}
@@ -1,220 +1,245 @@
-use glyphon::{Buffer, Color, FontSystem, Metrics, SwashCache, TextAtlas, TextRenderer};
+//! The egui debug panel, for tuning what the key bindings only step through.
+//! The renderer hands it a [`Panel`] of its settings every frame and applies
+//! whatever comes back changed.
+
+use crate::light::{Light, LightId};
+use egui::{Color32, ComboBox, Context, DragValue, Slider, ViewportId};
+use egui_wgpu::renderer::ScreenDescriptor;
 use wgpu::{
-    CommandEncoderDescriptor, Device, LoadOp, MultisampleState, Operations, Queue, RenderPass,
-    RenderPassColorAttachment, RenderPassDescriptor, StoreOp, Surface, SurfaceConfiguration,
-    SurfaceTexture, TextureView, TextureViewDescriptor,
+    CommandBuffer, CommandEncoder, Device, LoadOp, Operations, Queue, RenderPassTimestampWrites,
+    TextureView,
+};
+use winit::{
+    event::{ElementState, WindowEvent},
+    window::Window,
 };
 
-const DEFAULT_FONT: &'static [u8] = include_bytes!("../res/DejaVuSans.ttf");
-const SCALE: f32 = 0.7;
+/// The settings the panel edits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Panel {
+    /// Linear RGB.
+    pub clear_color: [f32; 3],
+    pub lights: Vec<(LightId, Light)>,
+    pub lights_orbiting: bool,
+    pub camera_speed: f32,
+    /// Vertical, in degrees.
+    pub fov: f32,
+    pub wireframe: bool,
+    pub z_prepass: bool,
+    pub render_scale: f32,
+}
+
+/// What a [`Console::run`] laid out, painted by the next
+/// [`Console::render`].
+struct Output {
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    pixels_per_point: f32,
+}
 
 pub struct Console {
-    font_system: FontSystem,
-    cache: SwashCache,
-    atlas: TextAtlas,
-    renderer: TextRenderer,
-    buffer: Buffer,
-    // width: f32,
-    // height: f32,
+    context: Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    output: Option<Output>,
+    visible: bool,
+    /// Index into [`Panel::lights`] of the light being edited.
+    selected_light: usize,
 }
 
 impl Console {
-    pub fn new() {
-        let temp = egui::Context::default();
+    pub fn new(device: &Device, format: wgpu::TextureFormat, window: &Window) -> Self {
+        let context = Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+
+        Self {
+            context,
+            state,
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+            output: None,
+            visible: false,
+            selected_light: 0,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Whether there's a laid out panel for [`Console::render`] to draw.
+    pub fn is_laid_out(&self) -> bool {
+        self.output.is_some()
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.output = None;
+    }
+
+    /// True when egui used `event`, so it shouldn't reach the scene. Key
+    /// releases always go through, so nothing stays held.
+    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        let response = self.state.on_window_event(window, event);
+        let releases_key = matches!(
+            event,
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Released
+        );
+
+        response.consumed && !releases_key
+    }
+
+    /// Lays out the panel over `panel`, to be drawn with the next frame.
+    /// Does nothing while it's hidden.
+    pub fn run(&mut self, window: &Window, panel: &mut Panel) {
+        if !self.visible {
+            return;
+        }
+
+        let input = self.state.take_egui_input(window);
+        let selected_light = &mut self.selected_light;
+        let output = self.context.run(input, |context| {
+            egui::Window::new("Debug")
+                .default_width(260.0)
+                .show(context, |ui| show(ui, panel, selected_light));
+        });
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        self.output = Some(Output {
+            paint_jobs: self
+                .context
+                .tessellate(output.shapes, output.pixels_per_point),
+            textures_delta: output.textures_delta,
+            pixels_per_point: output.pixels_per_point,
+        });
+    }
+
+    /// Records drawing the last laid out panel over `view` into `encoder`,
+    /// returning the commands egui needs submitted ahead of it.
+    pub fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        size_in_pixels: [u32; 2],
+        timestamp_writes: Option<RenderPassTimestampWrites>,
+    ) -> Vec<CommandBuffer> {
+        let Some(output) = self.output.take() else {
+            return vec![];
+        };
+        let screen = ScreenDescriptor {
+            size_in_pixels,
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let commands =
+            self.renderer
+                .update_buffers(device, queue, encoder, &output.paint_jobs, &screen);
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Console pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+            self.renderer.render(&mut pass, &output.paint_jobs, &screen);
+        }
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+
+        commands
+    }
+}
+
+fn show(ui: &mut egui::Ui, panel: &mut Panel, selected_light: &mut usize) {
+    ui.horizontal(|ui| {
+        ui.label("Clear color");
+        ui.color_edit_button_rgb(&mut panel.clear_color);
+    });
+    ui.checkbox(&mut panel.wireframe, "Wireframe");
+    ui.checkbox(&mut panel.z_prepass, "Depth pre-pass");
+    ui.add(
+        Slider::new(&mut panel.render_scale, 0.25..=2.0)
+            .fixed_decimals(2)
+            .text("Render scale"),
+    );
+
+    ui.separator();
+    ui.add(
+        Slider::new(&mut panel.camera_speed, 0.5..=100.0)
+            .logarithmic(true)
+            .text("Camera speed"),
+    );
+    ui.add(
+        Slider::new(&mut panel.fov, 5.0..=120.0)
+            .suffix("°")
+            .text("Field of view"),
+    );
+
+    ui.separator();
+    ui.checkbox(&mut panel.lights_orbiting, "Orbit the lights");
+    if panel.lights.is_empty() {
+        ui.colored_label(Color32::GRAY, "No lights");
+        return;
     }
+    *selected_light = (*selected_light).min(panel.lights.len() - 1);
+    ComboBox::from_label("Light")
+        .selected_text(light_name(
+            *selected_light,
+            &panel.lights[*selected_light].1,
+        ))
+        .show_ui(ui, |ui| {
+            for (index, (_, light)) in panel.lights.iter().enumerate() {
+                ui.selectable_value(selected_light, index, light_name(index, light));
+            }
+        });
+
+    let (position, color, intensity) = match &mut panel.lights[*selected_light].1 {
+        Light::Point(light) => (&mut light.position, &mut light.color, &mut light.intensity),
+        Light::Spot(light) => (&mut light.position, &mut light.color, &mut light.intensity),
+    };
+    ui.horizontal(|ui| {
+        ui.label("Position");
+        for axis in [&mut position.x, &mut position.y, &mut position.z] {
+            ui.add(DragValue::new(axis).speed(0.1));
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Color");
+        let mut rgb: [f32; 3] = (*color).into();
+        if ui.color_edit_button_rgb(&mut rgb).changed() {
+            *color = rgb.into();
+        }
+    });
+    ui.add(Slider::new(intensity, 0.0..=10.0).text("Intensity"));
+}
 
-    // pub fn new(device: &wgpu::Device, queue: &Queue, config: &SurfaceConfiguration) -> Self {
-    //     let mut font_system = FontSystem::new();
-    //     let cache = SwashCache::new();
-    //     let mut atlas = TextAtlas::new(device, queue, config.format);
-    //     let text_renderer =
-    //         TextRenderer::new(&mut atlas, device, MultisampleState::default(), None);
-    //     let mut buffer = Buffer::new(&mut font_system, Metrics::new(30.0, 42.0));
-
-    //     let physical_width = config.width as f32 * SCALE;
-    //     let physical_height = config.height as f32 * SCALE;
-
-    //     buffer.set_size(&mut font_system, physical_width, physical_height);
-    //     buffer.set_text(
-    //         &mut font_system,
-    //         "ahoy there",
-    //         glyphon::Attrs::new().family(glyphon::Family::SansSerif),
-    //         glyphon::Shaping::Advanced,
-    //     );
-    //     buffer.shape_until_scroll(&mut font_system);
-
-    //     Self {
-    //         font_system,
-    //         cache,
-    //         atlas,
-    //         renderer: text_renderer,
-    //         buffer,
-    //         // width: physical_width,
-    //         // height: physical_height,
-    //     }
-    // }
-
-    // pub fn render(
-    //     &mut self,
-    //     device: &Device,
-    //     queue: &Queue,
-    //     surface: &Surface,
-    //     config: &SurfaceConfiguration,
-    // ) {
-    //     self.renderer
-    //         .prepare(
-    //             &device,
-    //             &queue,
-    //             &mut self.font_system,
-    //             &mut self.atlas,
-    //             glyphon::Resolution {
-    //                 width: config.width,
-    //                 height: config.height,
-    //             },
-    //             [glyphon::TextArea {
-    //                 buffer: &self.buffer,
-    //                 left: 10.0,
-    //                 top: 10.0,
-    //                 scale: 1.0,
-    //                 bounds: glyphon::TextBounds {
-    //                     left: 0,
-    //                     top: 0,
-    //                     right: 600,
-    //                     bottom: 160,
-    //                 },
-    //                 default_color: Color::rgb(255, 0, 255),
-    //             }],
-    //             &mut self.cache,
-    //         )
-    //         .unwrap();
-
-    //     let frame = surface.get_current_texture().unwrap();
-    //     let view = frame.texture.create_view(&TextureViewDescriptor::default());
-    //     let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
-
-    //     {
-    //         let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-    //             label: Some("[Console] render pass"),
-    //             // color_attachments: &[Some(RenderPassColorAttachment {
-    //             //     view: &view,
-    //             //     resolve_target: None,
-    //             //     ops: Operations {
-    //             //         // load: LoadOp::Clear(wgpu::Color {
-    //             //         //     r: 1.0,
-    //             //         //     g: 1.0,
-    //             //         //     b: 1.0,
-    //             //         //     a: 0.2,
-    //             //         // }),
-    //             //         load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
-    //             //         store: StoreOp::Store,
-    //             //     },
-    //             // })],
-    //             color_attachments: &[Some(RenderPassColorAttachment {
-    //                 view: &view,
-    //                 resolve_target: None,
-    //                 ops: Operations {
-    //                     // load: LoadOp::Load,
-    //                     // load: LoadOp::Clear(wgpu::Color::RED),
-    //                     load: LoadOp::Clear(wgpu::Color {
-    //                         r: 0.0,
-    //                         g: 1.0,
-    //                         b: 1.0,
-    //                         a: -1.0,
-    //                     }),
-    //                     store: StoreOp::Store,
-    //                 },
-    //             })],
-    //             depth_stencil_attachment: None,
-    //             timestamp_writes: None,
-    //             occlusion_query_set: None,
-    //         });
-
-    //         self.renderer.render(&self.atlas, &mut pass).unwrap();
-    //     }
-
-    //     queue.submit(Some(encoder.finish()));
-    //     frame.present();
-
-    //     self.atlas.trim();
-    // }
-
-    // pub fn render<'pass>(
-    //     &'pass mut self,
-    //     frame: &SurfaceTexture,
-    //     mut pass: &mut RenderPass<'pass>,
-    //     device: &Device,
-    //     queue: &Queue,
-    //     surface: &Surface,
-    //     config: &SurfaceConfiguration,
-    // ) {
-    //     self.renderer
-    //         .prepare(
-    //             &device,
-    //             &queue,
-    //             &mut self.font_system,
-    //             &mut self.atlas,
-    //             glyphon::Resolution {
-    //                 width: config.width,
-    //                 height: config.height,
-    //             },
-    //             [glyphon::TextArea {
-    //                 buffer: &self.buffer,
-    //                 left: 10.0,
-    //                 top: 10.0,
-    //                 scale: 1.0,
-    //                 bounds: glyphon::TextBounds {
-    //                     left: 0,
-    //                     top: 0,
-    //                     right: 600,
-    //                     bottom: 160,
-    //                 },
-    //                 default_color: Color::rgb(255, 255, 255),
-    //             }],
-    //             &mut self.cache,
-    //         )
-    //         .unwrap();
-
-    //     self.renderer.render(&self.atlas, &mut pass).unwrap();
-
-    //     // let frame = surface.get_current_texture().unwrap();
-    //     // let view = frame.texture.create_view(&TextureViewDescriptor::default());
-    //     // let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
-
-    //     {
-    //         // let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-    //         //     label: Some("[Console] render pass"),
-    //         //     // color_attachments: &[Some(RenderPassColorAttachment {
-    //         //     //     view: &view,
-    //         //     //     resolve_target: None,
-    //         //     //     ops: Operations {
-    //         //     //         // load: LoadOp::Clear(wgpu::Color {
-    //         //     //         //     r: 1.0,
-    //         //     //         //     g: 1.0,
-    //         //     //         //     b: 1.0,
-    //         //     //         //     a: 0.2,
-    //         //     //         // }),
-    //         //     //         load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
-    //         //     //         store: StoreOp::Store,
-    //         //     //     },
-    //         //     // })],
-    //         //     color_attachments: &[Some(RenderPassColorAttachment {
-    //         //         view: &view,
-    //         //         resolve_target: None,
-    //         //         ops: Operations {
-    //         //             load: LoadOp::Load,
-    //         //             // load: LoadOp::Clear(wgpu::Color::RED),
-    //         //             store: StoreOp::Store,
-    //         //         },
-    //         //     })],
-    //         //     depth_stencil_attachment: None,
-    //         //     timestamp_writes: None,
-    //         //     occlusion_query_set: None,
-    //         // });
-    //     }
-
-    //     // queue.submit(Some(encoder.finish()));
-    //     // frame.present();
-
-    //     // self.atlas.trim();
-    // }
+fn light_name(index: usize, light: &Light) -> String {
+    match light {
+        Light::Point(_) => format!("{index}: point"),
+        Light::Spot(_) => format!("{index}: spot"),
+    }
 }
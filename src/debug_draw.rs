@@ -0,0 +1,286 @@
+//! Lines drawn over the scene for debugging, e.g. bounding boxes and the
+//! picking ray. They're gathered again every frame, then uploaded and drawn
+//! together by [`DebugDraw`].
+
+use crate::{
+    model::{Aabb, VertexBufferFormat},
+    pipeline::{Pipeline, PipelineOptions},
+    shader::{Shader, ShaderDefines},
+    texture::Texture,
+};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Vector3, Vector4};
+use std::{
+    f32::consts::TAU,
+    ops::{Deref, DerefMut},
+};
+use wgpu::{
+    vertex_attr_array, BindGroup, BindGroupLayout, Buffer, BufferAddress, BufferDescriptor,
+    BufferUsages, Device, PipelineLayoutDescriptor, PrimitiveTopology, Queue, RenderPass,
+    TextureFormat, VertexAttribute,
+};
+
+/// Segments in each of a sphere's circles.
+const SPHERE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+impl VertexBufferFormat for LineVertex {
+    type Attributes = [VertexAttribute; 2];
+    const ATTRIBUTES: Self::Attributes = vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3
+    ];
+
+    fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// The lines of a frame, in pairs of vertices.
+#[derive(Clone, Debug, Default)]
+pub struct DebugLines {
+    vertices: Vec<LineVertex>,
+}
+
+impl DebugLines {
+    pub fn vertices(&self) -> &[LineVertex] {
+        &self.vertices
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn line(&mut self, a: Vector3<f32>, b: Vector3<f32>, color: [f32; 3]) {
+        self.vertices.extend([
+            LineVertex {
+                position: a.into(),
+                color,
+            },
+            LineVertex {
+                position: b.into(),
+                color,
+            },
+        ]);
+    }
+
+    /// The box's twelve edges, nothing for an empty one.
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 3]) {
+        if aabb.is_empty() {
+            return;
+        }
+
+        let corner = |index: usize| {
+            Vector3::new(
+                [aabb.min.x, aabb.max.x][index & 1],
+                [aabb.min.y, aabb.max.y][(index >> 1) & 1],
+                [aabb.min.z, aabb.max.z][(index >> 2) & 1],
+            )
+        };
+        // Each edge joins corners that differ along one axis
+        for index in 0..8 {
+            for axis in [1, 2, 4] {
+                if index & axis == 0 {
+                    self.line(corner(index), corner(index | axis), color);
+                }
+            }
+        }
+    }
+
+    /// `size` long lines along the x, y and z axes of `transform`, in red,
+    /// green and blue.
+    pub fn axes(&mut self, transform: &Matrix4<f32>, size: f32) {
+        let origin = (transform * Vector4::unit_w()).truncate();
+        let axes = [
+            (Vector4::unit_x(), [1.0, 0.0, 0.0]),
+            (Vector4::unit_y(), [0.0, 1.0, 0.0]),
+            (Vector4::unit_z(), [0.0, 0.0, 1.0]),
+        ];
+        for (axis, color) in axes {
+            let end = transform * (axis * size + Vector4::unit_w());
+            self.line(origin, end.truncate(), color);
+        }
+    }
+
+    /// Circles around the sphere in the planes of each pair of axes.
+    pub fn sphere(&mut self, center: Vector3<f32>, radius: f32, color: [f32; 3]) {
+        let point = |plane: usize, segment: usize| {
+            let angle = TAU * segment as f32 / SPHERE_SEGMENTS as f32;
+            let (sin, cos) = angle.sin_cos();
+            let mut offset = Vector3::new(0.0, 0.0, 0.0);
+            offset[plane] = cos * radius;
+            offset[(plane + 1) % 3] = sin * radius;
+
+            center + offset
+        };
+        for plane in 0..3 {
+            for segment in 0..SPHERE_SEGMENTS {
+                self.line(point(plane, segment), point(plane, segment + 1), color);
+            }
+        }
+    }
+}
+
+/// Draws [`DebugLines`] over the scene with a line list, tested against the
+/// depth without writing it. Derefs to the lines for adding to them.
+pub struct DebugDraw {
+    lines: DebugLines,
+    pipeline: Pipeline,
+    /// Grows to fit the most lines uploaded so far.
+    buffer: Buffer,
+    /// How many vertices fit in the buffer.
+    capacity: usize,
+    /// How many vertices were last uploaded.
+    uploaded: u32,
+}
+
+impl DebugDraw {
+    /// The pipeline's only bind group is the camera's.
+    pub fn new(
+        device: &Device,
+        color_format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let shader = Shader::embedded(device, "debug.wgsl", ShaderDefines::default());
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Debug pipeline layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Pipeline::new(
+            device,
+            &shader,
+            layout,
+            PipelineOptions::new("Debug pipeline", color_format, &[LineVertex::descriptor()])
+                .with_depth_format(Texture::DEPTH_FORMAT)
+                .with_topology(PrimitiveTopology::LineList)
+                .without_depth_write(),
+        );
+        let capacity = 1024;
+
+        Self {
+            lines: DebugLines::default(),
+            pipeline,
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            uploaded: 0,
+        }
+    }
+
+    fn create_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("Debug line buffer"),
+            size: (std::mem::size_of::<LineVertex>() * capacity) as BufferAddress,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// For rebuilding from an edited shader.
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+
+    /// Uploads the lines for [`DebugDraw::draw`], replacing the buffer with
+    /// one twice the size as often as it takes to fit them.
+    pub fn upload(&mut self, device: &Device, queue: &Queue) {
+        let vertices = self.lines.vertices();
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(vertices));
+        }
+        self.uploaded = vertices.len() as u32;
+    }
+
+    /// Draws the lines from the last upload.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera_bind_group: &'a BindGroup) {
+        if self.uploaded == 0 {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.uploaded, 0..1);
+    }
+}
+
+impl Deref for DebugDraw {
+    type Target = DebugLines;
+
+    fn deref(&self) -> &Self::Target {
+        &self.lines
+    }
+}
+
+impl DerefMut for DebugDraw {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DebugLines, SPHERE_SEGMENTS};
+    use crate::model::Aabb;
+    use cgmath::{assert_abs_diff_eq, InnerSpace, Matrix4, Vector3};
+
+    #[test]
+    fn shapes_are_made_of_lines() {
+        let mut lines = DebugLines::default();
+        lines.aabb(&Aabb::EMPTY, [1.0; 3]);
+        assert!(lines.vertices().is_empty());
+
+        // Every edge of the box runs along one axis
+        let aabb = Aabb::new(Vector3::new(-1.0, 0.0, 2.0), Vector3::new(1.0, 3.0, 4.0));
+        lines.aabb(&aabb, [1.0; 3]);
+        assert_eq!(lines.vertices().len(), 24);
+        for edge in lines.vertices().chunks(2) {
+            let (a, b) = (
+                Vector3::from(edge[0].position),
+                Vector3::from(edge[1].position),
+            );
+            let delta = b - a;
+            assert_eq!((0..3).filter(|&axis| delta[axis] != 0.0).count(), 1);
+        }
+
+        lines.clear();
+        let center = Vector3::new(1.0, 2.0, 3.0);
+        lines.sphere(center, 2.0, [1.0; 3]);
+        assert_eq!(lines.vertices().len(), SPHERE_SEGMENTS * 3 * 2);
+        for vertex in lines.vertices() {
+            let radius = (Vector3::from(vertex.position) - center).magnitude();
+            assert_abs_diff_eq!(radius, 2.0, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn axes_follow_the_transform() {
+        let mut lines = DebugLines::default();
+        let transform = Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0))
+            * Matrix4::from_angle_y(cgmath::Deg(90.0));
+        lines.axes(&transform, 2.0);
+
+        let vertices = lines.vertices();
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(vertices[0].position, [1.0, 0.0, 0.0]);
+        assert_eq!(vertices[0].color, [1.0, 0.0, 0.0]);
+        // Turned onto -z, then moved along x
+        let x_end = Vector3::from(vertices[1].position);
+        assert_abs_diff_eq!(x_end, Vector3::new(1.0, 0.0, -2.0), epsilon = 1e-5);
+        assert_eq!(vertices[5].color, [0.0, 0.0, 1.0]);
+    }
+}
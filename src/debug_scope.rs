@@ -0,0 +1,111 @@
+//! Debug groups around what's recorded, so frame captures in tools like
+//! RenderDoc show the frame's structure rather than a flat list of passes
+//! and draws.
+
+use std::ops::{Deref, DerefMut};
+use wgpu::{CommandEncoder, ComputePass, RenderPass};
+
+/// Opens a debug group named `$label` on a command encoder or pass, closed
+/// when the returned [`DebugScope`] is dropped. The scope derefs to what it
+/// was opened on, so it's recorded into in its place, e.g. shadowing a
+/// `render_pass` for a block.
+#[macro_export]
+macro_rules! debug_scope {
+    ($target: expr, $label: expr) => {
+        $crate::debug_scope::DebugScope::new(&mut $target, $label)
+    };
+}
+
+/// What debug groups can be recorded into.
+pub trait DebugGroups {
+    fn push_debug_group(&mut self, label: &str);
+    fn pop_debug_group(&mut self);
+}
+
+impl DebugGroups for CommandEncoder {
+    fn push_debug_group(&mut self, label: &str) {
+        CommandEncoder::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        CommandEncoder::pop_debug_group(self);
+    }
+}
+
+impl DebugGroups for RenderPass<'_> {
+    fn push_debug_group(&mut self, label: &str) {
+        RenderPass::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        RenderPass::pop_debug_group(self);
+    }
+}
+
+impl DebugGroups for ComputePass<'_> {
+    fn push_debug_group(&mut self, label: &str) {
+        ComputePass::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        ComputePass::pop_debug_group(self);
+    }
+}
+
+/// An open debug group, see [`debug_scope!`].
+pub struct DebugScope<'a, T: DebugGroups> {
+    target: &'a mut T,
+}
+
+impl<'a, T: DebugGroups> DebugScope<'a, T> {
+    pub fn new(target: &'a mut T, label: &str) -> Self {
+        target.push_debug_group(label);
+
+        Self { target }
+    }
+}
+
+impl<T: DebugGroups> Deref for DebugScope<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.target
+    }
+}
+
+impl<T: DebugGroups> DerefMut for DebugScope<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.target
+    }
+}
+
+impl<T: DebugGroups> Drop for DebugScope<'_, T> {
+    fn drop(&mut self) {
+        self.target.pop_debug_group();
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use crate::texture::test_device;
+    use std::iter;
+    use wgpu::{CommandEncoderDescriptor, ErrorFilter};
+
+    #[test]
+    fn scopes_are_balanced() {
+        let (device, queue) = test_device();
+        device.push_error_scope(ErrorFilter::Validation);
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Debug scope test encoder"),
+        });
+        {
+            let mut outer = debug_scope!(encoder, "Outer");
+            let mut inner = debug_scope!(*outer, "Inner");
+            inner.insert_debug_marker("Marker");
+        }
+        queue.submit(iter::once(encoder.finish()));
+
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+    }
+}
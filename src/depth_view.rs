@@ -0,0 +1,208 @@
+//! Debug view of a depth texture as linear grayscale, near black and far
+//! white.
+
+use crate::{camera::Projection, texture::Texture};
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    CommandEncoder, Device, LoadOp, Operations, PipelineLayoutDescriptor, Queue,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, SamplerBindingType,
+    ShaderStages, StoreOp, TextureFormat, TextureSampleType, TextureView, TextureViewDimension,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+struct DepthRange {
+    near: f32,
+    far: f32,
+}
+
+impl DepthRange {
+    fn new(projection: &Projection) -> Self {
+        Self {
+            near: projection.znear(),
+            far: projection.zfar(),
+        }
+    }
+}
+
+pub struct DepthView {
+    bind_group_layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+    range_buffer: Buffer,
+}
+
+impl DepthView {
+    pub fn new(device: &Device, target_format: TextureFormat, projection: &Projection) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Depth view bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let range_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Depth range buffer"),
+            contents: bytemuck::bytes_of(&DepthRange::new(projection)),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/depth.wgsl"));
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Depth view pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth view pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            range_buffer,
+        }
+    }
+
+    /// Keeps the linearization in step with the projection's planes.
+    pub fn update(&self, queue: &Queue, projection: &Projection) {
+        queue.write_buffer(
+            &self.range_buffer,
+            0,
+            bytemuck::bytes_of(&DepthRange::new(projection)),
+        );
+    }
+
+    /// Binds a depth texture made by
+    /// [`Texture::create_depth_target`], `None` for any other texture.
+    pub fn bind_group(&self, device: &Device, depth: &Texture) -> Option<BindGroup> {
+        Some(device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Depth view bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&depth.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(depth.depth_sampler.as_ref()?),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: self.range_buffer.as_entire_binding(),
+                },
+            ],
+        }))
+    }
+
+    pub fn draw(&self, encoder: &mut CommandEncoder, target: &TextureView, depth: &BindGroup) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Depth view pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(wgpu::Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, depth, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use super::DepthView;
+    use crate::{
+        camera::Projection,
+        texture::{test_device, Texture},
+    };
+    use image::Rgba;
+    use std::iter;
+    use wgpu::{LoadOp, Operations, RenderPassDepthStencilAttachment, StoreOp, TextureFormat};
+
+    #[test]
+    fn far_plane_is_white() {
+        let (device, queue) = test_device();
+        let projection = Projection::new(4, 4, cgmath::Deg(45.0), 0.1, 100.0);
+        let depth = Texture::create_depth_target(&device, 4, 4, 1);
+        let target = Texture::create_render_target(&device, 4, 4, TextureFormat::Rgba8Unorm, 1);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &depth.view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let depth_view = DepthView::new(&device, TextureFormat::Rgba8Unorm, &projection);
+        let bind_group = depth_view.bind_group(&device, &depth).unwrap();
+        depth_view.draw(&mut encoder, &target.view, &bind_group);
+        queue.submit(iter::once(encoder.finish()));
+
+        let image = target.read_to_image(&device, &queue).unwrap();
+        assert!(image.pixels().all(|&pixel| pixel == Rgba([255; 4])));
+    }
+}
@@ -0,0 +1,262 @@
+//! A model matrix and color for each of a frame's single draws, e.g. the
+//! light markers, so they need no instance buffer. They're push constants
+//! where the device supports them, otherwise read from a uniform buffer at a
+//! dynamic offset per draw.
+
+use crate::shader::ShaderDefines;
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Vector3};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, BufferBinding,
+    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Device, Features,
+    PushConstantRange, Queue, RenderPass, ShaderStages,
+};
+
+/// Laid out to match `Draw` in the shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct DrawData {
+    pub transform: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+impl DrawData {
+    const SIZE: u32 = std::mem::size_of::<Self>() as u32;
+}
+
+/// One draw's constants, from [`DrawConstants::push`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawSlot {
+    data: DrawData,
+    /// Into the fallback's uniform buffer.
+    offset: u32,
+}
+
+struct UniformFallback {
+    bind_group_layout: BindGroupLayout,
+    buffer: Buffer,
+    bind_group: BindGroup,
+    /// [`DrawData::SIZE`] rounded up to the device's offset alignment.
+    stride: u32,
+    /// How many draws fit in the buffer.
+    capacity: usize,
+}
+
+impl UniformFallback {
+    fn new(device: &Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Draw constants bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: DrawConstants::STAGES,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: BufferSize::new(DrawData::SIZE as u64),
+                },
+                count: None,
+            }],
+        });
+        let stride =
+            DrawData::SIZE.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment);
+        let capacity = 16;
+        let (buffer, bind_group) =
+            Self::create_buffer(device, &bind_group_layout, stride, capacity);
+
+        Self {
+            bind_group_layout,
+            buffer,
+            bind_group,
+            stride,
+            capacity,
+        }
+    }
+
+    fn create_buffer(
+        device: &Device,
+        layout: &BindGroupLayout,
+        stride: u32,
+        capacity: usize,
+    ) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Draw constants buffer"),
+            size: stride as BufferAddress * capacity as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Draw constants bind group"),
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: BufferSize::new(DrawData::SIZE as u64),
+                }),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+}
+
+/// The frame's draws, pushed anew every frame and uploaded together before
+/// they're drawn.
+pub struct DrawConstants {
+    draws: Vec<DrawData>,
+    /// Only without push constants.
+    fallback: Option<UniformFallback>,
+}
+
+impl DrawConstants {
+    pub const STAGES: ShaderStages = ShaderStages::VERTEX_FRAGMENT;
+
+    /// Falls back to the uniform buffer unless `device` was requested with
+    /// [`DrawConstants::required_features`] and limits.
+    pub fn new(device: &Device) -> Self {
+        let push_constants = device.features().contains(Features::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size >= DrawData::SIZE;
+
+        Self {
+            draws: vec![],
+            fallback: (!push_constants).then(|| UniformFallback::new(device)),
+        }
+    }
+
+    pub fn required_features() -> Features {
+        Features::PUSH_CONSTANTS
+    }
+
+    /// The smallest `max_push_constant_size` they fit in.
+    pub fn required_push_constant_size() -> u32 {
+        DrawData::SIZE
+    }
+
+    pub fn uses_push_constants(&self) -> bool {
+        self.fallback.is_none()
+    }
+
+    /// For the layouts of pipelines that use them, see
+    /// [`PipelineOptions::with_push_constant_ranges`](crate::pipeline::PipelineOptions::with_push_constant_ranges).
+    pub fn push_constant_ranges(&self) -> Vec<PushConstantRange> {
+        match self.fallback {
+            Some(_) => vec![],
+            None => vec![PushConstantRange {
+                stages: Self::STAGES,
+                range: 0..DrawData::SIZE,
+            }],
+        }
+    }
+
+    /// The fallback's bind group layout, for the group after a pipeline's
+    /// others.
+    pub fn bind_group_layout(&self) -> Option<&BindGroupLayout> {
+        self.fallback
+            .as_ref()
+            .map(|fallback| &fallback.bind_group_layout)
+    }
+
+    /// `PUSH_CONSTANTS` when they're used, for shaders to declare `Draw` to
+    /// match.
+    pub fn defines(&self) -> ShaderDefines {
+        match self.uses_push_constants() {
+            true => ShaderDefines::default().with_flag("PUSH_CONSTANTS"),
+            false => ShaderDefines::default(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.draws.clear();
+    }
+
+    /// Adds a draw for [`DrawConstants::set`], uploaded with the rest.
+    pub fn push(&mut self, transform: Matrix4<f32>, color: Vector3<f32>) -> DrawSlot {
+        let data = DrawData {
+            transform: transform.into(),
+            color: color.extend(1.0).into(),
+        };
+        let offset = match &self.fallback {
+            Some(fallback) => fallback.stride * self.draws.len() as u32,
+            None => 0,
+        };
+        self.draws.push(data);
+
+        DrawSlot { data, offset }
+    }
+
+    /// Writes the draws into the fallback's buffer, replacing it with one
+    /// big enough when they don't fit. With push constants there's nothing
+    /// to upload.
+    pub fn upload(&mut self, device: &Device, queue: &Queue) {
+        let Some(fallback) = &mut self.fallback else {
+            return;
+        };
+        if self.draws.len() > fallback.capacity {
+            fallback.capacity = self.draws.len().next_power_of_two();
+            (fallback.buffer, fallback.bind_group) = UniformFallback::create_buffer(
+                device,
+                &fallback.bind_group_layout,
+                fallback.stride,
+                fallback.capacity,
+            );
+        }
+        if self.draws.is_empty() {
+            return;
+        }
+
+        let stride = fallback.stride as usize;
+        let mut contents = vec![0; stride * self.draws.len()];
+        for (draw, bytes) in self.draws.iter().zip(contents.chunks_mut(stride)) {
+            bytes[..DrawData::SIZE as usize].copy_from_slice(bytemuck::bytes_of(draw));
+        }
+        queue.write_buffer(&fallback.buffer, 0, &contents);
+    }
+
+    /// Sets `slot`'s constants for the following draws, binding the
+    /// fallback's buffer at `group`.
+    pub fn set<'a>(&'a self, render_pass: &mut RenderPass<'a>, group: u32, slot: DrawSlot) {
+        match &self.fallback {
+            Some(fallback) => {
+                render_pass.set_bind_group(group, &fallback.bind_group, &[slot.offset])
+            }
+            None => render_pass.set_push_constants(Self::STAGES, 0, bytemuck::bytes_of(&slot.data)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use super::{DrawConstants, DrawData};
+    use crate::texture::test_device;
+    use cgmath::{Matrix4, Vector3};
+
+    #[test]
+    fn fallback_draws_are_aligned() {
+        // Requested without push constants
+        let (device, queue) = test_device();
+        let mut constants = DrawConstants::new(&device);
+        assert!(!constants.uses_push_constants());
+        assert!(constants.push_constant_ranges().is_empty());
+        assert!(constants.defines().is_empty());
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let slots: Vec<_> = (0..20)
+            .map(|index| {
+                let transform = Matrix4::from_translation(Vector3::new(index as f32, 0.0, 0.0));
+                constants.push(transform, Vector3::new(1.0, 0.5, 0.25))
+            })
+            .collect();
+        assert_eq!(slots[0].offset, 0);
+        assert_eq!(slots[1].offset % alignment, 0);
+        assert!(slots[1].offset >= DrawData::SIZE);
+        assert_eq!(slots[0].data.color, [1.0, 0.5, 0.25, 1.0]);
+
+        // Grown past its first capacity
+        constants.upload(&device, &queue);
+        let fallback = constants.fallback.as_ref().unwrap();
+        assert_eq!(fallback.capacity, 32);
+        assert_eq!(fallback.buffer.size(), fallback.stride as u64 * 32);
+    }
+}
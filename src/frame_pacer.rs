@@ -0,0 +1,102 @@
+//! Paces frames to a target rate against deadlines, so the event loop can
+//! sleep in between rather than poll.
+
+use std::{
+    hint,
+    time::{Duration, Instant},
+};
+
+/// Schedules each frame a frame time after the last one was due, so late
+/// wake ups don't add up.
+#[derive(Clone, Debug)]
+pub struct FramePacer {
+    /// `None` when uncapped.
+    frame_time: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    /// How long before a deadline sleeping gives way to spinning, which
+    /// wakes up on time where the scheduler might not.
+    pub const SPIN: Duration = Duration::from_micros(500);
+
+    /// Uncapped with a `target_fps` of `None`.
+    pub fn new(target_fps: Option<u32>) -> Self {
+        Self {
+            frame_time: frame_time(target_fps),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn target_fps(&self) -> Option<u32> {
+        self.frame_time
+            .map(|frame_time| (1.0 / frame_time.as_secs_f64()).round() as u32)
+    }
+
+    /// Takes effect from the next frame on.
+    pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+        self.frame_time = frame_time(target_fps);
+    }
+
+    /// When to wake up for the next frame, or `None` once it's due. Spins
+    /// through the last [`FramePacer::SPIN`] before the deadline.
+    pub fn poll(&self, now: Instant) -> Option<Instant> {
+        self.frame_time?;
+        if self.next_frame.saturating_duration_since(now) > Self::SPIN {
+            return Some(self.next_frame - Self::SPIN);
+        }
+
+        while Instant::now() < self.next_frame {
+            hint::spin_loop();
+        }
+        None
+    }
+
+    /// Schedules the frame after the one starting at `now`. Frames more
+    /// than a frame late start the schedule over rather than hurry to
+    /// catch up.
+    pub fn begin_frame(&mut self, now: Instant) {
+        let Some(frame_time) = self.frame_time else {
+            self.next_frame = now;
+            return;
+        };
+
+        self.next_frame += frame_time;
+        if self.next_frame < now {
+            self.next_frame = now + frame_time;
+        }
+    }
+}
+
+fn frame_time(target_fps: Option<u32>) -> Option<Duration> {
+    target_fps
+        .filter(|&fps| fps > 0)
+        .map(|fps| Duration::from_secs(1) / fps)
+}
+
+#[cfg(test)]
+mod test {
+    use super::FramePacer;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn deadlines_dont_drift() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(Some(100));
+        pacer.next_frame = start;
+        assert_eq!(pacer.target_fps(), Some(100));
+
+        // Woken up late, the next frame's still due on the schedule
+        pacer.begin_frame(start + Duration::from_millis(2));
+        let frame = start + Duration::from_millis(10);
+        assert_eq!(pacer.poll(start), Some(frame - FramePacer::SPIN));
+        assert_eq!(pacer.poll(frame), None);
+
+        // Too late to catch up
+        pacer.begin_frame(start + Duration::from_millis(50));
+        assert_eq!(pacer.next_frame, start + Duration::from_millis(60));
+
+        pacer.set_target_fps(None);
+        assert_eq!(pacer.poll(start), None);
+    }
+}
@@ -0,0 +1,214 @@
+//! How long frames take on the CPU and on the GPU pass by pass, and how much
+//! each one draws, for the overlay and for whoever embeds the renderer.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Rolling statistics over the last [`FrameStats::WINDOW`] frames.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    /// Oldest first.
+    frame_times: VecDeque<Duration>,
+    draws: u32,
+    instances: u32,
+    gpu_passes: Vec<(&'static str, Duration)>,
+    refresh_interval: Duration,
+    last_refresh: Option<Instant>,
+}
+
+impl FrameStats {
+    /// How many frames the statistics are taken over.
+    pub const WINDOW: usize = 120;
+
+    /// Asks to be shown at most once per `refresh_interval`, see
+    /// [`FrameStats::should_refresh`].
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(Self::WINDOW),
+            draws: 0,
+            instances: 0,
+            gpu_passes: vec![],
+            refresh_interval,
+            last_refresh: None,
+        }
+    }
+
+    /// Adds a frame, dropping the oldest once there are more than
+    /// [`FrameStats::WINDOW`].
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == Self::WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    /// What the last frame drew, kept until the next one's recorded.
+    pub fn record_draws(&mut self, draws: u32, instances: u32) {
+        self.draws = draws;
+        self.instances = instances;
+    }
+
+    /// How long each pass took on the GPU, averaged over a while, kept until
+    /// the next are recorded.
+    pub fn record_gpu_passes(&mut self, passes: &[(&'static str, Duration)]) {
+        self.gpu_passes.clear();
+        self.gpu_passes.extend_from_slice(passes);
+    }
+
+    /// The mean frame time, zero before any frames.
+    pub fn average(&self) -> Duration {
+        mean(self.frame_times.iter().copied())
+    }
+
+    pub fn fps(&self) -> f32 {
+        rate(self.average())
+    }
+
+    /// The standard deviation of the frame times, how unevenly frames are
+    /// paced.
+    pub fn jitter(&self) -> Duration {
+        let average = self.average().as_secs_f64();
+        let variance = match self.frame_times.len() {
+            0 => 0.0,
+            count => {
+                self.frame_times
+                    .iter()
+                    .map(|frame_time| (frame_time.as_secs_f64() - average).powi(2))
+                    .sum::<f64>()
+                    / count as f64
+            }
+        };
+
+        Duration::from_secs_f64(variance.sqrt())
+    }
+
+    /// The frame rate over the slowest 1% of the frames, at least the
+    /// slowest one.
+    pub fn one_percent_low(&self) -> f32 {
+        let mut frame_times: Vec<_> = self.frame_times.iter().copied().collect();
+        frame_times.sort_unstable_by(|a, b| b.cmp(a));
+        let slowest = frame_times.len().div_ceil(100);
+
+        rate(mean(frame_times.into_iter().take(slowest)))
+    }
+
+    pub fn draws(&self) -> u32 {
+        self.draws
+    }
+
+    pub fn instances(&self) -> u32 {
+        self.instances
+    }
+
+    /// Empty without timestamp queries, or before the first are read back.
+    pub fn gpu_passes(&self) -> &[(&'static str, Duration)] {
+        &self.gpu_passes
+    }
+
+    /// The GPU time of every pass timed, zero without timestamp queries.
+    pub fn gpu_time(&self) -> Duration {
+        self.gpu_passes.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// True at most once per refresh interval, so the statistics aren't
+    /// reshaped as text every frame.
+    pub fn should_refresh(&mut self) -> bool {
+        let now = Instant::now();
+        let due = self
+            .last_refresh
+            .is_none_or(|last| now - last >= self.refresh_interval);
+        if due {
+            self.last_refresh = Some(now);
+        }
+
+        due
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} ms ±{:.2} / {:.0} fps / 1% low {:.0} fps / {} instances / {} draws",
+            self.average().as_secs_f64() * 1000.0,
+            self.jitter().as_secs_f64() * 1000.0,
+            self.fps(),
+            self.one_percent_low(),
+            self.instances,
+            self.draws
+        )
+    }
+}
+
+fn mean(durations: impl ExactSizeIterator<Item = Duration>) -> Duration {
+    match durations.len() as u32 {
+        0 => Duration::ZERO,
+        count => durations.sum::<Duration>() / count,
+    }
+}
+
+/// Frames a second at `frame_time` each, zero without a frame time.
+fn rate(frame_time: Duration) -> f32 {
+    match frame_time.is_zero() {
+        true => 0.0,
+        false => 1.0 / frame_time.as_secs_f32(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FrameStats;
+    use std::time::Duration;
+
+    #[test]
+    fn stats_roll_over_the_window() {
+        let mut stats = FrameStats::new(Duration::ZERO);
+        assert_eq!(stats.average(), Duration::ZERO);
+        assert_eq!(stats.fps(), 0.0);
+        assert_eq!(stats.one_percent_low(), 0.0);
+        assert_eq!(stats.jitter(), Duration::ZERO);
+
+        // Pushed out by the full window after it
+        stats.record_frame(Duration::from_millis(100));
+        for _ in 0..FrameStats::WINDOW - 2 {
+            stats.record_frame(Duration::from_millis(10));
+        }
+        stats.record_frame(Duration::from_millis(40));
+        stats.record_frame(Duration::from_millis(20));
+        assert_eq!(
+            stats.average(),
+            Duration::from_millis(10 * 118 + 40 + 20) / 120
+        );
+        // The two slowest of 120 frames
+        assert!((stats.one_percent_low() - 1.0 / 0.03).abs() < 1e-3);
+        assert!(stats.jitter() > Duration::ZERO);
+
+        stats.record_draws(3, 100);
+        let text = stats.to_string();
+        assert!(text.ends_with("/ 100 instances / 3 draws"), "{text}");
+
+        stats.record_gpu_passes(&[
+            ("shadow", Duration::from_micros(300)),
+            ("main", Duration::from_micros(1200)),
+        ]);
+        assert_eq!(stats.gpu_time(), Duration::from_micros(1500));
+
+        // Evenly paced
+        let mut stats = FrameStats::new(Duration::ZERO);
+        for _ in 0..10 {
+            stats.record_frame(Duration::from_millis(8));
+        }
+        assert!(stats.jitter() < Duration::from_nanos(1));
+    }
+
+    #[test]
+    fn refreshes_are_throttled() {
+        let mut stats = FrameStats::new(Duration::from_secs(60));
+        assert!(stats.should_refresh());
+        assert!(!stats.should_refresh());
+        assert!(FrameStats::new(Duration::ZERO).should_refresh());
+    }
+}
@@ -0,0 +1,227 @@
+//! Times render passes on the GPU with timestamp queries, for the overlay.
+//! Reading them back never blocks, a frame's timings are picked up on a
+//! later one and frames are left untimed while that's pending.
+
+use std::{
+    mem,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+use wgpu::{
+    Buffer, BufferAddress, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+    Device, Features, Maintain, MapMode, QuerySet, QuerySetDescriptor, QueryType, Queue,
+    RenderPassTimestampWrites, QUERY_SIZE,
+};
+
+/// A frame's timestamps being read back.
+struct Readback {
+    passes: Vec<&'static str>,
+    /// Gets the map's result.
+    mapped: Receiver<Result<(), BufferAsyncError>>,
+}
+
+/// Averages the passes' timings over an interval, so they're steady enough
+/// to read.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    /// Where the queries are resolved, copied to the readback buffer since
+    /// query resolves can't target mappable buffers.
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds in a timestamp tick.
+    period: f32,
+    /// The passes timed this frame, each with a pair of queries in order.
+    passes: Vec<&'static str>,
+    readback: Option<Readback>,
+    interval: Duration,
+    interval_start: Instant,
+    /// Each pass's total over the interval so far, with how many frames it
+    /// was timed in.
+    totals: Vec<(&'static str, Duration, u32)>,
+    averages: Vec<(&'static str, Duration)>,
+}
+
+impl GpuTimer {
+    pub const MAX_PASSES: usize = 6;
+
+    /// `None` unless `device` was requested with
+    /// [`GpuTimer::required_features`].
+    pub fn new(device: &Device, queue: &Queue, interval: Duration) -> Option<Self> {
+        if !device.features().contains(Self::required_features()) {
+            return None;
+        }
+
+        let size = (QUERY_SIZE as usize * Self::MAX_PASSES * 2) as BufferAddress;
+        let buffer = |label, usage| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        Some(Self {
+            query_set: device.create_query_set(&QuerySetDescriptor {
+                label: Some("GPU timer queries"),
+                ty: QueryType::Timestamp,
+                count: Self::MAX_PASSES as u32 * 2,
+            }),
+            resolve_buffer: buffer(
+                "GPU timer resolve buffer",
+                BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            ),
+            readback_buffer: buffer(
+                "GPU timer readback buffer",
+                BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            ),
+            period: queue.get_timestamp_period(),
+            passes: vec![],
+            readback: None,
+            interval,
+            interval_start: Instant::now(),
+            totals: vec![],
+            averages: vec![],
+        })
+    }
+
+    pub fn required_features() -> Features {
+        Features::TIMESTAMP_QUERY
+    }
+
+    /// Times `passes` this frame, at most [`GpuTimer::MAX_PASSES`] of them,
+    /// unless the last timed frame is still being read back.
+    pub fn begin_frame(&mut self, passes: &[&'static str]) {
+        self.passes.clear();
+        if self.readback.is_none() {
+            self.passes
+                .extend(passes.iter().take(Self::MAX_PASSES).copied());
+        }
+    }
+
+    /// For the `timestamp_writes` of `pass`, `None` when it isn't timed this
+    /// frame.
+    pub fn timestamp_writes(&self, pass: &str) -> Option<RenderPassTimestampWrites<'_>> {
+        let index = self.passes.iter().position(|&timed| timed == pass)? as u32;
+
+        Some(RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    /// Copies the frame's timestamps out for reading back, after its passes.
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        if self.passes.is_empty() {
+            return;
+        }
+
+        let count = self.passes.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            (count * QUERY_SIZE) as BufferAddress,
+        );
+    }
+
+    /// Starts reading the frame's timestamps back, once it's submitted.
+    pub fn map(&mut self) {
+        if self.passes.is_empty() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.readback_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.readback = Some(Readback {
+            passes: mem::take(&mut self.passes),
+            mapped: receiver,
+        });
+    }
+
+    /// Picks up a finished readback, true when that completes an interval
+    /// and there are new [`GpuTimer::averages`].
+    pub fn poll(&mut self, device: &Device) -> bool {
+        let Some(Readback { passes, mapped }) = &self.readback else {
+            return false;
+        };
+        device.poll(Maintain::Poll);
+        let result = match mapped.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return false,
+            // Dropped with the device
+            Err(TryRecvError::Disconnected) => Err(BufferAsyncError),
+        };
+
+        if result.is_ok() {
+            let timestamps: Vec<u64> = {
+                let size = passes.len() as BufferAddress * 2 * QUERY_SIZE as BufferAddress;
+                let range = self.readback_buffer.slice(..size).get_mapped_range();
+                bytemuck::cast_slice(&range).to_vec()
+            };
+            self.readback_buffer.unmap();
+            for (&pass, duration) in passes.iter().zip(durations(&timestamps, self.period)) {
+                match self.totals.iter_mut().find(|(name, ..)| *name == pass) {
+                    Some((_, total, frames)) => {
+                        *total += duration;
+                        *frames += 1;
+                    }
+                    None => self.totals.push((pass, duration, 1)),
+                }
+            }
+        }
+        self.readback = None;
+
+        if self.interval_start.elapsed() < self.interval {
+            return false;
+        }
+        self.interval_start = Instant::now();
+        self.averages = self
+            .totals
+            .drain(..)
+            .map(|(pass, total, frames)| (pass, total / frames))
+            .collect();
+
+        true
+    }
+
+    /// Each pass timed over the last interval, with how long it took on
+    /// average.
+    pub fn averages(&self) -> &[(&'static str, Duration)] {
+        &self.averages
+    }
+}
+
+/// How long passed between each pair of `timestamps`, `period` nanoseconds
+/// a tick. Timestamps that went backwards, e.g. across a clock reset, read
+/// as no time at all.
+fn durations(timestamps: &[u64], period: f32) -> impl Iterator<Item = Duration> + '_ {
+    timestamps.chunks_exact(2).map(move |pair| {
+        let ticks = pair[1].saturating_sub(pair[0]);
+        Duration::from_nanos((ticks as f64 * period as f64) as u64)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::durations;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps_pair_into_durations() {
+        let timestamps = [100, 1100, 5000, 4000, 7];
+        let durations: Vec<_> = durations(&timestamps, 2.5).collect();
+        // The unpaired timestamp is left out
+        assert_eq!(
+            durations,
+            [Duration::from_nanos(2500), Duration::from_nanos(0)]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! The instances of the model drawn across the scene, and the per-instance
+//! vertex data the pipelines read them from.
+
+use crate::{instance_animation::InstanceSource, model::VertexBufferFormat};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix3, Matrix4, Quaternion, Vector3};
+use wgpu::{vertex_attr_array, VertexAttribute, VertexBufferLayout, VertexStepMode};
+
+pub struct Instance {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    /// Layer of the diffuse map drawn with the texture array pipeline,
+    /// ignored by the others.
+    pub texture_index: u32,
+}
+
+impl Instance {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position) * Matrix4::from(self.rotation)
+    }
+
+    /// What the instance animation writes its [`RawInstance`] from.
+    pub fn source(&self) -> InstanceSource {
+        InstanceSource::new(self.position, self.rotation, self.texture_index)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+/// Written by the instance animation, laid out to match `Instance` in
+/// `instances.wgsl`.
+pub struct RawInstance {
+    model: Matrix4<f32>,
+    normal: Matrix3<f32>,
+    texture_index: u32,
+    /// Up to the shader's 16 byte alignment.
+    _padding: [u32; 2],
+}
+
+impl VertexBufferFormat for RawInstance {
+    type Attributes = [VertexAttribute; 8];
+    const ATTRIBUTES: Self::Attributes = vertex_attr_array![
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x3,
+        11 => Float32x3,
+        12 => Float32x3,
+        13 => Uint32,
+    ];
+
+    fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
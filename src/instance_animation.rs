@@ -0,0 +1,272 @@
+//! Spins the instances on the GPU. A compute pass turns each instance's
+//! position and rotation into the instance data the render pipelines read as
+//! vertices, see [`AnimatedInstances`].
+
+use crate::{
+    pipeline::{ComputePipeline, ComputePipelineOptions},
+    shader::{Shader, ShaderDefines},
+};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Quaternion, Rad, Vector3};
+use std::ops::Deref;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, BufferDescriptor,
+    BufferUsages, Device, Queue, ShaderStages,
+};
+
+/// Instances each workgroup spins, as in `instances.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// What an instance is spun from, laid out to match `Source` in
+/// `instances.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct InstanceSource {
+    /// A quaternion's vector part, then its scalar.
+    pub rotation: [f32; 4],
+    pub position: [f32; 3],
+    pub texture_index: u32,
+}
+
+impl InstanceSource {
+    pub fn new(position: Vector3<f32>, rotation: Quaternion<f32>, texture_index: u32) -> Self {
+        Self {
+            rotation: rotation.v.extend(rotation.s).into(),
+            position: position.into(),
+            texture_index,
+        }
+    }
+}
+
+/// Turns every instance about its own y axis by the same angle.
+pub struct InstanceAnimation {
+    pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    angle_buffer: Buffer,
+    angle: Rad<f32>,
+}
+
+impl InstanceAnimation {
+    pub fn new(device: &Device) -> Self {
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Instance animation bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+            ],
+        });
+        let shader = Shader::embedded(device, "instances.wgsl", ShaderDefines::default());
+        let pipeline = ComputePipeline::new(
+            device,
+            &shader,
+            &[&bind_group_layout],
+            ComputePipelineOptions::new("Instance animation pipeline"),
+        );
+        let angle = Rad(0.0);
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            angle_buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Instance animation angle buffer"),
+                contents: bytemuck::bytes_of(&angle.0),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            }),
+            angle,
+        }
+    }
+
+    pub fn pipeline(&self) -> &ComputePipeline {
+        &self.pipeline
+    }
+
+    /// For rebuilding from an edited shader.
+    pub fn pipeline_mut(&mut self) -> &mut ComputePipeline {
+        &mut self.pipeline
+    }
+
+    /// Turns the instances `by` further from the next dispatch on.
+    pub fn advance(&mut self, queue: &Queue, by: Rad<f32>) {
+        self.angle = Rad((self.angle + by).0 % std::f32::consts::TAU);
+        queue.write_buffer(&self.angle_buffer, 0, bytemuck::bytes_of(&self.angle.0));
+    }
+
+    /// What each instance is turned by after its own rotation, for matching
+    /// them on the CPU, e.g. to cull them.
+    pub fn spin(&self) -> Matrix4<f32> {
+        Matrix4::from_angle_y(self.angle)
+    }
+}
+
+/// A storage buffer of [`InstanceSource`]s and the instance buffer the
+/// animation writes from them, which it derefs to.
+pub struct AnimatedInstances {
+    sources: Buffer,
+    instances: Buffer,
+    bind_group: BindGroup,
+    capacity: usize,
+}
+
+impl AnimatedInstances {
+    /// Room for as many instances as `sources`, each `instance_size` bytes
+    /// to match `Instance` in `instances.wgsl`.
+    pub fn new(
+        device: &Device,
+        label: &str,
+        animation: &InstanceAnimation,
+        sources: &[InstanceSource],
+        instance_size: usize,
+    ) -> Self {
+        let source_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("{label} sources")),
+            contents: bytemuck::cast_slice(sources),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let instances = device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: (instance_size * sources.len()) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&format!("{label} animation bind group")),
+            layout: &animation.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: animation.angle_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: source_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: instances.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            sources: source_buffer,
+            instances,
+            bind_group,
+            capacity: sources.len(),
+        }
+    }
+
+    /// Replaces the sources at the front, the instances after them keep
+    /// being spun from what they were.
+    pub fn write(&self, queue: &Queue, sources: &[InstanceSource]) {
+        let sources = &sources[..sources.len().min(self.capacity)];
+        if !sources.is_empty() {
+            queue.write_buffer(&self.sources, 0, bytemuck::cast_slice(sources));
+        }
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Enough to spin every instance.
+    pub fn workgroups(&self) -> u32 {
+        (self.capacity as u32).div_ceil(WORKGROUP_SIZE)
+    }
+}
+
+impl Deref for AnimatedInstances {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.instances
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use super::{AnimatedInstances, InstanceAnimation, InstanceSource};
+    use crate::texture::test_device;
+    use cgmath::{assert_abs_diff_eq, Deg, Matrix4, Quaternion, Rad, Rotation3, Vector3};
+    use std::{iter, sync::mpsc};
+    use wgpu::{
+        BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, Maintain,
+        MapMode,
+    };
+
+    #[test]
+    fn instances_are_spun_on_the_gpu() {
+        let (device, queue) = test_device();
+        let mut animation = InstanceAnimation::new(&device);
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let rotation = Quaternion::from_axis_angle(Vector3::unit_x(), Deg(30.0));
+        let sources = vec![InstanceSource::new(position, rotation, 7); 70];
+        // Model, normal and texture index, padded to 16 bytes
+        let instance_size = 112;
+        let instances = AnimatedInstances::new(
+            &device,
+            "Test instances",
+            &animation,
+            &sources,
+            instance_size,
+        );
+        assert_eq!(instances.workgroups(), 2);
+        animation.advance(&queue, Rad(0.5));
+
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: instances.size(),
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            compute_pass.set_pipeline(animation.pipeline());
+            compute_pass.set_bind_group(0, instances.bind_group(), &[]);
+            compute_pass.dispatch_workgroups(instances.workgroups(), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&instances, 0, &readback, 0, instances.size());
+        queue.submit(iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(Maintain::Wait);
+        receiver.recv().unwrap().unwrap();
+        let floats: Vec<f32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+
+        let expected =
+            Matrix4::from_translation(position) * Matrix4::from(rotation) * animation.spin();
+        // The last instance, past the first workgroup
+        let last = &floats[floats.len() - instance_size / 4..];
+        let model: &[[f32; 4]; 4] = bytemuck::from_bytes(bytemuck::cast_slice(&last[..16]));
+        assert_abs_diff_eq!(Matrix4::from(*model), expected, epsilon = 1e-5);
+        // The normal matrix's middle
+        assert_abs_diff_eq!(last[20], expected.y.y, epsilon = 1e-5);
+        assert_eq!(last[25].to_bits(), 7);
+    }
+}
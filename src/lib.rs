@@ -0,0 +1,48 @@
+//! A wgpu renderer for instanced models over a terrain, lit by point, spot
+//! and directional lights. [`Renderer`] draws into a window's surface, the
+//! application around it owns the window and its event loop, or headless
+//! into an image.
+
+pub use camera::{Camera, Projection};
+pub use frame_pacer::FramePacer;
+pub use frame_stats::FrameStats;
+pub use instance::Instance;
+pub use light::{Light, PointLight, SpotLight};
+pub use math::Ray;
+pub use model::{Material, MaterialKind, Mesh, Model, ModelVertex};
+pub use pipeline::{Pipeline, PipelineOptions};
+pub use renderer::{
+    AdapterOptions, AdapterPreference, BackendChoice, Renderer, RendererError, RendererOptions,
+    RendererResult, UnknownBackend, ADAPTER_VARIABLE,
+};
+pub use shader::{Shader, ShaderDefines};
+pub use shadow::ShadowSettings;
+pub use terrain::{HeightMap, Terrain, TerrainMode, TerrainOptions};
+pub use texture::{SamplerOptions, Texture, TextureError, TextureKind, TextureResult};
+pub use ui::TextManager;
+
+mod blit;
+pub mod camera;
+mod console;
+pub mod debug_draw;
+pub mod debug_scope;
+mod depth_view;
+mod draw_constants;
+mod frame_pacer;
+mod frame_stats;
+mod gpu_timer;
+pub mod instance;
+mod instance_animation;
+pub mod light;
+pub mod math;
+pub mod model;
+pub mod pipeline;
+mod renderer;
+mod screenshot;
+pub mod shader;
+mod shader_watcher;
+pub mod shadow;
+pub mod terrain;
+pub mod texture;
+pub mod ui;
+mod util;
@@ -0,0 +1,62 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::Vector3;
+
+/// Light from everywhere around, blended from `ground_color` on faces
+/// pointing down to `sky_color` on those pointing up, so nothing goes fully
+/// black. Laid out to match `AmbientLight` in the shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct AmbientLight {
+    pub sky_color: Vector3<f32>,
+    pub intensity: f32,
+    pub ground_color: Vector3<f32>,
+    _padding: u32,
+}
+
+impl Default for AmbientLight {
+    /// Close to the flat tenth of each light's color the lights used to
+    /// add, a little bluer from above.
+    fn default() -> Self {
+        Self::new(
+            Vector3::new(0.11, 0.12, 0.14),
+            Vector3::new(0.08, 0.07, 0.06),
+        )
+    }
+}
+
+impl AmbientLight {
+    pub fn new(sky_color: Vector3<f32>, ground_color: Vector3<f32>) -> Self {
+        Self {
+            sky_color,
+            intensity: 1.0,
+            ground_color,
+            _padding: 0,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity.max(0.0);
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AmbientLight;
+    use std::ptr;
+
+    #[test]
+    fn aligned() {
+        assert_eq!(std::mem::size_of::<AmbientLight>(), 32);
+
+        let ambient = AmbientLight::default();
+        let sky_ptr = ptr::addr_of!(ambient.sky_color).cast::<u8>();
+        let offsets = [
+            ptr::addr_of!(ambient.intensity).cast::<u8>(),
+            ptr::addr_of!(ambient.ground_color).cast::<u8>(),
+        ]
+        .map(|field| unsafe { field.offset_from(sky_ptr) });
+        assert_eq!(offsets, [12, 16]);
+    }
+}
@@ -0,0 +1,48 @@
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+use std::time::Duration;
+
+/// Spins a light around an axis through the origin at a steady rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightAnimation {
+    pub axis: Vector3<f32>,
+    pub degrees_per_second: f32,
+}
+
+impl LightAnimation {
+    pub fn new(axis: Vector3<f32>, degrees_per_second: f32) -> Self {
+        Self {
+            axis,
+            degrees_per_second,
+        }
+    }
+
+    /// How far the light turns over `dt`.
+    pub fn rotation(&self, dt: Duration) -> Quaternion<f32> {
+        Quaternion::from_axis_angle(
+            self.axis.normalize(),
+            Deg(self.degrees_per_second * dt.as_secs_f32()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LightAnimation;
+    use crate::vec3;
+    use cgmath::assert_abs_diff_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn turns_by_time_not_frames() {
+        let animation = LightAnimation::new(vec3!(0.0, 2.0, 0.0), 90.0);
+        let position = vec3!(1.0, 0.0, 0.0);
+
+        let second = animation.rotation(Duration::from_secs(1)) * position;
+        assert_abs_diff_eq!(second, vec3!(0.0, 0.0, -1.0), epsilon = 1e-6);
+
+        // However many frames the second is split into
+        let frame = animation.rotation(Duration::from_millis(10));
+        let stepped = (0..100).fold(position, |position, _| frame * position);
+        assert_abs_diff_eq!(stepped, second, epsilon = 1e-5);
+    }
+}
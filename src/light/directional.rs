@@ -0,0 +1,245 @@
+use crate::{camera::OPENGL_TO_WGPU_MATRIX, model::Aabb, shadow::ShadowPass};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use std::f32::consts::PI;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Device, Queue, SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension,
+};
+
+/// Light arriving from the same direction everywhere, like the sun's, with
+/// no falloff. Laid out to match `DirectionalLight` in the shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct DirectionalLight {
+    /// The way the light travels, always normalized.
+    direction: Vector3<f32>,
+    pub intensity: f32,
+    pub color: Vector3<f32>,
+    _padding: u32,
+    /// Into the shadow map's clip space, see [`DirectionalLight::fit_shadow`].
+    view_projection: Matrix4<f32>,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f32>, color: Vector3<f32>) -> Self {
+        Self {
+            direction: direction.normalize(),
+            intensity: 1.0,
+            color,
+            _padding: 0,
+            view_projection: Matrix4::identity(),
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+
+        self
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Vector3<f32>) {
+        self.direction = direction.normalize();
+    }
+
+    /// Points the light like the sun at `hours` past midnight, rising in the
+    /// east (+x) at 6, overhead at 12 and setting in the west at 18.
+    pub fn set_time_of_day(&mut self, hours: f32) {
+        let angle = (hours - 6.0) / 12.0 * PI;
+        let to_sun = Vector3::new(angle.cos(), angle.sin(), 0.0);
+        self.set_direction(-to_sun);
+    }
+
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.view_projection
+    }
+
+    /// Aims the shadow map at `bounds`, an orthographic view along the light
+    /// just covering them. Needs redoing after the direction changes.
+    pub fn fit_shadow(&mut self, bounds: &Aabb) {
+        if bounds.is_empty() {
+            return;
+        }
+
+        let center = Point3::from_vec(bounds.center());
+        let radius = (bounds.size().magnitude() / 2.0).max(0.01);
+        // Any up works as long as it isn't along the light
+        let up = match self.direction.y.abs() > 0.99 {
+            true => Vector3::unit_z(),
+            false => Vector3::unit_y(),
+        };
+        let view = Matrix4::look_to_rh(center - self.direction * radius, self.direction, up);
+        let projection = cgmath::ortho(-radius, radius, -radius, radius, 0.0, radius * 2.0);
+
+        self.view_projection = OPENGL_TO_WGPU_MATRIX * projection * view;
+    }
+
+    pub fn prepared(self, device: &Device, shadow: &ShadowPass) -> DirectionalLightBundle {
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("[Directional light] buffer"),
+            contents: bytemuck::bytes_of(&self),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("[Directional light] bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &buffer, shadow);
+
+        DirectionalLightBundle {
+            uniform: self,
+            buffer,
+            bind_group,
+            bind_group_layout,
+        }
+    }
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    buffer: &Buffer,
+    shadow: &ShadowPass,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("[Directional light] bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&shadow.map().view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(&shadow.map().sampler),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: shadow.settings_buffer().as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// The light along with the shadow map and settings it's drawn with.
+pub struct DirectionalLightBundle {
+    pub uniform: DirectionalLight,
+    pub buffer: Buffer,
+    pub bind_group: BindGroup,
+    pub bind_group_layout: BindGroupLayout,
+}
+
+impl DirectionalLightBundle {
+    /// Uploads changes made to `uniform`.
+    pub fn update(&self, queue: &Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+    }
+
+    /// Binds the shadow's replacement map, e.g. one at another resolution.
+    pub fn set_shadow(&mut self, device: &Device, shadow: &ShadowPass) {
+        self.bind_group = create_bind_group(device, &self.bind_group_layout, &self.buffer, shadow);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DirectionalLight;
+    use crate::{model::Aabb, vec3};
+    use cgmath::{assert_abs_diff_eq, Vector4};
+    use std::ptr;
+
+    #[test]
+    fn aligned() {
+        assert_eq!(std::mem::size_of::<DirectionalLight>(), 96);
+
+        let light = DirectionalLight::new(vec3!(0.0, -1.0, 0.0), vec3!(1.0, 1.0, 1.0));
+        let direction_ptr = ptr::addr_of!(light.direction).cast::<u8>();
+        let offsets = [
+            ptr::addr_of!(light.color).cast::<u8>(),
+            ptr::addr_of!(light.view_projection).cast::<u8>(),
+        ]
+        .map(|field| unsafe { field.offset_from(direction_ptr) });
+        assert_eq!(offsets, [16, 32]);
+    }
+
+    #[test]
+    fn shadow_covers_the_bounds() {
+        let mut light = DirectionalLight::new(vec3!(1.0, -2.0, 0.5), vec3!(1.0, 1.0, 1.0));
+        let bounds = Aabb::new(vec3!(-10.0, -1.0, -5.0), vec3!(10.0, 1.0, 5.0));
+        light.fit_shadow(&bounds);
+
+        for corner in 0..8 {
+            let pick = |bit: usize, axis: usize| match corner & bit != 0 {
+                true => bounds.max[axis],
+                false => bounds.min[axis],
+            };
+            let corner = Vector4::new(pick(1, 0), pick(2, 1), pick(4, 2), 1.0);
+            let clip = light.view_projection() * corner;
+            assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0);
+            assert!((0.0..=1.0).contains(&clip.z));
+        }
+        // The middle of the bounds lands in the middle of the map
+        let center = light.view_projection() * bounds.center().extend(1.0);
+        assert_abs_diff_eq!(center, Vector4::new(0.0, 0.0, 0.5, 1.0), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn follows_the_time_of_day() {
+        let mut light = DirectionalLight::new(vec3!(0.0, -3.0, 4.0), vec3!(1.0, 1.0, 1.0));
+        assert_abs_diff_eq!(light.direction(), vec3!(0.0, -0.6, 0.8));
+
+        // Shining straight down at noon, from the east in the morning
+        light.set_time_of_day(12.0);
+        assert_abs_diff_eq!(light.direction(), vec3!(0.0, -1.0, 0.0), epsilon = 1e-6);
+        light.set_time_of_day(6.0);
+        assert_abs_diff_eq!(light.direction(), vec3!(-1.0, 0.0, 0.0), epsilon = 1e-6);
+    }
+}
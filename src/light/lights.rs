@@ -0,0 +1,527 @@
+use super::{
+    slots::{LightId, LightSlots},
+    AmbientLight,
+};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, BufferDescriptor,
+    BufferUsages, Device, Queue, ShaderStages,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    pub intensity: f32,
+    pub color: Vector3<f32>,
+    /// Distance the light fades out over, 0 reaching everywhere unfaded.
+    pub radius: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3<f32>, color: Vector3<f32>) -> Self {
+        Self {
+            position,
+            intensity: 1.0,
+            color,
+            radius: 0.0,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius.max(0.0);
+
+        self
+    }
+}
+
+/// A light shining in a cone, full strength inside `inner_angle` of
+/// `direction` and fading out smoothly by `outer_angle`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub intensity: f32,
+    pub color: Vector3<f32>,
+    pub inner_angle: Rad<f32>,
+    pub outer_angle: Rad<f32>,
+    /// Distance the light fades out over, 0 reaching everywhere unfaded.
+    pub range: f32,
+}
+
+impl SpotLight {
+    /// A 20° to 30° cone reaching everywhere.
+    pub fn new(position: Vector3<f32>, direction: Vector3<f32>, color: Vector3<f32>) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity: 1.0,
+            color,
+            inner_angle: Deg(20.0).into(),
+            outer_angle: Deg(30.0).into(),
+            range: 0.0,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+
+        self
+    }
+
+    /// Angles are from the direction to the edge, the outer one kept at
+    /// least as wide as the inner one.
+    pub fn with_cone<A: Into<Rad<f32>>>(mut self, inner_angle: A, outer_angle: A) -> Self {
+        self.inner_angle = inner_angle.into();
+        self.outer_angle = Rad(outer_angle.into().0.max(self.inner_angle.0));
+
+        self
+    }
+
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = range.max(0.0);
+
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl Light {
+    pub fn position(&self) -> Vector3<f32> {
+        match self {
+            Self::Point(light) => light.position,
+            Self::Spot(light) => light.position,
+        }
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        match self {
+            Self::Point(light) => light.position = position,
+            Self::Spot(light) => light.position = position,
+        }
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        match self {
+            Self::Point(light) => light.color,
+            Self::Spot(light) => light.color,
+        }
+    }
+
+    /// Point lights shine every way, so have none.
+    pub fn direction(&self) -> Option<Vector3<f32>> {
+        match self {
+            Self::Point(_) => None,
+            Self::Spot(light) => Some(light.direction),
+        }
+    }
+
+    /// Points a spot light along `direction`, point lights shine every way
+    /// and are left alone.
+    pub fn set_direction(&mut self, direction: Vector3<f32>) {
+        if let Self::Spot(light) = self {
+            light.direction = direction.normalize();
+        }
+    }
+
+    fn raw(&self) -> RawLight {
+        match *self {
+            Self::Point(light) => RawLight {
+                position: light.position,
+                intensity: light.intensity,
+                color: light.color,
+                range: light.radius,
+                direction: Vector3::unit_z(),
+                light_type: RawLight::POINT,
+                cos_inner: -1.0,
+                cos_outer: -1.0,
+                _padding: [0; 2],
+            },
+            Self::Spot(light) => RawLight {
+                position: light.position,
+                intensity: light.intensity,
+                color: light.color,
+                range: light.range,
+                direction: light.direction.normalize(),
+                light_type: RawLight::SPOT,
+                cos_inner: light.inner_angle.0.cos(),
+                // A hard edge rather than dividing by 0 in the falloff
+                cos_outer: light.outer_angle.0.max(light.inner_angle.0).cos(),
+                _padding: [0; 2],
+            },
+        }
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Self::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Self::Spot(light)
+    }
+}
+
+/// Every kind of light packed the same way, laid out to match `Light` in the
+/// shaders with `light_type` saying which fields are read.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct RawLight {
+    position: Vector3<f32>,
+    intensity: f32,
+    color: Vector3<f32>,
+    range: f32,
+    direction: Vector3<f32>,
+    light_type: u32,
+    cos_inner: f32,
+    cos_outer: f32,
+    _padding: [u32; 2],
+}
+
+impl RawLight {
+    const POINT: u32 = 0;
+    const SPOT: u32 = 1;
+}
+
+/// The count at the start of the buffer, padded to where the array of
+/// lights starts.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct LightsHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl LightsHeader {
+    fn new(count: usize) -> Self {
+        Self {
+            count: count as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Any number of point and spot lights in a storage buffer the shaders loop
+/// over, bound with the ambient light. Changes are uploaded together by
+/// [`LightsBuffer::update`].
+pub struct LightsBuffer {
+    lights: LightSlots,
+    /// How many lights fit in the buffer before it has to grow.
+    capacity: usize,
+    buffer: Buffer,
+    /// Outgrown buffers the frame in flight may still read, see
+    /// [`LightsBuffer::destroy_retired`].
+    retired_buffers: Vec<Buffer>,
+    ambient: AmbientLight,
+    ambient_buffer: Buffer,
+    bind_group: BindGroup,
+    bind_group_layout: BindGroupLayout,
+    /// Whether the lights changed since they were last uploaded.
+    dirty: bool,
+}
+
+impl LightsBuffer {
+    /// The lights are added in order, see [`LightsBuffer::ids`].
+    pub fn new(device: &Device, lights: &[Light]) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("[Lights] bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // The shaders need room for at least one light, even if it's unused
+        let capacity = lights.len().max(1);
+        let mut contents = bytemuck::bytes_of(&LightsHeader::new(lights.len())).to_vec();
+        let raw: Vec<RawLight> = lights.iter().map(Light::raw).collect();
+        contents.extend_from_slice(bytemuck::cast_slice(&raw));
+        contents.resize(Self::size(capacity) as usize, 0);
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("[Lights] buffer"),
+            contents: &contents,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let ambient = AmbientLight::default();
+        let ambient_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("[Lights] ambient buffer"),
+            contents: bytemuck::bytes_of(&ambient),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &buffer, &ambient_buffer);
+
+        let mut slots = LightSlots::default();
+        for &light in lights {
+            slots.insert(light);
+        }
+
+        Self {
+            lights: slots,
+            capacity,
+            buffer,
+            retired_buffers: vec![],
+            ambient,
+            ambient_buffer,
+            bind_group,
+            bind_group_layout,
+            dirty: false,
+        }
+    }
+
+    fn size(capacity: usize) -> BufferAddress {
+        (std::mem::size_of::<LightsHeader>() + std::mem::size_of::<RawLight>() * capacity)
+            as BufferAddress
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        buffer: &Buffer,
+        ambient_buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("[Lights] bind group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: ambient_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Uploads the lights, ambient included, if any changed.
+    pub fn update(&mut self, queue: &Queue) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        queue.write_buffer(&self.ambient_buffer, 0, bytemuck::bytes_of(&self.ambient));
+        let header = LightsHeader::new(self.lights.len());
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&header));
+        if !self.lights.is_empty() {
+            // Packed without gaps, whichever slots are free
+            let raw: Vec<RawLight> = self.lights.iter().map(|(_, light)| light.raw()).collect();
+            queue.write_buffer(
+                &self.buffer,
+                std::mem::size_of::<LightsHeader>() as BufferAddress,
+                bytemuck::cast_slice(&raw),
+            );
+        }
+    }
+
+    /// Ids of every light, in the order they're drawn in.
+    pub fn ids(&self) -> impl Iterator<Item = LightId> + '_ {
+        self.lights.iter().map(|(id, _)| id)
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.lights.get(id)
+    }
+
+    /// Access to change a light, which is uploaded on the next update
+    /// whether it's changed or not.
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        let light = self.lights.get_mut(id)?;
+        self.dirty = true;
+
+        Some(light)
+    }
+
+    /// Like [`LightsBuffer::get_mut`] for every light.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (LightId, &mut Light)> {
+        self.dirty = true;
+
+        self.lights.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Adds a light, reusing the slot of one that was removed if there is
+    /// one. A full buffer is replaced with one twice the size, which also
+    /// replaces the bind group.
+    pub fn add_light(&mut self, device: &Device, light: impl Into<Light>) -> LightId {
+        if self.lights.len() == self.capacity {
+            self.capacity *= 2;
+            let buffer = device.create_buffer(&BufferDescriptor {
+                label: Some("[Lights] buffer"),
+                size: Self::size(self.capacity),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.retired_buffers
+                .push(std::mem::replace(&mut self.buffer, buffer));
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.buffer,
+                &self.ambient_buffer,
+            );
+        }
+        self.dirty = true;
+
+        self.lights.insert(light.into())
+    }
+
+    /// Removes a light, those after it move down in the buffer but keep
+    /// their ids. The buffer keeps its size.
+    pub fn remove_light(&mut self, id: LightId) -> Option<Light> {
+        let light = self.lights.remove(id)?;
+        self.dirty = true;
+
+        Some(light)
+    }
+
+    /// Returns false if there's no light `id`.
+    pub fn set_light(&mut self, id: LightId, light: impl Into<Light>) -> bool {
+        let Some(existing) = self.get_mut(id) else {
+            return false;
+        };
+        *existing = light.into();
+
+        true
+    }
+
+    /// Destroys buffers left behind by [`LightsBuffer::add_light`] growing,
+    /// to be called once the frame that may still use them is submitted.
+    pub fn destroy_retired(&mut self) {
+        for buffer in self.retired_buffers.drain(..) {
+            buffer.destroy();
+        }
+    }
+
+    pub fn ambient(&self) -> AmbientLight {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: AmbientLight) {
+        self.ambient = ambient;
+        self.dirty = true;
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Light, LightsHeader, PointLight, RawLight, SpotLight};
+    use crate::vec3;
+    use cgmath::{assert_abs_diff_eq, Deg};
+    use std::ptr;
+
+    #[test]
+    fn aligned() {
+        assert_eq!(std::mem::size_of::<LightsHeader>(), 16);
+        assert_eq!(std::mem::size_of::<RawLight>(), 64);
+
+        let light = Light::from(PointLight::new(vec3!(1.0, 2.0, 3.0), vec3!(4.0, 5.0, 6.0))).raw();
+        let position_ptr = ptr::addr_of!(light.position).cast::<u8>();
+        let offsets = [
+            ptr::addr_of!(light.intensity).cast::<u8>(),
+            ptr::addr_of!(light.color).cast::<u8>(),
+            ptr::addr_of!(light.range).cast::<u8>(),
+            ptr::addr_of!(light.direction).cast::<u8>(),
+            ptr::addr_of!(light.light_type).cast::<u8>(),
+            ptr::addr_of!(light.cos_inner).cast::<u8>(),
+            ptr::addr_of!(light.cos_outer).cast::<u8>(),
+        ]
+        .map(|field| unsafe { field.offset_from(position_ptr) });
+        assert_eq!(offsets, [12, 16, 28, 32, 44, 48, 52]);
+    }
+
+    #[test]
+    fn packs_spot_lights() {
+        let spot = SpotLight::new(
+            vec3!(0.0, 5.0, 0.0),
+            vec3!(0.0, -2.0, 0.0),
+            vec3!(1.0, 1.0, 1.0),
+        )
+        .with_cone(Deg(40.0), Deg(30.0));
+        let raw = Light::from(spot).raw();
+
+        assert_eq!(raw.light_type, RawLight::SPOT);
+        assert_abs_diff_eq!(raw.direction, vec3!(0.0, -1.0, 0.0));
+        // The outer edge can't be inside the inner one
+        assert_abs_diff_eq!(raw.cos_inner, 40.0f32.to_radians().cos());
+        assert_abs_diff_eq!(raw.cos_outer, raw.cos_inner);
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn grows_and_empties() {
+        use super::LightsBuffer;
+
+        let (device, queue) = crate::texture::test_device();
+        let light = PointLight::new(vec3!(0.0, 1.0, 0.0), vec3!(1.0, 1.0, 1.0));
+        let mut lights = LightsBuffer::new(&device, &[light.into()]);
+
+        let ids: Vec<_> = (0..3).map(|_| lights.add_light(&device, light)).collect();
+        assert_eq!(lights.capacity, 4);
+        assert_eq!(lights.retired_buffers.len(), 2);
+        lights.update(&queue);
+        lights.destroy_retired();
+
+        // Emptied, the shaders read a count of 0 from a buffer still holding
+        // room for a light
+        for id in lights.ids().collect::<Vec<_>>() {
+            lights.remove_light(id);
+        }
+        assert!(lights.is_empty());
+        assert!(lights.get(ids[0]).is_none());
+        lights.update(&queue);
+        assert_eq!(lights.buffer.size(), LightsBuffer::size(4));
+    }
+}
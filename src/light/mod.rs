@@ -1,77 +1,123 @@
-use crate::model::{Mesh, Model};
-use std::ops::Range;
+use crate::{
+    draw_constants::{DrawConstants, DrawSlot},
+    model::{Mesh, Model},
+};
+use cgmath::{InnerSpace, Matrix3, Matrix4, Vector3};
 use wgpu::BindGroup;
 
+mod ambient;
+mod animation;
+mod directional;
+mod lights;
+mod slots;
 mod uniform;
 
-pub use uniform::{LightBundle, LightUniform};
+pub use ambient::AmbientLight;
+pub use animation::LightAnimation;
+pub use directional::{DirectionalLight, DirectionalLightBundle};
+pub use lights::{Light, LightsBuffer, PointLight, SpotLight};
+pub use slots::LightId;
 
-pub trait DrawLight<'a> {
-    fn draw_light_mesh(
-        &mut self,
-        mesh: &'a Mesh,
-        camera_bind_group: &'a BindGroup,
-        light_bind_group: &'a BindGroup,
-    ) {
-        self.draw_light_mesh_instanced(mesh, 0..1, camera_bind_group, light_bind_group);
-    }
+/// Half the marker cube's width.
+const MARKER_SIZE: f32 = 0.25;
 
-    fn draw_light_mesh_instanced(
+/// Draws a small cube at a light with its [`marker_transform`] and color
+/// from [`DrawConstants`]. Directional lights come from nowhere in
+/// particular, so they're never drawn.
+pub trait DrawLight<'a> {
+    fn draw_light_mesh_at(
         &mut self,
         mesh: &'a Mesh,
-        instances: Range<u32>,
+        slot: DrawSlot,
+        constants: &'a DrawConstants,
         camera_bind_group: &'a BindGroup,
-        light_bind_group: &'a BindGroup,
     );
 
-    fn draw_light_model(
-        &mut self,
-        model: &'a Model,
-        camera_bind_group: &'a BindGroup,
-        light_bind_group: &'a BindGroup,
-    ) {
-        self.draw_light_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
-    }
-
-    fn draw_light_model_instanced(
+    fn draw_light_at(
         &mut self,
         model: &'a Model,
-        instances: Range<u32>,
+        slot: DrawSlot,
+        constants: &'a DrawConstants,
         camera_bind_group: &'a BindGroup,
-        light_bind_group: &'a BindGroup,
     );
 }
 
 impl<'a> DrawLight<'a> for wgpu::RenderPass<'a> {
-    fn draw_light_mesh_instanced(
+    fn draw_light_mesh_at(
         &mut self,
         mesh: &'a Mesh,
-        instances: Range<u32>,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
+        slot: DrawSlot,
+        constants: &'a DrawConstants,
+        camera_bind_group: &'a BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, camera_bind_group, &[]);
-        self.set_bind_group(1, light_bind_group, &[]);
+        constants.set(self, 1, slot);
 
-        self.draw_indexed(0..mesh.element_count, 0, instances);
+        self.draw_indexed(0..mesh.element_count, 0, 0..1);
     }
 
-    fn draw_light_model_instanced(
+    fn draw_light_at(
         &mut self,
         model: &'a Model,
-        instances: Range<u32>,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
+        slot: DrawSlot,
+        constants: &'a DrawConstants,
+        camera_bind_group: &'a BindGroup,
     ) {
-        model.meshes.iter().for_each(|mesh| {
-            self.draw_light_mesh_instanced(
-                mesh,
-                instances.clone(),
-                camera_bind_group,
-                light_bind_group,
-            )
-        });
+        model
+            .lod_meshes(0)
+            .for_each(|mesh| self.draw_light_mesh_at(mesh, slot, constants, camera_bind_group));
+    }
+}
+
+/// Places a unit cube at the light scaled down, spot lights' stretched along
+/// where they shine.
+pub fn marker_transform(light: &Light) -> Matrix4<f32> {
+    let translation = Matrix4::from_translation(light.position());
+    let Some(forward) = light.direction() else {
+        return translation * Matrix4::from_scale(MARKER_SIZE);
+    };
+
+    let up = match forward.y.abs() > 0.99 {
+        true => Vector3::unit_x(),
+        false => Vector3::unit_y(),
+    };
+    let right = up.cross(forward).normalize();
+    let basis = Matrix3::from_cols(right, forward.cross(right), forward);
+
+    translation
+        * Matrix4::from(basis)
+        * Matrix4::from_nonuniform_scale(MARKER_SIZE, MARKER_SIZE, MARKER_SIZE * 2.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{marker_transform, PointLight, SpotLight, MARKER_SIZE};
+    use cgmath::{assert_abs_diff_eq, Vector3, Vector4};
+
+    #[test]
+    fn markers_sit_at_their_lights() {
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let point = PointLight::new(position, Vector3::new(1.0, 1.0, 1.0));
+        let transform = marker_transform(&point.into());
+        assert_eq!(
+            transform * Vector4::new(0.0, 0.0, 0.0, 1.0),
+            position.extend(1.0)
+        );
+        assert_eq!(
+            transform * Vector4::new(1.0, 1.0, 1.0, 1.0),
+            (position + Vector3::new(MARKER_SIZE, MARKER_SIZE, MARKER_SIZE)).extend(1.0)
+        );
+
+        // Stretched along the direction
+        let direction = Vector3::new(1.0, 0.0, 0.0);
+        let spot = SpotLight::new(position, direction, Vector3::new(1.0, 1.0, 1.0));
+        let transform = marker_transform(&spot.into());
+        assert_abs_diff_eq!(
+            transform * Vector4::new(0.0, 0.0, 1.0, 1.0),
+            (position + direction * MARKER_SIZE * 2.0).extend(1.0),
+            epsilon = 1e-6
+        );
     }
 }
@@ -0,0 +1,154 @@
+use super::Light;
+
+/// Names a light in a [`LightsBuffer`](super::LightsBuffer), staying valid
+/// while others are added and removed around it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LightId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Clone, Debug)]
+struct Slot {
+    /// Bumped whenever the slot is freed, so ids of the light that was in it
+    /// stop matching.
+    generation: u32,
+    light: Option<Light>,
+}
+
+/// The lights by id, reusing the slots of removed ones. Iterates in slot
+/// order, which is the order they're packed into the buffer in.
+#[derive(Clone, Debug, Default)]
+pub struct LightSlots {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl LightSlots {
+    pub fn insert(&mut self, light: Light) -> LightId {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.light = Some(light);
+
+                LightId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    light: Some(light),
+                });
+
+                LightId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        let slot = self.slot_mut(id)?;
+        let light = slot.light.take();
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        self.len -= 1;
+
+        light
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.light.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.slot_mut(id)?.light.as_mut()
+    }
+
+    fn slot_mut(&mut self, id: LightId) -> Option<&mut Slot> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation && slot.light.is_some())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = LightId {
+                index: index as u32,
+                generation: slot.generation,
+            };
+
+            slot.light.as_ref().map(|light| (id, light))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (LightId, &mut Light)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let id = LightId {
+                    index: index as u32,
+                    generation: slot.generation,
+                };
+
+                slot.light.as_mut().map(|light| (id, light))
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LightSlots;
+    use crate::{light::PointLight, vec3};
+
+    fn light(x: f32) -> PointLight {
+        PointLight::new(vec3!(x, 0.0, 0.0), vec3!(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn removed_slots_are_reused_with_new_ids() {
+        let mut slots = LightSlots::default();
+        let first = slots.insert(light(1.0).into());
+        let second = slots.insert(light(2.0).into());
+
+        assert!(slots.remove(first).is_some());
+        let third = slots.insert(light(3.0).into());
+        assert_ne!(third, first);
+        // The old id doesn't reach the light now in its slot
+        assert!(slots.get(first).is_none());
+        assert!(slots.remove(first).is_none());
+        assert_eq!(slots.get(third).unwrap().position().x, 3.0);
+        assert_eq!(slots.get(second).unwrap().position().x, 2.0);
+        assert_eq!(slots.len(), 2);
+    }
+
+    #[test]
+    fn removing_every_light_leaves_none() {
+        let mut slots = LightSlots::default();
+        let ids = [1.0, 2.0].map(|x| slots.insert(light(x).into()));
+        for id in ids {
+            slots.remove(id);
+        }
+
+        assert_eq!(slots.len(), 0);
+        assert!(slots.is_empty());
+        assert_eq!(slots.iter().count(), 0);
+    }
+}
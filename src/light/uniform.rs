@@ -1,88 +1,115 @@
+use super::{LightAnimation, LightId, LightsBuffer, PointLight};
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Deg, Quaternion, Rotation3, Vector3};
-use wgpu::{
-    util::{BufferInitDescriptor, DeviceExt},
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue,
-    ShaderStages,
-};
+use cgmath::Vector3;
+use std::time::Duration;
+use wgpu::{BindGroup, BindGroupLayout, Device, Queue};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
 pub struct LightUniform {
     position: Vector3<f32>,
-    _padding_1: u32,
+    /// Scales `color`, which stays the light's hue.
+    intensity: f32,
     color: Vector3<f32>,
-    _padding_2: u32,
+    _padding: u32,
 }
 
 impl LightUniform {
     pub fn new(position: Vector3<f32>, color: Vector3<f32>) -> Self {
         Self {
             position,
-            _padding_1: 0,
+            intensity: 1.0,
             color,
-            _padding_2: 0,
+            _padding: 0,
         }
     }
 
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+
+        self
+    }
+
     pub fn prepared(self, device: &Device) -> LightBundle {
-        let buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("([Light] buffer"),
-            contents: bytemuck::bytes_of(&self),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        });
-
-        // SAFETY: size_of::<LightUniform> will never be zero and if a uniform size is zero it shouldn't exist in the first place
-        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("[Light] bind group layout"),
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                    // min_binding_size: Some(unsafe {
-                    //     mem::transmute(mem::size_of::<LightUniform>())
-                    // }),
-                },
-                count: None,
-            }],
-        });
-
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("[Light] bind group"),
-            layout: &bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
-        });
+        let mut lights = LightsBuffer::new(device, &[]);
+        let id = lights.add_light(device, PointLight::from(self));
 
         LightBundle {
             uniform: self,
-            buffer,
-            bind_group,
-            bind_group_layout,
+            lights,
+            id,
+            animation: None,
+            animating: true,
+            dirty: false,
         }
     }
 }
 
+impl From<LightUniform> for PointLight {
+    fn from(uniform: LightUniform) -> Self {
+        PointLight::new(uniform.position, uniform.color).with_intensity(uniform.intensity)
+    }
+}
+
+/// A single light, kept for code written before [`LightsBuffer`] held any
+/// number of them.
 pub struct LightBundle {
-    pub uniform: LightUniform,
-    pub buffer: Buffer,
-    pub bind_group: BindGroup,
-    pub bind_group_layout: BindGroupLayout,
+    uniform: LightUniform,
+    pub lights: LightsBuffer,
+    id: LightId,
+    animation: Option<LightAnimation>,
+    /// Whether the animation runs, so it can be paused without losing it.
+    animating: bool,
+    /// Whether the light changed since it was last uploaded.
+    dirty: bool,
 }
 
 impl LightBundle {
-    pub fn update(&mut self, queue: &Queue) {
-        let old_position: Vector3<f32> = self.uniform.position.into();
-        self.uniform.position =
-            (Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), Deg(1.0)) * old_position).into();
+    pub fn uniform(&self) -> &LightUniform {
+        &self.uniform
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.uniform.position = position;
+        self.dirty = true;
+    }
+
+    pub fn set_color(&mut self, color: Vector3<f32>) {
+        self.uniform.color = color;
+        self.dirty = true;
+    }
+
+    /// Moves the light on every update, `None` leaving it where it is.
+    pub fn set_animation(&mut self, animation: Option<LightAnimation>) {
+        self.animation = animation;
+    }
+
+    /// Pauses or resumes the animation.
+    pub fn animation_enabled(&mut self, enabled: bool) {
+        self.animating = enabled;
+    }
+
+    /// Advances the animation by `dt` and uploads the light if it changed.
+    pub fn update(&mut self, queue: &Queue, dt: Duration) {
+        if let Some(animation) = self.animation.filter(|_| self.animating) {
+            self.set_position(animation.rotation(dt) * self.uniform.position);
+        }
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        self.lights
+            .set_light(self.id, PointLight::from(self.uniform));
+        self.lights.update(queue);
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        self.lights.bind_group()
+    }
 
-        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        self.lights.bind_group_layout()
     }
 }
 
@@ -100,7 +127,9 @@ mod test {
 
         let uniform = LightUniform::new(vec3!(1.0, 2.0, 3.0), vec3!(4.0, 5.0, 6.0));
         let position_ptr = ptr::addr_of!(uniform.position).cast::<u8>();
+        let intensity_ptr = ptr::addr_of!(uniform.intensity).cast::<u8>();
         let color_ptr = ptr::addr_of!(uniform.color).cast::<u8>();
+        assert_eq!(unsafe { intensity_ptr.offset_from(position_ptr) }, 12);
         assert_eq!(unsafe { color_ptr.offset_from(position_ptr) }, 16);
     }
 }
@@ -0,0 +1,213 @@
+//! Rays for picking and the terrain, and the noise terrain is generated
+//! from.
+
+use crate::model::Aabb;
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// A half-line from `origin`, with a unit `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// How far along the ray it enters the box, 0 when it starts inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        self.clip_aabb(aabb).map(|(near, _)| near)
+    }
+
+    /// How far along the ray it enters and leaves the box, entering at 0
+    /// when it starts inside.
+    pub fn clip_aabb(&self, aabb: &Aabb) -> Option<(f32, f32)> {
+        if aabb.is_empty() {
+            return None;
+        }
+
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let (origin, direction) = (self.origin[axis], self.direction[axis]);
+            let (min, max) = (aabb.min[axis], aabb.max[axis]);
+
+            // Parallel to the slab, it's either always in it or never
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+            if near > far {
+                return None;
+            }
+        }
+
+        Some((near, far))
+    }
+}
+
+/// Seeded gradient noise, Ken Perlin's improved noise in two dimensions.
+#[derive(Clone)]
+pub struct Perlin {
+    /// A shuffle of 0 to 255, repeated so lookups can run past the end.
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut state = seed;
+        for i in (1..table.len()).rev() {
+            let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        Self {
+            permutation: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    /// Between about -1 and 1, 0 on every integer lattice point.
+    pub fn noise(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (cell_x, cell_y) = ((x0 as i32 & 255) as usize, (y0 as i32 & 255) as usize);
+        let (x, y) = (x - x0, y - y0);
+        let hash = |i: usize, j: usize| self.permutation[self.permutation[i] as usize + j];
+
+        let (u, v) = (fade(x), fade(y));
+        let near = lerp(
+            u,
+            gradient(hash(cell_x, cell_y), x, y),
+            gradient(hash(cell_x + 1, cell_y), x - 1.0, y),
+        );
+        let far = lerp(
+            u,
+            gradient(hash(cell_x, cell_y + 1), x, y - 1.0),
+            gradient(hash(cell_x + 1, cell_y + 1), x - 1.0, y - 1.0),
+        );
+
+        lerp(v, near, far)
+    }
+
+    /// Fractal Brownian motion, `octaves` layers of noise each `lacunarity`
+    /// times the frequency and `persistence` times the amplitude of the one
+    /// before. Normalized back to about -1 to 1.
+    pub fn fbm(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, persistence: f32) -> f32 {
+        let (mut sum, mut total) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (1.0, 1.0);
+        for _ in 0..octaves {
+            sum += self.noise(x * frequency, y * frequency) * amplitude;
+            total += amplitude;
+            frequency *= lacunarity;
+            amplitude *= persistence;
+        }
+
+        match total > 0.0 {
+            true => sum / total,
+            false => 0.0,
+        }
+    }
+}
+
+/// Smooths the weights between lattice points, 6t^5 - 15t^4 + 10t^3.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, from: f32, to: f32) -> f32 {
+    from + t * (to - from)
+}
+
+/// Dots the offset with one of eight directions picked by `hash`.
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Perlin, Ray};
+    use crate::model::Aabb;
+    use cgmath::{assert_abs_diff_eq, Point3, Vector3};
+
+    #[test]
+    fn intersects_boxes() {
+        let unit = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -2.0));
+        assert_abs_diff_eq!(ray.direction, Vector3::new(0.0, 0.0, -1.0));
+        assert_abs_diff_eq!(ray.intersect_aabb(&unit).unwrap(), 4.0);
+
+        // Pointing away, passing beside it and starting inside
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(away.intersect_aabb(&unit), None);
+        let beside = Ray::new(Point3::new(2.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(beside.intersect_aabb(&unit), None);
+        let inside = Ray::new(Point3::new(0.5, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(inside.intersect_aabb(&unit), Some(0.0));
+
+        let diagonal = Ray::new(Point3::new(-3.0, -3.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+        let distance = diagonal.intersect_aabb(&unit).unwrap();
+        assert_abs_diff_eq!(
+            diagonal.at(distance),
+            Point3::new(-1.0, -1.0, 0.0),
+            epsilon = 1e-5
+        );
+        assert_eq!(diagonal.intersect_aabb(&Aabb::EMPTY), None);
+    }
+
+    #[test]
+    fn noise_is_seeded() {
+        let perlin = Perlin::new(7);
+        assert_eq!(perlin.noise(3.0, -2.0), 0.0);
+
+        let samples: Vec<f32> = (0..64)
+            .map(|i| perlin.noise(i as f32 * 0.37, i as f32 * -0.61))
+            .collect();
+        assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+        assert!(samples.iter().any(|&sample| sample != 0.0));
+
+        // The same for the same seed, different for another
+        let again = Perlin::new(7);
+        let other = Perlin::new(8);
+        assert!(samples
+            .iter()
+            .enumerate()
+            .all(|(i, &sample)| again.noise(i as f32 * 0.37, i as f32 * -0.61) == sample));
+        assert!(samples
+            .iter()
+            .enumerate()
+            .any(|(i, &sample)| other.noise(i as f32 * 0.37, i as f32 * -0.61) != sample));
+    }
+}
@@ -0,0 +1,147 @@
+use cgmath::{InnerSpace, Matrix4, Vector3};
+
+/// Axis-aligned bounding box. [`Aabb::EMPTY`] contains nothing and is the
+/// identity for [`Aabb::union`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    };
+
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points<I>(points: I) -> Self
+    where
+        I: IntoIterator<Item = Vector3<f32>>,
+    {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |bounds, point| bounds.extended(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn extended(&self, point: Vector3<f32>) -> Self {
+        Self {
+            min: component_min(self.min, point),
+            max: component_max(self.max, point),
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: component_min(self.min, other.min),
+            max: component_max(self.max, other.max),
+        }
+    }
+
+    /// How far `point` is from the nearest point in the box, 0 inside it.
+    pub fn distance(&self, point: Vector3<f32>) -> f32 {
+        let nearest = component_max(self.min, component_min(self.max, point));
+
+        (point - nearest).magnitude()
+    }
+
+    /// Bounds of this box's corners after `transform`, e.g. an instance's
+    /// world-space bounds from its model matrix.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+
+        Self::from_points((0..8).map(|corner| {
+            let point = Vector3::new(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+
+            (transform * point.extend(1.0)).truncate()
+        }))
+    }
+}
+
+fn component_min(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn component_max(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Aabb;
+    use cgmath::{Deg, Matrix4, Vector3};
+
+    #[test]
+    fn union() {
+        let a = Aabb::new(Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 1.0));
+        let b = Aabb::new(Vector3::new(0.5, -2.0, 0.5), Vector3::new(3.0, 0.0, 0.5));
+
+        let union = a.union(&b);
+        assert_eq!(union.min, Vector3::new(-1.0, -2.0, 0.0));
+        assert_eq!(union.max, Vector3::new(3.0, 1.0, 1.0));
+
+        assert_eq!(a.union(&Aabb::EMPTY), a);
+        assert!(Aabb::from_points([]).is_empty());
+    }
+
+    #[test]
+    fn distance() {
+        let bounds = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(bounds.distance(Vector3::new(0.5, 0.0, 0.0)), 0.0);
+        assert_eq!(bounds.distance(Vector3::new(4.0, 0.5, 0.0)), 3.0);
+        assert_eq!(bounds.distance(Vector3::new(4.0, 5.0, 1.0)), 5.0);
+    }
+
+    #[test]
+    fn transformed() {
+        let bounds = Aabb::new(Vector3::new(-1.0, -2.0, -3.0), Vector3::new(1.0, 2.0, 3.0));
+        let transform = Matrix4::from_translation(Vector3::new(10.0, 0.0, 0.0))
+            * Matrix4::from_angle_y(Deg(90.0));
+
+        let transformed = bounds.transformed(&transform);
+        let epsilon = 1e-5;
+        for (actual, expected) in [
+            (transformed.min, Vector3::new(7.0, -2.0, -1.0)),
+            (transformed.max, Vector3::new(13.0, 2.0, 1.0)),
+        ] {
+            assert!((actual.x - expected.x).abs() < epsilon);
+            assert!((actual.y - expected.y).abs() < epsilon);
+            assert!((actual.z - expected.z).abs() < epsilon);
+        }
+
+        assert!(Aabb::EMPTY.transformed(&transform).is_empty());
+    }
+}
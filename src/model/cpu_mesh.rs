@@ -0,0 +1,92 @@
+use super::{
+    compute_normals, compute_tangents_with_progress, missing_tangents, optimize, Aabb, ModelVertex,
+};
+
+/// Geometry in system memory, what a [`Mesh`](super::Mesh)'s buffers are
+/// uploaded from. Everything the loaders do to vertices happens here, so it
+/// runs without a device.
+#[derive(Clone, Debug, Default)]
+pub struct CpuMesh {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl CpuMesh {
+    pub fn new(vertices: Vec<ModelVertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|vertex| vertex.position.into()))
+    }
+
+    /// Whether the tangents were never filled in, see
+    /// [`missing_tangents`](super::missing_tangents).
+    pub fn missing_tangents(&self) -> bool {
+        missing_tangents(&self.vertices)
+    }
+
+    pub fn compute_tangents(&mut self) {
+        self.compute_tangents_with_progress(None);
+    }
+
+    pub fn compute_tangents_with_progress(&mut self, progress: Option<&dyn Fn(f32)>) {
+        compute_tangents_with_progress(&mut self.vertices, &self.indices, progress);
+    }
+
+    pub fn compute_normals(&mut self) {
+        compute_normals(&mut self.vertices, &self.indices);
+    }
+
+    /// Merges bit-identical vertices, see
+    /// [`deduplicate_vertices`](optimize::deduplicate_vertices).
+    pub fn deduplicate(&mut self) {
+        let (vertices, indices) = optimize::deduplicate_vertices(&self.vertices, &self.indices);
+        self.vertices = vertices;
+        self.indices = indices;
+    }
+
+    /// Deduplicates, then reorders the triangles for the vertex cache.
+    pub fn optimize(&mut self) {
+        self.deduplicate();
+        self.indices = optimize::optimize_vertex_cache(&self.indices, self.vertices.len());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CpuMesh;
+    use crate::model::primitives::cube_data;
+    use cgmath::Vector3;
+
+    #[test]
+    fn cpu_only_processing() {
+        let cube = cube_data(2.0).geometry;
+        let mut mesh = CpuMesh::new(
+            cube.indices
+                .iter()
+                .map(|&index| cube.vertices[index as usize])
+                .collect(),
+            (0..cube.indices.len() as u32).collect(),
+        );
+        for vertex in &mut mesh.vertices {
+            vertex.tangent = [0.0; 3];
+            vertex.bitangent = [0.0; 3];
+        }
+        assert!(mesh.missing_tangents());
+
+        // Every face corner is shared again once the duplicates merge
+        mesh.optimize();
+        assert_eq!(mesh.vertices.len(), cube.vertices.len());
+
+        mesh.compute_tangents();
+        assert!(!mesh.missing_tangents());
+        assert_eq!(mesh.triangle_count(), cube.triangle_count());
+        assert_eq!(mesh.bounds().min, Vector3::new(-1.0, -1.0, -1.0));
+        assert_eq!(mesh.bounds().max, Vector3::new(1.0, 1.0, 1.0));
+    }
+}
@@ -1,12 +1,37 @@
-use crate::Texture;
+use crate::texture::{cached_sampler, Texture};
 use bytemuck::{Pod, Zeroable};
-use std::ops::Range;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
     vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-    BindingResource, Buffer, BufferAddress, Device, RenderPass, VertexBufferLayout, VertexStepMode,
+    BindingResource, Buffer, BufferAddress, BufferUsages, Device, Features, Queue, RenderPass,
+    Sampler, VertexBufferLayout, VertexStepMode,
 };
 
+mod bounds;
+mod cpu_mesh;
+pub mod optimize;
+pub mod primitives;
 pub mod resource;
+mod stats;
+mod tangents;
+mod uniform;
+
+pub use bounds::Aabb;
+pub use cpu_mesh::CpuMesh;
+pub use stats::ModelStats;
+pub use tangents::{
+    compute_normals, compute_tangents, compute_tangents_with_progress, missing_tangents,
+};
+pub use uniform::MaterialUniform;
 
 pub trait VertexBufferFormat {
     type Attributes;
@@ -19,25 +44,320 @@ pub trait VertexBufferFormat {
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    /// Levels of detail, finest first. Empty when the model only has one.
+    pub lods: Vec<LodLevel>,
+    destroyed: AtomicBool,
+}
+
+/// Materials to draw individual meshes of a model with instead of their own,
+/// keyed by mesh index.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaterialOverrides(HashMap<usize, usize>);
+
+impl MaterialOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, mesh_index: usize, material_index: usize) {
+        self.0.insert(mesh_index, material_index);
+    }
+
+    pub fn remove(&mut self, mesh_index: usize) -> Option<usize> {
+        self.0.remove(&mesh_index)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Material index to draw a mesh with. Overrides pointing past the
+    /// model's materials, e.g. left over from a previous model, are ignored.
+    pub fn resolve(&self, mesh_index: usize, mesh_material: usize, material_count: usize) -> usize {
+        match self.0.get(&mesh_index) {
+            Some(&material) if material < material_count => material,
+            _ => mesh_material,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LodLevel {
+    pub mesh_indices: Vec<usize>,
+    /// Furthest distance from the camera this level is drawn at.
+    pub max_distance: f32,
+}
+
+/// Index of the first level covering `distance`, or the coarsest level when
+/// it's beyond all of them.
+pub fn select_lod(lods: &[LodLevel], distance: f32) -> usize {
+    lods.iter()
+        .position(|lod| distance <= lod.max_distance)
+        .unwrap_or(lods.len().saturating_sub(1))
+}
+
+impl Model {
+    pub fn from_meshes(meshes: Vec<Mesh>, materials: Vec<Material>) -> Self {
+        Self {
+            meshes,
+            materials,
+            lods: vec![],
+            destroyed: AtomicBool::new(false),
+        }
+    }
+
+    /// Frees the model's buffers and the textures only it references, rather
+    /// than waiting for the last handle to drop. Destroying twice is a no-op.
+    /// Work already submitted keeps the resources alive until it finishes,
+    /// but the model must not be recorded into any later pass.
+    pub fn destroy(&self) {
+        if self.destroyed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        for mesh in &self.meshes {
+            mesh.vertex_buffer.destroy();
+            mesh.index_buffer.destroy();
+            if let Some((line_buffer, _)) = &mesh.line_indices {
+                line_buffer.destroy();
+            }
+        }
+        for material in &self.materials {
+            material.uniform_buffer.destroy();
+        }
+
+        // Fallback and cached textures can be shared between materials and
+        // models, leave the ones someone else still holds
+        for (texture, references) in self.textures() {
+            if Arc::strong_count(texture) == references {
+                texture.destroy();
+            }
+        }
+    }
+
+    /// See [`Material::set_anisotropy`].
+    pub fn set_anisotropy(&mut self, device: &Device, layout: &BindGroupLayout, anisotropy: u16) {
+        for material in &mut self.materials {
+            material.set_anisotropy(device, layout, anisotropy);
+        }
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> ModelStats {
+        let mut stats = ModelStats::default();
+        for mesh in &self.meshes {
+            stats += mesh.stats();
+        }
+        for (texture, _) in self.textures() {
+            stats.texture_bytes += texture.byte_size();
+        }
+
+        stats
+    }
+
+    /// Each distinct texture the materials use and how many times they
+    /// reference it.
+    fn textures(&self) -> Vec<(&Arc<Texture>, usize)> {
+        let mut textures: Vec<(&Arc<Texture>, usize)> = vec![];
+        for material in &self.materials {
+            for texture in material.textures() {
+                match textures
+                    .iter_mut()
+                    .find(|(other, _)| Arc::ptr_eq(other, texture))
+                {
+                    Some((_, references)) => *references += 1,
+                    None => textures.push((texture, 1)),
+                }
+            }
+        }
+
+        textures
+    }
+
+    /// Meshes making up a level of detail, every mesh when there are no
+    /// levels.
+    pub fn lod_meshes(&self, level: usize) -> impl Iterator<Item = &Mesh> {
+        self.lod_mesh_indices(level)
+            .map(|index| &self.meshes[index])
+    }
+
+    pub fn lod_mesh_indices(&self, level: usize) -> impl Iterator<Item = usize> + '_ {
+        let mesh_indices = self.lods.get(level).map(|lod| lod.mesh_indices.as_slice());
+
+        (0..self.meshes.len())
+            .filter(move |index| mesh_indices.is_none_or(|indices| indices.contains(index)))
+    }
+
+    pub fn mesh(&self, name: &str) -> Option<&Mesh> {
+        self.meshes.iter().find(|mesh| mesh.name == name)
+    }
+
+    pub fn meshes_matching<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Mesh> {
+        self.meshes
+            .iter()
+            .filter(move |mesh| mesh.name.starts_with(prefix))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.lod_meshes(0)
+            .fold(Aabb::EMPTY, |bounds, mesh| bounds.union(&mesh.bounds))
+    }
+}
+
+/// Which shader a material is drawn with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaterialKind {
+    /// Blinn-Phong, from the diffuse and specular colors and shininess.
+    #[default]
+    Phong,
+    /// Metallic-roughness Cook-Torrance, in `pbr.wgsl`.
+    Pbr,
+}
+
+/// Every map a material binds, missing ones filled in with fallbacks that
+/// leave the uniform's values unchanged.
+#[derive(Clone, Debug)]
+pub struct MaterialTextures {
+    pub diffuse: Arc<Texture>,
+    pub normal: Arc<Texture>,
+    /// Roughness in green and metalness in blue, as glTF packs them.
+    pub metallic_roughness: Arc<Texture>,
+    /// Ambient occlusion in red.
+    pub occlusion: Arc<Texture>,
 }
 
 #[derive(Debug)]
 pub struct Material {
     pub name: String,
-    pub diffuse_texture: Texture,
-    pub normal_texture: Texture,
+    pub diffuse_texture: Arc<Texture>,
+    pub normal_texture: Arc<Texture>,
+    pub metallic_roughness_texture: Arc<Texture>,
+    pub occlusion_texture: Arc<Texture>,
+    pub uniform: MaterialUniform,
+    pub uniform_buffer: Buffer,
     pub bind_group: BindGroup,
+    pub kind: MaterialKind,
+    /// Set by [`Material::set_anisotropy`], overriding the textures' own.
+    anisotropy: Option<u16>,
 }
 
 impl Material {
     pub fn new(
         device: &Device,
         name: &str,
-        diffuse_texture: Texture,
-        normal_texture: Texture,
+        textures: MaterialTextures,
+        uniform: MaterialUniform,
+        kind: MaterialKind,
         layout: &BindGroupLayout,
     ) -> Self {
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Material buffer ({name})")),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let MaterialTextures {
+            diffuse,
+            normal,
+            metallic_roughness,
+            occlusion,
+        } = textures;
+        let textures = [&diffuse, &normal, &metallic_roughness, &occlusion];
+        let bind_group = Self::create_bind_group(
+            device,
+            name,
+            textures.map(|texture| texture.sampler.clone()),
+            textures.map(Arc::as_ref),
+            &uniform_buffer,
+            layout,
+        );
+
+        Self {
+            name: name.to_owned(),
+            diffuse_texture: diffuse,
+            normal_texture: normal,
+            metallic_roughness_texture: metallic_roughness,
+            occlusion_texture: occlusion,
+            uniform,
+            uniform_buffer,
+            bind_group,
+            kind,
+            anisotropy: None,
+        }
+    }
+
+    /// Diffuse, normal, metallic-roughness and occlusion, in binding order.
+    pub fn textures(&self) -> [&Arc<Texture>; 4] {
+        [
+            &self.diffuse_texture,
+            &self.normal_texture,
+            &self.metallic_roughness_texture,
+            &self.occlusion_texture,
+        ]
+    }
+
+    /// Uploads `uniform` in place of the material's own, to try out values
+    /// on a shared model. [`Material::uniform`] keeps what was loaded.
+    pub fn write_uniform(&self, queue: &Queue, uniform: &MaterialUniform) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(uniform));
+    }
+
+    /// Flagged for the blended pass rather than drawn with the opaque
+    /// geometry.
+    pub fn is_transparent(&self) -> bool {
+        self.uniform.is_transparent()
+    }
+
+    /// Rebinds every map with its texture's sampler options at another
+    /// anisotropy, the textures themselves are left as they are.
+    pub fn set_anisotropy(&mut self, device: &Device, layout: &BindGroupLayout, anisotropy: u16) {
+        self.anisotropy = Some(anisotropy);
+        self.refresh_bind_group(device, layout);
+    }
+
+    /// Rebuilds the bind group from the current textures, after replacing one
+    /// or a [`Texture::recreate`] that returned true.
+    pub fn refresh_bind_group(&mut self, device: &Device, layout: &BindGroupLayout) {
+        let samplers = self.textures().map(|texture| match self.anisotropy {
+            Some(anisotropy) => {
+                cached_sampler(device, texture.sampler_options.with_anisotropy(anisotropy))
+            }
+            None => texture.sampler.clone(),
+        });
+
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.name,
+            samplers,
+            self.textures().map(Arc::as_ref),
+            &self.uniform_buffer,
+            layout,
+        );
+    }
+
+    /// The maps are bound around the uniform at 4 so the Phong shaders'
+    /// bindings stay where they were, metallic-roughness at 5 and 6 and
+    /// occlusion at 7 and 8.
+    fn create_bind_group(
+        device: &Device,
+        name: &str,
+        samplers: [Arc<Sampler>; 4],
+        textures: [&Texture; 4],
+        uniform_buffer: &Buffer,
+        layout: &BindGroupLayout,
+    ) -> BindGroup {
+        let [diffuse_sampler, normal_sampler, metallic_roughness_sampler, occlusion_sampler] =
+            samplers;
+        let [diffuse_texture, normal_texture, metallic_roughness_texture, occlusion_texture] =
+            textures;
+
+        device.create_bind_group(&BindGroupDescriptor {
             label: Some(&format!("Texture bind group ({name})")),
             entries: &[
                 BindGroupEntry {
@@ -46,7 +366,7 @@ impl Material {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                    resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -54,18 +374,31 @@ impl Material {
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
+                    resource: wgpu::BindingResource::Sampler(&normal_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&metallic_roughness_texture.view),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::Sampler(&metallic_roughness_sampler),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(&occlusion_texture.view),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: BindingResource::Sampler(&occlusion_sampler),
                 },
             ],
             layout,
-        });
-
-        Self {
-            name: name.to_owned(),
-            diffuse_texture,
-            normal_texture,
-            bind_group,
-        }
+        })
     }
 }
 
@@ -75,7 +408,110 @@ pub struct Mesh {
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
     pub element_count: u32,
+    pub vertex_count: u32,
     pub material: usize,
+    pub bounds: Aabb,
+    /// The geometry the buffers were made from, kept only when loaded with
+    /// [`retain_cpu_data`](resource::LoadOptions::retain_cpu_data).
+    pub cpu_data: Option<CpuMesh>,
+    /// The triangles' edges as a line list and how many indices it has, for
+    /// wireframes on devices without [`Features::POLYGON_MODE_LINE`].
+    pub line_indices: Option<(Buffer, u32)>,
+}
+
+impl Mesh {
+    /// (Re)creates the GPU buffers for `mesh`, e.g. from a mesh's
+    /// [`cpu_data`](Self::cpu_data) after editing it.
+    pub fn upload(device: &Device, name: &str, mesh: &CpuMesh) -> Self {
+        Self::from_vertices(device, name, &mesh.vertices, &mesh.indices)
+    }
+
+    /// Uploads in-memory geometry. Tangents are computed when the caller
+    /// leaves them all zeroed, the mesh uses the model's first material.
+    pub fn from_vertices(
+        device: &Device,
+        name: &str,
+        vertices: &[ModelVertex],
+        indices: &[u32],
+    ) -> Self {
+        let mut computed;
+        let vertices = match missing_tangents(vertices) {
+            true => {
+                computed = vertices.to_vec();
+                compute_tangents(&mut computed, indices);
+                &computed
+            }
+            false => vertices,
+        };
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Vertex buffer ({name})")),
+            contents: bytemuck::cast_slice(vertices),
+            usage: BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Index buffer ({name})")),
+            contents: bytemuck::cast_slice(indices),
+            usage: BufferUsages::INDEX,
+        });
+        let line_indices = (!device.features().contains(Features::POLYGON_MODE_LINE)).then(|| {
+            let lines = line_list_indices(indices);
+            let buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(&format!("Line index buffer ({name})")),
+                contents: bytemuck::cast_slice(&lines),
+                usage: BufferUsages::INDEX,
+            });
+
+            (buffer, lines.len() as u32)
+        });
+
+        Self {
+            name: name.to_owned(),
+            vertex_buffer,
+            index_buffer,
+            element_count: indices.len() as u32,
+            vertex_count: vertices.len() as u32,
+            material: 0,
+            bounds: Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into())),
+            cpu_data: None,
+            line_indices,
+        }
+    }
+
+    /// The index buffer and count drawing the mesh's edges, with a
+    /// [`PrimitiveTopology::LineList`](wgpu::PrimitiveTopology::LineList)
+    /// pipeline when it has [`line_indices`](Self::line_indices) and a
+    /// [`PolygonMode::Line`](wgpu::PolygonMode::Line) one otherwise.
+    pub fn wireframe_indices(&self) -> (&Buffer, u32) {
+        match &self.line_indices {
+            Some((buffer, count)) => (buffer, *count),
+            None => (&self.index_buffer, self.element_count),
+        }
+    }
+
+    pub fn stats(&self) -> ModelStats {
+        ModelStats {
+            mesh_count: 1,
+            vertex_count: self.vertex_count as u64,
+            index_count: self.element_count as u64,
+            vertex_bytes: self.vertex_buffer.size(),
+            index_bytes: self.index_buffer.size(),
+            texture_bytes: 0,
+        }
+    }
+}
+
+/// Every edge of a triangle list once, as pairs of indices. Drawn as lines
+/// it's the triangles' wireframe.
+pub fn line_list_indices(triangles: &[u32]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    triangles
+        .chunks_exact(3)
+        .flat_map(|triangle| [[0, 1], [1, 2], [2, 0]].map(|[a, b]| (triangle[a], triangle[b])))
+        .filter(|&(a, b)| seen.insert((a.min(b), a.max(b))))
+        .flat_map(|(a, b)| [a, b])
+        .collect()
 }
 
 #[repr(C)]
@@ -87,16 +523,20 @@ pub struct ModelVertex {
 
     pub tangent: [f32; 3],
     pub bitangent: [f32; 3],
+    /// Multiplied into the diffuse color, white for formats without vertex
+    /// colors.
+    pub color: [f32; 4],
 }
 
 impl VertexBufferFormat for ModelVertex {
-    type Attributes = [wgpu::VertexAttribute; 5];
+    type Attributes = [wgpu::VertexAttribute; 6];
     const ATTRIBUTES: Self::Attributes = vertex_attr_array![
         0 => Float32x3,
         1 => Float32x2,
         2 => Float32x3,
         3 => Float32x3,
         4 => Float32x3,
+        5 => Float32x4,
     ];
 
     fn descriptor() -> VertexBufferLayout<'static> {
@@ -128,6 +568,17 @@ pub trait DrawModel<'a> {
         light_bind_group: &'a BindGroup,
     );
 
+    /// Like [`DrawModel::draw_mesh_instanced`], with the mesh's
+    /// [`wireframe_indices`](Mesh::wireframe_indices).
+    fn draw_mesh_wireframe_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        instances: Range<u32>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    );
+
     fn draw_model(
         &mut self,
         model: &'a Model,
@@ -153,6 +604,61 @@ pub trait DrawModel<'a> {
         camera_bind_group: &'a BindGroup,
         light_bind_group: &'a BindGroup,
     );
+
+    /// Draws the level of detail matching the instances' distance from the
+    /// camera, `instance_position` being their shared translation.
+    fn draw_model_lod(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        instance_position: Vector3<f32>,
+        camera_position: Point3<f32>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    );
+
+    fn draw_mesh_with_material_override(
+        &mut self,
+        model: &'a Model,
+        mesh_index: usize,
+        overrides: &MaterialOverrides,
+        instances: Range<u32>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    ) {
+        let mesh = &model.meshes[mesh_index];
+        let material = overrides.resolve(mesh_index, mesh.material, model.materials.len());
+
+        self.draw_mesh_instanced(
+            mesh,
+            &model.materials[material],
+            instances,
+            camera_bind_group,
+            light_bind_group,
+        );
+    }
+
+    /// Like [`DrawModel::draw_model_instanced`], with the overridden meshes
+    /// drawn using their replacement material.
+    fn draw_model_instanced_overridden(
+        &mut self,
+        model: &'a Model,
+        overrides: &MaterialOverrides,
+        instances: Range<u32>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    ) {
+        for mesh_index in model.lod_mesh_indices(0) {
+            self.draw_mesh_with_material_override(
+                model,
+                mesh_index,
+                overrides,
+                instances.clone(),
+                camera_bind_group,
+                light_bind_group,
+            );
+        }
+    }
 }
 
 impl<'a> DrawModel<'a> for RenderPass<'a> {
@@ -172,6 +678,23 @@ impl<'a> DrawModel<'a> for RenderPass<'a> {
         self.draw_indexed(0..mesh.element_count, 0, instances);
     }
 
+    fn draw_mesh_wireframe_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        instances: Range<u32>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    ) {
+        let (index_buffer, element_count) = mesh.wireframe_indices();
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
+        self.set_bind_group(2, light_bind_group, &[]);
+        self.draw_indexed(0..element_count, 0, instances);
+    }
+
     fn draw_model_instanced(
         &mut self,
         model: &'a Model,
@@ -179,7 +702,7 @@ impl<'a> DrawModel<'a> for RenderPass<'a> {
         camera_bind_group: &'a BindGroup,
         light_bind_group: &'a BindGroup,
     ) {
-        model.meshes.iter().for_each(move |mesh| {
+        model.lod_meshes(0).for_each(move |mesh| {
             let material = &model.materials[mesh.material];
             self.draw_mesh_instanced(
                 mesh,
@@ -199,7 +722,7 @@ impl<'a> DrawModel<'a> for RenderPass<'a> {
         camera_bind_group: &'a BindGroup,
         light_bind_group: &'a BindGroup,
     ) {
-        model.meshes.iter().for_each(move |mesh| {
+        model.lod_meshes(0).for_each(move |mesh| {
             self.draw_mesh_instanced(
                 mesh,
                 material,
@@ -209,4 +732,73 @@ impl<'a> DrawModel<'a> for RenderPass<'a> {
             )
         });
     }
+
+    fn draw_model_lod(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        instance_position: Vector3<f32>,
+        camera_position: Point3<f32>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    ) {
+        let distance = (instance_position - camera_position.to_vec()).magnitude();
+
+        model
+            .lod_meshes(select_lod(&model.lods, distance))
+            .for_each(move |mesh| {
+                let material = &model.materials[mesh.material];
+                self.draw_mesh_instanced(
+                    mesh,
+                    material,
+                    instances.clone(),
+                    camera_bind_group,
+                    light_bind_group,
+                );
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{line_list_indices, select_lod, LodLevel, MaterialOverrides};
+
+    #[test]
+    fn lod_distance_buckets() {
+        let lods = [10.0, 20.0, f32::INFINITY].map(|max_distance| LodLevel {
+            mesh_indices: vec![],
+            max_distance,
+        });
+
+        assert_eq!(select_lod(&lods, 0.0), 0);
+        assert_eq!(select_lod(&lods, 10.0), 0);
+        assert_eq!(select_lod(&lods, 10.5), 1);
+        assert_eq!(select_lod(&lods, 1000.0), 2);
+        assert_eq!(select_lod(&lods[..2], 1000.0), 1);
+        assert_eq!(select_lod(&[], 5.0), 0);
+    }
+
+    #[test]
+    fn material_override_resolution() {
+        let mut overrides = MaterialOverrides::new();
+        overrides.set(1, 2);
+        overrides.set(3, 7);
+
+        // Mesh 1 is overridden, every other mesh keeps its own material
+        assert_eq!(overrides.resolve(0, 0, 3), 0);
+        assert_eq!(overrides.resolve(1, 0, 3), 2);
+        assert_eq!(overrides.resolve(2, 1, 3), 1);
+        assert_eq!(overrides.resolve(3, 1, 3), 1);
+
+        overrides.remove(1);
+        assert_eq!(overrides.resolve(1, 0, 3), 0);
+    }
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        // A quad split along its diagonal, 1 to 2
+        let lines = line_list_indices(&[0, 2, 1, 1, 2, 3]);
+        assert_eq!(lines, [0, 2, 2, 1, 1, 0, 2, 3, 3, 1]);
+        assert!(line_list_indices(&[0, 1]).is_empty());
+    }
 }
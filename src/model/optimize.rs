@@ -0,0 +1,173 @@
+//! Import-time index buffer cleanup, see [`LoadOptions::optimize`].
+//!
+//! [`LoadOptions::optimize`]: super::resource::LoadOptions::optimize
+
+use super::ModelVertex;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    mem,
+};
+
+/// Post-transform cache size the reordering assumes, small enough to hold on
+/// pretty much any hardware.
+const CACHE_SIZE: usize = 16;
+
+const VERTEX_WORDS: usize = mem::size_of::<ModelVertex>() / 4;
+
+/// Merges bit-identical vertices and rewrites the indices to match, keeping
+/// the first occurrence's position in the buffer.
+pub fn deduplicate_vertices(
+    vertices: &[ModelVertex],
+    indices: &[u32],
+) -> (Vec<ModelVertex>, Vec<u32>) {
+    let mut unique = Vec::with_capacity(vertices.len());
+    let mut remap = HashMap::with_capacity(vertices.len());
+
+    let indices = indices
+        .iter()
+        .map(|&index| {
+            let vertex = vertices[index as usize];
+            match remap.entry(bytemuck::cast::<_, [u32; VERTEX_WORDS]>(vertex)) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    unique.push(vertex);
+                    *entry.insert(unique.len() as u32 - 1)
+                }
+            }
+        })
+        .collect();
+
+    (unique, indices)
+}
+
+/// Reorders triangles for post-transform vertex cache hits with Sander et
+/// al.'s tipsify, fanning around the vertex most likely to still be cached.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    let mut adjacency = vec![vec![]; vertex_count];
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &vertex in corners {
+            adjacency[vertex as usize].push(triangle);
+        }
+    }
+
+    let mut live = adjacency.iter().map(Vec::len).collect::<Vec<_>>();
+    let mut cache_time = vec![0; vertex_count];
+    let mut emitted = vec![false; triangle_count];
+    let mut dead_ends = vec![];
+    let mut output = Vec::with_capacity(indices.len());
+
+    let mut time = CACHE_SIZE + 1;
+    let mut cursor = 0;
+    let mut fanning = (vertex_count > 0).then_some(0);
+
+    while let Some(vertex) = fanning {
+        let mut candidates = vec![];
+
+        for &triangle in &adjacency[vertex] {
+            if emitted[triangle] {
+                continue;
+            }
+            emitted[triangle] = true;
+
+            for &corner in &indices[triangle * 3..triangle * 3 + 3] {
+                let corner = corner as usize;
+                output.push(corner as u32);
+                dead_ends.push(corner);
+                candidates.push(corner);
+                live[corner] -= 1;
+
+                if time - cache_time[corner] > CACHE_SIZE {
+                    cache_time[corner] = time;
+                    time += 1;
+                }
+            }
+        }
+
+        // Prefer a candidate whose remaining triangles still fit in the cache,
+        // the longer it's been cached the sooner it would fall out
+        fanning = candidates
+            .iter()
+            .filter(|&&candidate| live[candidate] > 0)
+            .map(|&candidate| {
+                let age = time - cache_time[candidate];
+                let priority = match age + 2 * live[candidate] <= CACHE_SIZE {
+                    true => age,
+                    false => 0,
+                };
+
+                (priority, candidate)
+            })
+            .max_by_key(|&(priority, _)| priority)
+            .map(|(_, candidate)| candidate)
+            .or_else(|| {
+                while let Some(dead_end) = dead_ends.pop() {
+                    if live[dead_end] > 0 {
+                        return Some(dead_end);
+                    }
+                }
+
+                while cursor < vertex_count {
+                    if live[cursor] > 0 {
+                        return Some(cursor);
+                    }
+                    cursor += 1;
+                }
+
+                None
+            });
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deduplicate_vertices, optimize_vertex_cache};
+    use crate::model::{primitives::uv_sphere_data, ModelVertex};
+
+    #[test]
+    fn deduplicated_quad() {
+        let corner = |x: f32, y: f32| ModelVertex {
+            position: [x, y, 0.0],
+            texture_coordinates: [x, y],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0],
+            bitangent: [0.0, -1.0, 0.0],
+            color: [1.0; 4],
+        };
+        let exploded = [
+            corner(0.0, 0.0),
+            corner(1.0, 0.0),
+            corner(1.0, 1.0),
+            corner(0.0, 0.0),
+            corner(1.0, 1.0),
+            corner(0.0, 1.0),
+        ];
+
+        let (vertices, indices) = deduplicate_vertices(&exploded, &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+
+        for (&index, original) in indices.iter().zip(&exploded) {
+            assert_eq!(vertices[index as usize].position, original.position);
+        }
+    }
+
+    #[test]
+    fn reordering_keeps_triangles() {
+        let sphere = uv_sphere_data(1.0, 12, 8);
+        let reordered =
+            optimize_vertex_cache(&sphere.geometry.indices, sphere.geometry.vertices.len());
+
+        let sorted = |indices: &[u32]| {
+            let mut triangles = indices.chunks(3).map(<[u32]>::to_vec).collect::<Vec<_>>();
+            triangles.sort();
+            triangles
+        };
+
+        // Triangles keep their winding, only their order changes
+        assert_eq!(sorted(&reordered), sorted(&sphere.geometry.indices));
+    }
+}
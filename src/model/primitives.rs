@@ -0,0 +1,264 @@
+//! Procedural meshes, generated with analytic normals and tangents so they
+//! light the same way as loaded models. Bitangents point against the texture
+//! v axis, matching [`compute_tangents`](super::compute_tangents).
+
+use super::{resource::MeshData, CpuMesh, Mesh, ModelVertex};
+use cgmath::{InnerSpace, Vector3};
+use std::f32::consts::{PI, TAU};
+use wgpu::Device;
+
+pub fn cube(device: &Device, size: f32) -> Mesh {
+    cube_data(size).upload(device, false)
+}
+
+pub fn uv_sphere(device: &Device, radius: f32, segments: u32, rings: u32) -> Mesh {
+    uv_sphere_data(radius, segments, rings).upload(device, false)
+}
+
+pub fn plane(device: &Device, width: f32, depth: f32, subdivisions: u32) -> Mesh {
+    plane_data(width, depth, subdivisions).upload(device, false)
+}
+
+pub fn cylinder(device: &Device, radius: f32, height: f32, segments: u32) -> Mesh {
+    cylinder_data(radius, height, segments).upload(device, false)
+}
+
+/// Cube centered on the origin with edges of length `size`, one texture per
+/// face.
+pub fn cube_data(size: f32) -> MeshData {
+    const FACES: [([f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0]),
+    ];
+
+    let half_extent = size / 2.0;
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for (normal, tangent) in FACES {
+        let normal = Vector3::from(normal);
+        let tangent = Vector3::from(tangent);
+        let up = normal.cross(tangent);
+        let first = vertices.len() as u32;
+
+        for (u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            vertices.push(ModelVertex {
+                position: ((normal + tangent * u + up * v) * half_extent).into(),
+                texture_coordinates: [(u + 1.0) / 2.0, (1.0 - v) / 2.0],
+                normal: normal.into(),
+                tangent: tangent.into(),
+                bitangent: up.into(),
+                color: [1.0; 4],
+            });
+        }
+
+        indices.extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+    }
+
+    mesh_data("Cube", vertices, indices)
+}
+
+/// Sphere centered on the origin, `segments` around the equator and `rings`
+/// from pole to pole. The seam column is duplicated so UVs wrap cleanly.
+pub fn uv_sphere_data(radius: f32, segments: u32, rings: u32) -> MeshData {
+    let segments = segments.max(3);
+    let rings = rings.max(2);
+
+    let mut vertices = vec![];
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let (sin_phi, cos_phi) = (v * PI).sin_cos();
+
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (u * TAU).sin_cos();
+            let normal = Vector3::new(sin_phi * cos_theta, cos_phi, -sin_phi * sin_theta);
+
+            vertices.push(ModelVertex {
+                position: (normal * radius).into(),
+                texture_coordinates: [u, v],
+                normal: normal.into(),
+                tangent: [-sin_theta, 0.0, -cos_theta],
+                bitangent: [-cos_phi * cos_theta, sin_phi, cos_phi * sin_theta],
+                color: [1.0; 4],
+            });
+        }
+    }
+
+    let columns = segments + 1;
+    let mut indices = vec![];
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let top_left = ring * columns + segment;
+            let bottom_left = top_left + columns;
+
+            // The triangle touching a pole collapses to a line, leave it out
+            if ring != rings - 1 {
+                indices.extend([top_left, bottom_left, bottom_left + 1]);
+            }
+            if ring != 0 {
+                indices.extend([top_left, bottom_left + 1, top_left + 1]);
+            }
+        }
+    }
+
+    mesh_data("UV sphere", vertices, indices)
+}
+
+/// Plane facing up along +y, centered on the origin, split into
+/// `subdivisions` cells along each side.
+pub fn plane_data(width: f32, depth: f32, subdivisions: u32) -> MeshData {
+    let subdivisions = subdivisions.max(1);
+
+    let mut vertices = vec![];
+    for row in 0..=subdivisions {
+        let v = row as f32 / subdivisions as f32;
+
+        for column in 0..=subdivisions {
+            let u = column as f32 / subdivisions as f32;
+
+            vertices.push(ModelVertex {
+                position: [(u - 0.5) * width, 0.0, (v - 0.5) * depth],
+                texture_coordinates: [u, v],
+                normal: [0.0, 1.0, 0.0],
+                tangent: [1.0, 0.0, 0.0],
+                bitangent: [0.0, 0.0, -1.0],
+                color: [1.0; 4],
+            });
+        }
+    }
+
+    let columns = subdivisions + 1;
+    let mut indices = vec![];
+    for row in 0..subdivisions {
+        for column in 0..subdivisions {
+            let near_left = row * columns + column;
+            let far_left = near_left + columns;
+
+            indices.extend([near_left, far_left, far_left + 1]);
+            indices.extend([near_left, far_left + 1, near_left + 1]);
+        }
+    }
+
+    mesh_data("Plane", vertices, indices)
+}
+
+/// Capped cylinder standing on the y axis, centered on the origin.
+pub fn cylinder_data(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let half_height = height / 2.0;
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+
+    // Side, the seam column is duplicated like the sphere's
+    for (y, v) in [(half_height, 0.0), (-half_height, 1.0)] {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (u * TAU).sin_cos();
+
+            vertices.push(ModelVertex {
+                position: [radius * cos_theta, y, -radius * sin_theta],
+                texture_coordinates: [u, v],
+                normal: [cos_theta, 0.0, -sin_theta],
+                tangent: [-sin_theta, 0.0, -cos_theta],
+                bitangent: [0.0, 1.0, 0.0],
+                color: [1.0; 4],
+            });
+        }
+    }
+
+    let columns = segments + 1;
+    for segment in 0..segments {
+        let top = segment;
+        let bottom = top + columns;
+
+        indices.extend([top, bottom, bottom + 1]);
+        indices.extend([top, bottom + 1, top + 1]);
+    }
+
+    // Caps are mapped top down, so the bottom's v axis is mirrored
+    for (y, facing) in [(half_height, 1.0), (-half_height, -1.0)] {
+        let center = vertices.len() as u32;
+        let cap_vertex = |x: f32, z: f32| ModelVertex {
+            position: [x, y, z],
+            texture_coordinates: [0.5 + x / (2.0 * radius), 0.5 + facing * z / (2.0 * radius)],
+            normal: [0.0, facing, 0.0],
+            tangent: [1.0, 0.0, 0.0],
+            bitangent: [0.0, 0.0, -facing],
+            color: [1.0; 4],
+        };
+
+        vertices.push(cap_vertex(0.0, 0.0));
+        for segment in 0..segments {
+            let (sin_theta, cos_theta) = (segment as f32 / segments as f32 * TAU).sin_cos();
+            vertices.push(cap_vertex(radius * cos_theta, -radius * sin_theta));
+        }
+
+        for segment in 0..segments {
+            let current = center + 1 + segment;
+            let next = center + 1 + (segment + 1) % segments;
+
+            if facing > 0.0 {
+                indices.extend([center, current, next]);
+            } else {
+                indices.extend([center, next, current]);
+            }
+        }
+    }
+
+    mesh_data("Cylinder", vertices, indices)
+}
+
+fn mesh_data(name: &str, vertices: Vec<ModelVertex>, indices: Vec<u32>) -> MeshData {
+    debug_assert!(vertices
+        .iter()
+        .all(|vertex| (Vector3::from(vertex.normal).magnitude() - 1.0).abs() < 1e-4));
+
+    MeshData {
+        name: name.to_owned(),
+        geometry: CpuMesh::new(vertices, indices),
+        material: 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cube_data, cylinder_data, plane_data, uv_sphere_data};
+    use cgmath::{InnerSpace, Vector3};
+
+    #[test]
+    fn winding_is_ccw() {
+        for mesh in [
+            uv_sphere_data(1.0, 16, 8),
+            cube_data(1.0),
+            plane_data(2.0, 3.0, 4),
+            cylinder_data(0.5, 2.0, 12),
+        ] {
+            for triangle in mesh.geometry.indices.chunks(3) {
+                let [a, b, c] =
+                    [0, 1, 2].map(|corner| mesh.geometry.vertices[triangle[corner] as usize]);
+                let face = (Vector3::from(b.position) - Vector3::from(a.position))
+                    .cross(Vector3::from(c.position) - Vector3::from(a.position));
+                let normal =
+                    Vector3::from(a.normal) + Vector3::from(b.normal) + Vector3::from(c.normal);
+
+                // Counter-clockwise seen from outside means the face normal
+                // points the same way as the vertex normals
+                assert!(
+                    face.magnitude() > 0.0,
+                    "{} has a degenerate triangle",
+                    mesh.name
+                );
+                assert!(
+                    face.dot(normal) > 0.0,
+                    "{} has a clockwise triangle",
+                    mesh.name
+                );
+            }
+        }
+    }
+}
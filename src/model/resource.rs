@@ -1,194 +1,2113 @@
-use super::{Material, Mesh, Model, ModelVertex};
-use crate::Texture;
+use super::{
+    compute_normals, primitives, Aabb, CpuMesh, LodLevel, Material, MaterialKind, MaterialTextures,
+    MaterialUniform, Mesh, Model, ModelStats, ModelVertex,
+};
+use crate::texture::{self, SamplerOptions, Texture, TextureData, TextureKind};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, One, Quaternion, SquareMatrix, Vector3};
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, RgbImage, RgbaImage};
+use ply_rs::ply::Property;
 use std::{
-    env, fs,
-    io::{self, BufReader, Cursor},
+    collections::{hash_map::Entry, HashMap},
+    env,
+    ffi::OsStr,
+    fmt, fs,
+    hash::Hash,
+    io::{self, BufRead, BufReader, Cursor},
+    mem,
     path::{Path, PathBuf},
-    sync::{Mutex, OnceLock},
-};
-use tobj::LoadOptions;
-use wgpu::{
-    util::{BufferInitDescriptor, DeviceExt},
-    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, BufferUsages, Device,
-    Queue,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
+use thiserror::Error;
+use wgpu::{BindGroupLayout, Device, Queue};
+
+/// How far from the camera the full detail level of a model is drawn.
+const LOD_BASE_DISTANCE: f32 = 10.0;
 
 pub fn resource_directory() -> io::Result<&'static PathBuf> {
     static RESOURCE_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
 
-    Ok(RESOURCE_DIRECTORY.get_or_init(|| PathBuf::from(env::var("RESOURCE_DIRECTORY").unwrap())))
+    // The build script bakes the copied resource directory in at compile time,
+    // the environment variable only overrides it
+    Ok(RESOURCE_DIRECTORY.get_or_init(|| {
+        env::var_os("RESOURCE_DIRECTORY")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(env!("RESOURCE_DIRECTORY")))
+    }))
 }
 
 pub fn load_texture(
     file_name: &str,
-    is_normal_map: bool,
+    kind: TextureKind,
+    sampler: SamplerOptions,
     device: &Device,
     queue: &Queue,
-) -> io::Result<Texture> {
-    Ok(Texture::from_bytes(
+) -> ModelResult<Texture> {
+    Ok(Texture::from_data(
         device,
         queue,
-        &fs::read(resource_directory()?.join(file_name))?,
-        None,
-        is_normal_map,
-    ))
+        &read_texture(file_name)?,
+        Some(file_name),
+        kind,
+        sampler,
+    )?)
+}
+
+/// Loads a cube texture from a single equirectangular image, or from
+/// `px`/`nx`/`py`/`ny`/`pz`/`nz` images of any format either inside a
+/// directory or sharing a prefix, e.g. `sky/` or `sky_` for `sky_px.png`.
+pub fn load_cubemap(dir_or_prefix: &str, device: &Device, queue: &Queue) -> ModelResult<Texture> {
+    let path = resource_directory()?.join(dir_or_prefix);
+    let label = Some(dir_or_prefix);
+    if path.is_file() {
+        let image = image::load_from_memory(&fs::read(&path)?)?;
+        return Ok(Texture::cubemap_from_equirectangular(
+            device, queue, &image, label,
+        )?);
+    }
+
+    let (directory, prefix) = match path.is_dir() {
+        true => (path.as_path(), ""),
+        false => (
+            path.parent().unwrap_or(&path),
+            path.file_name().and_then(OsStr::to_str).unwrap_or_default(),
+        ),
+    };
+    let faces = texture::CUBEMAP_FACES
+        .iter()
+        .map(|face| read_cubemap_face(directory, &format!("{prefix}{face}")))
+        .collect::<ModelResult<Vec<_>>>()?;
+    let [px, nx, py, ny, pz, nz] = faces.as_slice() else {
+        unreachable!("one image per face");
+    };
+
+    Ok(Texture::cubemap_from_images(
+        device,
+        queue,
+        [px, nx, py, ny, pz, nz],
+        label,
+    )?)
 }
 
+fn read_cubemap_face(directory: &Path, stem: &str) -> ModelResult<DynamicImage> {
+    let path = fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| path.is_file() && path.file_stem().and_then(OsStr::to_str) == Some(stem))
+        .ok_or_else(|| ModelError::MissingCubemapFace(directory.join(stem)))?;
+
+    Ok(image::load_from_memory(&fs::read(path)?)?)
+}
+
+/// Import transform baked into a model's vertices when it's read, for assets
+/// authored at another scale or with another up axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadOptions {
+    pub scale: f32,
+    pub rotation: Quaternion<f32>,
+    /// Mirrors texture coordinates vertically, for exporters whose textures
+    /// come out upside down in wgpu's coordinate system.
+    pub flip_uv_y: bool,
+    /// Merges duplicate vertices and reorders triangles for the vertex
+    /// cache. OBJs benefit the most, every face corner arrives as its own
+    /// vertex.
+    pub optimize: bool,
+    /// Keeps each mesh's [`CpuMesh`] around after uploading it, for
+    /// collision, picking or re-uploading. Doubles the memory a model takes.
+    pub retain_cpu_data: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            rotation: Quaternion::one(),
+            flip_uv_y: false,
+            optimize: false,
+            retain_cpu_data: false,
+        }
+    }
+}
+
+impl LoadOptions {
+    fn transforms(&self) -> bool {
+        self.scale != 1.0 || self.rotation != Quaternion::one() || self.flip_uv_y
+    }
+}
+
+/// The stages of a model load, reported alongside the fraction of the
+/// current stage that's done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadPhase {
+    Parse,
+    Textures,
+    Tangents,
+    Optimize,
+    Upload,
+}
+
+impl fmt::Display for LoadPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Parse => "parsing",
+            Self::Textures => "textures",
+            Self::Tangents => "tangents",
+            Self::Optimize => "optimizing",
+            Self::Upload => "uploading",
+        })
+    }
+}
+
+/// Optional progress callback threaded through the loaders, `None` costs a
+/// branch per report.
+fn report(progress: Option<&dyn Fn(LoadPhase, f32)>, phase: LoadPhase, fraction: f32) {
+    if let Some(progress) = progress {
+        progress(phase, fraction);
+    }
+}
+
+/// Reads and uploads a model, reporting each phase to `progress`. Uploads
+/// are reported between buffers, never while the queue is submitting.
 pub fn load_model(
     file_name: &str,
+    options: &LoadOptions,
+    progress: Option<&dyn Fn(LoadPhase, f32)>,
     device: &Device,
     queue: &Queue,
     layout: &BindGroupLayout,
-) -> io::Result<Model> {
+) -> ModelResult<Model> {
+    Ok(read_model(file_name, options, progress)?.upload(
+        device,
+        queue,
+        layout,
+        options.retain_cpu_data,
+        progress,
+    ))
+}
+
+/// Keeps loaded models and textures by canonical path, so loading the same
+/// file for several purposes shares one parse and GPU upload.
+#[derive(Debug, Default)]
+pub struct ResourceCache {
+    models: HashMap<PathBuf, Arc<Model>>,
+    textures: HashMap<(PathBuf, TextureKind, SamplerOptions), Arc<Texture>>,
+}
+
+impl ResourceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options only apply to the first load of a file, later calls return
+    /// the cached model as it was imported then.
+    pub fn load_model_cached(
+        &mut self,
+        file_name: &str,
+        options: &LoadOptions,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+    ) -> ModelResult<Arc<Model>> {
+        get_or_load(&mut self.models, cache_key(file_name)?, || {
+            load_model(file_name, options, None, device, queue, layout)
+        })
+    }
+
+    /// Caches a model uploaded elsewhere, e.g. from [`load_model_async`],
+    /// replacing any previous entry for the file.
+    pub fn insert_model(
+        &mut self,
+        file_name: &str,
+        model: impl Into<Arc<Model>>,
+    ) -> ModelResult<Arc<Model>> {
+        let model = model.into();
+        self.models.insert(cache_key(file_name)?, model.clone());
+
+        Ok(model)
+    }
+
+    /// Textures are keyed by their sampler options too, the same file
+    /// clamped and repeated is two entries.
+    pub fn load_texture_cached(
+        &mut self,
+        file_name: &str,
+        kind: TextureKind,
+        sampler: SamplerOptions,
+        device: &Device,
+        queue: &Queue,
+    ) -> ModelResult<Arc<Texture>> {
+        get_or_load(
+            &mut self.textures,
+            (cache_key(file_name)?, kind, sampler),
+            || load_texture(file_name, kind, sampler, device, queue),
+        )
+    }
+
+    pub fn evict_model(&mut self, file_name: &str) -> Option<Arc<Model>> {
+        self.models.remove(&cache_key(file_name).ok()?)
+    }
+
+    pub fn evict_texture(
+        &mut self,
+        file_name: &str,
+        kind: TextureKind,
+        sampler: SamplerOptions,
+    ) -> Option<Arc<Texture>> {
+        self.textures
+            .remove(&(cache_key(file_name).ok()?, kind, sampler))
+    }
+
+    /// Drops the cache's references, resources still held elsewhere stay alive.
+    pub fn clear(&mut self) {
+        self.models.clear();
+        self.textures.clear();
+    }
+}
+
+fn cache_key(file_name: &str) -> io::Result<PathBuf> {
+    fs::canonicalize(resource_directory()?.join(file_name))
+}
+
+fn get_or_load<K, V, F>(entries: &mut HashMap<K, Arc<V>>, key: K, load: F) -> ModelResult<Arc<V>>
+where
+    K: Eq + Hash,
+    F: FnOnce() -> ModelResult<V>,
+{
+    match entries.entry(key) {
+        Entry::Occupied(entry) => Ok(entry.get().clone()),
+        Entry::Vacant(entry) => Ok(entry.insert(Arc::new(load()?)).clone()),
+    }
+}
+
+/// Parses the model and decodes its textures on a worker thread. The result
+/// still has to be uploaded from the thread owning the device once
+/// [`PendingModel::poll`] yields it.
+pub fn load_model_async(file_name: &str, options: LoadOptions) -> PendingModel {
+    let (sender, receiver) = mpsc::channel();
+    let (progress_sender, progress_receiver) = mpsc::channel();
+    let worker_file_name = file_name.to_owned();
+
+    thread::spawn(move || {
+        // The receiver going away just means nobody wants the model anymore
+        let report = |phase, fraction| {
+            let _ = progress_sender.send((phase, fraction));
+        };
+        let _ = sender.send(read_model(&worker_file_name, &options, Some(&report)));
+    });
+
+    PendingModel {
+        file_name: file_name.to_owned(),
+        options,
+        receiver,
+        progress_receiver,
+    }
+}
+
+#[derive(Debug)]
+pub struct PendingModel {
+    pub file_name: String,
+    pub options: LoadOptions,
+    receiver: Receiver<ModelResult<ModelData>>,
+    progress_receiver: Receiver<(LoadPhase, f32)>,
+}
+
+impl PendingModel {
+    /// Returns the loaded data once the worker finishes, without blocking.
+    pub fn poll(&self) -> Option<ModelResult<ModelData>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(ModelError::LoaderExited(self.file_name.clone())))
+            }
+        }
+    }
+
+    /// The newest progress the worker reported since the last call, if any.
+    pub fn progress(&self) -> Option<(LoadPhase, f32)> {
+        self.progress_receiver.try_iter().last()
+    }
+}
+
+/// Notices changes under a directory by polling modification times, cheap
+/// enough for a resource directory and free of platform watcher quirks.
+#[derive(Debug)]
+pub struct ResourceWatcher {
+    directory: PathBuf,
+    interval: Duration,
+    last_poll: Instant,
+    last_modified: Option<SystemTime>,
+}
+
+impl ResourceWatcher {
+    pub fn new(directory: PathBuf, interval: Duration) -> Self {
+        Self {
+            last_modified: newest_modification(&directory),
+            last_poll: Instant::now(),
+            directory,
+            interval,
+        }
+    }
+
+    /// Whether anything was modified since the last change was reported,
+    /// checking at most once per interval.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < self.interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = newest_modification(&self.directory);
+        let changed = modified > self.last_modified;
+        self.last_modified = modified;
+
+        changed
+    }
+}
+
+/// Newest modification time of any file below `directory`, unreadable
+/// entries are skipped since they may be mid-save.
+fn newest_modification(directory: &Path) -> Option<SystemTime> {
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            match metadata.is_dir() {
+                true => newest_modification(&entry.path()),
+                false => metadata.modified().ok(),
+            }
+        })
+        .max()
+}
+
+/// Everything the loaders produce before touching the GPU, so it can be built
+/// off the render thread.
+#[derive(Debug)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub lods: Vec<LodLevel>,
+}
+
+#[derive(Debug)]
+pub struct MeshData {
+    pub name: String,
+    pub geometry: CpuMesh,
+    pub material: usize,
+}
+
+#[derive(Debug)]
+pub struct MaterialData {
+    pub name: String,
+    pub diffuse_texture: Option<TextureData>,
+    pub uniform: MaterialUniform,
+    pub normal_texture: Option<TextureData>,
+    /// Roughness in green and metalness in blue, scaling the uniform's.
+    pub metallic_roughness_texture: Option<TextureData>,
+    pub occlusion_texture: Option<TextureData>,
+    /// Shared by every map.
+    pub sampler: SamplerOptions,
+    pub kind: MaterialKind,
+}
+
+impl ModelData {
+    /// A small grey cube to draw while the real model is loading.
+    pub fn placeholder() -> Self {
+        Self {
+            meshes: vec![MeshData {
+                name: "Placeholder".to_owned(),
+                ..primitives::cube_data(0.5)
+            }],
+            materials: vec![MaterialData {
+                name: "Placeholder".to_owned(),
+                diffuse_texture: None,
+                uniform: MaterialUniform::default().with_diffuse([0.5, 0.5, 0.5, 1.0]),
+                normal_texture: None,
+                metallic_roughness_texture: None,
+                occlusion_texture: None,
+                sampler: SamplerOptions::default(),
+                kind: MaterialKind::Phong,
+            }],
+            lods: vec![],
+        }
+    }
+
+    pub fn upload(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        retain_cpu_data: bool,
+        progress: Option<&dyn Fn(LoadPhase, f32)>,
+    ) -> Model {
+        // Materials and meshes count as one step each
+        let steps = (self.materials.len() + self.meshes.len()).max(1) as f32;
+
+        let mut fallbacks = FallbackTextures::default();
+        let materials = self
+            .materials
+            .iter()
+            .enumerate()
+            .map(|(index, material)| {
+                report(progress, LoadPhase::Upload, index as f32 / steps);
+
+                let name = material.name.as_str();
+                // A texture the device can't sample shouldn't take the whole
+                // model down with it
+                let upload = |data: &Option<TextureData>, kind| {
+                    let data = data.as_ref()?;
+                    Texture::from_data(device, queue, data, Some(name), kind, material.sampler)
+                        .inspect_err(|error| {
+                            eprintln!("Failed to upload texture of {name}: {error}")
+                        })
+                        .ok()
+                        .map(Arc::new)
+                };
+                let diffuse_texture = upload(&material.diffuse_texture, TextureKind::Color)
+                    .unwrap_or_else(|| fallbacks.white(device, queue));
+                let normal_texture = upload(&material.normal_texture, TextureKind::NormalMap)
+                    .unwrap_or_else(|| fallbacks.flat_normal(device, queue));
+                // White leaves the uniform's roughness and metalness as they
+                // are and occludes nothing
+                let metallic_roughness_texture = upload(
+                    &material.metallic_roughness_texture,
+                    TextureKind::LinearData,
+                )
+                .unwrap_or_else(|| fallbacks.white(device, queue));
+                let occlusion_texture =
+                    upload(&material.occlusion_texture, TextureKind::LinearData)
+                        .unwrap_or_else(|| fallbacks.white(device, queue));
+
+                Material::new(
+                    device,
+                    name,
+                    MaterialTextures {
+                        diffuse: diffuse_texture,
+                        normal: normal_texture,
+                        metallic_roughness: metallic_roughness_texture,
+                        occlusion: occlusion_texture,
+                    },
+                    material.uniform,
+                    material.kind,
+                    layout,
+                )
+            })
+            .collect();
+
+        let meshes = self
+            .meshes
+            .iter()
+            .enumerate()
+            .map(|(index, mesh)| {
+                let step = self.materials.len() + index;
+                report(progress, LoadPhase::Upload, step as f32 / steps);
+
+                mesh.upload(device, retain_cpu_data)
+            })
+            .collect();
+        report(progress, LoadPhase::Upload, 1.0);
+
+        Model {
+            lods: self.lods.clone(),
+            ..Model::from_meshes(meshes, materials)
+        }
+    }
+
+    /// What the model will cost once uploaded, see
+    /// [`TextureData::byte_size`]. Fallbacks for missing maps aren't counted.
+    pub fn stats(&self) -> ModelStats {
+        let mut stats = ModelStats::default();
+        for mesh in &self.meshes {
+            stats += ModelStats {
+                mesh_count: 1,
+                vertex_count: mesh.geometry.vertices.len() as u64,
+                index_count: mesh.geometry.indices.len() as u64,
+                vertex_bytes: mem::size_of_val(mesh.geometry.vertices.as_slice()) as u64,
+                index_bytes: mem::size_of_val(mesh.geometry.indices.as_slice()) as u64,
+                texture_bytes: 0,
+            };
+        }
+
+        for material in &self.materials {
+            for texture in [
+                &material.diffuse_texture,
+                &material.normal_texture,
+                &material.metallic_roughness_texture,
+                &material.occlusion_texture,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                stats.texture_bytes += texture.byte_size();
+            }
+        }
+
+        stats
+    }
+
+    /// Appends a coarser level's meshes and materials, drawn up to
+    /// `max_distance` away from the camera.
+    fn append_lod(&mut self, lod: ModelData, max_distance: f32) {
+        let first_mesh = self.meshes.len();
+        let first_material = self.materials.len();
+
+        self.meshes
+            .extend(lod.meshes.into_iter().map(|mesh| MeshData {
+                material: mesh.material + first_material,
+                ..mesh
+            }));
+        self.materials.extend(lod.materials);
+        self.lods.push(LodLevel {
+            mesh_indices: (first_mesh..self.meshes.len()).collect(),
+            max_distance,
+        });
+    }
+}
+
+impl MeshData {
+    /// Bakes the import transform into the vertices. The scale is uniform,
+    /// so normals and tangents only need the rotation.
+    pub fn transform(&mut self, options: &LoadOptions) {
+        let rotate =
+            |vector: [f32; 3]| -> [f32; 3] { (options.rotation * Vector3::from(vector)).into() };
+
+        for vertex in &mut self.geometry.vertices {
+            vertex.position = rotate((Vector3::from(vertex.position) * options.scale).into());
+            vertex.normal = rotate(vertex.normal);
+            vertex.tangent = rotate(vertex.tangent);
+            vertex.bitangent = rotate(vertex.bitangent);
+
+            if options.flip_uv_y {
+                vertex.texture_coordinates[1] = 1.0 - vertex.texture_coordinates[1];
+                // The bitangent follows the v axis
+                vertex.bitangent = (-Vector3::from(vertex.bitangent)).into();
+            }
+        }
+    }
+
+    /// Uploads the geometry, keeping a copy of it on the mesh when
+    /// `retain_cpu_data` is set.
+    pub fn upload(&self, device: &Device, retain_cpu_data: bool) -> Mesh {
+        Mesh {
+            material: self.material,
+            cpu_data: retain_cpu_data.then(|| self.geometry.clone()),
+            ..Mesh::upload(device, &self.name, &self.geometry)
+        }
+    }
+}
+
+/// Reads a model and decodes its textures without touching the GPU,
+/// dispatching on the file extension.
+///
+/// Sibling files following the `cube.lod1.obj`, `cube.lod2.obj`, ...
+/// convention are read as progressively coarser levels of detail, each
+/// covering twice the distance of the previous one.
+pub fn read_model(
+    file_name: &str,
+    options: &LoadOptions,
+    progress: Option<&dyn Fn(LoadPhase, f32)>,
+) -> ModelResult<ModelData> {
+    let mut model = read_model_file(file_name, options, progress)?;
+
+    let mut max_distance = LOD_BASE_DISTANCE;
+    for (level, lod_file_name) in lod_file_names(file_name)?.iter().enumerate() {
+        if level == 0 {
+            model.lods.push(LodLevel {
+                mesh_indices: (0..model.meshes.len()).collect(),
+                max_distance,
+            });
+        }
+
+        max_distance *= 2.0;
+        model.append_lod(
+            read_model_file(lod_file_name, options, progress)?,
+            max_distance,
+        );
+    }
+
+    // The coarsest level draws everything further away
+    if let Some(last) = model.lods.last_mut() {
+        last.max_distance = f32::INFINITY;
+    }
+
+    Ok(model)
+}
+
+fn lod_file_names(file_name: &str) -> io::Result<Vec<String>> {
+    let path = Path::new(file_name);
+    let (Some(stem), Some(extension)) = (
+        path.file_stem().and_then(OsStr::to_str),
+        path.extension().and_then(OsStr::to_str),
+    ) else {
+        return Ok(vec![]);
+    };
+
+    let directory = resource_directory()?;
+    let mut file_names = vec![];
+    for level in 1.. {
+        let lod_path = path.with_file_name(format!("{stem}.lod{level}.{extension}"));
+        if !directory.join(&lod_path).is_file() {
+            break;
+        }
+
+        file_names.push(lod_path.to_string_lossy().into_owned());
+    }
+
+    Ok(file_names)
+}
+
+fn read_model_file(
+    file_name: &str,
+    options: &LoadOptions,
+    progress: Option<&dyn Fn(LoadPhase, f32)>,
+) -> ModelResult<ModelData> {
+    report(progress, LoadPhase::Parse, 0.0);
+    let mut model = match Path::new(file_name).extension().and_then(OsStr::to_str) {
+        Some("gltf" | "glb") => read_gltf(file_name, progress)?,
+        Some("ply") => read_ply(file_name)?,
+        Some("stl") => read_stl(file_name)?,
+        _ => read_obj(file_name, progress)?,
+    };
+
+    // The readers leave tangents to this pass so a whole model's worth can be
+    // reported as one phase, weighted by index count
+    let missing = |mesh: &MeshData| mesh.geometry.missing_tangents();
+    let total_indices = model
+        .meshes
+        .iter()
+        .filter(|mesh| missing(mesh))
+        .map(|mesh| mesh.geometry.indices.len())
+        .sum::<usize>()
+        .max(1) as f32;
+    let mut done_indices = 0;
+    for mesh in model.meshes.iter_mut().filter(|mesh| missing(mesh)) {
+        let mesh_progress = progress.map(|progress| {
+            let (done, count) = (done_indices as f32, mesh.geometry.indices.len() as f32);
+            move |fraction: f32| {
+                progress(
+                    LoadPhase::Tangents,
+                    (done + fraction * count) / total_indices,
+                )
+            }
+        });
+        mesh.geometry.compute_tangents_with_progress(
+            mesh_progress.as_ref().map(|report| report as &dyn Fn(f32)),
+        );
+        done_indices += mesh.geometry.indices.len();
+    }
+
+    let mesh_count = model.meshes.len().max(1) as f32;
+    for (index, mesh) in model.meshes.iter_mut().enumerate() {
+        if options.transforms() {
+            mesh.transform(options);
+        }
+        if options.optimize {
+            report(progress, LoadPhase::Optimize, index as f32 / mesh_count);
+            mesh.geometry.optimize();
+        }
+    }
+
+    Ok(model)
+}
+
+/// Reads an OBJ, leaving tangents to [`read_model`].
+pub fn read_obj(
+    file_name: &str,
+    progress: Option<&dyn Fn(LoadPhase, f32)>,
+) -> ModelResult<ModelData> {
     let object_cursor = Cursor::new(fs::read(resource_directory()?.join(file_name))?);
-    let mut object_reader = BufReader::new(object_cursor);
-    let (models, object_materials) = tobj::load_obj_buf(
-        &mut object_reader,
-        &LoadOptions {
-            single_index: true,
-            triangulate: true,
-            ..Default::default()
-        },
-        move |path| {
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(
-                &fs::read(resource_directory().unwrap().join(path)).unwrap(),
-            )))
-        },
-    )
-    .unwrap();
+    let (models, object_materials) =
+        parse_obj(file_name, &mut BufReader::new(object_cursor), |path| {
+            let path = resource_directory()
+                .map_err(|_| tobj::LoadError::OpenFileFailed)?
+                .join(path);
+            let source = fs::read(path).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+
+            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(source)))
+        })?;
 
+    let material_count = object_materials.len() as f32;
     let materials = object_materials
-        .unwrap()
         .into_iter()
-        .map(|material| -> io::Result<Material> {
-            let diffuse_texture: Texture =
-                load_texture(&material.diffuse_texture.unwrap(), false, device, queue)?;
-            let normal_texture =
-                load_texture(&material.normal_texture.unwrap(), true, device, queue)?;
-
-            Ok(Material::new(
-                device,
-                &material.name,
-                diffuse_texture,
-                normal_texture,
-                layout,
-            ))
+        .enumerate()
+        .map(|(index, material)| -> ModelResult<MaterialData> {
+            report(progress, LoadPhase::Textures, index as f32 / material_count);
+            let uniform = obj_material_uniform(&material);
+
+            Ok(MaterialData {
+                diffuse_texture: material
+                    .diffuse_texture
+                    .as_deref()
+                    .map(read_texture)
+                    .transpose()?,
+                uniform,
+                normal_texture: material
+                    .normal_texture
+                    .as_deref()
+                    .map(read_texture)
+                    .transpose()?,
+                metallic_roughness_texture: obj_metallic_roughness(&material)?,
+                occlusion_texture: None,
+                // MTL maps tile unless they ask for -clamp, which tobj
+                // doesn't parse
+                sampler: SamplerOptions::repeat(),
+                kind: obj_material_kind(&material),
+                name: material.name,
+            })
         })
-        .collect::<io::Result<Vec<_>>>()?;
+        .collect::<ModelResult<Vec<_>>>()?;
 
     let meshes = models
         .into_iter()
-        .map(|model| {
-            let mut vertices = (0..model.mesh.positions.len() / 3)
-                .map(|i| ModelVertex {
-                    position: [
-                        model.mesh.positions[i * 3],
-                        model.mesh.positions[i * 3 + 1],
-                        model.mesh.positions[i * 3 + 2],
-                    ],
-                    texture_coordinates: [
-                        model.mesh.texcoords[i * 2],
-                        model.mesh.texcoords[i * 2 + 1],
-                    ],
-                    normal: [
-                        model.mesh.normals[i * 3],
-                        model.mesh.normals[i * 3 + 1],
-                        model.mesh.normals[i * 3 + 2],
-                    ],
+        .map(|model| obj_mesh(file_name, model))
+        .collect();
+
+    Ok(ModelData {
+        meshes,
+        materials,
+        lods: vec![],
+    })
+}
+
+/// Kd, Ks, Ns, d and Ke from the MTL, anything missing falls back to the
+/// shader's old hardcoded lighting. The PBR extension's Pr and Pm set the
+/// roughness and metalness, default 1 under a map of their own since the
+/// map scales them.
+fn obj_material_uniform(material: &tobj::Material) -> MaterialUniform {
+    let [r, g, b] = material.diffuse.unwrap_or(FALLBACK_DIFFUSE);
+    let mut uniform = MaterialUniform::new(
+        [r, g, b, material.dissolve.unwrap_or(1.0)].into(),
+        material.specular.unwrap_or([1.0; 3]).into(),
+        material
+            .shininess
+            .unwrap_or(MaterialUniform::DEFAULT_SHININESS),
+    );
+
+    let param = |key: &str| material.unknown_param.get(key).map(|value| value.trim());
+    let factor = |key: &str, map: &str| match param(key) {
+        Some(value) => value.parse().ok(),
+        None => param(map).map(|_| 1.0),
+    };
+    if let Some(roughness) = factor("Pr", "map_Pr") {
+        uniform = uniform.with_roughness(roughness);
+    }
+    if let Some(metallic) = factor("Pm", "map_Pm") {
+        uniform = uniform.with_metallic(metallic);
+    }
+    // tobj leaves Ke to the unknown parameters
+    let emissive = param("Ke").and_then(|value| {
+        let channels = value
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<f32>, _>>()
+            .ok()?;
+        <[f32; 3]>::try_from(channels).ok()
+    });
+    if let Some(emissive) = emissive {
+        uniform = uniform.with_emissive(emissive.into());
+    }
+
+    uniform
+}
+
+/// Materials using any of the PBR extension's roughness or metalness
+/// parameters are meant for the PBR shader.
+fn obj_material_kind(material: &tobj::Material) -> MaterialKind {
+    match ["Pr", "Pm", "map_Pr", "map_Pm"]
+        .iter()
+        .any(|key| material.unknown_param.contains_key(*key))
+    {
+        true => MaterialKind::Pbr,
+        false => MaterialKind::Phong,
+    }
+}
+
+/// Reads map_Pr and map_Pm into one texture, see [`pack_metallic_roughness`].
+fn obj_metallic_roughness(material: &tobj::Material) -> ModelResult<Option<TextureData>> {
+    let read_map = |key: &str| -> ModelResult<Option<DynamicImage>> {
+        let Some(file_name) = material.unknown_param.get(key) else {
+            return Ok(None);
+        };
+
+        Ok(match read_texture(file_name.trim())? {
+            TextureData::Image(image) => Some(image),
+            // Block compressed maps can't be repacked without decoding them
+            TextureData::Levels(_) => {
+                eprintln!("Skipping {key} of {}, it's block compressed", material.name);
+                None
+            }
+        })
+    };
+    let roughness = read_map("map_Pr")?;
+    let metallic = read_map("map_Pm")?;
+
+    Ok(pack_metallic_roughness(roughness.as_ref(), metallic.as_ref()).map(TextureData::Image))
+}
+
+/// Packs separate roughness and metalness maps the way glTF does, roughness
+/// in green and metalness in blue, at the size of the roughness map. A
+/// missing map leaves its channel at 1.
+fn pack_metallic_roughness(
+    roughness: Option<&DynamicImage>,
+    metallic: Option<&DynamicImage>,
+) -> Option<DynamicImage> {
+    let (width, height) = roughness
+        .or(metallic)
+        .map(|image| (image.width(), image.height()))?;
+    let channel = |image: Option<&DynamicImage>| {
+        image.map(
+            |image| match (image.width(), image.height()) == (width, height) {
+                true => image.to_luma8(),
+                false => image
+                    .resize_exact(width, height, image::imageops::FilterType::Triangle)
+                    .to_luma8(),
+            },
+        )
+    };
+    let (roughness, metallic) = (channel(roughness), channel(metallic));
+
+    let value = |channel: &Option<GrayImage>, x, y| {
+        channel
+            .as_ref()
+            .map_or(u8::MAX, |channel| channel.get_pixel(x, y).0[0])
+    };
+    Some(DynamicImage::ImageRgba8(RgbaImage::from_fn(
+        width,
+        height,
+        |x, y| {
+            image::Rgba([
+                u8::MAX,
+                value(&roughness, x, y),
+                value(&metallic, x, y),
+                u8::MAX,
+            ])
+        },
+    )))
+}
+
+/// Objects and groups keep their own names, the file name only stands in
+/// for meshes tobj couldn't name.
+fn obj_mesh(file_name: &str, model: tobj::Model) -> MeshData {
+    let name = match model.name.as_str() {
+        "" | "unnamed_object" => file_name.to_owned(),
+        _ => model.name,
+    };
+
+    let vertices = (0..model.mesh.positions.len() / 3)
+        .map(|i| ModelVertex {
+            position: [
+                model.mesh.positions[i * 3],
+                model.mesh.positions[i * 3 + 1],
+                model.mesh.positions[i * 3 + 2],
+            ],
+            texture_coordinates: match model.mesh.texcoords.is_empty() {
+                true => [0.0; 2],
+                false => [model.mesh.texcoords[i * 2], model.mesh.texcoords[i * 2 + 1]],
+            },
+            // Generated from the faces below when there are none
+            normal: match model.mesh.normals.is_empty() {
+                true => [0.0; 3],
+                false => [
+                    model.mesh.normals[i * 3],
+                    model.mesh.normals[i * 3 + 1],
+                    model.mesh.normals[i * 3 + 2],
+                ],
+            },
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+            color: match model.mesh.vertex_color.is_empty() {
+                true => [1.0; 4],
+                false => [
+                    model.mesh.vertex_color[i * 3],
+                    model.mesh.vertex_color[i * 3 + 1],
+                    model.mesh.vertex_color[i * 3 + 2],
+                    1.0,
+                ],
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let mut geometry = CpuMesh::new(vertices, model.mesh.indices);
+    if model.mesh.normals.is_empty() {
+        geometry.compute_normals();
+    }
+
+    MeshData {
+        name,
+        geometry,
+        material: model.mesh.material_id.unwrap_or(0),
+    }
+}
+
+/// Reads a texture for the GPU, KTX2 and DDS containers as they are, Radiance
+/// images as floats and anything else decoded with the image crate.
+fn read_texture(file_name: &str) -> ModelResult<TextureData> {
+    let bytes = fs::read(resource_directory()?.join(file_name))?;
+
+    Ok(
+        match Path::new(file_name).extension().and_then(OsStr::to_str) {
+            Some("ktx2") => TextureData::Levels(texture::parse_ktx2(&bytes)?),
+            Some("dds") => TextureData::Levels(texture::parse_dds(&bytes)?),
+            Some("hdr") => TextureData::Image(texture::decode_hdr(&bytes)?),
+            _ => TextureData::Image(image::load_from_memory(&bytes)?),
+        },
+    )
+}
+
+/// Parses an OBJ and its material libraries, validating everything the
+/// upload step indexes into so it can't panic halfway through.
+fn parse_obj<R, L>(
+    file_name: &str,
+    reader: &mut R,
+    material_loader: L,
+) -> ModelResult<(Vec<tobj::Model>, Vec<tobj::Material>)>
+where
+    R: BufRead,
+    L: Fn(&Path) -> tobj::MTLLoadResult,
+{
+    let (models, materials) = tobj::load_obj_buf(
+        reader,
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        },
+        material_loader,
+    )?;
+    let materials = materials?;
+
+    if materials.is_empty() {
+        return Err(ModelError::NoMaterials(file_name.to_owned()));
+    }
+
+    Ok((models, materials))
+}
+
+pub fn read_ply(file_name: &str) -> ModelResult<ModelData> {
+    let source = fs::read(resource_directory()?.join(file_name))?;
+
+    Ok(ModelData {
+        meshes: vec![parse_ply(file_name, &mut source.as_slice())?],
+        // PLY has no materials, the vertex colors tint a white fallback
+        materials: vec![MaterialData {
+            name: "Default material".to_owned(),
+            diffuse_texture: None,
+            uniform: MaterialUniform::default(),
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            sampler: SamplerOptions::default(),
+            kind: MaterialKind::Phong,
+        }],
+        lods: vec![],
+    })
+}
+
+/// Parses the vertices and faces of an ASCII or binary PLY, polygons are
+/// split into fans and missing normals generated from the faces.
+fn parse_ply<R: io::Read>(file_name: &str, reader: &mut R) -> ModelResult<MeshData> {
+    let ply = ply_rs::parser::Parser::<ply_rs::ply::DefaultElement>::new().read_ply(reader)?;
+    let missing = |attribute| ModelError::MissingAttribute {
+        mesh: file_name.to_owned(),
+        attribute,
+    };
+
+    let elements = ply
+        .payload
+        .get("vertex")
+        .ok_or_else(|| missing("vertices"))?;
+    let has_normals = elements
+        .first()
+        .is_some_and(|element| element.contains_key("nx"));
+
+    let vertices = elements
+        .iter()
+        .map(|element| -> ModelResult<ModelVertex> {
+            let scalar = |key: &str| element.get(key).and_then(ply_scalar);
+            let position = ["x", "y", "z"].map(scalar);
+            let [Some(x), Some(y), Some(z)] = position else {
+                return Err(missing("positions"));
+            };
+
+            let color = |key: &str| match element.get(key) {
+                // Integer channels are 0-255, float ones already normalized
+                Some(Property::UChar(value)) => *value as f32 / 255.0,
+                Some(property) => ply_scalar(property).unwrap_or(1.0),
+                None => 1.0,
+            };
+
+            Ok(ModelVertex {
+                position: [x, y, z],
+                texture_coordinates: [0.0; 2],
+                normal: ["nx", "ny", "nz"].map(|key| scalar(key).unwrap_or(0.0)),
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
+                color: ["red", "green", "blue", "alpha"].map(color),
+            })
+        })
+        .collect::<ModelResult<Vec<_>>>()?;
+
+    let mut indices = vec![];
+    for face in ply.payload.get("face").ok_or_else(|| missing("faces"))? {
+        let corners = face
+            .get("vertex_indices")
+            .or_else(|| face.get("vertex_index"))
+            .and_then(ply_list)
+            .ok_or_else(|| missing("face indices"))?;
+
+        if let Some(corner) = corners
+            .iter()
+            .find(|&&corner| corner as usize >= vertices.len())
+        {
+            return Err(ModelError::UnsupportedFormat(format!(
+                "{file_name} references missing vertex {corner}"
+            )));
+        }
+
+        for window in 1..corners.len().saturating_sub(1) {
+            indices.extend([corners[0], corners[window], corners[window + 1]]);
+        }
+    }
+
+    let mut geometry = CpuMesh::new(vertices, indices);
+    if !has_normals {
+        geometry.compute_normals();
+    }
+
+    Ok(MeshData {
+        name: file_name.to_owned(),
+        geometry,
+        material: 0,
+    })
+}
+
+pub fn read_stl(file_name: &str) -> ModelResult<ModelData> {
+    let source = fs::read(resource_directory()?.join(file_name))?;
+    let [r, g, b] = FALLBACK_DIFFUSE;
+
+    Ok(ModelData {
+        meshes: vec![parse_stl(file_name, &source)?],
+        materials: vec![MaterialData {
+            name: "Default material".to_owned(),
+            diffuse_texture: None,
+            uniform: MaterialUniform::default().with_diffuse([r, g, b, 1.0]),
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            sampler: SamplerOptions::default(),
+            kind: MaterialKind::Phong,
+        }],
+        lods: vec![],
+    })
+}
+
+/// Parses a binary or ASCII STL into one mesh. Every facet arrives with its
+/// own corners, so positions within [`STL_WELD_EPSILON`] are welded before
+/// normals are generated, and texture coordinates are projected onto the
+/// two widest axes of the bounds.
+fn parse_stl(file_name: &str, source: &[u8]) -> ModelResult<MeshData> {
+    let triangles = match stl_is_binary(source) {
+        true => parse_binary_stl(source),
+        false => parse_ascii_stl(source),
+    }
+    .map_err(|error| ModelError::Stl(format!("{file_name}: {error}")))?;
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let mut welded = HashMap::new();
+    for position in triangles.into_iter().flatten() {
+        let key = position.map(|component| (component / STL_WELD_EPSILON).round() as i64);
+        let index = *welded.entry(key).or_insert_with(|| {
+            vertices.push(ModelVertex {
+                position,
+                texture_coordinates: [0.0; 2],
+                normal: [0.0; 3],
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
+                color: [1.0; 4],
+            });
+            vertices.len() as u32 - 1
+        });
+
+        indices.push(index);
+    }
+
+    let bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into()));
+    let size: [f32; 3] = bounds.size().into();
+    let min: [f32; 3] = bounds.min.into();
+    let mut axes = [0, 1, 2];
+    axes.sort_by(|a, b| size[*b].total_cmp(&size[*a]));
+    for vertex in &mut vertices {
+        let project = |axis: usize| match size[axis] > 0.0 {
+            true => (vertex.position[axis] - min[axis]) / size[axis],
+            false => 0.0,
+        };
+        vertex.texture_coordinates = [project(axes[0]), project(axes[1])];
+    }
+
+    let mut geometry = CpuMesh::new(vertices, indices);
+    geometry.compute_normals();
+
+    Ok(MeshData {
+        name: file_name.to_owned(),
+        geometry,
+        material: 0,
+    })
+}
+
+/// Binary files are only told apart by their length, some exporters start
+/// the 80 byte header with "solid" too.
+fn stl_is_binary(source: &[u8]) -> bool {
+    match source.get(80..84) {
+        Some(count) => {
+            let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+            source.len() == 84 + count * 50 || !source.starts_with(b"solid")
+        }
+        None => false,
+    }
+}
+
+fn parse_binary_stl(source: &[u8]) -> Result<Vec<[[f32; 3]; 3]>, String> {
+    let count = u32::from_le_bytes(source[80..84].try_into().unwrap()) as usize;
+    let facets = source
+        .get(84..84 + count * 50)
+        .ok_or_else(|| format!("expected {count} facets"))?;
+
+    Ok(facets
+        .chunks_exact(50)
+        .map(|facet| {
+            // The stored normal is skipped, it's often zero or stale
+            let float =
+                |offset: usize| f32::from_le_bytes(facet[offset..offset + 4].try_into().unwrap());
+            [12, 24, 36].map(|corner| [float(corner), float(corner + 4), float(corner + 8)])
+        })
+        .collect())
+}
+
+fn parse_ascii_stl(source: &[u8]) -> Result<Vec<[[f32; 3]; 3]>, String> {
+    let source = std::str::from_utf8(source).map_err(|error| error.to_string())?;
+
+    let mut triangles = vec![];
+    let mut corners = vec![];
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("vertex") => {
+                let mut position = [0.0; 3];
+                for component in &mut position {
+                    *component = words
+                        .next()
+                        .and_then(|word| word.parse().ok())
+                        .ok_or_else(|| format!("malformed vertex \"{}\"", line.trim()))?;
+                }
+                corners.push(position);
+            }
+            Some("endfacet") => match corners.as_slice() {
+                &[a, b, c] => {
+                    triangles.push([a, b, c]);
+                    corners.clear();
+                }
+                _ => return Err(format!("facet with {} vertices", corners.len())),
+            },
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+fn ply_scalar(property: &Property) -> Option<f32> {
+    Some(match *property {
+        Property::Char(value) => value as f32,
+        Property::UChar(value) => value as f32,
+        Property::Short(value) => value as f32,
+        Property::UShort(value) => value as f32,
+        Property::Int(value) => value as f32,
+        Property::UInt(value) => value as f32,
+        Property::Float(value) => value,
+        Property::Double(value) => value as f32,
+        _ => return None,
+    })
+}
+
+fn ply_list(property: &Property) -> Option<Vec<u32>> {
+    Some(match property {
+        Property::ListChar(values) => values.iter().map(|&value| value as u32).collect(),
+        Property::ListUChar(values) => values.iter().map(|&value| value as u32).collect(),
+        Property::ListShort(values) => values.iter().map(|&value| value as u32).collect(),
+        Property::ListUShort(values) => values.iter().map(|&value| value as u32).collect(),
+        Property::ListInt(values) => values.iter().map(|&value| value as u32).collect(),
+        Property::ListUInt(values) => values.clone(),
+        _ => return None,
+    })
+}
+
+/// Reads a glTF, leaving tangents the file doesn't store to [`read_model`].
+pub fn read_gltf(
+    file_name: &str,
+    progress: Option<&dyn Fn(LoadPhase, f32)>,
+) -> ModelResult<ModelData> {
+    let (document, buffers, images) = gltf::import(resource_directory()?.join(file_name))?;
+
+    let material_count = document.materials().len() as f32;
+    let mut materials = document
+        .materials()
+        .enumerate()
+        .map(|(index, material)| -> ModelResult<MaterialData> {
+            report(progress, LoadPhase::Textures, index as f32 / material_count);
+            let pbr = material.pbr_metallic_roughness();
+            let image = |texture: gltf::Texture| {
+                gltf_image(&images[texture.source().index()]).map(TextureData::Image)
+            };
+
+            Ok(MaterialData {
+                name: material.name().unwrap_or(file_name).to_owned(),
+                diffuse_texture: pbr
+                    .base_color_texture()
+                    .map(|info| image(info.texture()))
+                    .transpose()?,
+                uniform: MaterialUniform::default()
+                    .with_diffuse(pbr.base_color_factor())
+                    .with_metallic(pbr.metallic_factor())
+                    .with_roughness(pbr.roughness_factor())
+                    .with_emissive(material.emissive_factor().into()),
+                normal_texture: material
+                    .normal_texture()
+                    .map(|info| image(info.texture()))
+                    .transpose()?,
+                metallic_roughness_texture: pbr
+                    .metallic_roughness_texture()
+                    .map(|info| image(info.texture()))
+                    .transpose()?,
+                // The occlusion strength isn't kept, the map applies fully
+                occlusion_texture: material
+                    .occlusion_texture()
+                    .map(|info| image(info.texture()))
+                    .transpose()?,
+                sampler: pbr
+                    .base_color_texture()
+                    .map(|info| gltf_sampler(&info.texture().sampler()))
+                    .unwrap_or_default(),
+                kind: MaterialKind::Pbr,
+            })
+        })
+        .collect::<ModelResult<Vec<_>>>()?;
+
+    // Primitives without a material use the spec's default material, which
+    // gets appended after the document's own if anything references it
+    let default_material = materials.len();
+
+    // Meshes are placed by the node hierarchy, so bake each node's global
+    // transform into its vertices. Documents without scenes fall back to the
+    // raw mesh list.
+    let mut meshes = vec![];
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().try_for_each(|node| {
+            read_gltf_node(
+                file_name,
+                &node,
+                Matrix4::identity(),
+                default_material,
+                &buffers,
+                &mut meshes,
+            )
+        })?,
+        None => document.meshes().try_for_each(|mesh| {
+            read_gltf_mesh(
+                file_name,
+                &mesh,
+                Matrix4::identity(),
+                default_material,
+                &buffers,
+                &mut meshes,
+            )
+        })?,
+    }
+
+    if meshes.iter().any(|mesh| mesh.material == default_material) {
+        materials.push(MaterialData {
+            name: "Default material".to_owned(),
+            diffuse_texture: None,
+            uniform: MaterialUniform::default(),
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            sampler: SamplerOptions::default(),
+            kind: MaterialKind::Phong,
+        });
+    }
+
+    Ok(ModelData {
+        meshes,
+        materials,
+        lods: vec![],
+    })
+}
+
+/// The glTF sampler's wrapping, which defaults to repeat. Only `wrap_s` is
+/// kept since the options use one address mode for every axis.
+fn gltf_sampler(sampler: &gltf::texture::Sampler) -> SamplerOptions {
+    use gltf::texture::WrappingMode;
+
+    SamplerOptions {
+        address_mode: match sampler.wrap_s() {
+            WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+            WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+        },
+        ..Default::default()
+    }
+}
+
+fn read_gltf_node(
+    file_name: &str,
+    node: &gltf::Node,
+    parent_transform: Matrix4<f32>,
+    default_material: usize,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<MeshData>,
+) -> ModelResult<()> {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        read_gltf_mesh(
+            file_name,
+            &mesh,
+            transform,
+            default_material,
+            buffers,
+            meshes,
+        )?;
+    }
+
+    node.children().try_for_each(|child| {
+        read_gltf_node(
+            file_name,
+            &child,
+            transform,
+            default_material,
+            buffers,
+            meshes,
+        )
+    })
+}
+
+fn read_gltf_mesh(
+    file_name: &str,
+    mesh: &gltf::Mesh,
+    transform: Matrix4<f32>,
+    default_material: usize,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<MeshData>,
+) -> ModelResult<()> {
+    let name = mesh.name().unwrap_or(file_name);
+    let normal_matrix = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = normal_matrix
+        .invert()
+        .unwrap_or_else(Matrix3::identity)
+        .transpose();
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            eprintln!(
+                "Skipping {:?} primitive in {name}, only triangles are supported",
+                primitive.mode()
+            );
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions = reader
+            .read_positions()
+            .ok_or_else(|| ModelError::MissingAttribute {
+                mesh: name.to_owned(),
+                attribute: "positions",
+            })?
+            .collect::<Vec<_>>();
+        let normals = reader
+            .read_normals()
+            .map(|normals| normals.collect::<Vec<_>>());
+        let texture_coordinates = reader
+            .read_tex_coords(0)
+            .map(|coordinates| coordinates.into_f32().collect::<Vec<_>>())
+            .unwrap_or_else(|| vec![[0.0; 2]; positions.len()]);
+        let colors = reader
+            .read_colors(0)
+            .map(|colors| colors.into_rgba_f32().collect::<Vec<_>>())
+            .unwrap_or_else(|| vec![[1.0; 4]; positions.len()]);
+        let tangents = reader
+            .read_tangents()
+            .map(|tangents| tangents.collect::<Vec<_>>());
+        let indices = reader
+            .read_indices()
+            .map(|indices| indices.into_u32().collect::<Vec<_>>())
+            .unwrap_or_else(|| (0..positions.len() as u32).collect());
+
+        // Zipped with the positions below, which would drop the vertices
+        // past a shorter attribute
+        let lengths = [
+            (
+                "normals",
+                normals.as_ref().map_or(positions.len(), Vec::len),
+            ),
+            ("texture coordinates", texture_coordinates.len()),
+            ("colors", colors.len()),
+            (
+                "tangents",
+                tangents.as_ref().map_or(positions.len(), Vec::len),
+            ),
+        ];
+        if let Some((attribute, len)) = lengths.into_iter().find(|&(_, len)| len != positions.len())
+        {
+            return Err(ModelError::UnsupportedFormat(format!(
+                "{name} has {len} {attribute} for {} positions",
+                positions.len()
+            )));
+        }
+        if let Some(index) = indices
+            .iter()
+            .find(|&&index| index as usize >= positions.len())
+        {
+            return Err(ModelError::UnsupportedFormat(format!(
+                "{name} references missing vertex {index}"
+            )));
+        }
+
+        let mut vertices = positions
+            .iter()
+            .zip(&texture_coordinates)
+            .zip(&colors)
+            .enumerate()
+            .map(|(index, ((position, texture_coordinates), color))| {
+                let position = transform * Vector3::from(*position).extend(1.0);
+                // Filled in from the faces below when there are none
+                let normal = normals.as_ref().map_or([0.0; 3], |normals| {
+                    (normal_matrix * Vector3::from(normals[index]))
+                        .normalize()
+                        .into()
+                });
+
+                ModelVertex {
+                    position: position.truncate().into(),
+                    texture_coordinates: *texture_coordinates,
+                    normal,
                     tangent: [0.0; 3],
                     bitangent: [0.0; 3],
+                    color: *color,
+                }
+            })
+            .collect::<Vec<_>>();
+        if normals.is_none() {
+            compute_normals(&mut vertices, &indices);
+        }
+
+        // glTF stores the bitangent sign in w, and shares wgpu's top-left
+        // texture origin, so no flip is needed here
+        if let Some(tangents) = tangents {
+            vertices
+                .iter_mut()
+                .zip(tangents)
+                .for_each(|(vertex, [x, y, z, w])| {
+                    let tangent = (normal_matrix * Vector3::new(x, y, z)).normalize();
+                    let normal = Vector3::from(vertex.normal);
+                    vertex.tangent = tangent.into();
+                    vertex.bitangent = (normal.cross(tangent) * w).into();
                 })
-                .collect::<Vec<_>>();
-
-            let indices = &model.mesh.indices;
-            let mut triangles_included = vec![0; vertices.len()];
-
-            // Calculate tangents and bitangets. We're going to
-            // use the triangles, so we need to loop through the
-            // indices in chunks of 3
-            for c in indices.chunks(3) {
-                let v0 = vertices[c[0] as usize];
-                let v1 = vertices[c[1] as usize];
-                let v2 = vertices[c[2] as usize];
-
-                let pos0: cgmath::Vector3<_> = v0.position.into();
-                let pos1: cgmath::Vector3<_> = v1.position.into();
-                let pos2: cgmath::Vector3<_> = v2.position.into();
-
-                let uv0: cgmath::Vector2<_> = v0.texture_coordinates.into();
-                let uv1: cgmath::Vector2<_> = v1.texture_coordinates.into();
-                let uv2: cgmath::Vector2<_> = v2.texture_coordinates.into();
-
-                // Calculate the edges of the triangle
-                let delta_pos1 = pos1 - pos0;
-                let delta_pos2 = pos2 - pos0;
-
-                // This will give us a direction to calculate the
-                // tangent and bitangent
-                let delta_uv1 = uv1 - uv0;
-                let delta_uv2 = uv2 - uv0;
-
-                // Solving the following system of equations will
-                // give us the tangent and bitangent.
-                //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
-                //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
-                // Luckily, the place I found this equation provided
-                // the solution!
-                let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-                let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-                // We flip the bitangent to enable right-handed normal
-                // maps with wgpu texture coordinate system
-                let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
-
-                // We'll use the same tangent/bitangent for each vertex in the triangle
-                vertices[c[0] as usize].tangent =
-                    (tangent + cgmath::Vector3::from(vertices[c[0] as usize].tangent)).into();
-                vertices[c[1] as usize].tangent =
-                    (tangent + cgmath::Vector3::from(vertices[c[1] as usize].tangent)).into();
-                vertices[c[2] as usize].tangent =
-                    (tangent + cgmath::Vector3::from(vertices[c[2] as usize].tangent)).into();
-                vertices[c[0] as usize].bitangent =
-                    (bitangent + cgmath::Vector3::from(vertices[c[0] as usize].bitangent)).into();
-                vertices[c[1] as usize].bitangent =
-                    (bitangent + cgmath::Vector3::from(vertices[c[1] as usize].bitangent)).into();
-                vertices[c[2] as usize].bitangent =
-                    (bitangent + cgmath::Vector3::from(vertices[c[2] as usize].bitangent)).into();
-
-                // Used to average the tangents/bitangents
-                triangles_included[c[0] as usize] += 1;
-                triangles_included[c[1] as usize] += 1;
-                triangles_included[c[2] as usize] += 1;
-            }
+        }
 
-            // Average the tangents/bitangents
-            for (i, n) in triangles_included.into_iter().enumerate() {
-                let denom = 1.0 / n as f32;
-                let vertex = &mut vertices[i];
-                vertex.tangent = (cgmath::Vector3::from(vertex.tangent) * denom).into();
-                vertex.bitangent = (cgmath::Vector3::from(vertex.bitangent) * denom).into();
-            }
+        meshes.push(MeshData {
+            name: name.to_owned(),
+            geometry: CpuMesh::new(vertices, indices),
+            material: primitive.material().index().unwrap_or(default_material),
+        });
+    }
 
-            let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("Vertex buffer ({name})"),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: BufferUsages::VERTEX,
-            });
+    Ok(())
+}
 
-            let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("Index buffer ({name})"),
-                contents: bytemuck::cast_slice(&model.mesh.indices),
-                usage: BufferUsages::INDEX,
-            });
+fn gltf_image(data: &gltf::image::Data) -> ModelResult<DynamicImage> {
+    use gltf::image::Format;
+
+    let (width, height) = (data.width, data.height);
+    let pixels = data.pixels.clone();
+    let wide_pixels = || {
+        data.pixels
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>()
+    };
+
+    match data.format {
+        Format::R8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => {
+            GrayAlphaImage::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8)
+        }
+        Format::R8G8B8 => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => {
+            RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+        }
+        Format::R16 => {
+            ImageBuffer::from_raw(width, height, wide_pixels()).map(DynamicImage::ImageLuma16)
+        }
+        Format::R16G16 => {
+            ImageBuffer::from_raw(width, height, wide_pixels()).map(DynamicImage::ImageLumaA16)
+        }
+        Format::R16G16B16 => {
+            ImageBuffer::from_raw(width, height, wide_pixels()).map(DynamicImage::ImageRgb16)
+        }
+        Format::R16G16B16A16 => {
+            ImageBuffer::from_raw(width, height, wide_pixels()).map(DynamicImage::ImageRgba16)
+        }
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => None,
+    }
+    .ok_or_else(|| ModelError::UnsupportedFormat(format!("{:?} image", data.format)))
+}
+
+/// Diffuse color for OBJ materials that specify neither `map_Kd` nor `Kd`
+const FALLBACK_DIFFUSE: [f32; 3] = [0.8, 0.8, 0.8];
+
+/// STL positions closer than this are treated as the same vertex.
+const STL_WELD_EPSILON: f32 = 1e-5;
+
+/// 1x1 stand-ins for maps a material doesn't provide, shared between every
+/// material of a model instead of allocated per material.
+#[derive(Default)]
+struct FallbackTextures {
+    white: Option<Arc<Texture>>,
+    flat_normal: Option<Arc<Texture>>,
+}
+
+impl FallbackTextures {
+    /// The material's diffuse color is applied by the shader, so a missing
+    /// diffuse map only has to leave it unchanged.
+    fn white(&mut self, device: &Device, queue: &Queue) -> Arc<Texture> {
+        self.white
+            .get_or_insert_with(|| Arc::new(Texture::solid_color(device, queue, [255; 4])))
+            .clone()
+    }
+
+    fn flat_normal(&mut self, device: &Device, queue: &Queue) -> Arc<Texture> {
+        self.flat_normal
+            .get_or_insert_with(|| Arc::new(Texture::flat_normal(device, queue)))
+            .clone()
+    }
+}
+
+pub type ModelResult<T> = Result<T, ModelError>;
+
+#[derive(Debug, Error)]
+pub enum ModelError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Texture(#[from] texture::TextureError),
+    #[error("Failed to parse obj: {0}")]
+    Obj(#[from] tobj::LoadError),
+    #[error("Failed to parse stl: {0}")]
+    Stl(String),
+    #[error("Failed to parse gltf: {0}")]
+    Gltf(#[from] gltf::Error),
+    #[error("Model {0} has no materials")]
+    NoMaterials(String),
+    #[error("Mesh {mesh} has no {attribute}")]
+    MissingAttribute {
+        mesh: String,
+        attribute: &'static str,
+    },
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+    #[error("No cubemap face {0:?} found")]
+    MissingCubemapFace(PathBuf),
+    #[error("Loader for {0} exited without a result")]
+    LoaderExited(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cache_key, get_or_load, obj_material_kind, obj_material_uniform, obj_mesh,
+        pack_metallic_roughness, parse_obj, parse_ply, parse_stl, read_gltf_mesh, LoadOptions,
+        ModelData, ModelError, ResourceWatcher,
+    };
+    use crate::model::{primitives::cube_data, Aabb, MaterialKind, MaterialUniform, ModelVertex};
+    use cgmath::{Deg, InnerSpace, Matrix4, One, Quaternion, Rotation3, Vector3, Vector4};
+    use image::{DynamicImage, GrayImage, Luma};
+    use std::{
+        collections::HashMap,
+        env, fs, mem,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    const TRIANGLE: &str = "\
+o Triangle
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+vt 0.0 0.0
+vt 1.0 0.0
+vt 0.0 1.0
+vn 0.0 0.0 1.0
+f 1/1/1 2/2/1 3/3/1
+";
+
+    #[test]
+    fn obj_without_materials() {
+        let result = parse_obj("triangle.obj", &mut TRIANGLE.as_bytes(), |_| {
+            unreachable!("no material library is referenced")
+        });
+
+        assert!(matches!(result, Err(ModelError::NoMaterials(name)) if name == "triangle.obj"));
+    }
+
+    #[test]
+    fn material_without_texture_maps() {
+        let source = format!("mtllib triangle.mtl\nusemtl Flat\n{TRIANGLE}");
+        let (_, materials) = parse_obj("triangle.obj", &mut source.as_bytes(), |_| {
+            tobj::load_mtl_buf(&mut "newmtl Flat\nKd 0.5 0.5 0.5\n".as_bytes())
+        })
+        .unwrap();
+
+        // Missing maps are filled in with fallback textures at upload time
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials[0].diffuse_texture, None);
+        assert_eq!(materials[0].normal_texture, None);
+    }
+
+    #[test]
+    fn mtl_scalar_properties() {
+        let source = format!("mtllib triangle.mtl\nusemtl Glass\n{TRIANGLE}");
+        let (_, materials) = parse_obj("triangle.obj", &mut source.as_bytes(), |_| {
+            tobj::load_mtl_buf(
+                &mut "newmtl Glass\nKd 0.1 0.2 0.3\nKs 0.5 0.5 0.5\nNs 96\nd 0.25\nKe 2 0 0.5\n"
+                    .as_bytes(),
+            )
+        })
+        .unwrap();
+
+        let uniform = obj_material_uniform(&materials[0]);
+        assert_eq!(uniform.diffuse, Vector4::new(0.1, 0.2, 0.3, 0.25));
+        assert_eq!(uniform.specular, Vector3::new(0.5, 0.5, 0.5));
+        assert_eq!(uniform.shininess, 96.0);
+        assert_eq!(uniform.emissive, Vector3::new(2.0, 0.0, 0.5));
+        assert!(uniform.is_transparent());
+
+        let uniform = obj_material_uniform(&tobj::Material::default());
+        assert_eq!(uniform.shininess, MaterialUniform::DEFAULT_SHININESS);
+        assert!(!uniform.is_transparent());
+    }
+
+    #[test]
+    fn mtl_pbr_properties() {
+        let source = format!("mtllib triangle.mtl\nusemtl Steel\n{TRIANGLE}");
+        let (_, materials) = parse_obj("triangle.obj", &mut source.as_bytes(), |_| {
+            tobj::load_mtl_buf(&mut "newmtl Steel\nKd 0.5 0.5 0.5\nPr 0.25\nPm 1\n".as_bytes())
+        })
+        .unwrap();
+
+        let uniform = obj_material_uniform(&materials[0]);
+        assert_eq!((uniform.roughness, uniform.metallic), (0.25, 1.0));
+        assert_eq!(obj_material_kind(&materials[0]), MaterialKind::Pbr);
+        assert_eq!(
+            obj_material_kind(&tobj::Material::default()),
+            MaterialKind::Phong
+        );
+    }
+
+    #[test]
+    fn packs_metallic_roughness_like_gltf() {
+        let roughness = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([64])));
+        let metallic = DynamicImage::ImageLuma8(GrayImage::from_pixel(1, 1, Luma([200])));
+
+        let packed = pack_metallic_roughness(Some(&roughness), Some(&metallic))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(packed.dimensions(), (2, 2));
+        assert_eq!(packed.get_pixel(1, 1).0, [255, 64, 200, 255]);
+
+        // The missing roughness is left at 1
+        let packed = pack_metallic_roughness(None, Some(&metallic))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(packed.get_pixel(0, 0).0, [255, 255, 200, 255]);
+        assert!(pack_metallic_roughness(None, None).is_none());
+    }
+
+    #[test]
+    fn obj_meshes_keep_object_names() {
+        let second = TRIANGLE.replace("o Triangle", "o Second");
+        let source = format!("mtllib triangle.mtl\nusemtl Flat\n{TRIANGLE}{second}");
+        let (models, _) = parse_obj("triangles.obj", &mut source.as_bytes(), |_| {
+            tobj::load_mtl_buf(&mut "newmtl Flat\nKd 0.5 0.5 0.5\n".as_bytes())
+        })
+        .unwrap();
+
+        let names = models
+            .into_iter()
+            .map(|model| obj_mesh("triangles.obj", model).name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Triangle", "Second"]);
+    }
 
-            Mesh {
-                name: file_name.to_owned(),
-                vertex_buffer,
-                index_buffer,
-                element_count: model.mesh.indices.len() as u32,
-                material: model.mesh.material_id.unwrap_or(0),
+    #[test]
+    fn obj_normals_come_from_the_faces() {
+        let source = "mtllib triangle.mtl\nusemtl Flat\n\
+            v 0.0 0.0 0.0\nv 1.0 0.0 0.0\nv 0.0 1.0 0.0\nf 1 2 3\n";
+        let (mut models, _) = parse_obj("triangle.obj", &mut source.as_bytes(), |_| {
+            tobj::load_mtl_buf(&mut "newmtl Flat\nKd 0.5 0.5 0.5\n".as_bytes())
+        })
+        .unwrap();
+
+        let mesh = obj_mesh("triangle.obj", models.remove(0));
+        for vertex in &mesh.geometry.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    const COLORED_QUAD: &str = "\
+ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+
+    #[test]
+    fn ascii_ply_with_colors() {
+        let mesh = parse_ply("quad.ply", &mut COLORED_QUAD.as_bytes()).unwrap();
+
+        assert_eq!(mesh.geometry.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.geometry.vertices[0].color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(mesh.geometry.vertices[2].color, [0.0, 0.0, 1.0, 1.0]);
+        for vertex in &mesh.geometry.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn binary_ply() {
+        let mut source = b"ply
+format binary_little_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar uint vertex_indices
+end_header
+"
+        .to_vec();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            source.extend(value.to_le_bytes());
+        }
+        source.push(3);
+        for index in [0u32, 1, 2] {
+            source.extend(index.to_le_bytes());
+        }
+
+        let mesh = parse_ply("triangle.ply", &mut source.as_slice()).unwrap();
+        assert_eq!(mesh.geometry.vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(mesh.geometry.vertices[1].color, [1.0; 4]);
+        assert_eq!(mesh.geometry.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn binary_stl_welds_shared_corners() {
+        let mut source = vec![0; 80];
+        source.extend(2u32.to_le_bytes());
+        for facet in [
+            [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+            [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        ] {
+            source.extend([0; 12]);
+            for value in facet.into_iter().flatten() {
+                source.extend(value.to_le_bytes());
             }
+            source.extend([0; 2]);
+        }
+
+        let mesh = parse_stl("square.stl", &source).unwrap();
+        assert_eq!(mesh.geometry.vertices.len(), 4);
+        assert_eq!(mesh.geometry.indices, [0, 1, 2, 0, 2, 3]);
+        for vertex in &mesh.geometry.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+            assert!(vertex
+                .texture_coordinates
+                .iter()
+                .all(|coordinate| (0.0..=1.0).contains(coordinate)));
+        }
+    }
+
+    #[test]
+    fn ascii_stl() {
+        let source = "\
+solid triangle
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid triangle
+";
+        let mesh = parse_stl("triangle.stl", source.as_bytes()).unwrap();
+        assert_eq!(mesh.geometry.vertices.len(), 3);
+        assert_eq!(mesh.geometry.vertices[1].position, [1.0, 0.0, 0.0]);
+
+        let truncated = source.replace("      vertex 0 1 0\n", "");
+        assert!(matches!(
+            parse_stl("triangle.stl", truncated.as_bytes()),
+            Err(ModelError::Stl(_))
+        ));
+    }
+
+    #[test]
+    fn triangle_stats() {
+        let source = format!("mtllib triangle.mtl\nusemtl Flat\n{TRIANGLE}");
+        let (models, _) = parse_obj("triangle.obj", &mut source.as_bytes(), |_| {
+            tobj::load_mtl_buf(&mut "newmtl Flat\nKd 0.5 0.5 0.5\n".as_bytes())
         })
-        .collect();
+        .unwrap();
+        let model = ModelData {
+            meshes: models
+                .into_iter()
+                .map(|model| obj_mesh("triangle.obj", model))
+                .collect(),
+            materials: vec![],
+            lods: vec![],
+        };
+
+        let stats = model.stats();
+        assert_eq!(stats.mesh_count, 1);
+        assert_eq!(stats.vertex_count, 3);
+        assert_eq!(stats.triangle_count(), 1);
+        assert_eq!(stats.vertex_bytes, 3 * mem::size_of::<ModelVertex>() as u64);
+        assert_eq!(stats.index_bytes, 3 * 4);
+        assert_eq!(stats.texture_bytes, 0);
+    }
+
+    #[test]
+    fn cached_load_reads_once() {
+        let mut entries = HashMap::new();
+        let mut reads = 0;
+        let mut load = || {
+            get_or_load(&mut entries, cache_key("cube.obj").unwrap(), || {
+                reads += 1;
+                Ok(reads)
+            })
+            .unwrap()
+        };
+
+        let (first, second) = (load(), load());
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(*second, 1);
+        assert_eq!(reads, 1);
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn cached_textures_are_uploaded_once() {
+        use super::ResourceCache;
+        use crate::texture::{test_device, SamplerOptions, TextureKind};
+
+        let (device, queue) = test_device();
+        let mut cache = ResourceCache::new();
+        let mut load = |file_name| {
+            cache
+                .load_texture_cached(
+                    file_name,
+                    TextureKind::Color,
+                    SamplerOptions::default(),
+                    &device,
+                    &queue,
+                )
+                .unwrap()
+        };
+
+        let first = load("cube-diffuse.jpg");
+        assert!(Arc::ptr_eq(&first, &load("./cube-diffuse.jpg")));
+
+        cache.evict_texture(
+            "cube-diffuse.jpg",
+            TextureKind::Color,
+            SamplerOptions::default(),
+        );
+        let reloaded = cache
+            .load_texture_cached(
+                "cube-diffuse.jpg",
+                TextureKind::Color,
+                SamplerOptions::default(),
+                &device,
+                &queue,
+            )
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+    }
+
+    #[test]
+    fn cache_key_is_canonical() {
+        assert_eq!(
+            cache_key("cube.obj").unwrap(),
+            cache_key("./cube.obj").unwrap()
+        );
+    }
+
+    #[test]
+    fn scaled_import_doubles_bounds() {
+        let mut cube = cube_data(1.0);
+        let before = Aabb::from_points(
+            cube.geometry
+                .vertices
+                .iter()
+                .map(|vertex| vertex.position.into()),
+        );
+
+        cube.transform(&LoadOptions {
+            scale: 2.0,
+            ..Default::default()
+        });
+
+        let after = Aabb::from_points(
+            cube.geometry
+                .vertices
+                .iter()
+                .map(|vertex| vertex.position.into()),
+        );
+        assert_eq!(after.min, before.min * 2.0);
+        assert_eq!(after.max, before.max * 2.0);
+    }
+
+    #[test]
+    fn rotated_import_keeps_normals_normalized() {
+        let mut cube = cube_data(1.0);
+        cube.transform(&LoadOptions {
+            rotation: Quaternion::from_axis_angle(
+                Vector3::new(1.0, 1.0, 0.0).normalize(),
+                Deg(37.0),
+            ),
+            ..Default::default()
+        });
+
+        for vertex in cube.geometry.vertices {
+            assert!((Vector3::from(vertex.normal).magnitude() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn watcher_sees_modified_files() {
+        let directory = env::temp_dir().join(format!("wgpu-renderer-watch-{}", std::process::id()));
+        fs::create_dir_all(directory.join("textures")).unwrap();
+        let texture = directory.join("textures").join("cube.png");
+        fs::write(&texture, "before").unwrap();
+
+        let mut watcher = ResourceWatcher::new(directory.clone(), Duration::ZERO);
+        assert!(!watcher.poll());
+
+        // Pushed forward explicitly, filesystem timestamps can be coarse
+        let file = fs::File::options().write(true).open(&texture).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        drop(file);
+
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// A binary glTF of one triangle in the xy plane, without normals,
+    /// indexed with `indices`.
+    fn triangle_glb(indices: [u16; 3]) -> Vec<u8> {
+        let mut bin: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        bin.extend(indices.iter().flat_map(|index| index.to_le_bytes()));
+        bin.resize(44, 0);
+        let mut json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 44 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }]
+        }"#
+        .as_bytes()
+        .to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut glb = b"glTF".to_vec();
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        for (chunk, kind) in [(&json, b"JSON"), (&bin, b"BIN\0")] {
+            glb.extend((chunk.len() as u32).to_le_bytes());
+            glb.extend(kind);
+            glb.extend(chunk.iter());
+        }
+
+        glb
+    }
+
+    #[test]
+    fn gltf_normals_come_from_the_faces() {
+        let read = |indices| {
+            let (document, buffers, _) = gltf::import_slice(triangle_glb(indices)).unwrap();
+            let mesh = document.meshes().next().unwrap();
+            let mut meshes = vec![];
+            read_gltf_mesh(
+                "triangle.glb",
+                &mesh,
+                Matrix4::one(),
+                0,
+                &buffers,
+                &mut meshes,
+            )
+            .map(|()| meshes)
+        };
+
+        let meshes = read([0, 1, 2]).unwrap();
+        for vertex in &meshes[0].geometry.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
 
-    Ok(Model { meshes, materials })
+        assert!(matches!(
+            read([0, 1, 3]),
+            Err(ModelError::UnsupportedFormat(message)) if message.contains("missing vertex 3")
+        ));
+    }
 }
@@ -0,0 +1,76 @@
+use std::{fmt, ops::AddAssign};
+
+/// What a model costs to keep around and draw. Texture memory is estimated
+/// from dimensions and format rather than what the driver reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModelStats {
+    pub mesh_count: usize,
+    pub vertex_count: u64,
+    pub index_count: u64,
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    pub texture_bytes: u64,
+}
+
+impl ModelStats {
+    pub fn triangle_count(&self) -> u64 {
+        self.index_count / 3
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.vertex_bytes + self.index_bytes + self.texture_bytes
+    }
+}
+
+impl AddAssign for ModelStats {
+    fn add_assign(&mut self, other: Self) {
+        self.mesh_count += other.mesh_count;
+        self.vertex_count += other.vertex_count;
+        self.index_count += other.index_count;
+        self.vertex_bytes += other.vertex_bytes;
+        self.index_bytes += other.index_bytes;
+        self.texture_bytes += other.texture_bytes;
+    }
+}
+
+/// Short form for the overlay, e.g. "12,288 tris / 4.2 MB".
+impl fmt::Display for ModelStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.triangle_count().to_string();
+        let mut triangles = String::with_capacity(digits.len() * 4 / 3);
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                triangles.push(',');
+            }
+            triangles.push(digit);
+        }
+
+        write!(
+            f,
+            "{triangles} tris / {:.1} MB",
+            self.total_bytes() as f64 / 1_000_000.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ModelStats;
+
+    #[test]
+    fn overlay_format() {
+        let stats = ModelStats {
+            index_count: 12_288 * 3,
+            vertex_bytes: 3_000_000,
+            texture_bytes: 1_200_000,
+            ..Default::default()
+        };
+        assert_eq!(stats.to_string(), "12,288 tris / 4.2 MB");
+
+        let stats = ModelStats {
+            index_count: 36,
+            ..Default::default()
+        };
+        assert_eq!(stats.to_string(), "12 tris / 0.0 MB");
+    }
+}
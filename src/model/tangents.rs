@@ -0,0 +1,224 @@
+use super::ModelVertex;
+use cgmath::{InnerSpace, Vector3};
+
+/// Fills in per-vertex tangents and bitangents from the triangles' uv
+/// gradients, averaged over every triangle sharing the vertex. Vertices
+/// without a usable gradient get an arbitrary basis around their normal.
+pub fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    compute_tangents_with_progress(vertices, indices, None);
+}
+
+/// Indices handled between progress reports, a whole number of triangles.
+const PROGRESS_CHUNK: usize = 3 * 65536;
+
+/// [`compute_tangents`], reporting the fraction of triangles done every
+/// [`PROGRESS_CHUNK`] indices.
+pub fn compute_tangents_with_progress(
+    vertices: &mut [ModelVertex],
+    indices: &[u32],
+    progress: Option<&dyn Fn(f32)>,
+) {
+    let mut triangles_included = vec![0; vertices.len()];
+
+    for (chunk_index, chunk) in indices.chunks(PROGRESS_CHUNK).enumerate() {
+        if let Some(progress) = progress {
+            progress((chunk_index * PROGRESS_CHUNK) as f32 / indices.len() as f32);
+        }
+
+        accumulate_tangents(vertices, chunk, &mut triangles_included);
+    }
+
+    if let Some(progress) = progress {
+        progress(1.0);
+    }
+
+    // Average the tangents/bitangents
+    for (i, n) in triangles_included.into_iter().enumerate() {
+        let vertex = &mut vertices[i];
+
+        if n == 0 {
+            let normal = Vector3::from(vertex.normal);
+            let reference = match normal.x.abs() > 0.9 {
+                true => Vector3::unit_y(),
+                false => Vector3::unit_x(),
+            };
+            let tangent = normal.cross(reference).normalize();
+            vertex.tangent = tangent.into();
+            vertex.bitangent = normal.cross(tangent).into();
+
+            continue;
+        }
+
+        let denom = 1.0 / n as f32;
+        vertex.tangent = (cgmath::Vector3::from(vertex.tangent) * denom).into();
+        vertex.bitangent = (cgmath::Vector3::from(vertex.bitangent) * denom).into();
+    }
+}
+
+/// Sums every triangle's tangent basis into its vertices, counting how many
+/// triangles touched each so they can be averaged.
+fn accumulate_tangents(
+    vertices: &mut [ModelVertex],
+    indices: &[u32],
+    triangles_included: &mut [u32],
+) {
+    // Calculate tangents and bitangets. We're going to
+    // use the triangles, so we need to loop through the
+    // indices in chunks of 3
+    for c in indices.chunks(3) {
+        let v0 = vertices[c[0] as usize];
+        let v1 = vertices[c[1] as usize];
+        let v2 = vertices[c[2] as usize];
+
+        let pos0: cgmath::Vector3<_> = v0.position.into();
+        let pos1: cgmath::Vector3<_> = v1.position.into();
+        let pos2: cgmath::Vector3<_> = v2.position.into();
+
+        let uv0: cgmath::Vector2<_> = v0.texture_coordinates.into();
+        let uv1: cgmath::Vector2<_> = v1.texture_coordinates.into();
+        let uv2: cgmath::Vector2<_> = v2.texture_coordinates.into();
+
+        // Calculate the edges of the triangle
+        let delta_pos1 = pos1 - pos0;
+        let delta_pos2 = pos2 - pos0;
+
+        // This will give us a direction to calculate the
+        // tangent and bitangent
+        let delta_uv1 = uv1 - uv0;
+        let delta_uv2 = uv2 - uv0;
+
+        // Solving the following system of equations will
+        // give us the tangent and bitangent.
+        //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
+        //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+        // Luckily, the place I found this equation provided
+        // the solution!
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+
+        // Meshes without texture coordinates have no uv gradient to follow,
+        // those vertices get an arbitrary basis below
+        if determinant.abs() <= f32::EPSILON {
+            continue;
+        }
+
+        let r = 1.0 / determinant;
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        // We flip the bitangent to enable right-handed normal
+        // maps with wgpu texture coordinate system
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+        // We'll use the same tangent/bitangent for each vertex in the triangle
+        vertices[c[0] as usize].tangent =
+            (tangent + cgmath::Vector3::from(vertices[c[0] as usize].tangent)).into();
+        vertices[c[1] as usize].tangent =
+            (tangent + cgmath::Vector3::from(vertices[c[1] as usize].tangent)).into();
+        vertices[c[2] as usize].tangent =
+            (tangent + cgmath::Vector3::from(vertices[c[2] as usize].tangent)).into();
+        vertices[c[0] as usize].bitangent =
+            (bitangent + cgmath::Vector3::from(vertices[c[0] as usize].bitangent)).into();
+        vertices[c[1] as usize].bitangent =
+            (bitangent + cgmath::Vector3::from(vertices[c[1] as usize].bitangent)).into();
+        vertices[c[2] as usize].bitangent =
+            (bitangent + cgmath::Vector3::from(vertices[c[2] as usize].bitangent)).into();
+
+        // Used to average the tangents/bitangents
+        triangles_included[c[0] as usize] += 1;
+        triangles_included[c[1] as usize] += 1;
+        triangles_included[c[2] as usize] += 1;
+    }
+}
+
+/// Whether a mesh was built without tangents, all of them still zeroed.
+pub fn missing_tangents(vertices: &[ModelVertex]) -> bool {
+    vertices
+        .iter()
+        .all(|vertex| vertex.tangent == [0.0; 3] && vertex.bitangent == [0.0; 3])
+}
+
+/// Replaces the normals with the area weighted average of the faces sharing
+/// each vertex, for formats that don't always store them.
+pub fn compute_normals(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+
+    for c in indices.chunks(3) {
+        let pos0 = Vector3::from(vertices[c[0] as usize].position);
+        let pos1 = Vector3::from(vertices[c[1] as usize].position);
+        let pos2 = Vector3::from(vertices[c[2] as usize].position);
+
+        // The cross product's length is twice the triangle's area, so larger
+        // faces weigh in more
+        let normal = (pos1 - pos0).cross(pos2 - pos0);
+        for &index in c {
+            normals[index as usize] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = match normal.magnitude2() > 0.0 {
+            true => normal.normalize().into(),
+            false => [0.0, 1.0, 0.0],
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compute_normals, compute_tangents, compute_tangents_with_progress};
+    use crate::model::primitives::cube_data;
+    use cgmath::{InnerSpace, Vector3};
+    use std::cell::RefCell;
+
+    #[test]
+    fn cube_tangents() {
+        let cube = cube_data(1.0);
+        let mut vertices = cube.geometry.vertices.clone();
+        for vertex in &mut vertices {
+            vertex.tangent = [0.0; 3];
+            vertex.bitangent = [0.0; 3];
+        }
+
+        compute_tangents(&mut vertices, &cube.geometry.indices);
+
+        let epsilon = 1e-5;
+        for (computed, expected) in vertices.iter().zip(&cube.geometry.vertices) {
+            for (computed, expected) in [
+                (computed.tangent, expected.tangent),
+                (computed.bitangent, expected.bitangent),
+            ] {
+                let difference = Vector3::from(computed).normalize() - Vector3::from(expected);
+                assert!(difference.magnitude() < epsilon);
+            }
+        }
+    }
+
+    #[test]
+    fn tangent_progress() {
+        let cube = cube_data(1.0);
+        let mut vertices = cube.geometry.vertices.clone();
+        let reports = RefCell::new(vec![]);
+
+        compute_tangents_with_progress(
+            &mut vertices,
+            &cube.geometry.indices,
+            Some(&|fraction| reports.borrow_mut().push(fraction)),
+        );
+
+        // A single chunk for a cube, started and finished
+        assert_eq!(reports.into_inner(), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn cube_normals() {
+        let cube = cube_data(1.0);
+        let mut vertices = cube.geometry.vertices.clone();
+        for vertex in &mut vertices {
+            vertex.normal = [0.0; 3];
+        }
+
+        compute_normals(&mut vertices, &cube.geometry.indices);
+
+        for (computed, expected) in vertices.iter().zip(&cube.geometry.vertices) {
+            assert_eq!(computed.normal, expected.normal);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Vector3, Vector4};
+
+/// Scalar material properties, multiplied with the sampled textures in the
+/// standard and PBR shaders. Diffuse alpha is the material's opacity, and
+/// doubles as the base color for PBR.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct MaterialUniform {
+    pub diffuse: Vector4<f32>,
+    pub specular: Vector3<f32>,
+    /// The Blinn-Phong exponent, higher giving smaller, sharper highlights.
+    pub shininess: f32,
+    /// Scales `specular`, for tuning highlights without touching its color.
+    pub specular_strength: f32,
+    /// PBR only, 0 for dielectrics and 1 for bare metal.
+    pub metallic: f32,
+    /// PBR only, from mirror-like at 0 to fully diffuse at 1.
+    pub roughness: f32,
+    _padding: f32,
+    /// Light the material gives off itself, added after lighting. Above 1 it
+    /// isn't clamped until the output, so a bloom pass can pick it out.
+    pub emissive: Vector3<f32>,
+    _emissive_padding: f32,
+}
+
+impl MaterialUniform {
+    /// Shininess used when a format doesn't specify one.
+    pub const DEFAULT_SHININESS: f32 = 32.0;
+
+    /// The roughness is matched to the shininess, so Phong materials drawn
+    /// with the PBR shader keep highlights of about the same size.
+    pub fn new(diffuse: Vector4<f32>, specular: Vector3<f32>, shininess: f32) -> Self {
+        // An exponent of 0 would light every fragment fully
+        let shininess = shininess.max(1.0);
+
+        Self {
+            diffuse,
+            specular,
+            shininess,
+            specular_strength: 1.0,
+            metallic: 0.0,
+            roughness: shininess_roughness(shininess),
+            _padding: 0.0,
+            emissive: Vector3::new(0.0, 0.0, 0.0),
+            _emissive_padding: 0.0,
+        }
+    }
+
+    pub fn with_shininess(mut self, shininess: f32) -> Self {
+        self.shininess = shininess.max(1.0);
+
+        self
+    }
+
+    pub fn with_specular_strength(mut self, specular_strength: f32) -> Self {
+        self.specular_strength = specular_strength.max(0.0);
+
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic.clamp(0.0, 1.0);
+
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness.clamp(0.0, 1.0);
+
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Vector3<f32>) -> Self {
+        self.emissive = emissive;
+
+        self
+    }
+
+    pub fn with_diffuse(mut self, diffuse: [f32; 4]) -> Self {
+        self.diffuse = diffuse.into();
+
+        self
+    }
+
+    /// Partially transparent materials need the blended pass.
+    pub fn is_transparent(&self) -> bool {
+        self.diffuse.w < 1.0
+    }
+}
+
+/// The usual Blinn-Phong to Beckmann conversion, whose slope roughness GGX's
+/// squared roughness stands in for.
+fn shininess_roughness(shininess: f32) -> f32 {
+    (2.0 / (shininess + 2.0)).sqrt().sqrt()
+}
+
+impl Default for MaterialUniform {
+    fn default() -> Self {
+        Self::new(
+            Vector4::new(1.0, 1.0, 1.0, 1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            Self::DEFAULT_SHININESS,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MaterialUniform;
+    use cgmath::{Vector3, Vector4};
+    use std::ptr;
+
+    #[test]
+    fn aligned() {
+        let size = std::mem::size_of::<MaterialUniform>();
+        println!("Size of [MaterialUniform] {size} bytes");
+        assert_eq!(size, 64);
+
+        // vec3 specular packs with the trailing f32, like the WGSL struct,
+        // while emissive starts a new 16 bytes after the roughness
+        let uniform = MaterialUniform::default();
+        let diffuse_ptr = ptr::addr_of!(uniform.diffuse).cast::<u8>();
+        let specular_ptr = ptr::addr_of!(uniform.specular).cast::<u8>();
+        let shininess_ptr = ptr::addr_of!(uniform.shininess).cast::<u8>();
+        let strength_ptr = ptr::addr_of!(uniform.specular_strength).cast::<u8>();
+        assert_eq!(unsafe { specular_ptr.offset_from(diffuse_ptr) }, 16);
+        assert_eq!(unsafe { shininess_ptr.offset_from(diffuse_ptr) }, 28);
+        assert_eq!(unsafe { strength_ptr.offset_from(diffuse_ptr) }, 32);
+        let roughness_ptr = ptr::addr_of!(uniform.roughness).cast::<u8>();
+        assert_eq!(unsafe { roughness_ptr.offset_from(diffuse_ptr) }, 40);
+        let emissive_ptr = ptr::addr_of!(uniform.emissive).cast::<u8>();
+        assert_eq!(unsafe { emissive_ptr.offset_from(diffuse_ptr) }, 48);
+    }
+
+    #[test]
+    fn roughness_follows_shininess() {
+        let sharp = MaterialUniform::default().with_shininess(512.0);
+        let broad = MaterialUniform::new(
+            Vector4::new(1.0, 1.0, 1.0, 1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            4.0,
+        );
+        assert!(broad.roughness > MaterialUniform::default().roughness);
+        // Overriding the shininess afterwards leaves the roughness alone
+        assert_eq!(sharp.roughness, MaterialUniform::default().roughness);
+        assert_eq!(broad.with_roughness(2.0).roughness, 1.0);
+    }
+}
@@ -1,86 +1,624 @@
+//! Render and compute pipelines that keep what they were created from, so
+//! they can be rebuilt faithfully from an edited shader, see
+//! [`ShaderWatcher`](crate::shader_watcher::ShaderWatcher).
+
+use crate::shader::{Shader, ShaderKey};
+use std::{fmt, iter, ops::Deref};
 use wgpu::{
-    Device, PipelineLayout, PrimitiveTopology, RenderPipeline, ShaderModule, TextureFormat,
+    BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, CompareFunction,
+    ComputePipelineDescriptor, Device, ErrorFilter, PipelineLayout, PipelineLayoutDescriptor,
+    PolygonMode, PrimitiveTopology, PushConstantRange, RenderPipeline, ShaderModule, TextureFormat,
     VertexBufferLayout,
 };
 
-pub struct Pipeline {
-    shader: ShaderModule,
-    layout: PipelineLayout,
-    inner: RenderPipeline,
+/// How a pipeline's output is combined with what's already drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Replaces it.
+    #[default]
+    Opaque,
+    /// Covers it by the output's alpha.
+    AlphaBlend,
+    /// Brightens it, scaled by the output's alpha.
+    Additive,
+}
+
+impl BlendMode {
+    pub fn state(self) -> BlendState {
+        match self {
+            Self::Opaque => BlendState::REPLACE,
+            Self::AlphaBlend => BlendState::ALPHA_BLENDING,
+            Self::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+        }
+    }
+
+    /// Blended output is tested against the depth without writing it, so
+    /// whatever's drawn behind it afterwards still shows through.
+    pub fn writes_depth(self) -> bool {
+        self == Self::Opaque
+    }
+}
+
+/// Everything about a pipeline but its shader and layout.
+#[derive(Clone, Debug)]
+pub struct PipelineOptions {
+    pub label: &'static str,
+    /// `None` for a depth-only pipeline.
+    pub color_format: Option<TextureFormat>,
+    pub depth_format: Option<TextureFormat>,
+    pub vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    pub topology: PrimitiveTopology,
+    pub polygon_mode: PolygonMode,
+    pub blend_mode: BlendMode,
+    /// Off to test against the depth without writing it even when opaque,
+    /// see [`BlendMode::writes_depth`].
+    pub depth_write: bool,
+    pub depth_compare: CompareFunction,
+    /// For the layout from [`PipelineOptions::layout`].
+    pub push_constant_ranges: Vec<PushConstantRange>,
 }
 
-pub struct PipelineOptions<'a> {
-    label: Option<&'a str>,
-    device: &'a Device,
+impl PipelineOptions {
+    /// Filled, opaque triangle lists without a depth attachment.
+    pub fn new(
+        label: &'static str,
+        color_format: TextureFormat,
+        vertex_layouts: &[VertexBufferLayout<'static>],
+    ) -> Self {
+        Self::with_targets(label, Some(color_format), vertex_layouts)
+    }
+
+    /// Only writes `depth_format`, for a pipeline without a fragment stage,
+    /// see [`Pipeline::with_stages`].
+    pub fn depth_only(
+        label: &'static str,
+        depth_format: TextureFormat,
+        vertex_layouts: &[VertexBufferLayout<'static>],
+    ) -> Self {
+        Self::with_targets(label, None, vertex_layouts).with_depth_format(depth_format)
+    }
+
+    fn with_targets(
+        label: &'static str,
+        color_format: Option<TextureFormat>,
+        vertex_layouts: &[VertexBufferLayout<'static>],
+    ) -> Self {
+        Self {
+            label,
+            color_format,
+            depth_format: None,
+            vertex_layouts: vertex_layouts.to_vec(),
+            topology: PrimitiveTopology::TriangleList,
+            polygon_mode: PolygonMode::Fill,
+            blend_mode: BlendMode::Opaque,
+            depth_write: true,
+            depth_compare: CompareFunction::Less,
+            push_constant_ranges: vec![],
+        }
+    }
+
+    pub fn with_depth_format(self, depth_format: TextureFormat) -> Self {
+        Self {
+            depth_format: Some(depth_format),
+            ..self
+        }
+    }
+
+    pub fn with_topology(self, topology: PrimitiveTopology) -> Self {
+        Self { topology, ..self }
+    }
+
+    pub fn with_polygon_mode(self, polygon_mode: PolygonMode) -> Self {
+        Self {
+            polygon_mode,
+            ..self
+        }
+    }
+
+    pub fn with_blend_mode(self, blend_mode: BlendMode) -> Self {
+        Self { blend_mode, ..self }
+    }
+
+    pub fn without_depth_write(self) -> Self {
+        Self {
+            depth_write: false,
+            ..self
+        }
+    }
+
+    /// E.g. [`CompareFunction::Equal`] to shade only what's left of a
+    /// depth pre-pass, usually [`PipelineOptions::without_depth_write`] too.
+    pub fn with_depth_compare(self, depth_compare: CompareFunction) -> Self {
+        Self {
+            depth_compare,
+            ..self
+        }
+    }
+
+    pub fn with_push_constant_ranges(self, push_constant_ranges: Vec<PushConstantRange>) -> Self {
+        Self {
+            push_constant_ranges,
+            ..self
+        }
+    }
+
+    /// A layout with `bind_group_layouts` and the push constant ranges.
+    pub fn layout(
+        &self,
+        device: &Device,
+        bind_group_layouts: &[&BindGroupLayout],
+    ) -> PipelineLayout {
+        device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&format!("{} layout", self.label)),
+            bind_group_layouts,
+            push_constant_ranges: &self.push_constant_ranges,
+        })
+    }
+
+    fn writes_depth(&self) -> bool {
+        self.depth_write && self.blend_mode.writes_depth()
+    }
+}
+
+/// An entry point of a shader, one of a pipeline's stages.
+#[derive(Clone)]
+pub struct ShaderStage {
+    pub shader: Shader,
+    pub entry_point: &'static str,
+}
+
+impl ShaderStage {
+    pub fn new(shader: &Shader, entry_point: &'static str) -> Self {
+        Self {
+            shader: shader.clone(),
+            entry_point,
+        }
+    }
+
+    pub fn key(&self) -> StageKey {
+        StageKey {
+            shader: self.shader.key.clone(),
+            entry_point: self.entry_point,
+        }
+    }
+
+    /// Its shader replaced by the one in `shaders` with the same key, if
+    /// there is one.
+    fn reloaded(&self, shaders: &[Shader]) -> Self {
+        match shaders.iter().find(|shader| shader.key == self.shader.key) {
+            Some(shader) => Self::new(shader, self.entry_point),
+            None => self.clone(),
+        }
+    }
+}
+
+/// Tells stages apart by their entry points as well as their shaders, since
+/// pipelines can share a shader while running different parts of it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StageKey {
+    pub shader: ShaderKey,
+    pub entry_point: &'static str,
+}
+
+impl fmt::Display for StageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.shader, self.entry_point)
+    }
+}
+
+/// What [`Pipeline`] and [`ComputePipeline`] share, for rebuilding either
+/// from reloaded shaders.
+pub trait Rebuild {
+    /// What each of its stages runs, for matching them with reloaded
+    /// shaders.
+    fn stages(&self) -> Vec<StageKey>;
+
+    fn label(&self) -> &'static str;
+
+    /// Recreates the pipeline with each of `shaders` in place of its own
+    /// with the same key. The current pipeline is kept when that fails,
+    /// e.g. for a shader whose bindings no longer match the layout.
+    fn rebuild(&mut self, device: &Device, shaders: &[Shader]) -> Result<(), wgpu::Error>;
+}
+
+/// Derefs to the [`RenderPipeline`] for binding.
+pub struct Pipeline {
+    vertex: ShaderStage,
+    /// `None` for a depth-only pipeline.
+    fragment: Option<ShaderStage>,
     layout: PipelineLayout,
-    color_format: TextureFormat,
-    depth_format: Option<TextureFormat>,
-    vertex_layouts: &'a [VertexBufferLayout<'a>],
-    shader: ShaderModule,
-    topology: Option<PrimitiveTopology>,
+    options: PipelineOptions,
+    inner: RenderPipeline,
 }
 
 impl Pipeline {
-    pub fn new<'a>(
-        PipelineOptions {
-            label,
+    /// With `shader`'s `vs_main` and `fs_main` as its entry points.
+    pub fn new(
+        device: &Device,
+        shader: &Shader,
+        layout: PipelineLayout,
+        options: PipelineOptions,
+    ) -> Self {
+        Self::with_stages(
             device,
+            ShaderStage::new(shader, "vs_main"),
+            Some(ShaderStage::new(shader, "fs_main")),
             layout,
-            color_format,
-            depth_format,
-            vertex_layouts,
-            shader,
-            topology,
-        }: PipelineOptions<'a>,
+            options,
+        )
+    }
+
+    /// With stages from shaders of their own, e.g. to share a vertex stage
+    /// between fragment stages. Without a fragment stage it only writes the
+    /// depth, see [`PipelineOptions::depth_only`].
+    pub fn with_stages(
+        device: &Device,
+        vertex: ShaderStage,
+        fragment: Option<ShaderStage>,
+        layout: PipelineLayout,
+        options: PipelineOptions,
     ) -> Self {
-        let inner = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        Self {
+            inner: create_pipeline(device, &layout, &vertex, fragment.as_ref(), &options),
+            vertex,
+            fragment,
+            layout,
+            options,
+        }
+    }
+}
+
+impl Rebuild for Pipeline {
+    fn stages(&self) -> Vec<StageKey> {
+        iter::once(&self.vertex)
+            .chain(&self.fragment)
+            .map(ShaderStage::key)
+            .collect()
+    }
+
+    fn label(&self) -> &'static str {
+        self.options.label
+    }
+
+    fn rebuild(&mut self, device: &Device, shaders: &[Shader]) -> Result<(), wgpu::Error> {
+        let vertex = self.vertex.reloaded(shaders);
+        let fragment = self.fragment.as_ref().map(|stage| stage.reloaded(shaders));
+        self.inner = validated(device, || {
+            create_pipeline(
+                device,
+                &self.layout,
+                &vertex,
+                fragment.as_ref(),
+                &self.options,
+            )
+        })?;
+        (self.vertex, self.fragment) = (vertex, fragment);
+
+        Ok(())
+    }
+}
+
+impl Deref for Pipeline {
+    type Target = RenderPipeline;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// Everything about a compute pipeline but its shader and bind group
+/// layouts.
+#[derive(Clone, Debug)]
+pub struct ComputePipelineOptions {
+    pub label: &'static str,
+    pub entry_point: &'static str,
+}
+
+impl ComputePipelineOptions {
+    /// With `cs_main` as its entry point.
+    pub fn new(label: &'static str) -> Self {
+        Self {
             label,
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: vertex_layouts,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: color_format,
-                    blend: Some(wgpu::BlendState {
-                        alpha: wgpu::BlendComponent::REPLACE,
-                        color: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: topology.unwrap_or(PrimitiveTopology::TriangleList),
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
-                format,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+            entry_point: "cs_main",
+        }
+    }
+}
+
+/// Like [`Pipeline`], derefs to the [`wgpu::ComputePipeline`] for
+/// dispatching.
+pub struct ComputePipeline {
+    shader: Shader,
+    layout: PipelineLayout,
+    options: ComputePipelineOptions,
+    inner: wgpu::ComputePipeline,
+}
+
+impl ComputePipeline {
+    /// With a layout of `bind_group_layouts`, in order.
+    pub fn new(
+        device: &Device,
+        shader: &Shader,
+        bind_group_layouts: &[&BindGroupLayout],
+        options: ComputePipelineOptions,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&format!("{} layout", options.label)),
+            bind_group_layouts,
+            push_constant_ranges: &[],
         });
 
         Self {
-            shader,
+            inner: create_compute_pipeline(device, &layout, &shader.module, &options),
+            shader: shader.clone(),
             layout,
-            inner,
+            options,
+        }
+    }
+}
+
+impl Rebuild for ComputePipeline {
+    fn stages(&self) -> Vec<StageKey> {
+        vec![StageKey {
+            shader: self.shader.key.clone(),
+            entry_point: self.options.entry_point,
+        }]
+    }
+
+    fn label(&self) -> &'static str {
+        self.options.label
+    }
+
+    fn rebuild(&mut self, device: &Device, shaders: &[Shader]) -> Result<(), wgpu::Error> {
+        let Some(shader) = shaders.iter().find(|shader| shader.key == self.shader.key) else {
+            return Ok(());
+        };
+        self.inner = validated(device, || {
+            create_compute_pipeline(device, &self.layout, &shader.module, &self.options)
+        })?;
+        self.shader = shader.clone();
+
+        Ok(())
+    }
+}
+
+impl Deref for ComputePipeline {
+    type Target = wgpu::ComputePipeline;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// What `create` made, unless it raised a validation error.
+fn validated<T>(device: &Device, create: impl FnOnce() -> T) -> Result<T, wgpu::Error> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let created = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error),
+        None => Ok(created),
+    }
+}
+
+fn create_compute_pipeline(
+    device: &Device,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    options: &ComputePipelineOptions,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(options.label),
+        layout: Some(layout),
+        module: shader,
+        entry_point: options.entry_point,
+    })
+}
+
+fn create_pipeline(
+    device: &Device,
+    layout: &PipelineLayout,
+    vertex: &ShaderStage,
+    fragment: Option<&ShaderStage>,
+    options: &PipelineOptions,
+) -> RenderPipeline {
+    let targets: Vec<_> = options
+        .color_format
+        .map(|format| wgpu::ColorTargetState {
+            format,
+            blend: Some(options.blend_mode.state()),
+            write_mask: wgpu::ColorWrites::ALL,
+        })
+        .into_iter()
+        .map(Some)
+        .collect();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(options.label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &vertex.shader.module,
+            entry_point: vertex.entry_point,
+            buffers: &options.vertex_layouts,
+        },
+        fragment: fragment.map(|stage| wgpu::FragmentState {
+            module: &stage.shader.module,
+            entry_point: stage.entry_point,
+            targets: &targets,
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: options.topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: options.polygon_mode,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: options.depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: options.writes_depth(),
+            depth_compare: options.depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlendMode, PipelineOptions};
+    use wgpu::{BlendFactor, BlendState, TextureFormat};
+
+    #[test]
+    fn only_opaque_pipelines_write_depth() {
+        assert_eq!(BlendMode::default().state(), BlendState::REPLACE);
+        assert!(BlendMode::Opaque.writes_depth());
+        for mode in [BlendMode::AlphaBlend, BlendMode::Additive] {
+            assert!(!mode.writes_depth());
+        }
+        assert_eq!(
+            BlendMode::Additive.state().color.dst_factor,
+            BlendFactor::One
+        );
+
+        let options = PipelineOptions::new("Test pipeline", TextureFormat::Rgba8Unorm, &[]);
+        assert_eq!(options.blend_mode, BlendMode::Opaque);
+        assert!(options.writes_depth());
+        assert!(!options.clone().without_depth_write().writes_depth());
+        let options = options.with_blend_mode(BlendMode::AlphaBlend);
+        assert_eq!(options.blend_mode.state(), BlendState::ALPHA_BLENDING);
+        assert!(!options.writes_depth());
+
+        let depth_only =
+            PipelineOptions::depth_only("Test pre-pass", TextureFormat::Depth32Float, &[]);
+        assert_eq!(depth_only.color_format, None);
+        assert_eq!(depth_only.depth_format, Some(TextureFormat::Depth32Float));
+        assert!(depth_only.writes_depth());
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn failed_rebuilds_keep_the_pipeline() {
+        use super::{Pipeline, Rebuild};
+        use crate::texture::test_device;
+        use wgpu::PipelineLayoutDescriptor;
+
+        let (device, _) = test_device();
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let flat = "
+            @vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }
+            @fragment fn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }
+        ";
+        let options = PipelineOptions::new("Test pipeline", TextureFormat::Rgba8Unorm, &[]);
+        let flat = test_shader(&device, "test.wgsl", flat);
+        let mut pipeline = Pipeline::new(&device, &flat, layout, options);
+        let id = pipeline.global_id();
+
+        // Reads a uniform the layout has no binding for
+        let unbound = "
+            @group(0) @binding(0) var<uniform> color: vec4<f32>;
+            @vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }
+            @fragment fn fs_main() -> @location(0) vec4<f32> { return color; }
+        ";
+        let unbound = test_shader(&device, "test.wgsl", unbound);
+        assert!(pipeline.rebuild(&device, &[unbound]).is_err());
+        assert_eq!(pipeline.global_id(), id);
+
+        pipeline.rebuild(&device, &[flat]).unwrap();
+        assert_ne!(pipeline.global_id(), id);
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn stages_come_from_their_own_shaders() {
+        use super::{Pipeline, Rebuild, ShaderStage};
+        use crate::texture::{test_device, Texture};
+        use wgpu::{CompareFunction, PipelineLayoutDescriptor};
+
+        let (device, _) = test_device();
+        let layout = || {
+            device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            })
+        };
+        let vertex = "
+            @vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }
+        ";
+        let vertex = ShaderStage::new(&test_shader(&device, "vertex.wgsl", vertex), "vs_main");
+        let fragments = "
+            @fragment fn fs_red() -> @location(0) vec4<f32> { return vec4<f32>(1.0, 0.0, 0.0, 1.0); }
+            @fragment fn fs_blue() -> @location(0) vec4<f32> { return vec4<f32>(0.0, 0.0, 1.0, 1.0); }
+        ";
+        let fragments = test_shader(&device, "fragments.wgsl", fragments);
+        let options = |label| PipelineOptions::new(label, TextureFormat::Rgba8Unorm, &[]);
+
+        // One vertex stage shared by fragment stages of another shader
+        let mut red = Pipeline::with_stages(
+            &device,
+            vertex.clone(),
+            Some(ShaderStage::new(&fragments, "fs_red")),
+            layout(),
+            options("Red pipeline"),
+        );
+        let blue = Pipeline::with_stages(
+            &device,
+            vertex.clone(),
+            Some(ShaderStage::new(&fragments, "fs_blue")),
+            layout(),
+            options("Blue pipeline"),
+        );
+        assert_eq!(red.stages()[0], blue.stages()[0]);
+        assert_ne!(red.stages()[1], blue.stages()[1]);
+        assert_eq!(red.stages()[1].to_string(), "fragments.wgsl fs_red");
+
+        // Only the fragment shader reloaded, the vertex stage keeps its own
+        red.rebuild(&device, &[fragments]).unwrap();
+        assert_eq!(red.stages()[0], vertex.key());
+
+        let depth_only = Pipeline::with_stages(
+            &device,
+            vertex,
+            None,
+            layout(),
+            PipelineOptions::depth_only("Test pre-pass", Texture::DEPTH_FORMAT, &[])
+                .with_depth_compare(CompareFunction::Equal),
+        );
+        assert_eq!(depth_only.stages().len(), 1);
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    fn test_shader(
+        device: &wgpu::Device,
+        file: &'static str,
+        source: &str,
+    ) -> crate::shader::Shader {
+        use crate::shader::{Shader, ShaderDefines, ShaderKey};
+        use std::sync::Arc;
+        use wgpu::{ShaderModuleDescriptor, ShaderSource};
+
+        Shader {
+            key: ShaderKey::new(file, ShaderDefines::default()),
+            module: Arc::new(device.create_shader_module(ShaderModuleDescriptor {
+                label: Some(file),
+                source: ShaderSource::Wgsl(source.into()),
+            })),
         }
     }
 }
@@ -0,0 +1,269 @@
+//! Which adapter the renderer runs on, for machines with more than one and
+//! those with none on the primary backends.
+
+use super::{RendererError, RendererResult};
+use std::{env, fmt, str::FromStr};
+use thiserror::Error;
+use wgpu::{
+    Adapter, AdapterInfo, Backends, CreateSurfaceError, DeviceType, Instance, InstanceDescriptor,
+    PowerPreference, RequestAdapterOptions, Surface,
+};
+
+/// Overrides the [`AdapterPreference`] a renderer's created with, picking
+/// the first adapter whose name contains its value.
+pub const ADAPTER_VARIABLE: &str = "WGPU_RENDERER_ADAPTER";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdapterPreference {
+    /// Usually the discrete GPU on laptops with two.
+    #[default]
+    HighPerformance,
+    LowPower,
+    /// The first adapter whose name contains this, ignoring case.
+    ByName(String),
+}
+
+impl AdapterPreference {
+    /// [`ADAPTER_VARIABLE`]'s name if it's set, otherwise `self`.
+    pub fn or_env(self) -> Self {
+        match env::var(ADAPTER_VARIABLE) {
+            Ok(name) if !name.trim().is_empty() => Self::ByName(name.trim().to_owned()),
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for AdapterPreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HighPerformance => write!(f, "high performance"),
+            Self::LowPower => write!(f, "low power"),
+            Self::ByName(name) => write!(f, "{name:?}"),
+        }
+    }
+}
+
+/// Where adapters are looked for, tried in [`BackendChoice::CHAIN`]'s order
+/// unless `--backend` forces one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendChoice {
+    /// Vulkan, DX12, DX11 or Metal, whichever the platform has.
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+    /// A software rasterizer such as llvmpipe or WARP, on any backend.
+    Fallback,
+}
+
+impl BackendChoice {
+    pub const CHAIN: [Self; 3] = [Self::Primary, Self::Gl, Self::Fallback];
+
+    pub fn backends(self) -> Backends {
+        match self {
+            Self::Primary => Backends::VULKAN | Backends::DX12 | Backends::DX11 | Backends::METAL,
+            Self::Vulkan => Backends::VULKAN,
+            Self::Metal => Backends::METAL,
+            Self::Dx12 => Backends::DX12,
+            Self::Dx11 => Backends::DX11,
+            Self::Gl => Backends::GL,
+            Self::Fallback => Backends::all(),
+        }
+    }
+}
+
+impl fmt::Display for BackendChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Primary => "primary",
+            Self::Vulkan => "vulkan",
+            Self::Metal => "metal",
+            Self::Dx12 => "dx12",
+            Self::Dx11 => "dx11",
+            Self::Gl => "gl",
+            Self::Fallback => "fallback",
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown backend {0:?}, expected primary, vulkan, metal, dx12, dx11, gl or fallback")]
+pub struct UnknownBackend(String);
+
+impl FromStr for BackendChoice {
+    type Err = UnknownBackend;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [
+            Self::CHAIN.as_slice(),
+            &[Self::Vulkan, Self::Metal, Self::Dx12, Self::Dx11],
+        ]
+        .concat()
+        .into_iter()
+        .find(|backend| backend.to_string().eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| UnknownBackend(name.to_owned()))
+    }
+}
+
+/// How [`select_adapter`] picks an adapter.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AdapterOptions {
+    pub preference: AdapterPreference,
+    /// Only looks here, `None` falls back along [`BackendChoice::CHAIN`].
+    pub backend: Option<BackendChoice>,
+}
+
+/// Goes through the backends until one has an adapter `options` pick that
+/// can draw to the surface `create_surface` makes, if it makes one. Every
+/// adapter looked at is listed along the way. The instance is returned to
+/// make surfaces for the adapter later on.
+pub async fn select_adapter(
+    options: &AdapterOptions,
+    mut create_surface: impl FnMut(&Instance) -> Result<Option<Surface>, CreateSurfaceError>,
+) -> RendererResult<(Instance, Option<Surface>, Adapter)> {
+    let chain = match options.backend {
+        Some(backend) => vec![backend],
+        None => BackendChoice::CHAIN.to_vec(),
+    };
+    let mut found = vec![];
+    for backend in chain {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: backend.backends(),
+            ..Default::default()
+        });
+        let surface = match create_surface(&instance) {
+            Ok(surface) => surface,
+            Err(error) => {
+                println!("Skipped the {backend} backends, failed to create a surface: {error}");
+                continue;
+            }
+        };
+
+        let adapter = pick(&instance, backend, surface.as_ref(), &options.preference).await;
+        let adapter = match adapter {
+            Ok(adapter) => adapter,
+            Err(adapters) => {
+                println!("No adapter on the {backend} backends");
+                for adapter in adapters {
+                    if !found.contains(&adapter) {
+                        found.push(adapter);
+                    }
+                }
+                continue;
+            }
+        };
+        println!("Using the {backend} backends");
+
+        return Ok((instance, surface, adapter));
+    }
+
+    Err(RendererError::NoAdapter {
+        preference: options.preference.clone(),
+        adapters: found,
+    })
+}
+
+/// The adapter `preference` picks on `backend`, or else the descriptions of
+/// the adapters that were there.
+async fn pick(
+    instance: &Instance,
+    backend: BackendChoice,
+    surface: Option<&Surface>,
+    preference: &AdapterPreference,
+) -> Result<Adapter, Vec<String>> {
+    let force_fallback_adapter = backend == BackendChoice::Fallback;
+    let fits = |adapter: &Adapter| {
+        surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+            && (!force_fallback_adapter || adapter.get_info().device_type == DeviceType::Cpu)
+    };
+    let mut adapters = instance
+        .enumerate_adapters(backend.backends())
+        .collect::<Vec<_>>();
+    for adapter in &adapters {
+        let unsupported = match fits(adapter) {
+            true => "",
+            false if force_fallback_adapter => ", not a software adapter",
+            false => ", can't draw to the surface",
+        };
+        println!(
+            "Discovered adapter: {}{unsupported}",
+            describe(&adapter.get_info())
+        );
+    }
+
+    let power_preference = match preference {
+        AdapterPreference::HighPerformance => PowerPreference::HighPerformance,
+        AdapterPreference::LowPower => PowerPreference::LowPower,
+        AdapterPreference::ByName(name) => {
+            let index = adapters
+                .iter()
+                .position(|adapter| fits(adapter) && name_matches(&adapter.get_info().name, name));
+            return match index {
+                Some(index) => Ok(adapters.swap_remove(index)),
+                None => Err(descriptions(&adapters)),
+            };
+        }
+    };
+
+    instance
+        .request_adapter(&RequestAdapterOptions {
+            power_preference,
+            compatible_surface: surface,
+            force_fallback_adapter,
+        })
+        .await
+        .ok_or_else(|| descriptions(&adapters))
+}
+
+/// The adapter's name, backend, type and driver, for startup diagnostics.
+pub fn describe(info: &AdapterInfo) -> String {
+    let mut description = format!("{} ({:?}, {:?}", info.name, info.backend, info.device_type);
+    let driver = format!("{} {}", info.driver, info.driver_info);
+    if !driver.trim().is_empty() {
+        description += &format!(", driver {}", driver.trim());
+    }
+
+    description + ")"
+}
+
+fn descriptions(adapters: &[Adapter]) -> Vec<String> {
+    adapters
+        .iter()
+        .map(|adapter| describe(&adapter.get_info()))
+        .collect()
+}
+
+fn name_matches(name: &str, wanted: &str) -> bool {
+    name.to_lowercase().contains(&wanted.to_lowercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{name_matches, AdapterPreference, BackendChoice};
+
+    #[test]
+    fn names_match_by_substring() {
+        assert!(name_matches(
+            "NVIDIA GeForce RTX 3060 Laptop GPU",
+            "geforce"
+        ));
+        assert!(name_matches("llvmpipe (LLVM 15.0.6, 256 bits)", "llvmpipe"));
+        assert!(!name_matches("Intel(R) UHD Graphics 620", "nvidia"));
+        assert_eq!(
+            AdapterPreference::ByName("intel".to_owned()).to_string(),
+            "\"intel\""
+        );
+    }
+
+    #[test]
+    fn backends_parse_from_their_names() {
+        assert_eq!("GL".parse::<BackendChoice>().unwrap(), BackendChoice::Gl);
+        assert_eq!(
+            "fallback".parse::<BackendChoice>().unwrap(),
+            BackendChoice::Fallback
+        );
+        assert!("opengl".parse::<BackendChoice>().is_err());
+    }
+}
@@ -0,0 +1,3421 @@
+//! [`Renderer`], which owns the device and everything drawn with it, and
+//! the key bindings that drive it.
+
+use crate::{
+    blit::{Blit, ScaledTarget},
+    camera::{
+        Camera, CameraConstraints, CameraController, CameraPath, CameraPose, CameraResult,
+        CameraTransition, CameraUniform, Controller, Frustum, Keyframe, Projection, ProjectionKind,
+        ZoomMode,
+    },
+    console::{Console, Panel},
+    debug_draw::DebugDraw,
+    debug_scope,
+    depth_view::DepthView,
+    draw_constants::{DrawConstants, DrawSlot},
+    frame_stats::FrameStats,
+    gpu_timer::GpuTimer,
+    instance::{Instance, RawInstance},
+    instance_animation::{AnimatedInstances, InstanceAnimation, InstanceSource},
+    light::{
+        self, AmbientLight, DirectionalLight, DirectionalLightBundle, DrawLight, Light,
+        LightAnimation, LightId, LightsBuffer, PointLight, SpotLight,
+    },
+    math::Ray,
+    model::{
+        self,
+        resource::{LoadOptions, ModelData, PendingModel, ResourceCache, ResourceWatcher},
+        select_lod, Aabb, DrawModel, Material, MaterialKind, MaterialOverrides, Mesh, Model,
+        ModelVertex, VertexBufferFormat,
+    },
+    pipeline::{ComputePipeline, Rebuild},
+    screenshot::Screenshots,
+    shader::{Shader, ShaderKey},
+    shader_watcher::ShaderWatcher,
+    shadow::{ShadowPass, ShadowSettings},
+    terrain::{Brush, BrushKind, HeightMap, NoiseParams, Terrain, TerrainMode, TerrainOptions},
+    texture::{SamplerOptions, Texture, TextureResult},
+    ui, vec3,
+};
+use adapter::describe;
+use bytemuck::{Pod, Zeroable};
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Matrix4, Quaternion, Rad, Rotation3, SquareMatrix, Vector2,
+    Vector3, Zero,
+};
+use image::RgbaImage;
+use pipelines::{SceneLayouts, ScenePipelines};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io, iter, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    vertex_attr_array, Adapter, Backend, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferBindingType, BufferUsages, CommandEncoder, CommandEncoderDescriptor, CompositeAlphaMode,
+    ComputePassDescriptor, Device, DownlevelFlags, Features, Limits, LoadOp, Operations,
+    PresentMode, Queue, RenderPass, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, SamplerBindingType, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, StoreOp, Surface, SurfaceConfiguration, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
+};
+
+mod adapter;
+mod pipelines;
+
+pub use adapter::{
+    AdapterOptions, AdapterPreference, BackendChoice, UnknownBackend, ADAPTER_VARIABLE,
+};
+
+/// Everything the renderer asks the adapter for, each only used when it's
+/// there.
+pub fn requested_features() -> Features {
+    Features::TEXTURE_COMPRESSION_BC
+        | Features::POLYGON_MODE_LINE
+        | GpuTimer::required_features()
+        | DrawConstants::required_features()
+}
+
+// const INSTANCES_PER_ROW: u32 = 1;
+const INSTANCES_PER_ROW: u32 = 10;
+/// How long the view takes to move over when switching cameras.
+const CAMERA_TRANSITION: Duration = Duration::from_millis(300);
+/// Factor the field of view is scaled by per scrolled line in FOV zoom.
+const FOV_ZOOM_STEP: f32 = 0.9;
+/// How far from the middle of the scene the lights circle.
+const LIGHT_ORBIT_RADIUS: f32 = 8.0;
+/// How far each light reaches.
+const LIGHT_RADIUS: f32 = 20.0;
+/// How far the last picking ray is drawn in the debug view.
+const PICK_RAY_LENGTH: f32 = 100.0;
+/// How fast Y spins the instances, in degrees per second.
+const INSTANCE_SPIN_SPEED: f32 = 30.0;
+/// How fast the lights circle, in degrees per second.
+const LIGHT_ORBIT_SPEED: f32 = 45.0;
+/// Hours past midnight the sun is lit for.
+const SUN_TIME_OF_DAY: f32 = 10.0;
+/// Where F5 saves the camera pose and startup restores it from.
+const CAMERA_FILE: &str = "camera.ron";
+/// The camera path played with P and added to with K.
+const CAMERA_PATH_FILE: &str = "camera_path.ron";
+/// Heights drawn as the ground, from the resource directory, see
+/// [`HeightMap::load`]. The ground is generated from noise without it.
+const TERRAIN_FILE: &str = "terrain.raw";
+/// Samples along each side of terrain generated from noise.
+const TERRAIN_NOISE_SIZE: usize = 257;
+/// Spreads the terrain out around the origin, below the instances.
+const TERRAIN_SPACING: f32 = 0.5;
+const TERRAIN_HEIGHT_SCALE: f32 = 4.0;
+const TERRAIN_ELEVATION: f32 = -4.0;
+/// Cells along each side of a terrain chunk, the unit it's culled in.
+const TERRAIN_CHUNK_SIZE: usize = 64;
+/// How far Home and End move the terrain's LOD bias, in levels.
+const TERRAIN_LOD_BIAS_STEP: f32 = 0.5;
+/// How far above the ground T keeps the fly camera.
+const TERRAIN_EYE_HEIGHT: f32 = 1.7;
+/// Seconds between the keyframes K adds to the camera path.
+const KEYFRAME_SPACING: f32 = 2.0;
+/// Factor the + and - keys scale the camera speed by.
+const SPEED_STEP: f32 = 1.25;
+/// Shininess N cycles every material of the model through, before going
+/// back to their own.
+const SHININESS_PREVIEWS: [f32; 4] = [8.0, 32.0, 128.0, 512.0];
+/// Factor the [ and ] keys scale the ambient light's intensity by.
+const AMBIENT_STEP: f32 = 1.25;
+/// Shadow map sizes the G key steps through.
+const SHADOW_RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096];
+/// Widest PCF kernel the J key reaches, 7x7 texels.
+const MAX_PCF_RADIUS: u32 = 3;
+/// Factor the shadow bias keys scale a bias by.
+const SHADOW_BIAS_STEP: f32 = 2.0;
+/// What the lit color is scaled by before it's tone mapped. Reinhard halves
+/// a 1, so 2 leaves mid grey where it was without tone mapping.
+const DEFAULT_EXPOSURE: f32 = 2.0;
+/// Factor the Page Up and Page Down keys scale the exposure by.
+const EXPOSURE_STEP: f32 = 1.25;
+/// How long the GPU pass timings in the overlay are averaged over.
+const GPU_TIMING_INTERVAL: Duration = Duration::from_millis(500);
+/// What V cycles the surface through, from waiting for the display to
+/// presenting straight away, where they're supported. Mailbox waits without
+/// blocking on it, replacing the queued frame.
+const PRESENT_MODES: [PresentMode; 3] = [
+    PresentMode::Fifo,
+    PresentMode::Mailbox,
+    PresentMode::Immediate,
+];
+/// How often the frame stats in the overlay are refreshed.
+const FRAME_STATS_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the surface is reconfigured at most while the window's being
+/// resized.
+const RECONFIGURE_INTERVAL: Duration = Duration::from_millis(50);
+/// How often frames skipped on timeouts are reported at most.
+const TIMEOUT_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+/// How long messages like a saved screenshot stay in the overlay.
+const FLASH_DURATION: Duration = Duration::from_secs(3);
+/// Names of the passes [`GpuTimer`] times, shown in the overlay.
+const SHADOW_PASS_TIMING: &str = "shadow";
+const PREPASS_TIMING: &str = "pre-pass";
+const MAIN_PASS_TIMING: &str = "main";
+const BLIT_PASS_TIMING: &str = "blit";
+const TEXT_PASS_TIMING: &str = "text";
+const CONSOLE_PASS_TIMING: &str = "console";
+/// What the scene's cleared to until the debug panel changes it.
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// The range [`Renderer::set_render_scale`] clamps to, from a quarter of the
+/// window's resolution to supersampling at twice it.
+const MIN_RENDER_SCALE: f32 = 0.25;
+const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// How the scene is rendered, independently of what's in it.
+#[derive(Clone, Copy, Debug)]
+struct RendererSettings {
+    /// Fills the depth with the opaque meshes before they're shaded, so
+    /// only their visible fragments are. Toggled with F8, it's skipped for
+    /// wireframes.
+    z_prepass: bool,
+    /// Times the window's resolution the scene's drawn at.
+    render_scale: f32,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            z_prepass: false,
+            render_scale: 1.0,
+        }
+    }
+}
+
+/// What [`Renderer::new`] and [`Renderer::new_headless`] start from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RendererOptions {
+    pub adapter: AdapterOptions,
+    /// Generates the terrain from noise with this seed, even when there's a
+    /// saved one. `None` loads the saved terrain where there is one.
+    pub terrain_seed: Option<u64>,
+    pub terrain_mode: TerrainMode,
+}
+
+pub type RendererResult<T> = Result<T, RendererError>;
+
+#[derive(Debug, Error)]
+pub enum RendererError {
+    #[error("Failed to create the surface: {0}")]
+    Surface(#[from] wgpu::CreateSurfaceError),
+    #[error("No {preference} adapter can be rendered with, found {adapters:?}")]
+    NoAdapter {
+        preference: AdapterPreference,
+        adapters: Vec<String>,
+    },
+    #[error("The adapter can't draw to the window's new surface")]
+    UnsupportedSurface,
+    #[error("Failed to create the device: {0}")]
+    Device(#[from] wgpu::RequestDeviceError),
+}
+
+/// A window's surface and what it was made with, for making it again when
+/// the application resumes.
+struct WindowSurface {
+    instance: wgpu::Instance,
+    adapter: Adapter,
+    /// `None` while suspended.
+    surface: Option<Surface>,
+}
+
+/// Draws the scene into a window's surface. The application feeds it the
+/// window's events and asks for a frame whenever the window wants one.
+/// Without a window, see [`Renderer::new_headless`], frames are drawn into
+/// an offscreen target instead.
+pub struct Renderer {
+    /// `None` when headless.
+    surface: Option<WindowSurface>,
+    device: Device,
+    queue: Queue,
+    /// What the device was given of [`requested_features`], everything
+    /// else is drawn without.
+    features: Features,
+    /// Describes the offscreen target as well when headless.
+    config: SurfaceConfiguration,
+    /// The size to reconfigure to once [`RECONFIGURE_INTERVAL`] has passed
+    /// since the last time, set again when the surface is lost or outdated.
+    pending_size: Option<PhysicalSize<u32>>,
+    last_configured: Option<Instant>,
+    /// Frames skipped on timeouts since the last warning about them.
+    skipped_frames: u32,
+    last_timeout_warning: Option<Instant>,
+    /// What [`Renderer::render_to_image`] draws into, and headless frames.
+    /// Made when it's first needed and dropped when the size changes.
+    offscreen: Option<Texture>,
+    size: winit::dpi::PhysicalSize<u32>,
+    /// What the surface supports, V cycling through those in
+    /// [`PRESENT_MODES`].
+    present_modes: Vec<PresentMode>,
+
+    settings: RendererSettings,
+    clear_color: wgpu::Color,
+    /// Draws the model's edges alone, toggled with F4.
+    wireframe: bool,
+    /// Highest anisotropy the adapter filters with.
+    max_anisotropy: u16,
+    texture_anisotropy: u16,
+
+    model: Arc<Model>,
+    /// Where the current model was loaded from, reloaded when hot reload
+    /// notices a change.
+    model_source: Option<(String, LoadOptions)>,
+    material_overrides: MaterialOverrides,
+    /// Which of [`SHININESS_PREVIEWS`] the materials are drawn with.
+    shininess_preview: Option<usize>,
+    /// Draws every material with one shader rather than its own
+    /// [`MaterialKind`], cycled with M.
+    shading_override: Option<MaterialKind>,
+    pending_models: Vec<PendingModel>,
+    resource_watcher: Option<ResourceWatcher>,
+    /// Times the render passes where timestamp queries are supported,
+    /// recording them into `frame_stats`.
+    gpu_timer: Option<GpuTimer>,
+    frame_stats: FrameStats,
+    /// Rebuilds pipelines from their edited shaders, only in debug builds.
+    shader_watcher: Option<ShaderWatcher>,
+    retired_models: Vec<Arc<Model>>,
+    resource_cache: ResourceCache,
+    texture_bind_group_layout: BindGroupLayout,
+    /// Spins the instances about their y axes, writing them into the
+    /// instance and caster buffers every frame.
+    instance_animation: InstanceAnimation,
+    /// Whether the instances are spinning, toggled with Y.
+    instances_spinning: bool,
+    /// Holds the visible instances at its front, rewritten every frame.
+    instance_buffer: AnimatedInstances,
+    instances: Vec<Instance>,
+    /// Indices of the instances that survived frustum culling, in the order
+    /// they're in the instance buffer.
+    visible_instances: Vec<usize>,
+
+    depth_texture: Texture,
+    /// Set while the render scale isn't 1, F2 toggling it to a half.
+    scaled_target: Option<ScaledTarget>,
+    blit: Blit,
+    /// Shows the depth buffer instead of the scene, toggled with F3.
+    show_depth: bool,
+    depth_view: DepthView,
+    /// Draws the axes, the instances' bounds, the lights and the last
+    /// picking ray over the scene, toggled with F7.
+    show_debug: bool,
+    debug_draw: DebugDraw,
+
+    /// Named viewpoints switched between with the number keys, only the
+    /// active one is driven by the controller.
+    cameras: Vec<(String, Camera)>,
+    active_camera: usize,
+    /// Cameras drawn with their own projection rather than the shared one.
+    projection_overrides: HashMap<String, Projection>,
+    camera_transition: Option<CameraTransition>,
+    camera_path: CameraPath,
+    /// Seconds into the camera path while it drives the active camera.
+    path_playback: Option<f32>,
+    projection: Projection,
+    camera_uniform: CameraUniform,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup,
+    camera_controller: Controller,
+
+    lights: LightsBuffer,
+    light_orbit: LightAnimation,
+    /// Holds the lights where they are, toggled with L.
+    light_orbit_paused: bool,
+    /// Lights dropped at the camera with Insert, most recent last, for
+    /// Delete to take away again.
+    placed_lights: Vec<LightId>,
+    sun: DirectionalLightBundle,
+    shadow: ShadowPass,
+    /// Every instance, culled or not, since those out of view can still
+    /// shadow what's in it.
+    caster_buffer: AnimatedInstances,
+    pipelines: ScenePipelines,
+    /// Where each light's marker is drawn, rewritten every frame.
+    draw_constants: DrawConstants,
+    light_markers: Vec<DrawSlot>,
+    terrain: Terrain,
+    /// What the terrain was generated from, `None` when it was loaded from
+    /// [`TERRAIN_FILE`]. R moves on to the next seed.
+    terrain_seed: Option<u64>,
+    /// Keeps the fly camera on the terrain, toggled with T.
+    terrain_walk: bool,
+    terrain_brush: Brush,
+    /// Whether B is held, handing the mouse buttons to the brush.
+    brush_held: bool,
+    /// What the brush is doing while a button's held with B, see
+    /// [`brush_kind`].
+    brush_stroke: Option<BrushKind>,
+
+    text_manager: ui::TextManager,
+    /// The egui debug panel, toggled with the backquote key. `None` when
+    /// headless, as egui takes its input from the window.
+    console: Option<Console>,
+    /// A message shown at the bottom of the overlay until it's
+    /// [`FLASH_DURATION`] old.
+    flash: Option<(String, Instant)>,
+    /// Set by F12, the next frame is copied into `screenshots`.
+    screenshot_requested: bool,
+    screenshots: Screenshots,
+
+    // pipelines: Vec<Pipeline>,
+    mouse_pressed: bool,
+    /// In pixels from the window's top left.
+    cursor_position: Vector2<f32>,
+    /// The instance last clicked on.
+    picked_instance: Option<usize>,
+    /// The ray the last click picked along.
+    pick_ray: Option<Ray>,
+}
+
+impl Renderer {
+    /// Renders into `window`, at its current size, on the adapter
+    /// [`ADAPTER_VARIABLE`] names or else the one `options` pick.
+    ///
+    /// # Safety
+    ///
+    /// `window` has to outlive the renderer, which draws into its surface.
+    pub async unsafe fn new(window: &Window, options: RendererOptions) -> RendererResult<Self> {
+        let (surface, device, queue, config, present_modes, max_anisotropy) =
+            Self::initialize_surface(window, &options.adapter).await?;
+        let console = Console::new(&device, config.format, window);
+
+        Ok(Self {
+            console: Some(console),
+            ..Self::with_device(
+                Some(surface),
+                device,
+                queue,
+                config,
+                present_modes,
+                max_anisotropy,
+                &options,
+            )
+        })
+    }
+
+    /// Renders into a `width` by `height` [`TextureFormat::Rgba8UnormSrgb`]
+    /// target rather than a window, e.g. for tests, read with
+    /// [`Renderer::render_to_image`]. Picks the adapter as [`Renderer::new`]
+    /// does.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        options: RendererOptions,
+    ) -> RendererResult<Self> {
+        let adapter_options = AdapterOptions {
+            preference: options.adapter.preference.clone().or_env(),
+            ..options.adapter.clone()
+        };
+        let (_, _, adapter) = adapter::select_adapter(&adapter_options, |_| Ok(None)).await?;
+        println!("Selected adapter: {}", describe(&adapter.get_info()));
+
+        let (device, queue, max_anisotropy) = Self::initialize_device(&adapter).await?;
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            format: TextureFormat::Rgba8UnormSrgb,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: PresentMode::Fifo,
+            alpha_mode: CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        Ok(Self::with_device(
+            None,
+            device,
+            queue,
+            config,
+            vec![],
+            max_anisotropy,
+            &options,
+        ))
+    }
+
+    fn with_device(
+        surface: Option<WindowSurface>,
+        device: Device,
+        queue: Queue,
+        config: SurfaceConfiguration,
+        present_modes: Vec<PresentMode>,
+        max_anisotropy: u16,
+        options: &RendererOptions,
+    ) -> Self {
+        let size = PhysicalSize::new(config.width, config.height);
+        let texture_bind_group_layout = Self::initialize_texture(&device, TextureViewDimension::D2);
+        let texture_array_bind_group_layout =
+            Self::initialize_texture(&device, TextureViewDimension::D2Array);
+        let instances = Self::initialize_instances();
+        let instance_animation = InstanceAnimation::new(&device);
+        let sources: Vec<_> = instances.iter().map(Instance::source).collect();
+        let animated = |label| {
+            AnimatedInstances::new(
+                &device,
+                label,
+                &instance_animation,
+                &sources,
+                mem::size_of::<RawInstance>(),
+            )
+        };
+        let (instance_buffer, caster_buffer) = (
+            animated("Instance buffer"),
+            animated("Shadow caster buffer"),
+        );
+        let (
+            camera,
+            projection,
+            camera_uniform,
+            camera_controller,
+            camera_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+        ) = Self::initialize_camera(&device, &config);
+        let depth_texture = Texture::create_depth_texture(&device, &config);
+        let mut model = ModelData::placeholder().upload(
+            &device,
+            &queue,
+            &texture_bind_group_layout,
+            false,
+            None,
+        );
+        model.set_anisotropy(&device, &texture_bind_group_layout, max_anisotropy);
+        let model = Arc::new(model);
+
+        let lights = Self::initialize_lights(&device);
+        let shadow = ShadowPass::new(
+            &device,
+            ShadowSettings::default(),
+            &[ModelVertex::descriptor(), RawInstance::descriptor()],
+        );
+        let sun = Self::initialize_sun(&device, &shadow);
+
+        let draw_constants = DrawConstants::new(&device);
+        let pipelines = ScenePipelines::new(
+            &device,
+            config.format,
+            &SceneLayouts {
+                texture: &texture_bind_group_layout,
+                texture_array: &texture_array_bind_group_layout,
+                camera: &camera_bind_group_layout,
+                lights: lights.bind_group_layout(),
+                sun: &sun.bind_group_layout,
+            },
+            &draw_constants,
+        );
+        let (terrain, terrain_seed) =
+            Self::initialize_terrain(&device, &queue, options.terrain_seed, options.terrain_mode);
+
+        let text_manager = ui::TextManager::new(&device, &queue, &config);
+        let blit = Blit::new(&device, config.format);
+        let depth_view = DepthView::new(&device, config.format, &projection);
+        let debug_draw = DebugDraw::new(&device, config.format, &camera_bind_group_layout);
+        let gpu_timer = GpuTimer::new(&device, &queue, GPU_TIMING_INTERVAL);
+        let shader_watcher = ShaderWatcher::new(Duration::from_millis(500))
+            .map(|watcher| watcher.with_features(device.features()));
+
+        let mut state = Self {
+            surface,
+            features: device.features(),
+            device,
+            queue,
+            config,
+            pending_size: None,
+            last_configured: None,
+            skipped_frames: 0,
+            last_timeout_warning: None,
+            offscreen: None,
+            size,
+            present_modes,
+
+            settings: RendererSettings::default(),
+            clear_color: CLEAR_COLOR,
+            wireframe: false,
+            max_anisotropy,
+            texture_anisotropy: max_anisotropy,
+
+            model,
+            model_source: None,
+            material_overrides: MaterialOverrides::new(),
+            shininess_preview: None,
+            shading_override: None,
+            pending_models: vec![],
+            resource_watcher: None,
+            gpu_timer,
+            frame_stats: FrameStats::new(FRAME_STATS_INTERVAL),
+            shader_watcher,
+            retired_models: vec![],
+            resource_cache: ResourceCache::new(),
+            texture_bind_group_layout,
+            instance_animation,
+            instances_spinning: false,
+            instance_buffer,
+            visible_instances: (0..instances.len()).collect(),
+            instances,
+
+            depth_texture,
+            scaled_target: None,
+            blit,
+            show_depth: false,
+            depth_view,
+            show_debug: false,
+            debug_draw,
+
+            cameras: vec![
+                ("Main".to_owned(), camera),
+                (
+                    "Overview".to_owned(),
+                    Camera::new((0.0, 40.0, 30.0), Deg(-90.0), Deg(-50.0)),
+                ),
+            ],
+            active_camera: 0,
+            projection_overrides: HashMap::new(),
+            camera_transition: None,
+            camera_path: Self::initialize_camera_path(),
+            path_playback: None,
+            projection,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            camera_controller,
+
+            lights,
+            light_orbit: LightAnimation::new(Vector3::unit_y(), LIGHT_ORBIT_SPEED),
+            light_orbit_paused: false,
+            placed_lights: vec![],
+            sun,
+            shadow,
+            caster_buffer,
+
+            text_manager,
+            console: None,
+            flash: None,
+            screenshot_requested: false,
+            screenshots: Screenshots::default(),
+
+            pipelines,
+            draw_constants,
+            light_markers: vec![],
+            terrain,
+            terrain_seed,
+            terrain_walk: false,
+            terrain_brush: Brush::default(),
+            brush_held: false,
+            brush_stroke: None,
+            // pipelines: vec![],
+            mouse_pressed: false,
+            cursor_position: Vector2::zero(),
+            picked_instance: None,
+            pick_ray: None,
+        };
+        state.fit_shadow();
+        state.load_model(
+            "cube.obj",
+            LoadOptions {
+                optimize: true,
+                ..Default::default()
+            },
+        );
+        state.update_overlay();
+
+        state
+    }
+
+    /// # Safety
+    ///
+    /// As for [`Renderer::new`].
+    async unsafe fn initialize_surface(
+        window: &Window,
+        options: &AdapterOptions,
+    ) -> RendererResult<(
+        WindowSurface,
+        Device,
+        Queue,
+        SurfaceConfiguration,
+        Vec<PresentMode>,
+        u16,
+    )> {
+        let size = window.inner_size();
+        let options = AdapterOptions {
+            preference: options.preference.clone().or_env(),
+            ..options.clone()
+        };
+        let (instance, surface, adapter) = adapter::select_adapter(&options, |instance| {
+            instance.create_surface(window).map(Some)
+        })
+        .await?;
+        let surface = surface.expect("a surface was created with the adapter");
+        println!("Selected adapter: {}", describe(&adapter.get_info()));
+        let (device, queue, max_anisotropy) = Self::initialize_device(&adapter).await?;
+
+        let surface_capabilities = surface.get_capabilities(&adapter);
+        let surface_format = surface_capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(TextureFormat::is_srgb)
+            .unwrap_or(surface_capabilities.formats[0]);
+
+        // The first mode can be one that tears, Fifo is always there
+        // wherever the surface can wait for the display at all
+        let present_modes = surface_capabilities.present_modes;
+        let present_mode = match present_modes.contains(&PresentMode::Fifo) {
+            true => PresentMode::Fifo,
+            false => present_modes[0],
+        };
+        // Screenshots copy straight out of the frame where the surface
+        // allows it
+        let usage = TextureUsages::RENDER_ATTACHMENT
+            | (surface_capabilities.usages & TextureUsages::COPY_SRC);
+        let config = SurfaceConfiguration {
+            usage,
+            format: surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode,
+            alpha_mode: surface_capabilities.alpha_modes.first().cloned().unwrap(),
+            view_formats: vec![],
+        };
+        surface.configure(&device, &config);
+
+        Ok((
+            WindowSurface {
+                instance,
+                adapter,
+                surface: Some(surface),
+            },
+            device,
+            queue,
+            config,
+            present_modes,
+            max_anisotropy,
+        ))
+    }
+
+    /// The device and queue with whichever of [`requested_features`] the
+    /// adapter has, and the highest anisotropy it filters with.
+    async fn initialize_device(adapter: &Adapter) -> RendererResult<(Device, Queue, u16)> {
+        let mut features = requested_features();
+        let mut max_push_constant_size = DrawConstants::required_push_constant_size();
+        // GL emulates push constants with uniforms, and wgpu-hal reads their
+        // data through misaligned pointers
+        if adapter.limits().max_push_constant_size < max_push_constant_size
+            || adapter.get_info().backend == Backend::Gl
+        {
+            features -= DrawConstants::required_features();
+            max_push_constant_size = 0;
+        }
+        let missing = features - adapter.features();
+        if !missing.is_empty() {
+            println!("Drawing without {missing:?}");
+        }
+        let features = adapter.features() & features;
+
+        // GL and software adapters tend to fall short of the defaults
+        let limits = [
+            Limits::default(),
+            Limits::downlevel_defaults(),
+            Limits::downlevel_webgl2_defaults(),
+        ]
+        .into_iter()
+        .find(|limits| limits.check_limits(&adapter.limits()))
+        .unwrap_or_else(|| adapter.limits());
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // Block compressed textures are decoded on the CPU where
+                    // it's not available, wireframes drawn from line lists,
+                    // draw constants read from a uniform buffer and passes
+                    // left untimed
+                    features,
+                    limits: Limits {
+                        max_push_constant_size,
+                        ..limits
+                    },
+                    label: Some("Renderer device"),
+                },
+                None,
+            )
+            .await?;
+        // Logged rather than panicking as wgpu does by default, the
+        // descriptions name the labels of what was involved
+        device.on_uncaptured_error(Box::new(|error| eprintln!("{error}")));
+
+        // Samplers ignore the anisotropy clamp where it's unsupported, but
+        // the setting shouldn't claim otherwise
+        let max_anisotropy = match adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::ANISOTROPIC_FILTERING)
+        {
+            true => SamplerOptions::MAX_ANISOTROPY,
+            false => 1,
+        };
+
+        Ok((device, queue, max_anisotropy))
+    }
+
+    fn initialize_texture(
+        device: &Device,
+        diffuse_view_dimension: TextureViewDimension,
+    ) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: diffuse_view_dimension,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Metallic-roughness and occlusion, only read by the PBR
+                // shader
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some(match diffuse_view_dimension {
+                TextureViewDimension::D2Array => "Texture array bind group layout",
+                _ => "Texture bind group layout",
+            }),
+        })
+    }
+
+    fn initialize_instances() -> Vec<Instance> {
+        const SPACE_BETWEEN: f32 = 3.0;
+
+        (0..INSTANCES_PER_ROW)
+            .flat_map(|z| {
+                (0..INSTANCES_PER_ROW).map(move |x| {
+                    let x = SPACE_BETWEEN * (x as f32 - INSTANCES_PER_ROW as f32 / 2.0);
+                    let z = SPACE_BETWEEN * (z as f32 - INSTANCES_PER_ROW as f32 / 2.0);
+
+                    // Single centered model
+                    // let position = Vector3::new(0.0, 0.0, 0.0);
+                    // let rotation = Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), Deg(180.0));
+
+                    // Many dispersed models
+                    let position = Vector3::new(x, 0.0, z);
+                    let rotation = if position.is_zero() {
+                        Quaternion::from_axis_angle(Vector3::unit_z(), Deg(0.0))
+                    } else {
+                        Quaternion::from_axis_angle(position.normalize(), Deg(45.0))
+                    };
+
+                    Instance {
+                        position,
+                        rotation,
+                        texture_index: 0,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Colored lights spaced around the instances and a spot light above
+    /// them, see [`Renderer::update_lights`].
+    fn initialize_lights(device: &Device) -> LightsBuffer {
+        let colors = [
+            vec3!(1.0, 0.3, 0.3),
+            vec3!(0.3, 1.0, 0.3),
+            vec3!(0.3, 0.3, 1.0),
+            vec3!(1.0, 0.9, 0.7),
+        ];
+        let mut lights: Vec<Light> = colors
+            .iter()
+            .enumerate()
+            .map(|(index, &color)| {
+                let angle = Deg(360.0 / colors.len() as f32 * index as f32);
+                let position = Quaternion::from_axis_angle(Vector3::unit_y(), angle)
+                    * vec3!(LIGHT_ORBIT_RADIUS, 3.0, 0.0);
+
+                PointLight::new(position, color)
+                    .with_radius(LIGHT_RADIUS)
+                    .into()
+            })
+            .collect();
+        // Tilted off the vertical, so circling it sweeps the cone across the
+        // instances
+        let spot = SpotLight::new(
+            vec3!(0.0, 8.0, 0.0),
+            vec3!(0.8, -1.0, 0.0),
+            vec3!(1.0, 1.0, 1.0),
+        )
+        .with_intensity(2.0)
+        .with_cone(Deg(12.0), Deg(20.0))
+        .with_range(LIGHT_RADIUS);
+        lights.push(spot.into());
+
+        LightsBuffer::new(device, &lights)
+    }
+
+    /// A dim morning sun, see [`SUN_TIME_OF_DAY`].
+    fn initialize_sun(device: &Device, shadow: &ShadowPass) -> DirectionalLightBundle {
+        let mut sun =
+            DirectionalLight::new(-Vector3::unit_y(), vec3!(1.0, 0.95, 0.8)).with_intensity(0.4);
+        sun.set_time_of_day(SUN_TIME_OF_DAY);
+
+        sun.prepared(device, shadow)
+    }
+
+    /// The ground from [`TERRAIN_FILE`], or from noise when there isn't one
+    /// or there's a `seed`.
+    fn initialize_terrain(
+        device: &Device,
+        queue: &Queue,
+        seed: Option<u64>,
+        mode: TerrainMode,
+    ) -> (Terrain, Option<u64>) {
+        let path =
+            model::resource::resource_directory().map(|directory| directory.join(TERRAIN_FILE));
+        let height_map = match (seed, path) {
+            (None, Ok(path)) if path.exists() => HeightMap::load(&path.to_string_lossy())
+                .map_err(|error| eprintln!("Failed to load {}: {error}", path.display()))
+                .ok(),
+            _ => None,
+        };
+
+        match height_map {
+            Some(height_map) => (Self::create_terrain(device, queue, height_map, mode), None),
+            None => {
+                let seed = seed.unwrap_or_default();
+                (
+                    Self::generate_terrain(device, queue, seed, mode),
+                    Some(seed),
+                )
+            }
+        }
+    }
+
+    fn generate_terrain(device: &Device, queue: &Queue, seed: u64, mode: TerrainMode) -> Terrain {
+        let params = NoiseParams {
+            seed,
+            ..Default::default()
+        };
+        let height_map = HeightMap::from_noise(TERRAIN_NOISE_SIZE, TERRAIN_NOISE_SIZE, params);
+
+        Self::create_terrain(device, queue, height_map, mode)
+    }
+
+    /// Chunks the map, centered under the origin and scaled by its own height
+    /// scale when it has one.
+    fn create_terrain(
+        device: &Device,
+        queue: &Queue,
+        height_map: HeightMap,
+        mode: TerrainMode,
+    ) -> Terrain {
+        // Recorded on the map so it's saved with it
+        let height_scale = height_map.height_scale().unwrap_or(TERRAIN_HEIGHT_SCALE);
+        let height_map = height_map.with_height_scale(height_scale);
+        let half_extent = |samples: usize| samples.saturating_sub(1) as f32 * TERRAIN_SPACING / 2.0;
+        let options = TerrainOptions {
+            origin: vec3!(
+                -half_extent(height_map.width()),
+                TERRAIN_ELEVATION,
+                -half_extent(height_map.depth())
+            ),
+            spacing: TERRAIN_SPACING,
+            height_scale,
+            ..Default::default()
+        };
+
+        // R32Float heights need no features, so uploading them can't fail
+        Terrain::new(device, queue, height_map, options, TERRAIN_CHUNK_SIZE, mode).unwrap()
+    }
+
+    fn initialize_camera_path() -> CameraPath {
+        match settings_file(CAMERA_PATH_FILE) {
+            Ok(path) if path.exists() => CameraPath::load(&path).unwrap_or_else(|error| {
+                eprintln!("Failed to load {}: {error}", path.display());
+                CameraPath::default()
+            }),
+            _ => CameraPath::default(),
+        }
+    }
+
+    fn initialize_camera(
+        device: &Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> (
+        Camera,
+        Projection,
+        CameraUniform,
+        Controller,
+        Buffer,
+        BindGroupLayout,
+        BindGroup,
+    ) {
+        let sensitivity = 1.0;
+
+        // Start where the camera was last saved, if it was
+        let pose = match settings_file(CAMERA_FILE) {
+            Ok(path) if path.exists() => CameraPose::load(&path).unwrap_or_else(|error| {
+                eprintln!("Failed to load {}: {error}", path.display());
+                CameraPose::default()
+            }),
+            _ => CameraPose::default(),
+        };
+        let camera = pose.camera();
+        let projection = Projection::new(
+            config.width,
+            config.height,
+            cgmath::Rad(pose.fovy),
+            pose.znear,
+            pose.zfar,
+        );
+        let mut fly_controller = CameraController::new(pose.speed, sensitivity);
+        fly_controller.set_smoothing(0.2, 0.05);
+        let camera_controller = Controller::Fly(fly_controller);
+        let mut camera_uniform = CameraUniform::new(&camera, &projection);
+        camera_uniform.set_exposure(DEFAULT_EXPOSURE);
+
+        let camera_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera buffer"),
+            contents: bytemuck::bytes_of(&camera_uniform),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Camera bind group layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Camera bind group"),
+            layout: &camera_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        (
+            camera,
+            projection,
+            camera_uniform,
+            camera_controller,
+            camera_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+        )
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.config.present_mode
+    }
+
+    /// Reconfigures the surface to present with `mode`, false when it isn't
+    /// supported.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> bool {
+        if !self.present_modes.contains(&mode) {
+            return false;
+        }
+
+        self.config.present_mode = mode;
+        if let Some(surface) = self.surface() {
+            surface.configure(&self.device, &self.config);
+        }
+        true
+    }
+
+    /// Resizes the surface and everything drawn at its size before the next
+    /// frame, at most once per [`RECONFIGURE_INTERVAL`]. Ignores empty
+    /// sizes, e.g. while the window's minimized, and the size it already has.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width > 0 && size.height > 0 && self.pending_size.unwrap_or(self.size) != size {
+            self.pending_size = Some(size);
+        }
+    }
+
+    /// Drops the surface, e.g. when the application's suspended on Android,
+    /// and draws nothing until [`Renderer::resume`] makes it again. The
+    /// device and everything on it is kept.
+    pub fn suspend(&mut self) {
+        if let Some(window_surface) = &mut self.surface {
+            window_surface.surface = None;
+            self.offscreen = None;
+        }
+    }
+
+    /// Makes the surface again after [`Renderer::suspend`], at the window's
+    /// current size, and does nothing otherwise.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Renderer::new`], `window` is the one it was made with.
+    pub unsafe fn resume(&mut self, window: &Window) -> RendererResult<()> {
+        let Some(window_surface) = &mut self.surface else {
+            return Ok(());
+        };
+        if window_surface.surface.is_some() {
+            return Ok(());
+        }
+
+        let surface = window_surface.instance.create_surface(window)?;
+        if !window_surface.adapter.is_surface_supported(&surface) {
+            return Err(RendererError::UnsupportedSurface);
+        }
+        window_surface.surface = Some(surface);
+
+        // Configured straight away before the next frame, with what the
+        // surface had before
+        let size = window.inner_size();
+        self.pending_size = Some(match size.width > 0 && size.height > 0 {
+            true => size,
+            false => self.size,
+        });
+        self.last_configured = None;
+
+        Ok(())
+    }
+
+    /// Whether the surface is gone until [`Renderer::resume`].
+    pub fn is_suspended(&self) -> bool {
+        self.surface
+            .as_ref()
+            .is_some_and(|window_surface| window_surface.surface.is_none())
+    }
+
+    fn surface(&self) -> Option<&Surface> {
+        self.surface.as_ref()?.surface.as_ref()
+    }
+
+    /// Applies the pending size if there is one and it's been long enough,
+    /// straight away when headless or suspended.
+    fn reconfigure(&mut self) {
+        let Some(size) = self.pending_size else {
+            return;
+        };
+        let due = self.surface().is_none()
+            || self
+                .last_configured
+                .is_none_or(|last| last.elapsed() >= RECONFIGURE_INTERVAL);
+        if due {
+            self.pending_size = None;
+            self.last_configured = Some(Instant::now());
+            self.size = size;
+            self.config.width = size.width;
+            self.config.height = size.height;
+            if let Some(surface) = self.surface() {
+                surface.configure(&self.device, &self.config);
+            }
+            self.offscreen = None;
+            self.projection.resize(size.width, size.height);
+            for projection in self.projection_overrides.values_mut() {
+                projection.resize(size.width, size.height);
+            }
+            self.depth_texture = Texture::create_depth_texture(&self.device, &self.config);
+            self.update_scaled_target();
+            self.text_manager.resize(&self.config);
+        }
+    }
+
+    /// The active camera.
+    pub fn camera(&self) -> &Camera {
+        &self.cameras[self.active_camera].1
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.cameras[self.active_camera].1
+    }
+
+    /// The point and spot lights. Changes are uploaded on the next
+    /// [`Renderer::update`].
+    pub fn lights(&self) -> &LightsBuffer {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut LightsBuffer {
+        &mut self.lights
+    }
+
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// What's drawn from, part way back to the previous camera while
+    /// switching.
+    fn view_camera(&self) -> Camera {
+        match &self.camera_transition {
+            Some(transition) => transition.blend(self.camera()),
+            None => *self.camera(),
+        }
+    }
+
+    fn active_projection(&self) -> &Projection {
+        self.projection_overrides
+            .get(&self.cameras[self.active_camera].0)
+            .unwrap_or(&self.projection)
+    }
+
+    fn active_projection_mut(&mut self) -> &mut Projection {
+        self.projection_overrides
+            .get_mut(&self.cameras[self.active_camera].0)
+            .unwrap_or(&mut self.projection)
+    }
+
+    /// Adds a camera, or replaces the one of the same name. Without a
+    /// projection of its own it shares the window's.
+    pub fn add_camera(&mut self, name: &str, camera: Camera, projection: Option<Projection>) {
+        match self.cameras.iter_mut().find(|(other, _)| other == name) {
+            Some((_, existing)) => *existing = camera,
+            None => self.cameras.push((name.to_owned(), camera)),
+        }
+        match projection {
+            Some(projection) => self
+                .projection_overrides
+                .insert(name.to_owned(), projection),
+            None => self.projection_overrides.remove(name),
+        };
+    }
+
+    /// Hands the controller to the named camera, easing the view over for
+    /// `transition`. Returns false if there's no such camera.
+    pub fn set_active_camera(&mut self, name: &str, transition: Duration) -> bool {
+        match self.cameras.iter().position(|(other, _)| other == name) {
+            Some(index) => self.set_active_camera_index(index, transition),
+            None => false,
+        }
+    }
+
+    fn set_active_camera_index(&mut self, index: usize, transition: Duration) -> bool {
+        if index >= self.cameras.len() {
+            return false;
+        }
+        if index == self.active_camera {
+            return true;
+        }
+
+        self.camera_transition =
+            (!transition.is_zero()).then(|| CameraTransition::new(self.view_camera(), transition));
+        self.active_camera = index;
+        self.camera_controller
+            .reset_to(&self.cameras[self.active_camera].1);
+
+        true
+    }
+
+    /// Writes the active camera's pose and projection parameters to `path`.
+    fn save_camera(&self, path: &Path) -> CameraResult<()> {
+        CameraPose::capture(
+            self.camera(),
+            self.active_projection(),
+            self.camera_controller.speed(),
+        )
+        .save(path)
+    }
+
+    /// Moves the active camera to the pose saved in `path`, without a
+    /// transition.
+    fn load_camera(&mut self, path: &Path) -> CameraResult<()> {
+        let pose = CameraPose::load(path)?;
+        let (name, camera) = &mut self.cameras[self.active_camera];
+        let projection = match self.projection_overrides.get_mut(name.as_str()) {
+            Some(projection) => projection,
+            None => &mut self.projection,
+        };
+        pose.apply(camera, projection);
+        self.camera_transition = None;
+        self.camera_controller
+            .reset_to(&self.cameras[self.active_camera].1);
+        self.camera_controller.set_speed(pose.speed);
+        self.update_overlay();
+
+        Ok(())
+    }
+
+    /// Starts driving the active camera along the camera path, or hands it
+    /// back to the controller.
+    fn toggle_path_playback(&mut self) {
+        if self.path_playback.take().is_some() {
+            self.camera_controller
+                .reset_to(&self.cameras[self.active_camera].1);
+            return;
+        }
+        if self.camera_path.is_empty() {
+            eprintln!("The camera path is empty, add keyframes with K");
+            return;
+        }
+
+        self.camera_transition = None;
+        self.path_playback = Some(self.camera_path.keyframes()[0].time);
+    }
+
+    /// Adds the current view to the end of the camera path and saves it.
+    fn add_path_keyframe(&mut self) {
+        let time = match self.camera_path.is_empty() {
+            true => 0.0,
+            false => self.camera_path.end() + KEYFRAME_SPACING,
+        };
+        self.camera_path
+            .push(Keyframe::from_camera(&self.view_camera(), time));
+
+        let result = settings_file(CAMERA_PATH_FILE)
+            .map_err(Into::into)
+            .and_then(|path| self.camera_path.save(&path));
+        if let Err(error) = result {
+            eprintln!("Camera path: {error}");
+        }
+    }
+
+    /// Where the instance is drawn this frame, spun by the instance
+    /// animation.
+    fn instance_matrix(&self, index: usize) -> Matrix4<f32> {
+        self.instances[index].matrix() * self.instance_animation.spin()
+    }
+
+    /// The bounds of every instance of the model.
+    fn scene_bounds(&self) -> Aabb {
+        let bounds = self.model.bounds();
+        (0..self.instances.len()).fold(Aabb::EMPTY, |scene, index| {
+            scene.union(&bounds.transformed(&self.instance_matrix(index)))
+        })
+    }
+
+    /// Eases the active camera back until every instance of the model is in
+    /// view, keeping the direction it faces.
+    fn frame_model(&mut self) {
+        let scene = self.scene_bounds();
+        if scene.is_empty() {
+            return;
+        }
+
+        self.camera_transition = Some(CameraTransition::new(self.view_camera(), CAMERA_TRANSITION));
+        let (name, camera) = &mut self.cameras[self.active_camera];
+        let projection = self
+            .projection_overrides
+            .get(name.as_str())
+            .unwrap_or(&self.projection);
+        camera.frame_aabb(&scene, projection);
+        self.camera_controller
+            .reset_to(&self.cameras[self.active_camera].1);
+    }
+
+    /// The world space ray under the cursor.
+    fn cursor_ray(&self) -> Ray {
+        self.view_camera().screen_ray(
+            self.active_projection(),
+            self.cursor_position,
+            (self.config.width, self.config.height),
+        )
+    }
+
+    /// The nearest visible instance whose bounds are under the cursor.
+    fn pick_instance(&self) -> Option<usize> {
+        let ray = self.cursor_ray();
+        let bounds = self.model.bounds();
+
+        self.visible_instances
+            .iter()
+            .filter_map(|&index| {
+                let distance =
+                    ray.intersect_aabb(&bounds.transformed(&self.instance_matrix(index)))?;
+                Some((index, distance))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.settings.render_scale
+    }
+
+    /// Renders the scene at `scale` times the window's resolution, clamped
+    /// to [`MIN_RENDER_SCALE`] and [`MAX_RENDER_SCALE`], and blits it onto
+    /// the screen, or straight to the screen at 1. The overlay's drawn at
+    /// the window's resolution either way.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.settings.render_scale = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        self.update_scaled_target();
+        self.update_overlay();
+    }
+
+    fn update_scaled_target(&mut self) {
+        let scale = self.settings.render_scale;
+        self.scaled_target = (scale != 1.0).then(|| {
+            ScaledTarget::new(
+                &self.device,
+                &self.blit,
+                self.config.width,
+                self.config.height,
+                self.config.format,
+                scale,
+            )
+        });
+    }
+
+    /// Hands `event` to the debug panel while it's open, true when it was
+    /// used there and shouldn't reach [`Renderer::handle_input`].
+    pub fn handle_console_input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.console
+            .as_mut()
+            .is_some_and(|console| console.handle_input(window, event))
+    }
+
+    /// Whether the debug panel's open, while the cursor's left to it.
+    pub fn is_console_visible(&self) -> bool {
+        self.console.as_ref().is_some_and(Console::is_visible)
+    }
+
+    /// Lays out the debug panel for the next frame and applies what was
+    /// changed in it.
+    pub fn run_console(&mut self, window: &Window) {
+        if !self.is_console_visible() {
+            return;
+        }
+
+        let mut panel = self.panel();
+        let before = panel.clone();
+        if let Some(console) = &mut self.console {
+            console.run(window, &mut panel);
+        }
+        if panel != before {
+            self.apply_panel(panel);
+        }
+    }
+
+    fn panel(&self) -> Panel {
+        let color = self.clear_color;
+
+        Panel {
+            clear_color: [color.r as f32, color.g as f32, color.b as f32],
+            lights: self
+                .lights
+                .ids()
+                .filter_map(|id| Some((id, *self.lights.get(id)?)))
+                .collect(),
+            lights_orbiting: !self.light_orbit_paused,
+            camera_speed: self.camera_controller.speed(),
+            fov: Deg::from(self.active_projection().target_fovy()).0,
+            wireframe: self.wireframe,
+            z_prepass: self.settings.z_prepass,
+            render_scale: self.settings.render_scale,
+        }
+    }
+
+    fn apply_panel(&mut self, panel: Panel) {
+        let [r, g, b] = panel.clear_color.map(f64::from);
+        self.clear_color = wgpu::Color { r, g, b, a: 1.0 };
+        for (id, light) in panel.lights {
+            if self.lights.get(id) != Some(&light) {
+                self.lights.set_light(id, light);
+            }
+        }
+        self.light_orbit_paused = !panel.lights_orbiting;
+        self.camera_controller.set_speed(panel.camera_speed);
+        self.active_projection_mut().set_fovy(Deg(panel.fov));
+        self.wireframe = panel.wireframe;
+        self.settings.z_prepass = panel.z_prepass;
+        // Remade targets would be wasted on the other settings changing
+        if panel.render_scale != self.settings.render_scale {
+            self.set_render_scale(panel.render_scale);
+        }
+        self.update_overlay();
+    }
+
+    /// Handles the key bindings and mouse, true when `event` was used.
+    pub fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Backquote),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } if self.console.is_some() => {
+                let visible = self.is_console_visible();
+                if let Some(console) = &mut self.console {
+                    console.set_visible(!visible);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } if *key == KeyCode::F3 => self.show_depth ^= state.is_pressed(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F4),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.wireframe = !self.wireframe;
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F8),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.settings.z_prepass = !self.settings.z_prepass;
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F7),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.show_debug = !self.show_debug,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F12),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match self.config.usage.contains(TextureUsages::COPY_SRC) {
+                true => self.screenshot_requested = true,
+                false => self.flash("Screenshots aren't supported by this surface".to_owned()),
+            },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Tab),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self
+                .camera_controller
+                .toggle(&self.cameras[self.active_camera].1),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if digit_index(*key).is_some() => {
+                if let Some(index) = digit_index(*key) {
+                    self.set_active_camera_index(index, CAMERA_TRANSITION);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if adjust_shadow(self.shadow.settings(), *key).is_some() => {
+                if let Some(settings) = adjust_shadow(self.shadow.settings(), *key) {
+                    self.set_shadow_settings(settings);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if ambient_step(*key).is_some() => {
+                if let Some(step) = ambient_step(*key) {
+                    let ambient = self.lights.ambient();
+                    self.set_ambient(ambient.with_intensity(ambient.intensity * step));
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if terrain_lod_bias_step(*key).is_some() => {
+                if let Some(step) = terrain_lod_bias_step(*key) {
+                    self.terrain.lod_bias += step;
+                    self.update_overlay();
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if exposure_step(*key).is_some() => {
+                if let Some(step) = exposure_step(*key) {
+                    let exposure = self.camera_uniform.exposure() * step;
+                    self.camera_uniform.set_exposure(exposure);
+                    self.update_overlay();
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if speed_step(*key).is_some() => {
+                if let Some(step) = speed_step(*key) {
+                    let speed = self.camera_controller.speed() * step;
+                    self.camera_controller.set_speed(speed);
+                    if self.pending_models.is_empty() {
+                        self.update_overlay();
+                    }
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.toggle_path_playback(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyK),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.add_path_keyframe(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyL),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.light_orbit_paused = !self.light_orbit_paused;
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyY),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.instances_spinning = !self.instances_spinning;
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let mode = next_present_mode(self.present_mode(), &self.present_modes);
+                self.set_present_mode(mode);
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.regenerate_terrain(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyU),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.toggle_terrain_mode(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.terrain_walk = !self.terrain_walk;
+                self.apply_terrain_walk();
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyN),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.cycle_shininess_preview(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyM),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.cycle_shading_override(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Insert),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.place_light(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Delete),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(id) = self.placed_lights.pop() {
+                    self.lights.remove_light(id);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.frame_model(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyZ),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let fly = self.camera_controller.fly_mut();
+                let zoom_mode = match fly.zoom_mode() {
+                    ZoomMode::Dolly => ZoomMode::Fov,
+                    ZoomMode::Fov => ZoomMode::Dolly,
+                };
+                fly.set_zoom_mode(zoom_mode);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyO),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let focus_distance = self.camera_controller.focus_distance();
+                let projection = self.active_projection_mut();
+                let kind = match projection.kind() {
+                    ProjectionKind::Perspective => ProjectionKind::Orthographic,
+                    ProjectionKind::Orthographic => ProjectionKind::Perspective,
+                };
+                projection.set_kind(kind, focus_distance);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } if *key == KeyCode::F5 || *key == KeyCode::F9 => {
+                if state.is_pressed() {
+                    let result = match settings_file(CAMERA_FILE) {
+                        Ok(path) if *key == KeyCode::F5 => self.save_camera(&path),
+                        Ok(path) => self.load_camera(&path),
+                        Err(error) => Err(error.into()),
+                    };
+                    if let Err(error) = result {
+                        eprintln!("Camera pose: {error}");
+                    }
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } if *key == KeyCode::KeyB => {
+                self.brush_held = state.is_pressed();
+                match self.brush_held {
+                    // Stops looking around, the brush would take the
+                    // button's release and leave the cursor locked
+                    true => self.mouse_pressed = false,
+                    false => self.brush_stroke = None,
+                }
+                self.update_overlay();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F6),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.save_terrain(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } if *key == KeyCode::F2 => {
+                if state.is_pressed() {
+                    let scale = match self.settings.render_scale == 1.0 {
+                        true => 0.5,
+                        false => 1.0,
+                    };
+                    self.set_render_scale(scale);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => return self.camera_controller.handle_keyboard(*key, *state),
+            WindowEvent::MouseWheel { delta, .. } => self.camera_controller.handle_scroll(delta),
+            WindowEvent::MouseInput { button, state, .. }
+                if self.brush_held && brush_kind(*button).is_some() =>
+            {
+                self.brush_stroke = match state.is_pressed() {
+                    true => brush_kind(*button),
+                    false => None,
+                };
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => {
+                self.mouse_pressed = state.is_pressed();
+                if self.mouse_pressed {
+                    self.picked_instance = self.pick_instance();
+                    self.pick_ray = Some(self.cursor_ray());
+                    if self.pending_models.is_empty() {
+                        self.update_overlay();
+                    }
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Vector2::new(position.x as f32, position.y as f32);
+            }
+            WindowEvent::MouseInput { button, state, .. } => {
+                return self.camera_controller.handle_mouse_button(*button, *state)
+            }
+            // Whatever's held is released in another window, the
+            // application still gets to see it
+            WindowEvent::Focused(false) => {
+                self.camera_controller.release_keys();
+                self.mouse_pressed = false;
+                self.brush_held = false;
+                self.brush_stroke = None;
+                self.update_overlay();
+                return false;
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Whether mouse motion turns or pans the camera, while a mouse
+    /// button's held.
+    pub fn is_mouse_looking(&self) -> bool {
+        self.mouse_pressed || self.camera_controller.is_panning()
+    }
+
+    /// Turns or pans the camera while a mouse button's held.
+    pub fn handle_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.is_mouse_looking() {
+            self.camera_controller.handle_mouse(dx, dy);
+        }
+    }
+
+    /// Reloads the model whenever something in the resource directory
+    /// changes. Off by default outside debug builds, polling isn't free.
+    pub fn enable_hot_reload(&mut self, enabled: bool) {
+        self.resource_watcher = match (enabled, model::resource::resource_directory()) {
+            (true, Ok(directory)) => Some(ResourceWatcher::new(
+                directory.clone(),
+                Duration::from_millis(500),
+            )),
+            (true, Err(error)) => {
+                eprintln!("Hot reload unavailable: {error}");
+                None
+            }
+            (false, _) => None,
+        };
+    }
+
+    /// Rebuilds the pipelines made from shaders that were edited, those that
+    /// fail keeping the shader they had.
+    fn poll_shader_watcher(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        let changed = watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+
+        let others: [&mut dyn Rebuild; 3] = [
+            self.debug_draw.pipeline_mut(),
+            self.instance_animation.pipeline_mut(),
+            self.shadow.pipeline_mut(),
+        ];
+        let pipelines = self
+            .pipelines
+            .iter_mut()
+            .map(|pipeline| pipeline as &mut dyn Rebuild)
+            .chain(others);
+        // Each shader is reloaded once, however many pipelines share it
+        let mut shaders: HashMap<ShaderKey, Option<Shader>> = HashMap::new();
+        for pipeline in pipelines {
+            let stages = pipeline.stages();
+            let mut reloaded: Vec<Shader> = vec![];
+            for key in stages.iter().map(|stage| &stage.shader) {
+                let shader = shaders.entry(key.clone()).or_insert_with(|| {
+                    match watcher.reload(key, &changed) {
+                        Ok(module) => module.map(|module| Shader {
+                            key: key.clone(),
+                            module: Arc::new(self.device.create_shader_module(
+                                ShaderModuleDescriptor {
+                                    label: Some(key.file),
+                                    source: ShaderSource::Naga(Cow::Owned(module)),
+                                },
+                            )),
+                        }),
+                        Err(error) => {
+                            eprintln!("Failed to reload {key}:\n{error}");
+                            None
+                        }
+                    }
+                });
+                // Both stages can come from the same shader
+                if let Some(shader) = shader
+                    .as_ref()
+                    .filter(|shader| !reloaded.iter().any(|other| other.key == shader.key))
+                {
+                    reloaded.push(shader.clone());
+                }
+            }
+            if reloaded.is_empty() {
+                continue;
+            }
+
+            let stages: Vec<String> = stages.iter().map(ToString::to_string).collect();
+            let stages = stages.join(", ");
+            match pipeline.rebuild(&self.device, &reloaded) {
+                Ok(()) => println!("Rebuilt {} from {stages}", pipeline.label()),
+                Err(error) => {
+                    eprintln!(
+                        "Failed to rebuild {} from {stages}: {error}",
+                        pipeline.label()
+                    )
+                }
+            }
+        }
+    }
+
+    fn poll_resource_watcher(&mut self) {
+        let Some(watcher) = &mut self.resource_watcher else {
+            return;
+        };
+        if !watcher.poll() {
+            return;
+        }
+
+        let Some((file_name, options)) = &self.model_source else {
+            return;
+        };
+        if self
+            .pending_models
+            .iter()
+            .any(|pending| &pending.file_name == file_name)
+        {
+            return;
+        }
+
+        println!("Reloading model: {file_name}");
+        self.pending_models
+            .push(model::resource::load_model_async(file_name, *options));
+    }
+
+    /// Draws one of the current model's meshes with another of its
+    /// materials, e.g. to highlight a selection. `None` restores the mesh's
+    /// own material.
+    pub fn set_material_override(&mut self, mesh_index: usize, material_index: Option<usize>) {
+        match material_index {
+            Some(material_index) => self.material_overrides.set(mesh_index, material_index),
+            None => {
+                self.material_overrides.remove(mesh_index);
+            }
+        }
+    }
+
+    /// The anisotropy the models' textures are filtered with, 1 when it's
+    /// off.
+    pub fn texture_quality(&self) -> u16 {
+        self.texture_anisotropy
+    }
+
+    /// Sets the anisotropic filtering of the current and later models, 1
+    /// turns it off. Clamped to what the adapter supports.
+    pub fn set_texture_quality(&mut self, anisotropy: u16) {
+        self.texture_anisotropy = anisotropy.clamp(1, self.max_anisotropy);
+
+        // The cache shares the model, take it out while the materials are
+        // rebound
+        let file_name = self.model_source.as_ref().map(|(name, _)| name.clone());
+        if let Some(file_name) = &file_name {
+            self.resource_cache.evict_model(file_name);
+        }
+        match Arc::get_mut(&mut self.model) {
+            Some(model) => model.set_anisotropy(
+                &self.device,
+                &self.texture_bind_group_layout,
+                self.texture_anisotropy,
+            ),
+            None => eprintln!("Texture quality applies once the current model is reloaded"),
+        }
+        if let Some(file_name) = &file_name {
+            if let Err(error) = self
+                .resource_cache
+                .insert_model(file_name, self.model.clone())
+            {
+                eprintln!("Failed to cache {file_name}: {error}");
+            }
+        }
+    }
+
+    fn update_overlay(&mut self) {
+        let mut text = format!(
+            "{}\n{}\nFOV {:.0}°\nSpeed {:.1}\nAmbient {:.2}\nExposure {:.2}\nInstances {}/{} ({} culled)",
+            self.frame_stats,
+            self.model.stats(),
+            cgmath::Deg::from(self.active_projection().fovy()).0,
+            self.camera_controller.speed(),
+            self.lights.ambient().intensity,
+            self.camera_uniform.exposure(),
+            self.visible_instances.len(),
+            self.instances.len(),
+            self.instances.len() - self.visible_instances.len()
+        );
+        text += &format!(
+            "\nTerrain chunks {}/{} LOD bias {:+.1}",
+            self.terrain.visible_count(),
+            self.terrain.chunks.len(),
+            self.terrain.lod_bias
+        );
+        if let Some(seed) = self.terrain_seed {
+            text += &format!(" seed {seed}");
+        }
+        if self.terrain.mode() == TerrainMode::Gpu {
+            text += " on the GPU";
+        }
+        if self.terrain_walk {
+            text += "\nWalking the terrain";
+        }
+        if self.brush_held {
+            text += &format!(
+                "\nBrush radius {:.1} strength {:.2}",
+                self.terrain_brush.radius, self.terrain_brush.strength
+            );
+        }
+        if let Some(index) = self.picked_instance {
+            text += &format!("\nPicked instance {index}");
+        }
+        if let Some(index) = self.shininess_preview {
+            text += &format!("\nShininess {}", SHININESS_PREVIEWS[index]);
+        }
+        let shadow = self.shadow.settings();
+        text += &match shadow.enabled {
+            true => format!(
+                "\nShadows {} PCF {} bias {:.4}/{:.3}",
+                shadow.resolution, shadow.pcf_radius, shadow.depth_bias, shadow.normal_bias
+            ),
+            false => "\nShadows off".to_owned(),
+        };
+        if let Some(kind) = self.shading_override {
+            text += &format!("\nShading {kind:?}");
+        }
+        if self.light_orbit_paused {
+            text += "\nLights paused";
+        }
+        if self.instances_spinning {
+            text += "\nInstances spinning";
+        }
+        if self.wireframe {
+            text += match self.features.contains(Features::POLYGON_MODE_LINE) {
+                true => "\nWireframe",
+                false => "\nWireframe from line lists",
+            };
+        }
+        if self.settings.z_prepass {
+            text += "\nDepth pre-pass";
+        }
+        text += &format!("\nPresent mode {:?}", self.present_mode());
+        text += &format!("\nResolution {}x{}", self.config.width, self.config.height);
+        if let Some(target) = &self.scaled_target {
+            let scene = target.color.handle();
+            text += &format!(
+                ", scene {}x{} at {:.0}%",
+                scene.width(),
+                scene.height(),
+                target.scale * 100.0
+            );
+        }
+        if !self.frame_stats.gpu_passes().is_empty() {
+            text += &format!(
+                "\nGPU {:.2} ms:",
+                self.frame_stats.gpu_time().as_secs_f64() * 1000.0
+            );
+            for (pass, duration) in self.frame_stats.gpu_passes() {
+                text += &format!(" {pass} {:.2}", duration.as_secs_f64() * 1000.0);
+            }
+        }
+        if let Some((message, _)) = &self.flash {
+            text += &format!("\n{message}");
+        }
+        self.text_manager.update(&text);
+    }
+
+    /// Shows `message` in the overlay for [`FLASH_DURATION`].
+    pub fn flash(&mut self, message: String) {
+        self.flash = Some((message, Instant::now()));
+        self.update_overlay();
+    }
+
+    /// Moves everything on by `dt`, picking up loaded models and edited
+    /// shaders.
+    pub fn update(&mut self, dt: Duration) {
+        // Before the camera's uploaded with the projection
+        self.reconfigure();
+        self.frame_stats.record_frame(dt);
+        if self.frame_stats.should_refresh() && self.pending_models.is_empty() {
+            self.update_overlay();
+        }
+        self.poll_resource_watcher();
+        self.poll_shader_watcher();
+        self.poll_pending_models();
+        if let Some(timer) = &mut self.gpu_timer {
+            if timer.poll(&self.device) {
+                self.frame_stats.record_gpu_passes(timer.averages());
+                self.update_overlay();
+            }
+        }
+        match self.screenshots.poll(&self.device) {
+            Some(Ok(path)) => {
+                println!("Saved screenshot: {}", path.display());
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                self.flash(format!("Saved {file_name}"));
+            }
+            Some(Err(error)) => self.flash(format!("Failed to save screenshot: {error}")),
+            None => {}
+        }
+        if self
+            .flash
+            .as_ref()
+            .is_some_and(|(_, shown)| shown.elapsed() >= FLASH_DURATION)
+        {
+            self.flash = None;
+            self.update_overlay();
+        }
+        match &mut self.path_playback {
+            Some(time) => {
+                *time += dt.as_secs_f32();
+                let time = *time;
+                self.cameras[self.active_camera].1 = self.camera_path.camera_at(time);
+                if self.camera_path.is_finished(time) {
+                    self.toggle_path_playback();
+                }
+            }
+            None => self
+                .camera_controller
+                .update(&mut self.cameras[self.active_camera].1, dt),
+        }
+        if let Some(transition) = &mut self.camera_transition {
+            if transition.advance(dt) {
+                self.camera_transition = None;
+            }
+        }
+
+        let zoom = self.camera_controller.take_fov_zoom();
+        let projection = self.active_projection_mut();
+        if zoom != 0.0 {
+            projection.set_fovy(projection.target_fovy() * FOV_ZOOM_STEP.powf(-zoom));
+        }
+        let fovy = projection.fovy();
+        projection.update(dt);
+        if projection.fovy() != fovy && self.pending_models.is_empty() {
+            self.update_overlay();
+        }
+        let view_camera = self.view_camera();
+        let projection = self
+            .projection_overrides
+            .get(&self.cameras[self.active_camera].0)
+            .unwrap_or(&self.projection);
+        self.camera_uniform.update(&view_camera, projection);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::bytes_of(&self.camera_uniform),
+        );
+        if let Some(kind) = self.brush_stroke {
+            self.paint_terrain(kind, dt);
+        }
+        if self.instances_spinning {
+            let by = Rad::from(Deg(INSTANCE_SPIN_SPEED * dt.as_secs_f32()));
+            self.instance_animation.advance(&self.queue, by);
+        }
+        self.cull_instances();
+        self.update_lights(dt);
+        self.update_debug_draw();
+        if self.show_depth {
+            self.depth_view
+                .update(&self.queue, self.active_projection());
+        }
+        self.text_manager.resize(&self.config);
+    }
+
+    /// Gathers this frame's debug lines and uploads them, none while the
+    /// debug view is off.
+    fn update_debug_draw(&mut self) {
+        self.debug_draw.clear();
+        if self.show_debug {
+            self.debug_draw.axes(&Matrix4::identity(), 1.0);
+            let bounds = self.model.bounds();
+            for &index in &self.visible_instances {
+                let color = match self.picked_instance == Some(index) {
+                    true => [1.0, 1.0, 0.0],
+                    false => [0.5, 0.5, 0.5],
+                };
+                let transform = self.instance_matrix(index);
+                self.debug_draw.aabb(&bounds.transformed(&transform), color);
+                self.debug_draw.axes(&transform, 0.5);
+            }
+            for light in self.lights.ids().filter_map(|id| self.lights.get(id)) {
+                self.debug_draw
+                    .sphere(light.position(), 0.5, [1.0, 1.0, 1.0]);
+            }
+            if let Some(ray) = self.pick_ray {
+                self.debug_draw.line(
+                    ray.origin.to_vec(),
+                    ray.at(PICK_RAY_LENGTH).to_vec(),
+                    [1.0, 0.0, 1.0],
+                );
+            }
+        }
+        self.debug_draw.upload(&self.device, &self.queue);
+    }
+
+    /// Aims the sun's shadow map at the scene, after the sun or the model
+    /// changes.
+    fn fit_shadow(&mut self) {
+        self.sun.uniform.fit_shadow(&self.scene_bounds());
+        self.sun.update(&self.queue);
+        self.shadow.update(&self.queue, &self.sun.uniform);
+    }
+
+    /// Steps to the next of [`SHININESS_PREVIEWS`], then back to the
+    /// materials' own shininess.
+    fn cycle_shininess_preview(&mut self) {
+        self.shininess_preview = match self.shininess_preview {
+            None => Some(0),
+            Some(index) if index + 1 < SHININESS_PREVIEWS.len() => Some(index + 1),
+            Some(_) => None,
+        };
+        self.apply_shininess_preview();
+        self.update_overlay();
+    }
+
+    /// Generates the terrain again from the seed after its current one,
+    /// keeping its LOD bias.
+    fn regenerate_terrain(&mut self) {
+        let seed = self.terrain_seed.map_or(0, |seed| seed.wrapping_add(1));
+        let lod_bias = self.terrain.lod_bias;
+        self.terrain = Self::generate_terrain(&self.device, &self.queue, seed, self.terrain.mode());
+        self.terrain.lod_bias = lod_bias;
+        self.terrain_seed = Some(seed);
+        self.apply_terrain_walk();
+        self.update_overlay();
+    }
+
+    /// Builds the same terrain again the other [`TerrainMode`]'s way.
+    fn toggle_terrain_mode(&mut self) {
+        let mode = match self.terrain.mode() {
+            TerrainMode::Cpu => TerrainMode::Gpu,
+            TerrainMode::Gpu => TerrainMode::Cpu,
+        };
+        let height_map = HeightMap::clone(self.terrain.height_map());
+        let mut terrain = Terrain::new(
+            &self.device,
+            &self.queue,
+            height_map,
+            self.terrain.options(),
+            TERRAIN_CHUNK_SIZE,
+            mode,
+        )
+        .unwrap();
+        terrain.lod_bias = self.terrain.lod_bias;
+        self.terrain = terrain;
+        self.update_overlay();
+    }
+
+    /// Strokes the terrain under the cursor with the brush for `dt`.
+    fn paint_terrain(&mut self, kind: BrushKind, dt: Duration) {
+        let Some(hit) = self.terrain.raycast(&self.cursor_ray()) else {
+            return;
+        };
+        let point = hit.position;
+        let brush = self.terrain_brush.with_kind(kind);
+        // The ground the camera follows is a copy from before the edit
+        if self
+            .terrain
+            .edit(&self.queue, &brush, point.x, point.z, dt.as_secs_f32())
+            && self.terrain_walk
+        {
+            self.apply_terrain_walk();
+        }
+    }
+
+    /// Writes the terrain's heights over [`TERRAIN_FILE`], which is loaded
+    /// in place of generated terrain from then on.
+    fn save_terrain(&self) {
+        let result = model::resource::resource_directory()
+            .map_err(Into::into)
+            .and_then(|directory| {
+                let path = directory.join(TERRAIN_FILE);
+                self.terrain.height_map().save(&path.to_string_lossy())
+            });
+        if let Err(error) = result {
+            eprintln!("Failed to save the terrain: {error}");
+        }
+    }
+
+    /// Keeps the fly camera over the terrain and [`TERRAIN_EYE_HEIGHT`]
+    /// above it while walking, freeing it otherwise.
+    fn apply_terrain_walk(&mut self) {
+        let constraints = self.terrain_walk.then(|| {
+            let bounds = self.terrain.bounds();
+            let (height_map, options) = (self.terrain.height_map().clone(), self.terrain.options());
+            CameraConstraints::new(
+                (bounds.min.x, f32::NEG_INFINITY, bounds.min.z),
+                (bounds.max.x, f32::INFINITY, bounds.max.z),
+            )
+            .with_ground(
+                move |x, z| {
+                    height_map
+                        .height_at(options, x, z)
+                        .unwrap_or(f32::NEG_INFINITY)
+                },
+                TERRAIN_EYE_HEIGHT,
+            )
+        });
+        self.camera_controller
+            .fly_mut()
+            .set_constraints(constraints);
+    }
+
+    /// Steps from each material's own shader to everything Phong, then
+    /// everything PBR, for comparing the two.
+    fn cycle_shading_override(&mut self) {
+        self.shading_override = match self.shading_override {
+            None => Some(MaterialKind::Phong),
+            Some(MaterialKind::Phong) => Some(MaterialKind::Pbr),
+            Some(MaterialKind::Pbr) => None,
+        };
+        self.update_overlay();
+    }
+
+    /// Opaque materials are shaded with the prepassed pipelines after a
+    /// depth pre-pass, blended ones are drawn after it either way.
+    fn shading_pipeline(&self, material: &Material) -> &RenderPipeline {
+        match (
+            self.shading_override.unwrap_or(material.kind),
+            material.is_transparent(),
+            self.uses_prepass(),
+        ) {
+            (MaterialKind::Phong, false, false) => &self.pipelines.standard,
+            (MaterialKind::Pbr, false, false) => &self.pipelines.pbr,
+            (MaterialKind::Phong, false, true) => &self.pipelines.prepassed,
+            (MaterialKind::Pbr, false, true) => &self.pipelines.prepassed_pbr,
+            (MaterialKind::Phong, true, _) => &self.pipelines.transparent,
+            (MaterialKind::Pbr, true, _) => &self.pipelines.transparent_pbr,
+        }
+    }
+
+    /// Whether this frame's opaque meshes are drawn into the depth first,
+    /// wireframes aren't.
+    fn uses_prepass(&self) -> bool {
+        self.settings.z_prepass && !self.wireframe
+    }
+
+    /// Draws `instances` of `mesh` with its material's pipeline, or its
+    /// edges with the wireframe pipeline whatever the material.
+    fn draw_mesh<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        instances: Range<u32>,
+    ) {
+        let (camera, lights) = (&self.camera_bind_group, self.lights.bind_group());
+        match self.wireframe {
+            true => {
+                render_pass.set_pipeline(&self.pipelines.wireframe);
+                render_pass
+                    .draw_mesh_wireframe_instanced(mesh, material, instances, camera, lights);
+            }
+            false => {
+                render_pass.set_pipeline(self.shading_pipeline(material));
+                render_pass.draw_mesh_instanced(mesh, material, instances, camera, lights);
+            }
+        }
+    }
+
+    fn apply_shininess_preview(&self) {
+        for material in &self.model.materials {
+            let uniform = match self.shininess_preview {
+                Some(index) => material.uniform.with_shininess(SHININESS_PREVIEWS[index]),
+                None => material.uniform,
+            };
+            material.write_uniform(&self.queue, &uniform);
+        }
+    }
+
+    /// Uploaded with the other lights on the next update.
+    fn set_ambient(&mut self, ambient: AmbientLight) {
+        self.lights.set_ambient(ambient);
+        if self.pending_models.is_empty() {
+            self.update_overlay();
+        }
+    }
+
+    /// Applies shadow settings, rebinding the map if it's rebuilt at
+    /// another resolution.
+    fn set_shadow_settings(&mut self, settings: ShadowSettings) {
+        if self
+            .shadow
+            .set_settings(&self.device, &self.queue, settings)
+        {
+            self.sun.set_shadow(&self.device, &self.shadow);
+        }
+        if self.pending_models.is_empty() {
+            self.update_overlay();
+        }
+    }
+
+    /// Circles the lights around the scene's vertical axis, turning spot
+    /// lights with them, unless paused.
+    fn update_lights(&mut self, dt: Duration) {
+        if !self.light_orbit_paused {
+            let rotation = self.light_orbit.rotation(dt);
+            for (_, light) in self.lights.iter_mut() {
+                light.set_position(rotation * light.position());
+                if let Some(direction) = light.direction() {
+                    light.set_direction(rotation * direction);
+                }
+            }
+        }
+        self.lights.update(&self.queue);
+
+        self.draw_constants.clear();
+        self.light_markers = self
+            .lights
+            .ids()
+            .filter_map(|id| self.lights.get(id))
+            .map(|light| {
+                self.draw_constants
+                    .push(light::marker_transform(light), light.color())
+            })
+            .collect();
+        self.draw_constants.upload(&self.device, &self.queue);
+    }
+
+    /// Drops a point light where the camera is, which joins the orbit.
+    fn place_light(&mut self) {
+        let position = self.view_camera().position.to_vec();
+        let light = PointLight::new(position, vec3!(1.0, 0.9, 0.7)).with_radius(LIGHT_RADIUS);
+        let id = self.lights.add_light(&self.device, light);
+        self.placed_lights.push(id);
+    }
+
+    /// Packs the instances whose bounds intersect the view into the front of
+    /// the instance buffer, and culls the terrain's chunks with them.
+    fn cull_instances(&mut self) {
+        let frustum = Frustum::from_matrix(
+            &(self.active_projection().matrix() * self.view_camera().matrix()),
+        );
+        let bounds = self.model.bounds();
+        let visible: Vec<usize> = (0..self.instances.len())
+            .filter(|&index| {
+                frustum.intersects_aabb(&bounds.transformed(&self.instance_matrix(index)))
+            })
+            .collect();
+
+        let sources: Vec<InstanceSource> = visible
+            .iter()
+            .map(|&index| self.instances[index].source())
+            .collect();
+        self.instance_buffer.write(&self.queue, &sources);
+
+        let mut changed = visible.len() != self.visible_instances.len();
+        self.visible_instances = visible;
+        let eye = self.view_camera().position.to_vec();
+        changed |= self.terrain.cull(&frustum, eye);
+        if changed && self.pending_models.is_empty() {
+            self.update_overlay();
+        }
+    }
+
+    /// Starts loading `file_name` from the resource directory, drawn in
+    /// place of the current model once it's loaded.
+    pub fn load_model(&mut self, file_name: &str, options: LoadOptions) {
+        self.pending_models
+            .push(model::resource::load_model_async(file_name, options));
+    }
+
+    /// Whether a model's still loading, picked up by a later
+    /// [`Renderer::update`].
+    pub fn is_loading(&self) -> bool {
+        !self.pending_models.is_empty()
+    }
+
+    /// Uploads any models the loader threads have finished with, replacing the
+    /// current model. Those still loading show their progress in the overlay.
+    fn poll_pending_models(&mut self) {
+        let mut index = 0;
+        while index < self.pending_models.len() {
+            let pending = &self.pending_models[index];
+            let Some(result) = pending.poll() else {
+                if let Some((phase, fraction)) = pending.progress() {
+                    self.text_manager.update(&format!(
+                        "Loading {} — {phase} {:.0}%",
+                        pending.file_name,
+                        fraction * 100.0
+                    ));
+                }
+                index += 1;
+                continue;
+            };
+
+            let pending = self.pending_models.remove(index);
+            match result {
+                Ok(data) => {
+                    let mut model = data.upload(
+                        &self.device,
+                        &self.queue,
+                        &self.texture_bind_group_layout,
+                        pending.options.retain_cpu_data,
+                        None,
+                    );
+                    model.set_anisotropy(
+                        &self.device,
+                        &self.texture_bind_group_layout,
+                        self.texture_anisotropy,
+                    );
+
+                    match self.resource_cache.insert_model(&pending.file_name, model) {
+                        Ok(model) => {
+                            let previous = mem::replace(&mut self.model, model);
+                            self.retire_model(previous);
+                            // Mesh indices don't carry over to another model
+                            if self.model_source.as_ref().map(|(name, _)| name)
+                                != Some(&pending.file_name)
+                            {
+                                self.material_overrides.clear();
+                            }
+                            self.model_source = Some((pending.file_name.clone(), pending.options));
+                            println!("Loaded model: {}", pending.file_name);
+                            self.apply_shininess_preview();
+                            self.fit_shadow();
+                        }
+                        Err(error) => eprintln!("Failed to load {}: {error}", pending.file_name),
+                    }
+                }
+                Err(error) => eprintln!("Failed to load {}: {error}", pending.file_name),
+            }
+
+            // Also replaces the progress message of a failed load
+            self.update_overlay();
+        }
+    }
+
+    /// Queues a model that's no longer drawn for destruction once the frame
+    /// that may still reference it has been submitted.
+    fn retire_model(&mut self, model: Arc<Model>) {
+        self.retired_models.push(model);
+    }
+
+    fn destroy_retired_models(&mut self) {
+        for model in self.retired_models.drain(..) {
+            // Whoever else holds it, e.g. the resource cache, still wants it
+            if Arc::strong_count(&model) == 1 {
+                model.destroy();
+            }
+        }
+    }
+
+    /// Draws a frame and presents it, or draws it into the offscreen target
+    /// when headless, or nothing while suspended. Frames the surface can't
+    /// give are skipped, it's
+    /// reconfigured when lost or outdated, only running out of memory is
+    /// returned.
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.reconfigure();
+        if self.is_suspended() {
+            return Ok(());
+        }
+        let Some(surface) = self.surface() else {
+            let target = self.take_offscreen();
+            self.draw_frame(target.handle(), true);
+            self.offscreen = Some(target);
+            return Ok(());
+        };
+
+        let frame = match surface.get_current_texture() {
+            Ok(frame) => frame,
+            // Outdated comes up while live resizing on X11 and Wayland
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.pending_size = Some(self.pending_size.unwrap_or(self.size));
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                self.skipped_frames += 1;
+                if self
+                    .last_timeout_warning
+                    .is_none_or(|last| last.elapsed() >= TIMEOUT_WARNING_INTERVAL)
+                {
+                    eprintln!(
+                        "Skipped {} frames, the surface timed out",
+                        self.skipped_frames
+                    );
+                    self.skipped_frames = 0;
+                    self.last_timeout_warning = Some(Instant::now());
+                }
+                return Ok(());
+            }
+            Err(error) => return Err(error),
+        };
+        self.draw_frame(&frame.texture, true);
+        frame.present();
+
+        Ok(())
+    }
+
+    /// Draws a frame into the offscreen target, with or without a window,
+    /// and reads it back. The overlay's left out, as it changes from frame
+    /// to frame. Blocks until the GPU has finished the frame.
+    pub fn render_to_image(&mut self) -> TextureResult<RgbaImage> {
+        self.reconfigure();
+        let target = self.take_offscreen();
+        self.draw_frame(target.handle(), false);
+        let image = target.read_to_image(&self.device, &self.queue);
+        self.offscreen = Some(target);
+
+        image
+    }
+
+    fn take_offscreen(&mut self) -> Texture {
+        self.offscreen.take().unwrap_or_else(|| {
+            Texture::create_render_target(
+                &self.device,
+                self.config.width,
+                self.config.height,
+                self.config.format,
+                1,
+            )
+        })
+    }
+
+    /// Records and submits everything drawn into `target`, which has the
+    /// surface's size and format.
+    fn draw_frame(&mut self, target: &wgpu::Texture, overlay: bool) {
+        let view = target.create_view(&TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Frame encoder"),
+            });
+        // Only the passes that run this frame, the others' queries would go
+        // unwritten
+        let mut timed_passes = vec![];
+        if self.shadow.settings().enabled {
+            timed_passes.push(SHADOW_PASS_TIMING);
+        }
+        if self.uses_prepass() {
+            timed_passes.push(PREPASS_TIMING);
+        }
+        timed_passes.push(MAIN_PASS_TIMING);
+        if self.scaled_target.is_some() {
+            timed_passes.push(BLIT_PASS_TIMING);
+        }
+        if overlay {
+            timed_passes.push(TEXT_PASS_TIMING);
+            if self.console.as_ref().is_some_and(Console::is_laid_out) {
+                timed_passes.push(CONSOLE_PASS_TIMING);
+            }
+        }
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin_frame(&timed_passes);
+        }
+
+        // Spins the casters as well, so the shadows turn with the instances
+        for instances in [&self.caster_buffer, &self.instance_buffer] {
+            Self::dispatch(
+                &mut encoder,
+                self.instance_animation.pipeline(),
+                &[instances.bind_group()],
+                instances.workgroups(),
+            );
+        }
+
+        self.shadow.render(
+            &mut debug_scope!(encoder, "Shadows"),
+            &self.model,
+            &self.caster_buffer,
+            0..self.instances.len() as u32,
+            self.timestamp_writes(SHADOW_PASS_TIMING),
+        );
+        let (depth_texture, draws) = {
+            let mut encoder = debug_scope!(encoder, "Scene");
+            match &self.scaled_target {
+                Some(target) => {
+                    let draws =
+                        self.render_scene(&mut encoder, &target.color.view, &target.depth.view);
+                    self.blit.draw(
+                        &mut encoder,
+                        &view,
+                        &target.bind_group,
+                        self.timestamp_writes(BLIT_PASS_TIMING),
+                    );
+                    (&target.depth, draws)
+                }
+                None => {
+                    let draws = self.render_scene(&mut encoder, &view, &self.depth_texture.view);
+                    (&self.depth_texture, draws)
+                }
+            }
+        };
+        // Covers the scene, which still has to be drawn to fill the depth.
+        // The bind group is made per frame since resizing replaces the
+        // texture, it's only for debugging
+        if self.show_depth {
+            if let Some(bind_group) = self.depth_view.bind_group(&self.device, depth_texture) {
+                self.depth_view.draw(&mut encoder, &view, &bind_group);
+            }
+        }
+
+        // Taken before the overlay, which isn't part of the scene
+        if mem::take(&mut self.screenshot_requested) {
+            if let Err(error) = self.screenshots.capture(&self.device, &mut encoder, target) {
+                self.flash(format!("Failed to take screenshot: {error}"));
+            }
+        }
+        let mut console_commands = vec![];
+        if overlay {
+            let mut encoder = debug_scope!(encoder, "Overlay");
+            let timer = self.gpu_timer.as_ref();
+            self.text_manager.render(
+                &self.device,
+                &self.queue,
+                &self.config,
+                &mut encoder,
+                &view,
+                timer.and_then(|timer| timer.timestamp_writes(TEXT_PASS_TIMING)),
+            );
+            if let Some(console) = &mut self.console {
+                console_commands = console.render(
+                    &self.device,
+                    &self.queue,
+                    &mut encoder,
+                    &view,
+                    [self.config.width, self.config.height],
+                    timer.and_then(|timer| timer.timestamp_writes(CONSOLE_PASS_TIMING)),
+                );
+            }
+        }
+        if let Some(timer) = &self.gpu_timer {
+            timer.resolve(&mut encoder);
+        }
+
+        self.queue.submit(
+            console_commands
+                .into_iter()
+                .chain(iter::once(encoder.finish())),
+        );
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.map();
+        }
+        self.screenshots.map();
+        self.frame_stats
+            .record_draws(draws, self.visible_instances.len() as u32);
+        self.destroy_retired_models();
+        self.lights.destroy_retired();
+    }
+
+    /// For the `timestamp_writes` of `pass`, `None` when it isn't timed.
+    fn timestamp_writes(&self, pass: &str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.gpu_timer
+            .as_ref()
+            .and_then(|timer| timer.timestamp_writes(pass))
+    }
+
+    /// Records a compute pass into `encoder` with `bind_groups` bound in
+    /// order, ahead of the render passes reading what it writes.
+    fn dispatch(
+        encoder: &mut CommandEncoder,
+        pipeline: &ComputePipeline,
+        bind_groups: &[&BindGroup],
+        workgroups: u32,
+    ) {
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some(pipeline.label()),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    /// Records the main pass into `target`, the swapchain or an offscreen
+    /// texture, with a depth attachment of the same size. The depth pre-pass
+    /// goes before it when it's on. Returns how many draws the meshes,
+    /// terrain chunks and light markers took.
+    fn render_scene(
+        &self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        depth_target: &TextureView,
+    ) -> u32 {
+        let mut draws = 0;
+        let (camera, lights) = (&self.camera_bind_group, self.lights.bind_group());
+        let uses_prepass = self.uses_prepass();
+        if uses_prepass {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Depth pre-pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: depth_target,
+                    depth_ops: Some(wgpu::Operations {
+                        load: LoadOp::Clear(1.0),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: self.timestamp_writes(PREPASS_TIMING),
+                occlusion_query_set: None,
+            });
+
+            // Bound like the standard pipeline, the fragment stage's groups
+            // are part of its layout all the same
+            render_pass.set_pipeline(&self.pipelines.prepass);
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            render_pass.set_bind_group(3, &self.sun.bind_group, &[]);
+            for (mesh, material, instances) in self.opaque_draws() {
+                render_pass.draw_mesh_instanced(mesh, material, instances, camera, lights);
+                draws += 1;
+            }
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Main pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(self.clear_color),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: depth_target,
+                    depth_ops: Some(wgpu::Operations {
+                        load: match uses_prepass {
+                            true => LoadOp::Load,
+                            false => LoadOp::Clear(1.0),
+                        },
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: self.timestamp_writes(MAIN_PASS_TIMING),
+                occlusion_query_set: None,
+            });
+
+            // Opaque geometry first, writing the depth what's blended over it
+            // is tested against, or shading what the pre-pass left visible.
+            // The pipeline follows each mesh's material, the standard and PBR
+            // ones share their bind group layouts so nothing needs rebinding
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(3, &self.sun.bind_group, &[]);
+            for (mesh, material, instances) in self.opaque_draws() {
+                self.draw_mesh(&mut render_pass, mesh, material, instances);
+                draws += 1;
+            }
+
+            {
+                let mut render_pass = debug_scope!(render_pass, "Terrain");
+                render_pass.set_pipeline(match self.terrain.mode() {
+                    TerrainMode::Cpu => &self.pipelines.terrain,
+                    TerrainMode::Gpu => &self.pipelines.displaced_terrain,
+                });
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(1, self.lights.bind_group(), &[]);
+                render_pass.set_bind_group(2, &self.sun.bind_group, &[]);
+                self.terrain.draw(&mut render_pass);
+                draws += self.terrain.visible_count() as u32;
+            }
+            self.debug_draw.draw(
+                &mut debug_scope!(render_pass, "Debug lines"),
+                &self.camera_bind_group,
+            );
+
+            // Then everything blended, the light cubes adding their glow and
+            // transparent meshes drawn from the farthest instance in
+            {
+                let mut render_pass = debug_scope!(render_pass, "Lights");
+                render_pass.set_pipeline(&self.pipelines.light);
+                for &marker in &self.light_markers {
+                    render_pass.draw_light_at(
+                        &self.model,
+                        marker,
+                        &self.draw_constants,
+                        &self.camera_bind_group,
+                    );
+                    draws += self.model.lod_meshes(0).count() as u32;
+                }
+            }
+            if self.model.materials.iter().any(Material::is_transparent) {
+                let mut render_pass = debug_scope!(render_pass, "Transparent meshes");
+                render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(3, &self.sun.bind_group, &[]);
+                let mut slots = self.instance_slots();
+                slots.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+                for (slot, level, _) in slots {
+                    for (mesh, material) in self
+                        .mesh_materials(level)
+                        .filter(|(_, material)| material.is_transparent())
+                    {
+                        self.draw_mesh(&mut render_pass, mesh, material, slot..slot + 1);
+                        draws += 1;
+                    }
+                }
+            }
+        }
+
+        draws
+    }
+
+    /// Each opaque mesh with its material and the instances it's drawn for,
+    /// all the visible ones at once unless they can be at different levels
+    /// of detail.
+    fn opaque_draws(&self) -> Vec<(&Mesh, &Material, Range<u32>)> {
+        let opaque = |(_, material): &(&Mesh, &Material)| !material.is_transparent();
+        match self.model.lods.is_empty() {
+            true => self
+                .mesh_materials(0)
+                .filter(opaque)
+                .map(|(mesh, material)| (mesh, material, 0..self.visible_instances.len() as u32))
+                .collect(),
+            false => self
+                .instance_slots()
+                .into_iter()
+                .flat_map(|(slot, level, _)| {
+                    self.mesh_materials(level)
+                        .filter(opaque)
+                        .map(move |(mesh, material)| (mesh, material, slot..slot + 1))
+                })
+                .collect(),
+        }
+    }
+
+    /// The model's meshes at a level of detail, each with the material it's
+    /// drawn with.
+    fn mesh_materials(&self, level: usize) -> impl Iterator<Item = (&Mesh, &Material)> {
+        self.model.lod_mesh_indices(level).map(|mesh_index| {
+            let mesh = &self.model.meshes[mesh_index];
+            let material = self.material_overrides.resolve(
+                mesh_index,
+                mesh.material,
+                self.model.materials.len(),
+            );
+
+            (mesh, &self.model.materials[material])
+        })
+    }
+
+    /// Each visible instance's slot in the instance buffer, with the level of
+    /// detail it's drawn at and how far it is from the camera.
+    fn instance_slots(&self) -> Vec<(u32, usize, f32)> {
+        let camera_position = self.view_camera().position.to_vec();
+        self.visible_instances
+            .iter()
+            .enumerate()
+            .map(|(slot, &index)| {
+                let distance = (self.instances[index].position - camera_position).magnitude();
+                let level = match self.model.lods.is_empty() {
+                    true => 0,
+                    false => select_lod(&self.model.lods, distance),
+                };
+
+                (slot as u32, level, distance)
+            })
+            .collect()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct PrimitiveVertex {
+    position: Vector3<f32>,
+    texture_coordinates: Vector2<f32>,
+}
+
+impl VertexBufferFormat for PrimitiveVertex {
+    type Attributes = [VertexAttribute; 2];
+    const ATTRIBUTES: Self::Attributes = vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x2
+    ];
+
+    fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Where files the renderer saves go, next to the resource directory.
+fn settings_file(file_name: &str) -> io::Result<PathBuf> {
+    let directory = model::resource::resource_directory()?;
+
+    Ok(directory.parent().unwrap_or(directory).join(file_name))
+}
+
+/// What + and - scale the camera speed by, on the main keys or the keypad.
+fn speed_step(key: KeyCode) -> Option<f32> {
+    match key {
+        KeyCode::Equal | KeyCode::NumpadAdd => Some(SPEED_STEP),
+        KeyCode::Minus | KeyCode::NumpadSubtract => Some(1.0 / SPEED_STEP),
+        _ => None,
+    }
+}
+
+/// What [ and ] scale the ambient light by.
+fn ambient_step(key: KeyCode) -> Option<f32> {
+    match key {
+        KeyCode::BracketRight => Some(AMBIENT_STEP),
+        KeyCode::BracketLeft => Some(1.0 / AMBIENT_STEP),
+        _ => None,
+    }
+}
+
+/// What the brush does with a mouse button held along with B.
+fn brush_kind(button: MouseButton) -> Option<BrushKind> {
+    match button {
+        MouseButton::Left => Some(BrushKind::Raise),
+        MouseButton::Right => Some(BrushKind::Lower),
+        MouseButton::Middle => Some(BrushKind::Smooth),
+        _ => None,
+    }
+}
+
+/// What Home and End add to the terrain's LOD bias, End coarsening it.
+fn terrain_lod_bias_step(key: KeyCode) -> Option<f32> {
+    match key {
+        KeyCode::End => Some(TERRAIN_LOD_BIAS_STEP),
+        KeyCode::Home => Some(-TERRAIN_LOD_BIAS_STEP),
+        _ => None,
+    }
+}
+
+/// What Page Up and Page Down scale the exposure by.
+fn exposure_step(key: KeyCode) -> Option<f32> {
+    match key {
+        KeyCode::PageUp => Some(EXPOSURE_STEP),
+        KeyCode::PageDown => Some(1.0 / EXPOSURE_STEP),
+        _ => None,
+    }
+}
+
+/// The shadow settings after pressing `key`. H turns shadows on and off, G
+/// steps through [`SHADOW_RESOLUTIONS`] and J through PCF radii up to
+/// [`MAX_PCF_RADIUS`], while , and . scale the depth bias and ; and ' the
+/// normal bias by [`SHADOW_BIAS_STEP`].
+fn adjust_shadow(settings: ShadowSettings, key: KeyCode) -> Option<ShadowSettings> {
+    Some(match key {
+        KeyCode::KeyH => ShadowSettings {
+            enabled: !settings.enabled,
+            ..settings
+        },
+        KeyCode::KeyG => ShadowSettings {
+            resolution: SHADOW_RESOLUTIONS
+                .iter()
+                .copied()
+                .find(|&resolution| resolution > settings.resolution)
+                .unwrap_or(SHADOW_RESOLUTIONS[0]),
+            ..settings
+        },
+        KeyCode::KeyJ => ShadowSettings {
+            pcf_radius: (settings.pcf_radius + 1) % (MAX_PCF_RADIUS + 1),
+            ..settings
+        },
+        KeyCode::Comma => ShadowSettings {
+            depth_bias: settings.depth_bias / SHADOW_BIAS_STEP,
+            ..settings
+        },
+        KeyCode::Period => ShadowSettings {
+            depth_bias: settings.depth_bias * SHADOW_BIAS_STEP,
+            ..settings
+        },
+        KeyCode::Semicolon => ShadowSettings {
+            normal_bias: settings.normal_bias / SHADOW_BIAS_STEP,
+            ..settings
+        },
+        KeyCode::Quote => ShadowSettings {
+            normal_bias: settings.normal_bias * SHADOW_BIAS_STEP,
+            ..settings
+        },
+        _ => return None,
+    })
+}
+
+/// The present mode V switches to after `current`, the next of
+/// [`PRESENT_MODES`] that's `supported`.
+fn next_present_mode(current: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    let start = PRESENT_MODES
+        .iter()
+        .position(|&mode| mode == current)
+        .unwrap_or(PRESENT_MODES.len() - 1);
+
+    (1..=PRESENT_MODES.len())
+        .map(|offset| PRESENT_MODES[(start + offset) % PRESENT_MODES.len()])
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(current)
+}
+
+/// Which camera the number keys 1 to 9 select.
+fn digit_index(key: KeyCode) -> Option<usize> {
+    let digits = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+
+    digits.iter().position(|&digit| digit == key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::next_present_mode;
+    use wgpu::PresentMode;
+
+    /// A headless renderer of `width`x`height`, `None` without an adapter.
+    #[cfg(feature = "gpu-tests")]
+    fn headless(width: u32, height: u32) -> Option<super::Renderer> {
+        use super::{Renderer, RendererError};
+
+        match pollster::block_on(Renderer::new_headless(width, height, Default::default())) {
+            Ok(renderer) => Some(renderer),
+            Err(RendererError::NoAdapter { .. }) => {
+                eprintln!("Skipped, there's no adapter");
+                None
+            }
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    #[test]
+    fn present_modes_cycle_through_the_supported() {
+        let all = [
+            PresentMode::Immediate,
+            PresentMode::Fifo,
+            PresentMode::Mailbox,
+        ];
+        assert_eq!(
+            next_present_mode(PresentMode::Fifo, &all),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::Immediate, &all),
+            PresentMode::Fifo
+        );
+
+        let without_mailbox = [PresentMode::Fifo, PresentMode::Immediate];
+        assert_eq!(
+            next_present_mode(PresentMode::Fifo, &without_mailbox),
+            PresentMode::Immediate
+        );
+        // Only ever Fifo, or a mode V doesn't cycle through
+        assert_eq!(
+            next_present_mode(PresentMode::Fifo, &[PresentMode::Fifo]),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::FifoRelaxed, &without_mailbox),
+            PresentMode::Fifo
+        );
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn headless_frames_draw_the_scene() {
+        use super::CLEAR_COLOR;
+        use image::Rgba;
+        use std::{thread, time::Duration};
+        use winit::dpi::PhysicalSize;
+
+        let Some(mut renderer) = headless(64, 48) else {
+            return;
+        };
+        while renderer.is_loading() {
+            renderer.update(Duration::ZERO);
+            thread::sleep(Duration::from_millis(10));
+        }
+        renderer.update(Duration::ZERO);
+        let image = renderer.render_to_image().unwrap();
+        assert_eq!(image.dimensions(), (64, 48));
+
+        // As the sRGB target stores it
+        let encode = |linear: f64| (linear.powf(1.0 / 2.2) * 255.0).round() as u8;
+        let clear = Rgba([
+            encode(CLEAR_COLOR.r),
+            encode(CLEAR_COLOR.g),
+            encode(CLEAR_COLOR.b),
+            255,
+        ]);
+        let differs =
+            |pixel: &Rgba<u8>| pixel.0.iter().zip(clear.0).any(|(&a, b)| a.abs_diff(b) > 2);
+        assert!(image.pixels().any(differs));
+
+        // Applied straight away without a surface to wait on
+        renderer.resize(PhysicalSize::new(32, 24));
+        let image = renderer.render_to_image().unwrap();
+        assert_eq!(image.dimensions(), (32, 24));
+
+        // What the debug panel changes is drawn with
+        let mut panel = renderer.panel();
+        panel.clear_color = [1.0, 0.0, 0.0];
+        renderer.apply_panel(panel);
+        let image = renderer.render_to_image().unwrap();
+        assert!(image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255]));
+
+        // Scaled scenes are blitted onto a target of the window's size
+        for scale in [0.5, 2.0] {
+            renderer.set_render_scale(scale);
+            let image = renderer.render_to_image().unwrap();
+            assert_eq!(image.dimensions(), (32, 24));
+            assert!(image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255]));
+        }
+        renderer.set_render_scale(10.0);
+        assert_eq!(renderer.render_scale(), 2.0);
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn cameras_switch_by_name() {
+        use crate::camera::{Camera, Projection};
+        use cgmath::{Deg, Point3};
+        use std::time::Duration;
+
+        let Some(mut renderer) = headless(32, 24) else {
+            return;
+        };
+        let main = *renderer.camera();
+        let side = Camera::new((10.0, 2.0, 0.0), Deg(180.0), Deg(0.0));
+        renderer.add_camera("Side", side, None);
+
+        assert!(renderer.set_active_camera("Side", Duration::ZERO));
+        assert_eq!(renderer.camera().position, side.position);
+        assert!(!renderer.set_active_camera("Missing", Duration::ZERO));
+        assert_eq!(renderer.camera().position, side.position);
+
+        // Adding one of the same name replaces it, projection included
+        let narrow = Projection::new(32, 24, Deg(20.0), 0.1, 100.0);
+        renderer.add_camera(
+            "Side",
+            Camera::new((0.0, 5.0, 5.0), Deg(0.0), Deg(0.0)),
+            Some(narrow),
+        );
+        assert_eq!(renderer.camera().position, Point3::new(0.0, 5.0, 5.0));
+        renderer.render_to_image().unwrap();
+
+        // Eased back over a transition, ending on the camera switched to
+        assert!(renderer.set_active_camera("Main", Duration::from_millis(100)));
+        assert_eq!(renderer.camera().position, main.position);
+        renderer.update(Duration::from_millis(200));
+        renderer.render_to_image().unwrap();
+    }
+}
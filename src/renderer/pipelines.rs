@@ -0,0 +1,297 @@
+//! The pipelines the scene is drawn with, set up together since they share
+//! the surface's format, the depth texture and most of their bind groups.
+
+use crate::{
+    draw_constants::DrawConstants,
+    instance::RawInstance,
+    model::{ModelVertex, VertexBufferFormat},
+    pipeline::{BlendMode, Pipeline, PipelineOptions, ShaderStage},
+    shader::{Shader, ShaderDefines},
+    terrain::{DisplacedPatch, TerrainVertex},
+    texture::Texture,
+};
+use std::iter;
+use wgpu::{
+    BindGroupLayout, CompareFunction, Device, Features, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveTopology, TextureFormat,
+};
+
+/// The bind group layouts the pipelines are laid out with.
+pub struct SceneLayouts<'a> {
+    pub texture: &'a BindGroupLayout,
+    /// Like the texture layout, with the diffuse map as a texture array.
+    pub texture_array: &'a BindGroupLayout,
+    pub camera: &'a BindGroupLayout,
+    pub lights: &'a BindGroupLayout,
+    pub sun: &'a BindGroupLayout,
+}
+
+pub struct ScenePipelines {
+    pub standard: Pipeline,
+    pub pbr: Pipeline,
+    /// Blended variants of the standard and PBR pipelines for materials that
+    /// are [`Material::is_transparent`](crate::model::Material::is_transparent).
+    pub transparent: Pipeline,
+    pub transparent_pbr: Pipeline,
+    /// The standard pipeline drawing lines, see
+    /// [`Mesh::wireframe_indices`](crate::model::Mesh::wireframe_indices).
+    pub wireframe: Pipeline,
+    /// Draws the opaque meshes' depth alone for
+    /// [`RendererSettings::z_prepass`](super::RendererSettings::z_prepass),
+    /// after which they're shaded with the prepassed variants of the
+    /// standard and PBR pipelines. Those only draw where the depth is equal
+    /// and leave it as it is.
+    pub prepass: Pipeline,
+    pub prepassed: Pipeline,
+    pub prepassed_pbr: Pipeline,
+    pub array: Pipeline,
+    pub light: Pipeline,
+    pub terrain: Pipeline,
+    /// For [`TerrainMode::Gpu`](crate::terrain::TerrainMode::Gpu).
+    pub displaced_terrain: Pipeline,
+}
+
+impl ScenePipelines {
+    /// Draws into `format` targets. The light markers' pipeline is laid out
+    /// for `draw_constants`, with its fallback's group after the camera's.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        layouts: &SceneLayouts,
+        draw_constants: &DrawConstants,
+    ) -> Self {
+        let (
+            standard_render_pipeline,
+            transparent_render_pipeline,
+            wireframe_render_pipeline,
+            prepass_render_pipeline,
+            prepassed_render_pipeline,
+        ) = {
+            let shader = Shader::embedded(device, "standard.wgsl", ShaderDefines::default());
+
+            let layout = || {
+                device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("Standard render pipeline layout"),
+                    bind_group_layouts: &[
+                        layouts.texture,
+                        layouts.camera,
+                        layouts.lights,
+                        layouts.sun,
+                    ],
+                    push_constant_ranges: &[],
+                })
+            };
+            let vertex_layouts = [ModelVertex::descriptor(), RawInstance::descriptor()];
+            let options = |label| {
+                PipelineOptions::new(label, format, &vertex_layouts)
+                    .with_depth_format(Texture::DEPTH_FORMAT)
+            };
+            // Each mesh has line_indices to draw instead where lines can't be
+            // rasterized from triangles
+            let wireframe = match device.features().contains(Features::POLYGON_MODE_LINE) {
+                true => options("Wireframe pipeline").with_polygon_mode(PolygonMode::Line),
+                false => options("Wireframe pipeline").with_topology(PrimitiveTopology::LineList),
+            };
+
+            (
+                Pipeline::new(device, &shader, layout(), options("Standard pipeline")),
+                Pipeline::new(
+                    device,
+                    &shader,
+                    layout(),
+                    options("Transparent pipeline").with_blend_mode(BlendMode::AlphaBlend),
+                ),
+                Pipeline::new(device, &shader, layout(), wireframe),
+                Pipeline::with_stages(
+                    device,
+                    ShaderStage::new(&shader, "vs_main"),
+                    None,
+                    layout(),
+                    PipelineOptions::depth_only(
+                        "Depth pre-pass pipeline",
+                        Texture::DEPTH_FORMAT,
+                        &vertex_layouts,
+                    ),
+                ),
+                Pipeline::new(
+                    device,
+                    &shader,
+                    layout(),
+                    options("Prepassed pipeline")
+                        .with_depth_compare(CompareFunction::Equal)
+                        .without_depth_write(),
+                ),
+            )
+        };
+
+        // Same bindings as the standard pipeline, for materials with
+        // MaterialKind::Pbr
+        let (pbr_render_pipeline, transparent_pbr_render_pipeline, prepassed_pbr_render_pipeline) = {
+            let shader = Shader::embedded(device, "pbr.wgsl", ShaderDefines::default());
+            let options = |label| {
+                PipelineOptions::new(
+                    label,
+                    format,
+                    &[ModelVertex::descriptor(), RawInstance::descriptor()],
+                )
+                .with_depth_format(Texture::DEPTH_FORMAT)
+            };
+            let pipeline = |options| {
+                let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("PBR render pipeline layout"),
+                    bind_group_layouts: &[
+                        layouts.texture,
+                        layouts.camera,
+                        layouts.lights,
+                        layouts.sun,
+                    ],
+                    push_constant_ranges: &[],
+                });
+
+                Pipeline::new(device, &shader, layout, options)
+            };
+
+            (
+                pipeline(options("PBR pipeline")),
+                pipeline(
+                    options("Transparent PBR pipeline").with_blend_mode(BlendMode::AlphaBlend),
+                ),
+                pipeline(
+                    options("Prepassed PBR pipeline")
+                        .with_depth_compare(CompareFunction::Equal)
+                        .without_depth_write(),
+                ),
+            )
+        };
+
+        // Draws instances of models whose diffuse map is a texture array,
+        // each instance selecting its layer
+        let array_render_pipeline = {
+            let shader = Shader::embedded(
+                device,
+                "standard.wgsl",
+                ShaderDefines::default().with_flag("TEXTURE_ARRAY"),
+            );
+            let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("Texture array render pipeline layout"),
+                bind_group_layouts: &[
+                    layouts.texture_array,
+                    layouts.camera,
+                    layouts.lights,
+                    layouts.sun,
+                ],
+                push_constant_ranges: &[],
+            });
+
+            Pipeline::new(
+                device,
+                &shader,
+                layout,
+                PipelineOptions::new(
+                    "Texture array pipeline",
+                    format,
+                    &[ModelVertex::descriptor(), RawInstance::descriptor()],
+                )
+                .with_depth_format(Texture::DEPTH_FORMAT),
+            )
+        };
+
+        // Each marker is a single draw placed by the draw constants
+        let light_render_pipeline = {
+            let shader = Shader::embedded(device, "light.wgsl", draw_constants.defines());
+            let options =
+                PipelineOptions::new("Lighting pipeline", format, &[ModelVertex::descriptor()])
+                    .with_depth_format(Texture::DEPTH_FORMAT)
+                    // Glows over what's behind, drawn with the transparent geometry
+                    .with_blend_mode(BlendMode::Additive)
+                    .with_push_constant_ranges(draw_constants.push_constant_ranges());
+            let bind_group_layouts: Vec<&BindGroupLayout> = iter::once(layouts.camera)
+                .chain(draw_constants.bind_group_layout())
+                .collect();
+
+            Pipeline::new(
+                device,
+                &shader,
+                options.layout(device, &bind_group_layouts),
+                options,
+            )
+        };
+
+        let terrain_render_pipeline = {
+            let shader = Shader::embedded(device, "terrain.wgsl", ShaderDefines::default());
+            let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("Terrain pipeline layout"),
+                bind_group_layouts: &[layouts.camera, layouts.lights, layouts.sun],
+                push_constant_ranges: &[],
+            });
+
+            Pipeline::new(
+                device,
+                &shader,
+                layout,
+                PipelineOptions::new("Terrain pipeline", format, &[TerrainVertex::descriptor()])
+                    .with_depth_format(Texture::DEPTH_FORMAT),
+            )
+        };
+        let displaced_terrain_render_pipeline = {
+            let shader =
+                Shader::embedded(device, "terrain_displaced.wgsl", ShaderDefines::default());
+            let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("Displaced terrain pipeline layout"),
+                bind_group_layouts: &[
+                    layouts.camera,
+                    layouts.lights,
+                    layouts.sun,
+                    &DisplacedPatch::bind_group_layout(device),
+                ],
+                push_constant_ranges: &[],
+            });
+
+            Pipeline::new(
+                device,
+                &shader,
+                layout,
+                PipelineOptions::new(
+                    "Displaced terrain pipeline",
+                    format,
+                    &DisplacedPatch::vertex_layouts(),
+                )
+                .with_depth_format(Texture::DEPTH_FORMAT),
+            )
+        };
+
+        Self {
+            standard: standard_render_pipeline,
+            pbr: pbr_render_pipeline,
+            transparent: transparent_render_pipeline,
+            transparent_pbr: transparent_pbr_render_pipeline,
+            wireframe: wireframe_render_pipeline,
+            prepass: prepass_render_pipeline,
+            prepassed: prepassed_render_pipeline,
+            prepassed_pbr: prepassed_pbr_render_pipeline,
+            array: array_render_pipeline,
+            light: light_render_pipeline,
+            terrain: terrain_render_pipeline,
+            displaced_terrain: displaced_terrain_render_pipeline,
+        }
+    }
+
+    /// Every pipeline, for rebuilding them from edited shaders.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Pipeline> {
+        [
+            &mut self.standard,
+            &mut self.pbr,
+            &mut self.transparent,
+            &mut self.transparent_pbr,
+            &mut self.wireframe,
+            &mut self.prepass,
+            &mut self.prepassed,
+            &mut self.prepassed_pbr,
+            &mut self.array,
+            &mut self.light,
+            &mut self.terrain,
+            &mut self.displaced_terrain,
+        ]
+        .into_iter()
+    }
+}
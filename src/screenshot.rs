@@ -0,0 +1,184 @@
+//! Saves frames as PNGs next to the executable. A frame's copy is mapped
+//! without blocking and encoded on a worker thread, so taking a screenshot
+//! holds up the frames after it by at most the copy itself.
+
+use crate::texture::{
+    readback::{PendingReadback, ReadbackRows},
+    TextureError,
+};
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use wgpu::{CommandEncoder, Device, Maintain};
+
+pub type ScreenshotResult<T> = Result<T, ScreenshotError>;
+
+#[derive(Debug, Error)]
+pub enum ScreenshotError {
+    #[error("failed to read the frame back: {0}")]
+    Texture(#[from] TextureError),
+    #[error("failed to encode the screenshot: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("failed to find where to save the screenshot: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Screenshots on their way from the GPU to disk.
+#[derive(Default)]
+pub struct Screenshots {
+    /// With when each was taken, for its file name.
+    readbacks: Vec<(SystemTime, PendingReadback)>,
+    /// Each gets the path its screenshot was saved to.
+    saving: Vec<Receiver<ScreenshotResult<PathBuf>>>,
+}
+
+impl Screenshots {
+    /// Records copying `texture`, e.g. a surface frame allowing
+    /// [`wgpu::TextureUsages::COPY_SRC`], into `encoder`.
+    pub fn capture(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> ScreenshotResult<()> {
+        let readback = PendingReadback::new(device, encoder, texture)?;
+        self.readbacks.push((SystemTime::now(), readback));
+
+        Ok(())
+    }
+
+    /// Starts mapping the copies captured since the last call, once their
+    /// encoder has been submitted.
+    pub fn map(&mut self) {
+        for (_, readback) in &mut self.readbacks {
+            readback.map();
+        }
+    }
+
+    /// Hands mapped copies to worker threads and returns a finished
+    /// screenshot's path, if any has been saved since the last call.
+    pub fn poll(&mut self, device: &Device) -> Option<ScreenshotResult<PathBuf>> {
+        if !self.readbacks.is_empty() {
+            device.poll(Maintain::Poll);
+        }
+
+        let mut index = 0;
+        while index < self.readbacks.len() {
+            let Some(result) = self.readbacks[index].1.poll() else {
+                index += 1;
+                continue;
+            };
+
+            let (taken, _) = self.readbacks.remove(index);
+            match result {
+                Ok(rows) => self.saving.push(save_async(rows, taken)),
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+
+        for index in 0..self.saving.len() {
+            let result = match self.saving[index].try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => continue,
+                Err(TryRecvError::Disconnected) => {
+                    Err(io::Error::other("the screenshot worker panicked").into())
+                }
+            };
+            self.saving.remove(index);
+
+            return Some(result);
+        }
+
+        None
+    }
+}
+
+fn save_async(rows: ReadbackRows, taken: SystemTime) -> Receiver<ScreenshotResult<PathBuf>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(save(rows, taken));
+    });
+
+    receiver
+}
+
+fn save(rows: ReadbackRows, taken: SystemTime) -> ScreenshotResult<PathBuf> {
+    let executable = env::current_exe()?;
+    let directory = executable.parent().unwrap_or(Path::new("."));
+    let path = unused_path(directory, &timestamp(taken));
+    rows.into_image().save(&path)?;
+
+    Ok(path)
+}
+
+/// `screenshot-<stamp>.png` in `directory`, numbered when several are taken
+/// within a second.
+fn unused_path(directory: &Path, stamp: &str) -> PathBuf {
+    let mut path = directory.join(format!("screenshot-{stamp}.png"));
+    let mut number = 2;
+    while path.exists() {
+        path = directory.join(format!("screenshot-{stamp}-{number}.png"));
+        number += 1;
+    }
+
+    path
+}
+
+/// `time` in UTC as `YYYYMMDD-HHMMSS`.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    let seconds = seconds % 86_400;
+
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The date `days` after 1970-01-01, after Howard Hinnant's
+/// `civil_from_days` with eras starting on March 1st.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = match month_from_march < 10 {
+        true => month_from_march + 3,
+        false => month_from_march - 9,
+    };
+    let year = era * 400 + year_of_era + (month <= 2) as u64;
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::timestamp;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101-000000");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(timestamp(leap_day), "20240229-235959");
+        assert_eq!(
+            timestamp(leap_day + Duration::from_secs(1)),
+            "20240301-000000"
+        );
+    }
+}
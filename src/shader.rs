@@ -0,0 +1,474 @@
+//! Shaders put together from the files under `shaders/` by a small
+//! preprocessor, line by line:
+//!
+//! - `//!include "common.wgsl"` pastes in another file, once however often
+//!   it's included.
+//! - `//!define NAME value` defines `NAME`, substituting `value` wherever it
+//!   appears as a word afterwards. Those in [`ShaderDefines`] take
+//!   precedence, so these act as defaults.
+//! - `//!ifdef NAME`, `//!ifndef NAME`, `//!else` and `//!endif` keep or
+//!   drop the lines between them.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use wgpu::{Device, ShaderModule, ShaderModuleDescriptor, ShaderSource};
+
+/// The shaders built into the binary, by file name.
+const EMBEDDED: &[(&str, &str)] = &[
+    ("common.wgsl", include_str!("../shaders/common.wgsl")),
+    ("debug.wgsl", include_str!("../shaders/debug.wgsl")),
+    ("instances.wgsl", include_str!("../shaders/instances.wgsl")),
+    ("light.wgsl", include_str!("../shaders/light.wgsl")),
+    ("pbr.wgsl", include_str!("../shaders/pbr.wgsl")),
+    ("shadow.wgsl", include_str!("../shaders/shadow.wgsl")),
+    ("standard.wgsl", include_str!("../shaders/standard.wgsl")),
+    ("terrain.wgsl", include_str!("../shaders/terrain.wgsl")),
+    (
+        "terrain_displaced.wgsl",
+        include_str!("../shaders/terrain_displaced.wgsl"),
+    ),
+];
+
+/// Names defined for a shader, ordered so equal sets hash alike.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines(BTreeMap<String, String>);
+
+impl ShaderDefines {
+    /// Defines `name` as `value`, replacing any earlier value.
+    pub fn with(mut self, name: &str, value: impl ToString) -> Self {
+        self.0.insert(name.to_owned(), value.to_string());
+        self
+    }
+
+    /// Defines `name` for `//!ifdef`, without a value to substitute.
+    pub fn with_flag(self, name: &str) -> Self {
+        self.with(name, "")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// What a shader module is made from, the same for every pipeline that can
+/// share it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderKey {
+    /// Under `shaders/`.
+    pub file: &'static str,
+    pub defines: ShaderDefines,
+}
+
+impl ShaderKey {
+    pub fn new(file: &'static str, defines: ShaderDefines) -> Self {
+        Self { file, defines }
+    }
+}
+
+/// The file, followed by its defines in brackets if there are any.
+impl fmt::Display for ShaderKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if !self.defines.is_empty() {
+            let names: Vec<&str> = self.defines.0.keys().map(String::as_str).collect();
+            write!(f, " [{}]", names.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Cheap to clone, pipelines made from it share the module.
+#[derive(Clone, Debug)]
+pub struct Shader {
+    pub key: ShaderKey,
+    pub module: Arc<ShaderModule>,
+}
+
+impl Shader {
+    /// Preprocesses one of the shaders built into the binary.
+    ///
+    /// # Panics
+    ///
+    /// If it fails to preprocess, which the tests rule out for those the
+    /// renderer uses.
+    pub fn embedded(device: &Device, file: &'static str, defines: ShaderDefines) -> Self {
+        let key = ShaderKey::new(file, defines);
+        let source = preprocess(file, &key.defines, read_embedded)
+            .unwrap_or_else(|error| panic!("{error}"))
+            .source;
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(file),
+            source: ShaderSource::Wgsl(source.into()),
+        });
+
+        Self {
+            key,
+            module: Arc::new(module),
+        }
+    }
+}
+
+pub fn read_embedded(file: &str) -> io::Result<String> {
+    EMBEDDED
+        .iter()
+        .find(|(name, _)| *name == file)
+        .map(|(_, source)| source.to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not an embedded shader"))
+}
+
+/// Reads shaders from `directory` instead, e.g. to pick up edits.
+pub fn read_from(directory: &Path) -> impl Fn(&str) -> io::Result<String> {
+    let directory = PathBuf::from(directory);
+
+    move |file| fs::read_to_string(directory.join(file))
+}
+
+/// A shader's source with its directives resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preprocessed {
+    pub source: String,
+    /// Every file it was put together from, itself first.
+    pub files: Vec<String>,
+}
+
+/// Resolves `file`'s directives, reading it and what it includes with
+/// `read`.
+pub fn preprocess<R>(file: &str, defines: &ShaderDefines, read: R) -> PreprocessResult<Preprocessed>
+where
+    R: Fn(&str) -> io::Result<String>,
+{
+    let mut preprocessor = Preprocessor {
+        read,
+        defines: defines.clone(),
+        files: vec![],
+        including: vec![],
+        source: String::new(),
+    };
+    let source = (preprocessor.read)(file).map_err(|source| PreprocessError::Read {
+        file: file.to_owned(),
+        source,
+    })?;
+    preprocessor.process(file, &source)?;
+
+    Ok(Preprocessed {
+        source: preprocessor.source,
+        files: preprocessor.files,
+    })
+}
+
+/// An `//!ifdef` or `//!ifndef` and the `//!else` that may follow it.
+struct Conditional {
+    /// Where it opened.
+    line: usize,
+    /// Whether its own lines are kept, regardless of those around it.
+    condition: bool,
+    /// Whether the lines around it are kept.
+    enclosing: bool,
+    in_else: bool,
+}
+
+impl Conditional {
+    fn is_active(&self) -> bool {
+        self.enclosing && self.condition != self.in_else
+    }
+}
+
+struct Preprocessor<R> {
+    read: R,
+    defines: ShaderDefines,
+    files: Vec<String>,
+    /// The files being processed, outermost first, to catch cycles.
+    including: Vec<String>,
+    source: String,
+}
+
+impl<R> Preprocessor<R>
+where
+    R: Fn(&str) -> io::Result<String>,
+{
+    fn process(&mut self, file: &str, source: &str) -> PreprocessResult<()> {
+        self.files.push(file.to_owned());
+        self.including.push(file.to_owned());
+
+        let mut conditionals: Vec<Conditional> = vec![];
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let syntax = |message: String| PreprocessError::Syntax {
+                file: file.to_owned(),
+                line,
+                message,
+            };
+            let active = conditionals.last().is_none_or(Conditional::is_active);
+            let Some(directive) = text.trim_start().strip_prefix("//!") else {
+                if active {
+                    self.source += &substitute(text, &self.defines);
+                    self.source.push('\n');
+                }
+                continue;
+            };
+
+            let (name, argument) = directive
+                .trim()
+                .split_once(char::is_whitespace)
+                .map_or((directive.trim(), ""), |(name, argument)| {
+                    (name, argument.trim())
+                });
+            match name {
+                "ifdef" | "ifndef" => {
+                    let define = identifier(argument)
+                        .ok_or_else(|| syntax(format!("//!{name} needs a name")))?;
+                    conditionals.push(Conditional {
+                        line,
+                        condition: self.defines.is_defined(define) == (name == "ifdef"),
+                        enclosing: active,
+                        in_else: false,
+                    });
+                }
+                "else" => match conditionals.last_mut() {
+                    Some(conditional) if !conditional.in_else => conditional.in_else = true,
+                    Some(_) => return Err(syntax("a second //!else".to_owned())),
+                    None => return Err(syntax("//!else without an //!ifdef".to_owned())),
+                },
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| syntax("//!endif without an //!ifdef".to_owned()))?;
+                }
+                // Only what's kept is resolved
+                _ if !active => {}
+                "include" => {
+                    let include = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .filter(|include| !include.is_empty())
+                        .ok_or_else(|| syntax("//!include needs a quoted file".to_owned()))?;
+                    if self.including.iter().any(|file| file == include) {
+                        return Err(syntax(format!("{include} ends up including itself")));
+                    }
+                    if self.files.iter().any(|file| file == include) {
+                        continue;
+                    }
+                    let included =
+                        (self.read)(include).map_err(|source| PreprocessError::Include {
+                            file: file.to_owned(),
+                            line,
+                            include: include.to_owned(),
+                            source,
+                        })?;
+                    self.process(include, &included)?;
+                }
+                "define" => {
+                    let (define, value) = argument
+                        .split_once(char::is_whitespace)
+                        .map_or((argument, ""), |(define, value)| (define, value.trim()));
+                    let define = identifier(define)
+                        .ok_or_else(|| syntax("//!define needs a name".to_owned()))?;
+                    if !self.defines.is_defined(define) {
+                        self.defines.0.insert(define.to_owned(), value.to_owned());
+                    }
+                }
+                _ => return Err(syntax(format!("unknown directive //!{name}"))),
+            }
+        }
+        if let Some(conditional) = conditionals.last() {
+            return Err(PreprocessError::Syntax {
+                file: file.to_owned(),
+                line: conditional.line,
+                message: "//!ifdef without an //!endif".to_owned(),
+            });
+        }
+        self.including.pop();
+
+        Ok(())
+    }
+}
+
+/// `text` if it's a single identifier.
+fn identifier(text: &str) -> Option<&str> {
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    let valid = (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    valid.then_some(text)
+}
+
+/// `line` with each word that's defined with a value replaced by it.
+fn substitute(line: &str, defines: &ShaderDefines) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        output += &rest[..start];
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        output += match defines.get(word) {
+            Some(value) if !value.is_empty() => value,
+            _ => word,
+        };
+        rest = &rest[end..];
+    }
+    output += rest;
+
+    output
+}
+
+pub type PreprocessResult<T> = Result<T, PreprocessError>;
+
+/// Located at the line of the file the directive's on.
+#[derive(Debug, Error)]
+pub enum PreprocessError {
+    #[error("Failed to read {file}: {source}")]
+    Read { file: String, source: io::Error },
+    #[error("{file}:{line}: failed to include {include}: {source}")]
+    Include {
+        file: String,
+        line: usize,
+        include: String,
+        source: io::Error,
+    },
+    #[error("{file}:{line}: {message}")]
+    Syntax {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::{preprocess, read_from, PreprocessError, ShaderDefines};
+    use crate::shader_watcher::{shader_capabilities, validate_shader};
+    use std::{collections::HashMap, fs, io, path::Path};
+
+    fn files<'a>(files: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> io::Result<String> + 'a {
+        let files: HashMap<_, _> = files.iter().copied().collect();
+        move |file| {
+            files
+                .get(file)
+                .map(|source| source.to_string())
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn directives_are_resolved() {
+        let read = files(&[
+            (
+                "main.wgsl",
+                "//!include \"common.wgsl\"\n\
+                 //!define SAMPLES 4\n\
+                 //!ifdef SHADOWS\n\
+                 shadowed(SAMPLES);\n\
+                 //!ifndef SOFT\n\
+                 hard();\n\
+                 //!endif\n\
+                 //!else\n\
+                 unshadowed(SAMPLES_2);\n\
+                 //!endif\n\
+                 //!include \"common.wgsl\"\n",
+            ),
+            ("common.wgsl", "//!include \"types.wgsl\"\ncommon();\n"),
+            ("types.wgsl", "types();\n"),
+        ]);
+
+        let plain = preprocess("main.wgsl", &ShaderDefines::default(), &read).unwrap();
+        assert_eq!(
+            plain.source,
+            "types();\ncommon();\nunshadowed(SAMPLES_2);\n"
+        );
+        assert_eq!(plain.files, ["main.wgsl", "common.wgsl", "types.wgsl"]);
+
+        // Given defines win over the shader's own
+        let defines = ShaderDefines::default()
+            .with_flag("SHADOWS")
+            .with("SAMPLES", 16);
+        let shadowed = preprocess("main.wgsl", &defines, &read).unwrap();
+        assert_eq!(
+            shadowed.source,
+            "types();\ncommon();\nshadowed(16);\nhard();\n"
+        );
+        let soft = preprocess("main.wgsl", &defines.with_flag("SOFT"), &read).unwrap();
+        assert_eq!(soft.source, "types();\ncommon();\nshadowed(16);\n");
+    }
+
+    #[test]
+    fn errors_point_at_the_directive() {
+        let read = files(&[
+            ("unclosed.wgsl", "a();\n//!ifdef A\nb();\n"),
+            ("stray.wgsl", "//!ifdef A\n//!endif\n//!endif\n"),
+            ("unknown.wgsl", "a();\n  //!import \"b.wgsl\"\n"),
+            ("missing.wgsl", "//!include \"common.wgsl\"\n"),
+            ("cycle.wgsl", "//!include \"again.wgsl\"\n"),
+            ("again.wgsl", "a();\n//!include \"cycle.wgsl\"\n"),
+        ]);
+        let location = |file| match preprocess(file, &ShaderDefines::default(), &read) {
+            Err(PreprocessError::Syntax { file, line, .. })
+            | Err(PreprocessError::Include { file, line, .. }) => (file, line),
+            result => panic!("Expected an error in {file}, got {result:?}"),
+        };
+
+        assert_eq!(location("unclosed.wgsl"), ("unclosed.wgsl".to_owned(), 2));
+        assert_eq!(location("stray.wgsl"), ("stray.wgsl".to_owned(), 3));
+        assert_eq!(location("unknown.wgsl"), ("unknown.wgsl".to_owned(), 2));
+        assert_eq!(location("missing.wgsl"), ("missing.wgsl".to_owned(), 1));
+        assert_eq!(location("cycle.wgsl"), ("again.wgsl".to_owned(), 2));
+        let error = preprocess("none.wgsl", &ShaderDefines::default(), &read).unwrap_err();
+        assert!(matches!(error, PreprocessError::Read { .. }));
+    }
+
+    /// Every file in `shaders`, embedded or not, and the variants the
+    /// renderer builds, as the device would see them with all the features
+    /// it asks for.
+    #[test]
+    fn shaders_are_valid() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+        let mut shaders: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|file| file.ends_with(".wgsl"))
+            .map(|file| (file, ShaderDefines::default()))
+            .collect();
+        assert!(shaders.iter().any(|(file, _)| file == "standard.wgsl"));
+        let variants = [
+            ("standard.wgsl", "TEXTURE_ARRAY"),
+            ("light.wgsl", "PUSH_CONSTANTS"),
+        ];
+        for (file, flag) in variants {
+            shaders.push((file.to_owned(), ShaderDefines::default().with_flag(flag)));
+        }
+
+        let capabilities = shader_capabilities(crate::renderer::requested_features());
+        let read = read_from(&directory);
+        let failures: Vec<_> = shaders
+            .iter()
+            .filter_map(|(file, defines)| {
+                let path = format!("shaders/{file}");
+                let result = preprocess(file, defines, &read)
+                    .map_err(|error| format!("{path}: {error}"))
+                    .and_then(|preprocessed| {
+                        validate_shader(&preprocessed.source, &path, capabilities)
+                            .map_err(|error| error.to_string())
+                    });
+                result.err()
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
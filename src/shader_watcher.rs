@@ -0,0 +1,265 @@
+//! Picks up edits to the shaders while the renderer's running, so tweaking
+//! one doesn't take a rebuild. Only debug builds watch, release builds
+//! keep to the embedded shaders, see
+//! [`Shader::embedded`](crate::shader::Shader::embedded).
+
+use crate::shader::{preprocess, read_from, PreprocessError, ShaderKey};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+use wgpu::naga::{
+    front::wgsl,
+    valid::{Capabilities, ValidationFlags, Validator},
+    Module,
+};
+use wgpu::Features;
+
+/// Polls the modification times of the `.wgsl` files in a directory, like
+/// [`ResourceWatcher`](crate::model::resource::ResourceWatcher).
+#[derive(Debug)]
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    interval: Duration,
+    last_poll: Instant,
+    /// When each shader was last modified, by file name.
+    modified: HashMap<String, SystemTime>,
+    /// What reloaded shaders may use, see [`ShaderWatcher::with_features`].
+    capabilities: Capabilities,
+}
+
+impl ShaderWatcher {
+    /// Watches the `shaders` directory next to the manifest in debug builds,
+    /// `None` in release builds.
+    pub fn new(interval: Duration) -> Option<Self> {
+        cfg!(debug_assertions).then(|| {
+            Self::with_directory(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders"),
+                interval,
+            )
+        })
+    }
+
+    pub fn with_directory(directory: PathBuf, interval: Duration) -> Self {
+        Self {
+            modified: shader_modifications(&directory).collect(),
+            last_poll: Instant::now(),
+            directory,
+            interval,
+            capabilities: Capabilities::all(),
+        }
+    }
+
+    /// Validates reloaded shaders against what the device was created with,
+    /// rather than accepting anything naga can.
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.capabilities = shader_capabilities(features);
+        self
+    }
+
+    /// The names of the shader files modified since the last poll, checking
+    /// at most once per interval.
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        shader_modifications(&self.directory)
+            .filter(|(file_name, modified)| {
+                self.modified.insert(file_name.clone(), *modified) != Some(*modified)
+            })
+            .map(|(file_name, _)| file_name)
+            .collect()
+    }
+
+    /// `key`'s shader from the watched directory if it's made from any of
+    /// the `changed` files, `None` if it isn't.
+    pub fn reload(&self, key: &ShaderKey, changed: &[String]) -> ShaderResult<Option<Module>> {
+        let preprocessed = preprocess(key.file, &key.defines, read_from(&self.directory))?;
+        if !preprocessed.files.iter().any(|file| changed.contains(file)) {
+            return Ok(None);
+        }
+        let path = self.directory.join(key.file);
+
+        validate_shader(
+            &preprocessed.source,
+            &path.to_string_lossy(),
+            self.capabilities,
+        )
+        .map(Some)
+    }
+}
+
+/// The modification time of each `.wgsl` file in `directory`, skipping
+/// those that can't be read since they may be mid-save.
+fn shader_modifications(directory: &Path) -> impl Iterator<Item = (String, SystemTime)> {
+    fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "wgsl"))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((entry.file_name().to_string_lossy().into_owned(), modified))
+        })
+}
+
+/// What shaders may use on a device with `features`, as wgpu works it out.
+/// The downlevel capabilities are assumed, as with WebGPU's default limits.
+pub fn shader_capabilities(features: Features) -> Capabilities {
+    let mut capabilities = Capabilities::default();
+    let pairs = [
+        (Features::PUSH_CONSTANTS, Capabilities::PUSH_CONSTANT),
+        (Features::SHADER_F64, Capabilities::FLOAT64),
+        (
+            Features::SHADER_PRIMITIVE_INDEX,
+            Capabilities::PRIMITIVE_INDEX,
+        ),
+        (
+            Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+            Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                | Capabilities::SAMPLER_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+            Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Features::TEXTURE_FORMAT_16BIT_NORM,
+            Capabilities::STORAGE_TEXTURE_16BIT_NORM_FORMATS,
+        ),
+        (Features::MULTIVIEW, Capabilities::MULTIVIEW),
+        (
+            Features::SHADER_EARLY_DEPTH_TEST,
+            Capabilities::EARLY_DEPTH_TEST,
+        ),
+        (
+            Features::DUAL_SOURCE_BLENDING,
+            Capabilities::DUAL_SOURCE_BLENDING,
+        ),
+    ];
+    for (feature, capability) in pairs {
+        capabilities.set(capability, features.contains(feature));
+    }
+
+    capabilities
+}
+
+/// Parses and validates WGSL the way wgpu would, so a broken shader is
+/// caught before it's handed to the device. `path` labels the errors.
+pub fn validate_shader(
+    source: &str,
+    path: &str,
+    capabilities: Capabilities,
+) -> ShaderResult<Module> {
+    let module = wgsl::parse_str(source)
+        .map_err(|error| ShaderError::Parse(error.emit_to_string_with_path(source, path)))?;
+    Validator::new(ValidationFlags::all(), capabilities)
+        .validate(&module)
+        .map_err(|error| ShaderError::Invalid(error.emit_to_string_with_path(source, path)))?;
+
+    Ok(module)
+}
+
+pub type ShaderResult<T> = Result<T, ShaderError>;
+
+/// Parse and validation errors are rendered against the preprocessed
+/// source.
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error(transparent)]
+    Preprocess(#[from] PreprocessError),
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{shader_capabilities, validate_shader, ShaderError, ShaderWatcher};
+    use crate::shader::{ShaderDefines, ShaderKey};
+    use std::{
+        fs::{self, File},
+        time::{Duration, SystemTime},
+    };
+    use wgpu::{naga::valid::Capabilities, Features};
+
+    const FLAT: &str = "
+        @vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }
+        @fragment fn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }
+    ";
+
+    #[test]
+    fn broken_shaders_are_caught() {
+        let capabilities = Capabilities::default();
+        assert!(validate_shader(FLAT, "flat.wgsl", capabilities).is_ok());
+
+        let unparsed = validate_shader("@vertex fn vs_main( {}", "unparsed.wgsl", capabilities);
+        assert!(matches!(unparsed, Err(ShaderError::Parse(_))));
+        // Parses, but returns a float as a vector
+        let invalid = "@fragment fn fs_main() -> @location(0) vec4<f32> { return 1.0; }";
+        match validate_shader(invalid, "invalid.wgsl", capabilities) {
+            Err(ShaderError::Invalid(message)) => assert!(message.contains("invalid.wgsl")),
+            result => panic!("Expected a validation error, got {result:?}"),
+        }
+
+        // Only valid where the device has push constants
+        let pushed = "
+            struct Draw { color: vec4<f32> }
+            var<push_constant> draw: Draw;
+            @fragment fn fs_main() -> @location(0) vec4<f32> { return draw.color; }
+        ";
+        assert!(matches!(
+            validate_shader(pushed, "pushed.wgsl", capabilities),
+            Err(ShaderError::Invalid(_))
+        ));
+        let capabilities = shader_capabilities(Features::PUSH_CONSTANTS);
+        assert!(validate_shader(pushed, "pushed.wgsl", capabilities).is_ok());
+    }
+
+    #[test]
+    fn edited_shaders_are_reloaded() {
+        let directory = std::env::temp_dir().join("edited_shaders_are_reloaded");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("flat.wgsl");
+        fs::write(&path, FLAT).unwrap();
+        fs::write(directory.join("notes.txt"), "").unwrap();
+        let mut watcher = ShaderWatcher::with_directory(directory.clone(), Duration::ZERO);
+        assert!(watcher.poll().is_empty());
+
+        // Set apart from the first write, which can share its timestamp
+        let touch = |seconds: u64| {
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        touch(1);
+        let changed = watcher.poll();
+        assert_eq!(changed, ["flat.wgsl"]);
+        assert!(watcher.poll().is_empty());
+        let key = ShaderKey::new("flat.wgsl", ShaderDefines::default());
+        let module = watcher.reload(&key, &changed).unwrap().unwrap();
+        assert_eq!(module.entry_points.len(), 2);
+        let unrelated = ["other.wgsl".to_owned()];
+        assert!(watcher.reload(&key, &unrelated).unwrap().is_none());
+
+        // Reported rather than reloaded
+        fs::write(&path, "@vertex fn vs_main( {}").unwrap();
+        touch(2);
+        let changed = watcher.poll();
+        assert!(matches!(
+            watcher.reload(&key, &changed),
+            Err(ShaderError::Parse(_))
+        ));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! The depth-only pass drawing the scene from the sun into its shadow
+//! map, sampled by the standard shader to darken what the sun can't reach.
+
+use crate::{
+    light::DirectionalLight,
+    model::Model,
+    pipeline::{Pipeline, PipelineOptions, ShaderStage},
+    shader::{Shader, ShaderDefines},
+    texture::Texture,
+};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, SquareMatrix};
+use std::ops::Range;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, CommandEncoder,
+    Device, IndexFormat, LoadOp, Operations, PipelineLayoutDescriptor, Queue,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPassTimestampWrites,
+    ShaderStages, StoreOp, VertexBufferLayout,
+};
+
+/// Too little bias and surfaces shadow themselves in stripes, too much and
+/// shadows come loose from what casts them. Which is right depends on the
+/// scene's scale, so they're left adjustable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    /// How many texels either side of the one looked up are averaged in to
+    /// soften the edges, 0 for hard shadows.
+    pub pcf_radius: u32,
+    /// Taken off depths before they're compared with the map's, which spans
+    /// 0 to 1 across the scene.
+    pub depth_bias: f32,
+    /// How far along their normal positions are moved before they're looked
+    /// up, in world units. Covers surfaces at a grazing angle to the light,
+    /// which a depth bias alone would need to be huge for.
+    pub normal_bias: f32,
+    /// Skips the pass altogether, lighting everything.
+    pub enabled: bool,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            pcf_radius: 1,
+            depth_bias: 0.001,
+            normal_bias: 0.05,
+            enabled: true,
+        }
+    }
+}
+
+/// The settings the lit shaders read, laid out to match `ShadowSettings` in
+/// them.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct ShadowUniform {
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: u32,
+    enabled: u32,
+}
+
+impl From<ShadowSettings> for ShadowUniform {
+    fn from(settings: ShadowSettings) -> Self {
+        Self {
+            depth_bias: settings.depth_bias,
+            normal_bias: settings.normal_bias,
+            pcf_radius: settings.pcf_radius,
+            enabled: settings.enabled as u32,
+        }
+    }
+}
+
+pub struct ShadowPass {
+    settings: ShadowSettings,
+    map: Texture,
+    /// [`ShadowUniform`], bound with the map for the lit shaders.
+    settings_buffer: Buffer,
+    /// The light's view projection, apart from the uniform the standard
+    /// shader reads since that one is bound with the map.
+    light_buffer: Buffer,
+    bind_group: BindGroup,
+    pipeline: Pipeline,
+}
+
+impl ShadowPass {
+    /// `vertex_layouts` are those of the meshes and instances drawn, only
+    /// their positions and model matrices are read.
+    pub fn new(
+        device: &Device,
+        settings: ShadowSettings,
+        vertex_layouts: &[VertexBufferLayout<'static>],
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadow bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let light_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Shadow light buffer"),
+            contents: bytemuck::bytes_of(&Matrix4::<f32>::identity()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Shadow bind group"),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
+        });
+        let settings_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Shadow settings buffer"),
+            contents: bytemuck::bytes_of(&ShadowUniform::from(settings)),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self {
+            settings,
+            map: Texture::create_shadow_map(device, settings.resolution),
+            settings_buffer,
+            light_buffer,
+            bind_group,
+            pipeline: Self::create_pipeline(device, &bind_group_layout, vertex_layouts),
+        }
+    }
+
+    /// Depth-only, unbiased since the bias is applied where the map is
+    /// sampled instead, see [`ShadowSettings`].
+    fn create_pipeline(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        vertex_layouts: &[VertexBufferLayout<'static>],
+    ) -> Pipeline {
+        let shader = Shader::embedded(device, "shadow.wgsl", ShaderDefines::default());
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Shadow pipeline layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Pipeline::with_stages(
+            device,
+            ShaderStage::new(&shader, "vs_main"),
+            None,
+            layout,
+            PipelineOptions::depth_only("Shadow pipeline", Texture::DEPTH_FORMAT, vertex_layouts),
+        )
+    }
+
+    /// For rebuilding from an edited shader.
+    pub fn pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.pipeline
+    }
+
+    pub fn settings(&self) -> ShadowSettings {
+        self.settings
+    }
+
+    /// Uploads the settings, rebuilding the map if its resolution changed.
+    /// Returns whether it did, in which case the map has to be bound again
+    /// with [`DirectionalLightBundle::set_shadow`](crate::light::DirectionalLightBundle::set_shadow).
+    pub fn set_settings(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        settings: ShadowSettings,
+    ) -> bool {
+        let rebuilt = settings.resolution != self.settings.resolution;
+        if rebuilt {
+            self.map = Texture::create_shadow_map(device, settings.resolution);
+        }
+        queue.write_buffer(
+            &self.settings_buffer,
+            0,
+            bytemuck::bytes_of(&ShadowUniform::from(settings)),
+        );
+        self.settings = settings;
+
+        rebuilt
+    }
+
+    pub fn map(&self) -> &Texture {
+        &self.map
+    }
+
+    pub fn settings_buffer(&self) -> &Buffer {
+        &self.settings_buffer
+    }
+
+    /// Keeps the pass in step with the light's
+    /// [`DirectionalLight::fit_shadow`].
+    pub fn update(&self, queue: &Queue, light: &DirectionalLight) {
+        queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::bytes_of(&light.view_projection()),
+        );
+    }
+
+    /// Draws the depth of the instances of `model` in `instance_buffer`
+    /// into the map, unless shadows are disabled.
+    pub fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        model: &Model,
+        instance_buffer: &Buffer,
+        instances: Range<u32>,
+        timestamp_writes: Option<RenderPassTimestampWrites>,
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Shadow pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &self.map.view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        for mesh in model.lod_meshes(0) {
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.element_count, 0, instances.clone());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use super::{ShadowPass, ShadowSettings, ShadowUniform};
+    use crate::{
+        instance::RawInstance,
+        model::{ModelVertex, VertexBufferFormat},
+        texture::test_device,
+    };
+
+    #[test]
+    fn settings_rebuild_the_map() {
+        let (device, queue) = test_device();
+        let mut shadow = ShadowPass::new(
+            &device,
+            ShadowSettings::default(),
+            &[ModelVertex::descriptor(), RawInstance::descriptor()],
+        );
+        assert_eq!(shadow.map().byte_size(), 2048 * 2048 * 4);
+        assert_eq!(shadow.settings_buffer().size(), 16);
+
+        let settings = ShadowSettings {
+            resolution: 512,
+            ..Default::default()
+        };
+        assert!(shadow.set_settings(&device, &queue, settings));
+        assert_eq!(shadow.map().byte_size(), 512 * 512 * 4);
+        assert_eq!(shadow.settings().resolution, 512);
+
+        // Only the uniform changes with the bias
+        let settings = ShadowSettings {
+            depth_bias: 0.01,
+            ..settings
+        };
+        assert!(!shadow.set_settings(&device, &queue, settings));
+        assert_eq!(ShadowUniform::from(settings).depth_bias, 0.01);
+    }
+}
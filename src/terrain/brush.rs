@@ -0,0 +1,186 @@
+//! Editing a [`HeightMap`] a stroke at a time, strongest under the brush's
+//! center and fading out to nothing at its edge.
+
+use super::{HeightMap, TerrainOptions};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrushKind {
+    #[default]
+    Raise,
+    Lower,
+    /// Pulls heights towards the average of their neighbours.
+    Smooth,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    pub kind: BrushKind,
+    /// In world units.
+    pub radius: f32,
+    /// Height map units a second raised or lowered under the center, or how
+    /// much of the way to their neighbours' average heights are smoothed.
+    pub strength: f32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            kind: BrushKind::default(),
+            radius: 4.0,
+            strength: 0.25,
+        }
+    }
+}
+
+impl Brush {
+    pub fn with_kind(self, kind: BrushKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    /// Strokes the map laid out by `options` for `seconds` around world `x`,
+    /// `z`. Returns the samples changed along x and z, `None` when the brush
+    /// is off the map.
+    pub fn apply(
+        &self,
+        height_map: &mut HeightMap,
+        options: TerrainOptions,
+        x: f32,
+        z: f32,
+        seconds: f32,
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        // Where the center is in samples, and the samples it reaches
+        let center = (
+            (x - options.origin.x) / options.spacing,
+            (z - options.origin.z) / options.spacing,
+        );
+        let radius = self.radius / options.spacing;
+        let reach = |center: f32, samples: usize| {
+            let start = (center - radius).ceil().max(0.0) as usize;
+            let end = ((center + radius).floor() + 1.0).clamp(0.0, samples as f32) as usize;
+            (start < end).then_some(start..end)
+        };
+        let columns = reach(center.0, height_map.width())?;
+        let rows = reach(center.1, height_map.depth())?;
+
+        // Worked out before any are written, so smoothing reads the heights
+        // from before the stroke
+        let edits: Vec<_> = rows
+            .clone()
+            .flat_map(|z| columns.clone().map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let distance = (x as f32 - center.0).hypot(z as f32 - center.1);
+                let weight = falloff(distance, radius) * self.strength * seconds;
+                let height = height_map.get(x, z);
+                let edited = match self.kind {
+                    BrushKind::Raise => height + weight,
+                    BrushKind::Lower => height - weight,
+                    BrushKind::Smooth => {
+                        let average = neighbour_average(height_map, x, z);
+                        height + (average - height) * weight.min(1.0)
+                    }
+                };
+
+                (x, z, edited)
+            })
+            .collect();
+        for (x, z, height) in edits {
+            height_map.set(x, z, height);
+        }
+
+        Some((columns, rows))
+    }
+}
+
+/// 1 at the center easing out to 0 at `radius`, flat at both ends so
+/// strokes blend into what's around them.
+pub fn falloff(distance: f32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return match distance <= 0.0 {
+            true => 1.0,
+            false => 0.0,
+        };
+    }
+    let t = (1.0 - distance / radius).clamp(0.0, 1.0);
+
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The average height of the samples around `x`, `z` and itself, fewer along
+/// the borders.
+fn neighbour_average(height_map: &HeightMap, x: usize, z: usize) -> f32 {
+    let columns = x.saturating_sub(1)..(x + 2).min(height_map.width());
+    let rows = z.saturating_sub(1)..(z + 2).min(height_map.depth());
+    let count = columns.len() * rows.len();
+    let sum: f32 = rows
+        .flat_map(|z| columns.clone().map(move |x| (x, z)))
+        .map(|(x, z)| height_map.get(x, z))
+        .sum();
+
+    sum / count as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::{falloff, Brush, BrushKind};
+    use crate::terrain::{HeightMap, TerrainOptions};
+    use cgmath::assert_abs_diff_eq;
+
+    fn flat(width: usize, height: f32) -> HeightMap {
+        HeightMap::from_heights(width, width, vec![height; width * width]).unwrap()
+    }
+
+    #[test]
+    fn strokes_fade_out_from_the_center() {
+        assert_eq!(falloff(0.0, 2.0), 1.0);
+        assert_eq!(falloff(1.0, 2.0), 0.5);
+        assert_eq!(falloff(2.0, 2.0), 0.0);
+        assert_eq!(falloff(3.0, 2.0), 0.0);
+
+        let options = TerrainOptions {
+            spacing: 0.5,
+            ..Default::default()
+        };
+        let brush = Brush {
+            kind: BrushKind::Raise,
+            radius: 1.0,
+            strength: 2.0,
+        };
+        let mut map = flat(9, 0.0);
+        // Centered on sample (4, 4), reaching 2 samples either side
+        let changed = brush.apply(&mut map, options, 2.0, 2.0, 0.5);
+        assert_eq!(changed, Some((2..7, 2..7)));
+        assert_eq!(map.get(4, 4), 1.0);
+        assert_eq!(map.get(5, 4), 0.5);
+        assert_eq!(map.get(6, 4), 0.0);
+        assert_eq!(map.get(1, 4), 0.0);
+
+        let lower = brush.with_kind(BrushKind::Lower);
+        lower.apply(&mut map, options, 2.0, 2.0, 0.25);
+        assert_eq!(map.get(4, 4), 0.5);
+
+        // Clipped by the map's edges, and nothing at all off it
+        let changed = brush.apply(&mut map, options, 0.0, 4.0, 1.0);
+        assert_eq!(changed, Some((0..3, 6..9)));
+        assert_eq!(brush.apply(&mut map, options, -5.0, 2.0, 1.0), None);
+    }
+
+    #[test]
+    fn smoothing_levels_spikes() {
+        let mut map = flat(5, 1.0);
+        map.set(2, 2, 10.0);
+        let brush = Brush {
+            kind: BrushKind::Smooth,
+            radius: 1.5,
+            strength: 1.0,
+        };
+        brush.apply(&mut map, TerrainOptions::default(), 2.0, 2.0, 1.0);
+
+        // All the way to the average of the 3x3 around it at the center
+        assert_eq!(map.get(2, 2), 2.0);
+        // Partly towards their averages, which included the spike
+        let weight = falloff(1.0, 1.5);
+        assert_abs_diff_eq!(map.get(3, 2), 1.0 + weight, epsilon = 1e-6);
+        assert_eq!(map.get(0, 0), 1.0);
+    }
+}
@@ -0,0 +1,370 @@
+use super::{
+    displaced::DisplacedPatch,
+    lod::{self, LOD_STRIDES},
+    sample_position, Brush, HeightMap, RayHit, TerrainMesh, TerrainMode, TerrainOptions,
+    TerrainVertex, TriangleList,
+};
+use crate::{camera::Frustum, math::Ray, model::Aabb, texture::TextureResult};
+use cgmath::Vector3;
+use std::{ops::Range, sync::Arc};
+use wgpu::{Device, Queue, RenderPass};
+
+/// A block of the terrain's cells drawn on its own, so what's out of view
+/// can be skipped.
+#[derive(Debug)]
+pub struct TerrainChunk {
+    /// The samples covered along x, the last shared with the next chunk.
+    pub x: Range<usize>,
+    /// The samples covered along z, the last shared with the next chunk.
+    pub z: Range<usize>,
+    /// Around the chunk's samples and the skirt hanging below them.
+    pub bounds: Aabb,
+    /// Where each of [`LOD_STRIDES`]' levels is in the chunk's index
+    /// buffer, or the patch's when displaced on the GPU.
+    pub levels: Vec<Range<u32>>,
+}
+
+impl TerrainChunk {
+    fn new(
+        height_map: &HeightMap,
+        options: TerrainOptions,
+        x: Range<usize>,
+        z: Range<usize>,
+    ) -> Self {
+        let mut chunk = Self {
+            x,
+            z,
+            bounds: Aabb::EMPTY,
+            levels: vec![],
+        };
+        chunk.update_bounds(height_map, options, f32::INFINITY);
+
+        chunk
+    }
+
+    /// Fits the bounds to the samples, with the skirt hanging below them but
+    /// never above `bottom`, where it's already been built down to.
+    fn update_bounds(&mut self, height_map: &HeightMap, options: TerrainOptions, bottom: f32) {
+        let surface = Aabb::from_points(
+            self.z
+                .clone()
+                .flat_map(|z| self.x.clone().map(move |x| (x, z)))
+                .map(|(x, z)| sample_position(height_map, options, x, z)),
+        );
+        // Below anything along the border, whatever the neighbours' levels
+        let bottom = bottom.min(surface.min.y - options.spacing);
+
+        self.bounds = surface.extended(Vector3::new(surface.min.x, bottom, surface.min.z));
+    }
+
+    /// The grid followed by its skirt, hanging to the bottom of the bounds.
+    fn vertices(&self, height_map: &HeightMap, options: TerrainOptions) -> Vec<TerrainVertex> {
+        let mut vertices =
+            TriangleList::create_region(height_map, options, self.x.clone(), self.z.clone())
+                .vertices;
+        let skirt = lod::skirt_vertices(&vertices, self.x.len(), self.z.len(), self.bounds.min.y);
+        vertices.extend(skirt);
+
+        vertices
+    }
+
+    /// Builds the chunk's own vertices and every level's indices, filling in
+    /// [`TerrainChunk::levels`].
+    fn create_mesh(
+        &mut self,
+        device: &Device,
+        height_map: &HeightMap,
+        options: TerrainOptions,
+    ) -> TerrainMesh {
+        let (width, depth) = (self.x.len(), self.z.len());
+        let mut list = TriangleList {
+            vertices: self.vertices(height_map, options),
+            indices: vec![],
+        };
+        self.levels = LOD_STRIDES
+            .iter()
+            .map(|&stride| {
+                let start = list.indices.len() as u32;
+                list.indices.extend(lod::grid_indices(width, depth, stride));
+                list.indices
+                    .extend(lod::skirt_indices(width, depth, stride));
+
+                start..list.indices.len() as u32
+            })
+            .collect();
+
+        list.upload(
+            device,
+            &format!("Terrain chunk {}, {}", self.x.start, self.z.start),
+        )
+    }
+
+    /// Uploads the samples along `z` again after they've been edited, along
+    /// with the skirt since the border's normals or its bottom may have
+    /// moved.
+    fn write_rows(
+        &self,
+        queue: &Queue,
+        mesh: &TerrainMesh,
+        height_map: &HeightMap,
+        options: TerrainOptions,
+        z: Range<usize>,
+    ) {
+        let vertices = self.vertices(height_map, options);
+        let width = self.x.len();
+        let rows = (z.start - self.z.start) * width..(z.end - self.z.start) * width;
+        let skirt = width * self.z.len();
+        mesh.write_vertices(queue, rows.start, &vertices[rows]);
+        mesh.write_vertices(queue, skirt, &vertices[skirt..]);
+    }
+}
+
+/// What the chunks are drawn from, depending on the [`TerrainMode`].
+#[derive(Debug)]
+enum TerrainGeometry {
+    /// One for each chunk, in the same order.
+    Meshes(Vec<TerrainMesh>),
+    Displaced(Box<DisplacedPatch>),
+}
+
+/// A [`HeightMap`] drawn in square chunks, culled against the view.
+#[derive(Debug)]
+pub struct Terrain {
+    pub chunks: Vec<TerrainChunk>,
+    /// Added to every chunk's level, positive for fewer triangles and
+    /// negative for more. See [`lod::select_level`].
+    pub lod_bias: f32,
+    geometry: TerrainGeometry,
+    /// Kept for looking heights up, shared with whatever follows the ground.
+    height_map: Arc<HeightMap>,
+    options: TerrainOptions,
+    /// Indices into `chunks` of those in view along with the level each is
+    /// drawn at, see [`Terrain::cull`].
+    visible: Vec<(usize, usize)>,
+}
+
+impl Terrain {
+    /// Splits the map into chunks of `chunk_size` by `chunk_size` cells,
+    /// smaller along the far edges when the map doesn't divide evenly. Only
+    /// [`TerrainMode::Gpu`] can fail, uploading the heights.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        height_map: HeightMap,
+        options: TerrainOptions,
+        chunk_size: usize,
+        mode: TerrainMode,
+    ) -> TextureResult<Self> {
+        let columns = chunk_ranges(height_map.width(), chunk_size);
+        let rows = chunk_ranges(height_map.depth(), chunk_size);
+        let mut chunks: Vec<_> = rows
+            .iter()
+            .flat_map(|z| columns.iter().map(move |x| (x.clone(), z.clone())))
+            .map(|(x, z)| TerrainChunk::new(&height_map, options, x, z))
+            .collect();
+
+        let geometry = match mode {
+            TerrainMode::Cpu => TerrainGeometry::Meshes(
+                chunks
+                    .iter_mut()
+                    .map(|chunk| chunk.create_mesh(device, &height_map, options))
+                    .collect(),
+            ),
+            TerrainMode::Gpu => {
+                let patch = DisplacedPatch::new(device, queue, &height_map, options, &chunks)?;
+                for chunk in &mut chunks {
+                    chunk.levels = patch.levels().to_vec();
+                }
+                TerrainGeometry::Displaced(Box::new(patch))
+            }
+        };
+
+        Ok(Self {
+            visible: (0..chunks.len()).map(|index| (index, 0)).collect(),
+            chunks,
+            lod_bias: 0.0,
+            geometry,
+            height_map: Arc::new(height_map),
+            options,
+        })
+    }
+
+    pub fn mode(&self) -> TerrainMode {
+        match self.geometry {
+            TerrainGeometry::Meshes(_) => TerrainMode::Cpu,
+            TerrainGeometry::Displaced(_) => TerrainMode::Gpu,
+        }
+    }
+
+    pub fn height_map(&self) -> &Arc<HeightMap> {
+        &self.height_map
+    }
+
+    pub fn options(&self) -> TerrainOptions {
+        self.options
+    }
+
+    /// See [`HeightMap::height_at`].
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        self.height_map.height_at(self.options, x, z)
+    }
+
+    /// See [`HeightMap::normal_at`].
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vector3<f32>> {
+        self.height_map.normal_at(self.options, x, z)
+    }
+
+    /// Around every chunk, skirts included.
+    pub fn bounds(&self) -> Aabb {
+        self.chunks
+            .iter()
+            .fold(Aabb::EMPTY, |bounds, chunk| bounds.union(&chunk.bounds))
+    }
+
+    /// See [`HeightMap::raycast`].
+    pub fn raycast(&self, ray: &Ray) -> Option<RayHit> {
+        self.height_map.raycast(self.options, ray)
+    }
+
+    /// Strokes the map with `brush` for `seconds` around world `x`, `z`,
+    /// then uploads what changed of the chunks under it. Returns whether
+    /// anything did.
+    pub fn edit(&mut self, queue: &Queue, brush: &Brush, x: f32, z: f32, seconds: f32) -> bool {
+        // Copied first when something else still shares the map, e.g. the
+        // camera following the ground
+        let height_map = Arc::make_mut(&mut self.height_map);
+        let Some((columns, rows)) = brush.apply(height_map, self.options, x, z, seconds) else {
+            return false;
+        };
+        if let TerrainGeometry::Displaced(patch) = &self.geometry {
+            patch.write_heights(queue, height_map, columns.clone(), rows.clone());
+        }
+
+        // The normals either side read the changed samples too
+        let grow = |range: Range<usize>, samples: usize| {
+            range.start.saturating_sub(1)..(range.end + 1).min(samples)
+        };
+        let columns = grow(columns, height_map.width());
+        let rows = grow(rows, height_map.depth());
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            let (Some(_), Some(rows)) = (overlap(&chunk.x, &columns), overlap(&chunk.z, &rows))
+            else {
+                continue;
+            };
+            chunk.update_bounds(height_map, self.options, chunk.bounds.min.y);
+            match &mut self.geometry {
+                TerrainGeometry::Meshes(meshes) => {
+                    meshes[index].bounds = chunk.bounds;
+                    chunk.write_rows(queue, &meshes[index], height_map, self.options, rows);
+                }
+                TerrainGeometry::Displaced(patch) => patch.write_chunk(queue, index, chunk),
+            }
+        }
+
+        true
+    }
+
+    /// Keeps only the chunks `frustum` may see for drawing, each at a level
+    /// for its distance from `eye`. Returns whether how many there are
+    /// changed.
+    pub fn cull(&mut self, frustum: &Frustum, eye: Vector3<f32>) -> bool {
+        let visible: Vec<(usize, usize)> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| frustum.intersects_aabb(&chunk.bounds))
+            .map(|(index, chunk)| {
+                let bounds = &chunk.bounds;
+                let extent = bounds.size().x.max(bounds.size().z);
+                let level = lod::select_level(bounds.distance(eye), extent, self.lod_bias);
+
+                (index, level)
+            })
+            .collect();
+        let changed = visible.len() != self.visible.len();
+        self.visible = visible;
+
+        changed
+    }
+
+    pub fn visible_count(&self) -> usize {
+        self.visible.len()
+    }
+
+    /// Draws the chunks left by the last [`Terrain::cull`], with the
+    /// pipeline for the terrain's [`TerrainMode`] and the camera's and
+    /// lights' bind groups already set.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        let chunks = self
+            .visible
+            .iter()
+            .map(|&(index, level)| (index, self.chunks[index].levels[level].clone()));
+        match &self.geometry {
+            TerrainGeometry::Meshes(meshes) => {
+                for (index, indices) in chunks {
+                    meshes[index].draw_range(render_pass, indices);
+                }
+            }
+            TerrainGeometry::Displaced(patch) => {
+                patch.bind(render_pass);
+                for (index, indices) in chunks {
+                    patch.draw_chunk(render_pass, index as u32, indices);
+                }
+            }
+        }
+    }
+}
+
+/// Where `a` and `b` cover the same samples, if anywhere.
+fn overlap(a: &Range<usize>, b: &Range<usize>) -> Option<Range<usize>> {
+    let shared = a.start.max(b.start)..a.end.min(b.end);
+
+    (!shared.is_empty()).then_some(shared)
+}
+
+/// Splits a row of `samples` into runs of up to `chunk_size` cells, each
+/// starting on the sample the one before ends on.
+fn chunk_ranges(samples: usize, chunk_size: usize) -> Vec<Range<usize>> {
+    let cells = samples.saturating_sub(1);
+    let chunk_size = chunk_size.max(1);
+
+    (0..cells)
+        .step_by(chunk_size)
+        .map(|start| start..(start + chunk_size).min(cells) + 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::chunk_ranges;
+    use crate::terrain::{HeightMap, TerrainOptions, TriangleList};
+
+    #[test]
+    fn chunks_share_their_borders() {
+        assert_eq!(chunk_ranges(5, 2), [0..3, 2..5]);
+        assert_eq!(chunk_ranges(6, 2), [0..3, 2..5, 4..6]);
+        assert!(chunk_ranges(1, 2).is_empty());
+
+        let heights = (0..25).map(|i| ((i * 7) % 5) as f32).collect();
+        let map = HeightMap::from_heights(5, 5, heights).unwrap();
+        let options = TerrainOptions::default();
+        let whole = TriangleList::create(&map, options);
+        let left = TriangleList::create_region(&map, options, 0..3, 0..5);
+        let right = TriangleList::create_region(&map, options, 2..5, 0..5);
+        assert_eq!(
+            left.indices.len() + right.indices.len(),
+            whole.indices.len()
+        );
+
+        // The column both have is the same down to the bit, normals included
+        for z in 0..5 {
+            let [shared_left, shared_right, shared] = [
+                &left.vertices[z * 3 + 2],
+                &right.vertices[z * 3],
+                &whole.vertices[z * 5 + 2],
+            ];
+            assert_eq!(bytemuck::bytes_of(shared_left), bytemuck::bytes_of(shared));
+            assert_eq!(bytemuck::bytes_of(shared_right), bytemuck::bytes_of(shared));
+        }
+    }
+}
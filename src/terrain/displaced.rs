@@ -0,0 +1,512 @@
+//! Terrain drawn from one flat patch of grid coordinates, instanced for
+//! every chunk and lifted into place in `terrain_displaced.wgsl` from the
+//! heights uploaded as a texture. Nothing but the patch and the heights
+//! themselves is kept on the GPU, however big the map.
+
+use super::{
+    chunk::TerrainChunk,
+    lod::{self, LOD_STRIDES},
+    HeightMap, TerrainOptions,
+};
+use crate::{
+    model::VertexBufferFormat,
+    texture::{Texture, TextureResult},
+};
+use bytemuck::{Pod, Zeroable};
+use cgmath::Vector3;
+use std::ops::Range;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Device, IndexFormat, Queue, RenderPass, ShaderStages,
+    TextureSampleType, TextureViewDimension, VertexAttribute, VertexBufferLayout,
+};
+
+/// How the heights are laid out, laid out to match `TerrainUniform` in the
+/// shader.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct TerrainUniform {
+    origin: Vector3<f32>,
+    spacing: f32,
+    height_scale: f32,
+    uv_tiling: f32,
+    /// Samples along x and z, which coordinates past the far edges are
+    /// clamped to.
+    size: [u32; 2],
+}
+
+/// A sample of the patch, in samples from the chunk's first.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PatchVertex {
+    pub grid: [u32; 2],
+    /// Non-zero for the skirt's copies of the border, dropped to the
+    /// chunk's [`ChunkInstance::skirt_bottom`].
+    pub skirt: u32,
+}
+
+impl VertexBufferFormat for PatchVertex {
+    type Attributes = [VertexAttribute; 2];
+    const ATTRIBUTES: Self::Attributes = vertex_attr_array![
+        0 => Uint32x2,
+        1 => Uint32,
+    ];
+
+    fn descriptor() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Where one chunk's instance of the patch goes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct ChunkInstance {
+    /// The chunk's first sample along x and z.
+    pub offset: [u32; 2],
+    /// Height the skirt hangs down to, the same as
+    /// [`TerrainMode::Cpu`](super::TerrainMode::Cpu)'s.
+    pub skirt_bottom: f32,
+}
+
+impl From<&TerrainChunk> for ChunkInstance {
+    fn from(chunk: &TerrainChunk) -> Self {
+        Self {
+            offset: [chunk.x.start as u32, chunk.z.start as u32],
+            skirt_bottom: chunk.bounds.min.y,
+        }
+    }
+}
+
+impl VertexBufferFormat for ChunkInstance {
+    type Attributes = [VertexAttribute; 2];
+    const ATTRIBUTES: Self::Attributes = vertex_attr_array![
+        2 => Uint32x2,
+        3 => Float32,
+    ];
+
+    fn descriptor() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// The patch along with the heights and uniform it's displaced with.
+#[derive(Debug)]
+pub struct DisplacedPatch {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    /// A [`ChunkInstance`] for each chunk, in the same order.
+    instance_buffer: Buffer,
+    /// Where each of [`LOD_STRIDES`]' levels is in the index buffer.
+    levels: Vec<Range<u32>>,
+    heights: Texture,
+    /// Only read through the bind group.
+    _uniform_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl DisplacedPatch {
+    /// A patch as big as the largest of `chunks`, the smaller ones along
+    /// the far edges clamping the rest of it onto their last samples.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        height_map: &HeightMap,
+        options: TerrainOptions,
+        chunks: &[TerrainChunk],
+    ) -> TextureResult<Self> {
+        let side = chunks
+            .iter()
+            .map(|chunk| chunk.x.len().max(chunk.z.len()))
+            .max()
+            .unwrap_or_default();
+        let grid = (0..side * side).map(|index| PatchVertex {
+            grid: [(index % side) as u32, (index / side) as u32],
+            skirt: 0,
+        });
+        let skirt = lod::border(side, side).map(|(x, z)| PatchVertex {
+            grid: [x as u32, z as u32],
+            skirt: 1,
+        });
+        let vertices: Vec<_> = grid.chain(skirt).collect();
+
+        let mut indices = vec![];
+        let levels = LOD_STRIDES
+            .iter()
+            .map(|&stride| {
+                let start = indices.len() as u32;
+                indices.extend(lod::grid_indices(side, side, stride));
+                indices.extend(lod::skirt_indices(side, side, stride));
+
+                start..indices.len() as u32
+            })
+            .collect();
+
+        let instances: Vec<_> = chunks.iter().map(ChunkInstance::from).collect();
+        let uniform = TerrainUniform {
+            origin: options.origin,
+            spacing: options.spacing,
+            height_scale: options.height_scale,
+            uv_tiling: options.uv_tiling,
+            size: [height_map.width() as u32, height_map.depth() as u32],
+        };
+
+        let heights = height_map.upload(device, queue)?;
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Displaced terrain uniform buffer"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Displaced terrain bind group"),
+            layout: &Self::bind_group_layout(device),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&heights.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(Self {
+            vertex_buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Terrain patch vertex buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: BufferUsages::VERTEX,
+            }),
+            index_buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Terrain patch index buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: BufferUsages::INDEX,
+            }),
+            instance_buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Terrain patch instance buffer"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            }),
+            levels,
+            heights,
+            _uniform_buffer: uniform_buffer,
+            bind_group,
+        })
+    }
+
+    /// Group 3 of the displaced terrain pipeline, the heights and their
+    /// layout. Both are read in the vertex stage, where the heights can only
+    /// be loaded texel by texel.
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Displaced terrain bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// The vertex buffers the pipeline reads, the patch's then the chunks'.
+    pub fn vertex_layouts() -> [VertexBufferLayout<'static>; 2] {
+        [PatchVertex::descriptor(), ChunkInstance::descriptor()]
+    }
+
+    pub fn levels(&self) -> &[Range<u32>] {
+        &self.levels
+    }
+
+    /// Uploads the samples in `x` and `z` again after they've been edited.
+    pub fn write_heights(
+        &self,
+        queue: &Queue,
+        height_map: &HeightMap,
+        x: Range<usize>,
+        z: Range<usize>,
+    ) {
+        let heights: Vec<_> = z
+            .clone()
+            .flat_map(|z| x.clone().map(move |x| height_map.get(x, z)))
+            .collect();
+        self.heights
+            .write_gray_f32(
+                queue,
+                (x.start as u32, z.start as u32),
+                x.len() as u32,
+                z.len() as u32,
+                &heights,
+            )
+            // Uploaded as R32Float at the map's size, so anything on it fits
+            .unwrap();
+    }
+
+    /// Uploads the instance of the `index`th chunk again, e.g. after its
+    /// skirt was lowered.
+    pub fn write_chunk(&self, queue: &Queue, index: usize, chunk: &TerrainChunk) {
+        let offset = index * std::mem::size_of::<ChunkInstance>();
+        queue.write_buffer(
+            &self.instance_buffer,
+            offset as wgpu::BufferAddress,
+            bytemuck::bytes_of(&ChunkInstance::from(chunk)),
+        );
+    }
+
+    /// Sets the patch's buffers and bind group, once before every
+    /// [`DisplacedPatch::draw_chunk`].
+    pub fn bind<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_bind_group(3, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
+    }
+
+    /// Draws `indices` of the patch, one of [`DisplacedPatch::levels`], as
+    /// the chunk at `chunk`.
+    pub fn draw_chunk(&self, render_pass: &mut RenderPass, chunk: u32, indices: Range<u32>) {
+        render_pass.draw_indexed(indices, 0, chunk..chunk + 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TerrainUniform;
+    use crate::vec3;
+    use std::ptr;
+
+    #[test]
+    fn aligned() {
+        assert_eq!(std::mem::size_of::<TerrainUniform>(), 32);
+
+        let uniform = TerrainUniform {
+            origin: vec3!(0.0, 0.0, 0.0),
+            spacing: 1.0,
+            height_scale: 1.0,
+            uv_tiling: 1.0,
+            size: [2, 2],
+        };
+        let origin_ptr = ptr::addr_of!(uniform.origin).cast::<u8>();
+        let offsets = [
+            ptr::addr_of!(uniform.spacing).cast::<u8>(),
+            ptr::addr_of!(uniform.height_scale).cast::<u8>(),
+            ptr::addr_of!(uniform.size).cast::<u8>(),
+        ]
+        .map(|field| unsafe { field.offset_from(origin_ptr) });
+        assert_eq!(offsets, [12, 16, 24]);
+    }
+
+    #[cfg(feature = "gpu-tests")]
+    #[test]
+    fn modes_draw_the_same_ground() {
+        use super::DisplacedPatch;
+        use crate::{
+            camera::{Camera, CameraUniform, Projection},
+            model::VertexBufferFormat,
+            shader::{Shader, ShaderDefines},
+            terrain::{HeightMap, NoiseParams, Terrain, TerrainMode, TerrainOptions},
+            texture::{test_device, Texture},
+        };
+        use cgmath::{Deg, Point3};
+        use std::iter;
+        use wgpu::{util::DeviceExt, TextureFormat, VertexBufferLayout};
+
+        let (device, queue) = test_device();
+        let (width, height) = (64, 48);
+        let camera = {
+            let mut camera = Camera::new((0.0, 10.0, 14.0), Deg(0.0), Deg(0.0));
+            camera.look_at(Point3::new(0.0, 0.0, 0.0));
+            camera
+        };
+        let projection = Projection::new(width, height, Deg(60.0), 0.1, 100.0);
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&CameraUniform::new(&camera, &projection)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+        let empty_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[],
+        });
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &empty_layout,
+            entries: &[],
+        });
+
+        // Colored by normal, so the shader's have to match the CPU's too
+        let normals = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(
+                "@fragment
+                fn fs_main(
+                    @location(0) world_position: vec3<f32>,
+                    @location(1) world_normal: vec3<f32>,
+                    @location(2) uv: vec2<f32>,
+                ) -> @location(0) vec4<f32> {
+                    return vec4<f32>(normalize(world_normal) * 0.5 + 0.5, uv.x);
+                }"
+                .into(),
+            ),
+        });
+        let draw = |terrain: &Terrain, shader: Shader, buffers: &[VertexBufferLayout]| {
+            let displaced_layout = DisplacedPatch::bind_group_layout(&device);
+            let layouts: &[&wgpu::BindGroupLayout] = match terrain.mode() {
+                TerrainMode::Cpu => &[&camera_layout],
+                TerrainMode::Gpu => &[
+                    &camera_layout,
+                    &empty_layout,
+                    &empty_layout,
+                    &displaced_layout,
+                ],
+            };
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader.module,
+                    entry_point: "vs_main",
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &normals,
+                    entry_point: "fs_main",
+                    targets: &[Some(TextureFormat::Rgba8Unorm.into())],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                multiview: None,
+            });
+
+            let target =
+                Texture::create_render_target(&device, width, height, TextureFormat::Rgba8Unorm, 1);
+            let depth = Texture::create_depth_target(&device, width, height, 1);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &depth.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_bind_group(0, &camera_bind_group, &[]);
+                if terrain.mode() == TerrainMode::Gpu {
+                    render_pass.set_bind_group(1, &empty_bind_group, &[]);
+                    render_pass.set_bind_group(2, &empty_bind_group, &[]);
+                }
+                terrain.draw(&mut render_pass);
+            }
+            queue.submit(iter::once(encoder.finish()));
+
+            target.read_to_image(&device, &queue).unwrap()
+        };
+
+        // Chunks that don't divide the map evenly, to cover the clamping
+        let map = HeightMap::from_noise(41, 29, NoiseParams::default());
+        let options = TerrainOptions {
+            origin: (-10.0, -2.0, -7.0).into(),
+            spacing: 0.5,
+            height_scale: 6.0,
+            ..Default::default()
+        };
+        let terrain = |mode| Terrain::new(&device, &queue, map.clone(), options, 16, mode).unwrap();
+        let cpu = draw(
+            &terrain(TerrainMode::Cpu),
+            Shader::embedded(&device, "terrain.wgsl", ShaderDefines::default()),
+            &[super::super::TerrainVertex::descriptor()],
+        );
+        let gpu = draw(
+            &terrain(TerrainMode::Gpu),
+            Shader::embedded(&device, "terrain_displaced.wgsl", ShaderDefines::default()),
+            &DisplacedPatch::vertex_layouts(),
+        );
+
+        // Rounding may move the odd edge by a pixel
+        let differing = iter::zip(cpu.pixels(), gpu.pixels())
+            .filter(|(a, b)| iter::zip(a.0, b.0).any(|(a, b)| a.abs_diff(b) > 2))
+            .count();
+        assert!(cpu.pixels().any(|pixel| pixel.0 != [0, 0, 0, 0]));
+        assert!(
+            differing <= (width * height / 500) as usize,
+            "{differing} pixels differ"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Coarser index buffers for distant terrain chunks. Each level skips
+//! samples along both axes, and every level hangs a skirt below the chunk's
+//! border so the gaps left against a neighbour at another level are walled
+//! over rather than see-through.
+
+use super::TerrainVertex;
+
+/// Samples stepped over by each level's triangles, finest first.
+pub const LOD_STRIDES: [usize; 4] = [1, 2, 4, 8];
+
+/// The level for a chunk `distance` from the camera that's `extent` across.
+/// It stays at full detail within one extent, then drops a level each time
+/// the distance doubles. `bias` is added to the level, positive coarsening.
+pub fn select_level(distance: f32, extent: f32, bias: f32) -> usize {
+    let ratio = distance / extent.max(f32::EPSILON);
+    let level = match ratio > 1.0 {
+        true => ratio.log2().floor() + 1.0,
+        false => 0.0,
+    };
+
+    (level + bias)
+        .round()
+        .clamp(0.0, (LOD_STRIDES.len() - 1) as f32) as usize
+}
+
+/// The samples a level keeps out of `samples`, every `stride`th along with
+/// the last so the chunk covers the same ground at any level.
+fn level_samples(samples: usize, stride: usize) -> Vec<usize> {
+    let last = samples.saturating_sub(1);
+    let mut kept: Vec<usize> = (0..last).step_by(stride.max(1)).collect();
+    kept.push(last);
+
+    kept
+}
+
+/// Counterclockwise triangles over a `width` by `depth` grid of vertices,
+/// stepping `stride` samples at a time.
+pub fn grid_indices(width: usize, depth: usize, stride: usize) -> Vec<u32> {
+    if width < 2 || depth < 2 {
+        return vec![];
+    }
+
+    let columns = level_samples(width, stride);
+    let rows = level_samples(depth, stride);
+    let corner = |x: usize, z: usize| (z * width + x) as u32;
+    let mut indices = Vec::with_capacity((columns.len() - 1) * (rows.len() - 1) * 6);
+    for z in rows.windows(2) {
+        for x in columns.windows(2) {
+            let (a, b) = (corner(x[0], z[0]), corner(x[1], z[0]));
+            let (c, d) = (corner(x[0], z[1]), corner(x[1], z[1]));
+            // Counterclockwise seen from above
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+
+    indices
+}
+
+/// The grid's border samples in the order [`skirt_indices`] expects their
+/// skirt vertices after the grid's, along z = 0, the far z, x = 0 and the
+/// far x in turn.
+pub fn border(width: usize, depth: usize) -> impl Iterator<Item = (usize, usize)> {
+    let near = (0..width).map(|x| (x, 0));
+    let far = (0..width).map(move |x| (x, depth - 1));
+    let left = (0..depth).map(|z| (0, z));
+    let right = (0..depth).map(move |z| (width - 1, z));
+
+    near.chain(far).chain(left).chain(right)
+}
+
+/// Copies of the grid's [`border`] vertices dropped to `bottom`, appended
+/// after the grid to make what [`skirt_indices`] indexes.
+pub fn skirt_vertices(
+    grid: &[TerrainVertex],
+    width: usize,
+    depth: usize,
+    bottom: f32,
+) -> Vec<TerrainVertex> {
+    border(width, depth)
+        .map(|(x, z)| {
+            let top = grid[z * width + x];
+            TerrainVertex {
+                position: [top.position.x, bottom, top.position.z].into(),
+                ..top
+            }
+        })
+        .collect()
+}
+
+/// Walls from the border of [`grid_indices`] at `stride` down to the skirt
+/// vertices, facing out of the chunk.
+pub fn skirt_indices(width: usize, depth: usize, stride: usize) -> Vec<u32> {
+    if width < 2 || depth < 2 {
+        return vec![];
+    }
+
+    let grid = |x: usize, z: usize| (z * width + x) as u32;
+    let skirt = (width * depth) as u32;
+    let (near, far) = (skirt, skirt + width as u32);
+    let (left, right) = (far + width as u32, far + (width + depth) as u32);
+    let columns = level_samples(width, stride);
+    let rows = level_samples(depth, stride);
+
+    let mut indices = vec![];
+    // Wound so that walls along +x face -z and walls along +z face +x,
+    // flipped for the opposite sides
+    let mut wall =
+        |samples: &[usize], top: &dyn Fn(usize) -> u32, bottom: u32, flip: bool| {
+            for pair in samples.windows(2) {
+                let (top_0, top_1) = (top(pair[0]), top(pair[1]));
+                let (bottom_0, bottom_1) = (bottom + pair[0] as u32, bottom + pair[1] as u32);
+                match flip {
+                    false => indices
+                        .extend_from_slice(&[top_0, top_1, bottom_0, top_1, bottom_1, bottom_0]),
+                    true => indices
+                        .extend_from_slice(&[top_0, bottom_0, top_1, top_1, bottom_0, bottom_1]),
+                }
+            }
+        };
+    wall(&columns, &|x| grid(x, 0), near, false);
+    wall(&columns, &|x| grid(x, depth - 1), far, true);
+    wall(&rows, &|z| grid(0, z), left, true);
+    wall(&rows, &|z| grid(width - 1, z), right, false);
+
+    indices
+}
+
+#[cfg(test)]
+mod test {
+    use super::{grid_indices, select_level, skirt_indices, skirt_vertices};
+    use crate::terrain::TerrainVertex;
+    use cgmath::{Vector2, Vector3};
+    use std::collections::HashSet;
+
+    #[test]
+    fn coarser_levels_keep_every_other_sample() {
+        let indices = grid_indices(5, 5, 2);
+        assert_eq!(indices.len(), 2 * 2 * 6);
+        // Only samples at even x and z
+        assert!(indices
+            .iter()
+            .all(|&index| index % 5 % 2 == 0 && index / 5 % 2 == 0));
+
+        // Strides past the chunk's size still cover it, with one cell
+        assert_eq!(grid_indices(5, 4, 8), [0, 15, 4, 4, 15, 19]);
+        assert_eq!(grid_indices(4, 4, 2).len(), 2 * 2 * 6);
+    }
+
+    #[test]
+    fn skirts_close_every_border_edge() {
+        let (width, depth) = (5, 4);
+        let grid: Vec<_> = (0..width * depth)
+            .map(|index| TerrainVertex {
+                position: Vector3::new((index % width) as f32, 1.0, (index / width) as f32),
+                normal: Vector3::unit_y(),
+                uv: Vector2::new(0.0, 0.0),
+            })
+            .collect();
+        let mut vertices = grid.clone();
+        vertices.extend(skirt_vertices(&grid, width, depth, -1.0));
+        let center = Vector3::new(2.0, 0.0, 1.5);
+
+        for stride in [1, 2, 4] {
+            // Edges of the level's triangles used by only one of them
+            let mut edges = HashSet::new();
+            for triangle in grid_indices(width, depth, stride).chunks(3) {
+                for edge in [[0, 1], [1, 2], [2, 0]] {
+                    let [a, b] = edge.map(|i| triangle[i]);
+                    if !edges.remove(&(b, a)) {
+                        edges.insert((a, b));
+                    }
+                }
+            }
+
+            // Each has a wall under it, facing away from the chunk
+            let skirt = skirt_indices(width, depth, stride);
+            for triangle in skirt.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+                let normal = (b - a).cross(c - a);
+                let outward = (a + b + c) / 3.0 - center;
+                assert!(normal.x * outward.x + normal.z * outward.z > 0.0);
+                assert_eq!(normal.y, 0.0);
+            }
+            let walled: HashSet<_> = skirt
+                .chunks(3)
+                .flat_map(|triangle| {
+                    [[0, 1], [1, 2], [2, 0]].map(|[a, b]| (triangle[b], triangle[a]))
+                })
+                .collect();
+            assert!(!edges.is_empty());
+            assert!(edges.iter().all(|edge| walled.contains(edge)));
+        }
+    }
+
+    #[test]
+    fn distant_chunks_are_coarser() {
+        assert_eq!(select_level(0.0, 64.0, 0.0), 0);
+        assert_eq!(select_level(64.0, 64.0, 0.0), 0);
+        assert_eq!(select_level(100.0, 64.0, 0.0), 1);
+        assert_eq!(select_level(200.0, 64.0, 0.0), 2);
+        assert_eq!(select_level(10_000.0, 64.0, 0.0), 3);
+
+        assert_eq!(select_level(100.0, 64.0, 1.0), 2);
+        assert_eq!(select_level(100.0, 64.0, -1.0), 0);
+        assert_eq!(select_level(10_000.0, 64.0, 4.0), 3);
+    }
+}
@@ -1,39 +1,332 @@
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Vector2, Vector3};
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+use std::ops::Range;
 use thiserror::Error;
-use wgpu::{vertex_attr_array, VertexAttribute, VertexBufferLayout, VertexStepMode};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    vertex_attr_array, Buffer, BufferUsages, Device, IndexFormat, Queue, RenderPass,
+    VertexAttribute, VertexBufferLayout,
+};
 
-use crate::VertexBufferFormat;
+use crate::{
+    math::Perlin,
+    model::{Aabb, VertexBufferFormat},
+    texture::{SamplerOptions, Texture, TextureResult},
+};
 
+mod brush;
+mod chunk;
+mod displaced;
+mod lod;
+mod raycast;
+
+pub use brush::{Brush, BrushKind};
+pub use chunk::Terrain;
+pub use displaced::DisplacedPatch;
+pub use raycast::RayHit;
+
+/// Starts height map files, followed by the rest of the header and the
+/// heights, all little-endian. See [`HeightMap::to_bytes`].
+pub const HEIGHT_MAP_MAGIC: [u8; 4] = *b"XHMP";
+/// The header layout [`HeightMap::to_bytes`] writes.
+pub const HEIGHT_MAP_VERSION: u32 = 1;
+/// The magic, version, width, depth and height scale, 4 bytes each.
+const HEADER_LEN: usize = 20;
+
+/// Heights in rows along x, one after another along z.
+#[derive(Clone)]
 pub struct HeightMap {
     width: usize,
+    depth: usize,
     data: Vec<f32>,
+    height_scale: Option<f32>,
 }
 
 impl std::fmt::Debug for HeightMap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "HeightMap {{ width: {} }}", self.width)
+        write!(
+            f,
+            "HeightMap {{ width: {}, depth: {}, height_scale: {:?} }}",
+            self.width, self.depth, self.height_scale
+        )
     }
 }
 
 impl HeightMap {
+    /// Reads a square map of native-endian `f32`s, the format from before
+    /// the files had a header.
     pub fn new(data: &[u8]) -> HeightMapResult<Self> {
-        let prim_len = std::mem::size_of::<f32>();
-        let size = data.len() / prim_len;
-        let remainder = data.len() % prim_len;
-        if remainder > 0 {
-            return Err(HeightMapError::InvalidSize(remainder));
+        let samples = data.len() / std::mem::size_of::<f32>();
+        let width = (samples as f64).sqrt().round() as usize;
+
+        Self::with_dimensions(width, width, data)
+    }
+
+    /// Reads `width` by `depth` native-endian `f32`s.
+    pub fn with_dimensions(width: usize, depth: usize, data: &[u8]) -> HeightMapResult<Self> {
+        if byte_len(width, depth) != Some(data.len()) {
+            return Err(HeightMapError::InvalidSize {
+                width,
+                depth,
+                len: data.len(),
+            });
+        }
+
+        // Copied rather than cast, the bytes needn't be aligned for f32s
+        Self::from_heights(width, depth, bytemuck::pod_collect_to_vec(data))
+    }
+
+    pub fn from_heights(width: usize, depth: usize, heights: Vec<f32>) -> HeightMapResult<Self> {
+        if width.checked_mul(depth) != Some(heights.len()) {
+            return Err(HeightMapError::InvalidSize {
+                width,
+                depth,
+                len: heights.len() * std::mem::size_of::<f32>(),
+            });
+        }
+
+        Ok(Self {
+            width,
+            depth,
+            data: heights,
+            height_scale: None,
+        })
+    }
+
+    /// Reads a map written by [`HeightMap::to_bytes`]. Headerless square
+    /// maps are still read as they were by [`HeightMap::new`], with a
+    /// warning, though they're deprecated.
+    pub fn from_bytes(data: &[u8]) -> HeightMapResult<Self> {
+        if !data.starts_with(&HEIGHT_MAP_MAGIC) {
+            return match Self::new(data) {
+                Ok(map) => {
+                    eprintln!(
+                        "Height maps without a header are deprecated, save it again to add one"
+                    );
+                    Ok(map)
+                }
+                Err(_) => Err(HeightMapError::BadMagic {
+                    found: data.iter().copied().take(HEIGHT_MAP_MAGIC.len()).collect(),
+                }),
+            };
+        }
+
+        if data.len() < HEADER_LEN {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let (header, heights) = data.split_at(HEADER_LEN);
+        // The 4 bytes of the header's `index`th field, the magic being 0th
+        let field =
+            |index: usize| -> [u8; 4] { header[index * 4..(index + 1) * 4].try_into().unwrap() };
+        let version = u32::from_le_bytes(field(1));
+        if version != HEIGHT_MAP_VERSION {
+            return Err(HeightMapError::UnsupportedVersion(version));
         }
+        let width = u32::from_le_bytes(field(2)) as usize;
+        let depth = u32::from_le_bytes(field(3)) as usize;
+        if byte_len(width, depth) != Some(heights.len()) {
+            return Err(HeightMapError::InvalidSize {
+                width,
+                depth,
+                len: heights.len(),
+            });
+        }
+        let heights = heights
+            .chunks_exact(4)
+            .map(|height| f32::from_le_bytes(height.try_into().unwrap()))
+            .collect();
+
+        Ok(Self::from_heights(width, depth, heights)?
+            .with_height_scale(f32::from_le_bytes(field(4))))
+    }
+
+    /// The [`HEIGHT_MAP_MAGIC`] header followed by the heights, a height
+    /// scale of 1 written for maps without one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len() * 4);
+        bytes.extend_from_slice(&HEIGHT_MAP_MAGIC);
+        for field in [HEIGHT_MAP_VERSION, self.width as u32, self.depth as u32] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.height_scale.unwrap_or(1.0).to_le_bytes());
+        for height in &self.data {
+            bytes.extend_from_slice(&height.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Recorded with the map for [`TerrainOptions::height_scale`] when it's
+    /// saved.
+    pub fn with_height_scale(self, height_scale: f32) -> Self {
+        Self {
+            height_scale: Some(height_scale),
+            ..self
+        }
+    }
+
+    /// What the map was saved with, `None` for generated maps and those read
+    /// without a header.
+    pub fn height_scale(&self) -> Option<f32> {
+        self.height_scale
+    }
+
+    /// Rolling hills of fBm noise between 0 and 1, the same every time for
+    /// the same parameters.
+    pub fn from_noise(width: usize, depth: usize, params: NoiseParams) -> Self {
+        let perlin = Perlin::new(params.seed);
+        let data = (0..width * depth)
+            .map(|index| {
+                let (x, z) = ((index % width) as f32, (index / width) as f32);
+                let noise = perlin.fbm(
+                    x * params.frequency,
+                    z * params.frequency,
+                    params.octaves,
+                    params.lacunarity,
+                    params.persistence,
+                );
 
-        let width = ((size / prim_len) as f32).sqrt() as usize;
-        let data = bytemuck::cast_slice(data).to_vec();
+                (noise.clamp(-1.0, 1.0) + 1.0) / 2.0
+            })
+            .collect();
 
-        Ok(Self { width, data })
+        Self {
+            width,
+            depth,
+            data,
+            height_scale: None,
+        }
     }
 
+    /// See [`HeightMap::from_bytes`].
     pub fn load(path: &str) -> HeightMapResult<Self> {
-        Self::new(&std::fs::read(path)?)
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// See [`HeightMap::to_bytes`].
+    pub fn save(&self, path: &str) -> HeightMapResult<()> {
+        Ok(std::fs::write(path, self.to_bytes())?)
     }
+
+    /// Samples along x.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Samples along z.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The height at sample `x`, `z`.
+    ///
+    /// # Panics
+    ///
+    /// When either is outside the map, rather than reading a neighbouring
+    /// row.
+    pub fn get(&self, x: usize, z: usize) -> f32 {
+        assert!(
+            x < self.width && z < self.depth,
+            "Sample ({x}, {z}) is outside the {}x{} height map",
+            self.width,
+            self.depth
+        );
+
+        self.data[z * self.width + x]
+    }
+
+    /// Replaces the height at sample `x`, `z`.
+    ///
+    /// # Panics
+    ///
+    /// Like [`HeightMap::get`], when either is outside the map.
+    pub fn set(&mut self, x: usize, z: usize, height: f32) {
+        assert!(
+            x < self.width && z < self.depth,
+            "Sample ({x}, {z}) is outside the {}x{} height map",
+            self.width,
+            self.depth
+        );
+
+        self.data[z * self.width + x] = height;
+    }
+
+    /// The ground's height at world `x`, `z` with the map laid out by
+    /// `options`, blended between the four samples around it. `None` off
+    /// the map.
+    pub fn height_at(&self, options: TerrainOptions, x: f32, z: f32) -> Option<f32> {
+        let (corners, weights) = self.cell_at(options, x, z)?;
+        let height = bilerp(corners.map(|(x, z)| self.get(x, z)), weights);
+
+        Some(options.origin.y + height * options.height_scale)
+    }
+
+    /// The ground's normal at world `x`, `z`, blended like
+    /// [`HeightMap::height_at`] from the terrain's vertex normals.
+    pub fn normal_at(&self, options: TerrainOptions, x: f32, z: f32) -> Option<Vector3<f32>> {
+        let (corners, weights) = self.cell_at(options, x, z)?;
+        let normal = bilerp(
+            corners.map(|(x, z)| self.sample_normal(options, x, z)),
+            weights,
+        );
+
+        Some(normal.normalize())
+    }
+
+    /// The cell world `x`, `z` is in, see [`Cell`].
+    fn cell_at(&self, options: TerrainOptions, x: f32, z: f32) -> Option<Cell> {
+        let axis = |position: f32, origin: f32, samples: usize| {
+            let local = (position - origin) / options.spacing;
+            if !(0.0..=samples.checked_sub(1)? as f32).contains(&local) {
+                return None;
+            }
+            // The far edge belongs to the last cell
+            let cell = (local as usize).min(samples.saturating_sub(2));
+
+            Some((cell, (cell + 1).min(samples - 1), local - cell as f32))
+        };
+        let (x0, x1, tx) = axis(x, options.origin.x, self.width)?;
+        let (z0, z1, tz) = axis(z, options.origin.z, self.depth)?;
+
+        Some(([(x0, z0), (x1, z0), (x0, z1), (x1, z1)], (tx, tz)))
+    }
+
+    /// Central differences scaled by `options`, one-sided along the borders.
+    fn sample_normal(&self, options: TerrainOptions, x: usize, z: usize) -> Vector3<f32> {
+        let height = |x: usize, z: usize| self.get(x, z) * options.height_scale;
+        let (left, right) = (x.saturating_sub(1), (x + 1).min(self.width - 1));
+        let (back, front) = (z.saturating_sub(1), (z + 1).min(self.depth - 1));
+        let slope = |from: f32, to: f32, steps: usize| match steps {
+            0 => 0.0,
+            steps => (to - from) / (steps as f32 * options.spacing),
+        };
+        let dx = slope(height(left, z), height(right, z), right - left);
+        let dz = slope(height(x, back), height(x, front), front - back);
+
+        Vector3::new(-dx, 1.0, -dz).normalize()
+    }
+
+    /// Uploads the heights as an `R32Float` texture for displacing the
+    /// terrain on the GPU.
+    pub fn upload(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> TextureResult<Texture> {
+        Texture::from_gray_f32(
+            device,
+            queue,
+            self.width as u32,
+            self.depth as u32,
+            &self.data,
+            Some("Height map"),
+            SamplerOptions::default(),
+        )
+    }
+}
+
+/// Bytes of f32s in a `width` by `depth` map, `None` past what a `usize`
+/// holds.
+fn byte_len(width: usize, depth: usize) -> Option<usize> {
+    width
+        .checked_mul(depth)?
+        .checked_mul(std::mem::size_of::<f32>())
 }
 
 pub type HeightMapResult<T> = Result<T, HeightMapError>;
@@ -42,27 +335,223 @@ pub type HeightMapResult<T> = Result<T, HeightMapError>;
 pub enum HeightMapError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
-    #[error("Data is of an invalid size, having {0} too many bytes")]
-    InvalidSize(usize),
+    #[error("A {width}x{depth} height map doesn't match the {len} bytes of f32s given")]
+    InvalidSize {
+        width: usize,
+        depth: usize,
+        len: usize,
+    },
+    #[error("Not a height map, it starts with {found:?} rather than XHMP")]
+    BadMagic { found: Vec<u8> },
+    #[error("Height map version {0} isn't supported, only {HEIGHT_MAP_VERSION}")]
+    UnsupportedVersion(u32),
 }
 
-struct TriangleList {
-    width: usize,
-    depth: usize,
+/// What [`HeightMap::from_noise`] layers, see [`Perlin::fbm`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseParams {
+    pub seed: u64,
+    pub octaves: u32,
+    /// Of the first octave, in cycles per sample.
+    pub frequency: f32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            octaves: 5,
+            frequency: 1.0 / 64.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+/// How a [`HeightMap`]'s samples are laid out in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainOptions {
+    /// Where the first sample sits, the map extending along +x and +z.
+    pub origin: Vector3<f32>,
+    /// Distance between neighbouring samples.
+    pub spacing: f32,
+    /// What heights are multiplied by.
+    pub height_scale: f32,
+    /// How many times the UVs repeat across the map, 1 spanning it once.
+    pub uv_tiling: f32,
+}
+
+impl Default for TerrainOptions {
+    fn default() -> Self {
+        Self {
+            origin: Vector3::zero(),
+            spacing: 1.0,
+            height_scale: 1.0,
+            uv_tiling: 1.0,
+        }
+    }
+}
+
+/// Where a [`Terrain`]'s vertices come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerrainMode {
+    /// Built on the CPU, every chunk with buffers of its own.
+    #[default]
+    Cpu,
+    /// One flat patch instanced for every chunk, displaced in the vertex
+    /// shader from the heights uploaded as a texture. Far less to build and
+    /// keep around for big maps.
+    Gpu,
+}
+
+/// A grid of vertices at the [`HeightMap`]'s samples, two counterclockwise
+/// triangles to each cell between four of them.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleList {
+    pub vertices: Vec<TerrainVertex>,
+    pub indices: Vec<u32>,
 }
 
 impl TriangleList {
-    pub fn create(width: usize, depth: usize) -> Self {
-        todo!()
+    pub fn create(height_map: &HeightMap, options: TerrainOptions) -> Self {
+        Self::create_region(
+            height_map,
+            options,
+            0..height_map.width(),
+            0..height_map.depth(),
+        )
+    }
+
+    /// The samples in `x` and `z` only, along with the cells between them.
+    /// Vertices are the same as in the whole map's list, so regions sharing
+    /// a row of samples meet without cracks.
+    pub fn create_region(
+        height_map: &HeightMap,
+        options: TerrainOptions,
+        x: Range<usize>,
+        z: Range<usize>,
+    ) -> Self {
+        let vertices = z
+            .clone()
+            .flat_map(|z| x.clone().map(move |x| (x, z)))
+            .map(|(x, z)| terrain_vertex(height_map, options, x, z))
+            .collect();
+
+        let indices = lod::grid_indices(x.len(), z.len(), 1);
+
+        Self { vertices, indices }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|vertex| vertex.position))
+    }
+
+    pub fn upload(&self, device: &Device, name: &str) -> TerrainMesh {
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Terrain vertex buffer ({name})")),
+            contents: bytemuck::cast_slice(&self.vertices),
+            // Rewritten in part as the terrain's edited
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Terrain index buffer ({name})")),
+            contents: bytemuck::cast_slice(&self.indices),
+            usage: BufferUsages::INDEX,
+        });
+
+        TerrainMesh {
+            vertex_buffer,
+            index_buffer,
+            bounds: self.bounds(),
+        }
+    }
+}
+
+fn terrain_vertex(
+    height_map: &HeightMap,
+    options: TerrainOptions,
+    x: usize,
+    z: usize,
+) -> TerrainVertex {
+    let uv_step = |count: usize| options.uv_tiling / count.saturating_sub(1).max(1) as f32;
+
+    TerrainVertex {
+        position: sample_position(height_map, options, x, z),
+        normal: height_map.sample_normal(options, x, z),
+        uv: Vector2::new(
+            x as f32 * uv_step(height_map.width()),
+            z as f32 * uv_step(height_map.depth()),
+        ),
+    }
+}
+
+/// Where sample `x`, `z` ends up in the world.
+fn sample_position(
+    height_map: &HeightMap,
+    options: TerrainOptions,
+    x: usize,
+    z: usize,
+) -> Vector3<f32> {
+    options.origin
+        + Vector3::new(
+            x as f32 * options.spacing,
+            height_map.get(x, z) * options.height_scale,
+            z as f32 * options.spacing,
+        )
+}
+
+/// The samples at the corners of a cell, nearest first then along x then z,
+/// with how far across the cell a point is along each.
+type Cell = ([(usize, usize); 4], (f32, f32));
+
+/// Blends the corners of a [`Cell`].
+fn bilerp<T>(corners: [T; 4], (tx, tz): (f32, f32)) -> T
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    let [near_left, near_right, far_left, far_right] = corners;
+    let near = near_left * (1.0 - tx) + near_right * tx;
+    let far = far_left * (1.0 - tx) + far_right * tx;
+
+    near * (1.0 - tz) + far * tz
+}
+
+/// A [`TriangleList`] on the GPU, drawn with `terrain.wgsl`.
+#[derive(Debug)]
+pub struct TerrainMesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub bounds: Aabb,
+}
+
+impl TerrainMesh {
+    /// Replaces the vertices from `first` on with `vertices`, leaving the
+    /// rest of the buffer as it was.
+    pub fn write_vertices(&self, queue: &Queue, first: usize, vertices: &[TerrainVertex]) {
+        let offset = first * std::mem::size_of::<TerrainVertex>();
+        queue.write_buffer(
+            &self.vertex_buffer,
+            offset as wgpu::BufferAddress,
+            bytemuck::cast_slice(vertices),
+        );
+    }
+
+    /// Draws `indices` of the index buffer, e.g. one level of a chunk's.
+    pub fn draw_range<'a>(&'a self, render_pass: &mut RenderPass<'a>, indices: Range<u32>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
+        render_pass.draw_indexed(indices, 0, 0..1);
     }
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
-struct TerrainVertex {
-    position: Vector3<f32>,
-    normal: Vector3<f32>,
-    uv: Vector2<f32>,
+pub struct TerrainVertex {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
 }
 
 impl VertexBufferFormat for TerrainVertex {
@@ -70,7 +559,7 @@ impl VertexBufferFormat for TerrainVertex {
     const ATTRIBUTES: Self::Attributes = vertex_attr_array![
         0 => Float32x3,
         1 => Float32x3,
-        2 => Float32x3,
+        2 => Float32x2,
     ];
 
     fn descriptor() -> wgpu::VertexBufferLayout<'static> {
@@ -81,3 +570,199 @@ impl VertexBufferFormat for TerrainVertex {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{HeightMap, HeightMapError, NoiseParams, TerrainOptions, TriangleList};
+    use cgmath::{assert_abs_diff_eq, InnerSpace, Vector3};
+
+    fn height_map(width: usize, heights: &[f32]) -> HeightMap {
+        HeightMap::from_heights(width, heights.len() / width, heights.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn square_maps_are_measured() {
+        let heights: Vec<f32> = (0..256).map(|i| i as f32).collect();
+        let map = HeightMap::new(bytemuck::cast_slice(&heights)).unwrap();
+        assert_eq!((map.width(), map.depth()), (16, 16));
+        assert_eq!(map.get(3, 2), 35.0);
+    }
+
+    #[test]
+    fn maps_can_be_any_shape() {
+        let heights: Vec<f32> = (0..6).map(|i| i as f32).collect();
+        let map = HeightMap::with_dimensions(3, 2, bytemuck::cast_slice(&heights)).unwrap();
+        assert_eq!((map.width(), map.depth()), (3, 2));
+        assert_eq!(map.get(2, 1), 5.0);
+
+        // Bytes that don't make up whole samples, or not enough of them
+        let invalid = [
+            HeightMap::new(&[0; 7]),
+            HeightMap::new(bytemuck::cast_slice(&heights)),
+            HeightMap::with_dimensions(2, 2, bytemuck::cast_slice(&heights)),
+        ];
+        for result in invalid {
+            assert!(matches!(result, Err(HeightMapError::InvalidSize { .. })));
+        }
+    }
+
+    #[test]
+    fn saved_maps_load_the_same() {
+        let path = std::env::temp_dir().join("saved_maps_load_the_same.xhmp");
+        let path = path.to_string_lossy();
+        let map = height_map(3, &[0.0, 0.5, 1.0, -1.0, 2.5, 3.0]).with_height_scale(4.0);
+        map.save(&path).unwrap();
+        let loaded = HeightMap::load(&path).unwrap();
+        std::fs::remove_file(&*path).unwrap();
+        assert_eq!((loaded.width(), loaded.depth()), (3, 2));
+        assert_eq!(loaded.data, map.data);
+        assert_eq!(loaded.height_scale(), Some(4.0));
+
+        let mut bytes = map.to_bytes();
+        assert_eq!(bytes.len(), 20 + 6 * 4);
+        assert!(matches!(
+            HeightMap::from_bytes(&bytes[..30]),
+            Err(HeightMapError::InvalidSize {
+                width: 3,
+                depth: 2,
+                len: 10
+            })
+        ));
+        assert!(matches!(
+            HeightMap::from_bytes(&bytes[..12]),
+            Err(HeightMapError::Io(_))
+        ));
+        // Dimensions too big to count the bytes of are refused all the same
+        let mut huge = bytes.clone();
+        huge[8..16].fill(0xff);
+        let result = HeightMap::from_bytes(&huge);
+        assert!(matches!(
+            result,
+            Err(HeightMapError::InvalidSize { width, depth, .. })
+                if width == u32::MAX as usize && depth == u32::MAX as usize
+        ));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("A 4294967295x4294967295 height map"));
+        assert!(matches!(
+            HeightMap::from_heights(usize::MAX, 2, vec![]),
+            Err(HeightMapError::InvalidSize { .. })
+        ));
+
+        bytes[4] = 2;
+        assert!(matches!(
+            HeightMap::from_bytes(&bytes),
+            Err(HeightMapError::UnsupportedVersion(2))
+        ));
+        bytes[0] = b'Y';
+        assert!(matches!(
+            HeightMap::from_bytes(&bytes),
+            Err(HeightMapError::BadMagic { found }) if found == b"YHMP"
+        ));
+    }
+
+    #[test]
+    fn headerless_maps_still_load() {
+        let heights: Vec<f32> = (0..9).map(|i| i as f32).collect();
+        let map = HeightMap::from_bytes(bytemuck::cast_slice(&heights)).unwrap();
+        assert_eq!((map.width(), map.depth()), (3, 3));
+        assert_eq!(map.get(1, 2), 7.0);
+        assert_eq!(map.height_scale(), None);
+
+        // Saved again with a header, and a scale of 1 standing in
+        let map = HeightMap::from_bytes(&map.to_bytes()).unwrap();
+        assert_eq!(map.data, heights);
+        assert_eq!(map.height_scale(), Some(1.0));
+    }
+
+    #[test]
+    fn noise_maps_are_pinned_to_their_seed() {
+        let params = NoiseParams {
+            seed: 42,
+            ..Default::default()
+        };
+        let map = HeightMap::from_noise(64, 32, params);
+        assert_eq!((map.width(), map.depth()), (64, 32));
+        assert!(map.data.iter().all(|height| (0.0..=1.0).contains(height)));
+
+        // Catches anything about the noise changing by accident
+        let samples = [(0, 0), (10, 5), (37, 21), (63, 31)].map(|(x, z)| map.get(x, z));
+        let pinned = [0.5, 0.5071252, 0.35298157, 0.3843976];
+        for (sample, pinned) in samples.into_iter().zip(pinned) {
+            assert_abs_diff_eq!(sample, pinned, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn heights_between_samples_are_blended() {
+        // Rising 1 a sample along x and 2 along z
+        let map = height_map(2, &[0.0, 1.0, 2.0, 3.0]);
+        let options = TerrainOptions {
+            origin: Vector3::new(1.0, 5.0, -1.0),
+            spacing: 2.0,
+            height_scale: 10.0,
+            ..Default::default()
+        };
+
+        assert_eq!(map.height_at(options, 1.0, -1.0), Some(5.0));
+        assert_eq!(map.height_at(options, 3.0, -1.0), Some(15.0));
+        assert_eq!(map.height_at(options, 3.0, 1.0), Some(35.0));
+        assert_eq!(map.height_at(options, 2.0, 0.0), Some(20.0));
+        assert_eq!(map.height_at(options, 2.0, -1.0), Some(10.0));
+        for (x, z) in [(0.9, 0.0), (2.0, 1.1), (3.1, -1.0), (f32::NAN, 0.0)] {
+            assert_eq!(map.height_at(options, x, z), None);
+            assert_eq!(map.normal_at(options, x, z), None);
+        }
+
+        // Rising 5 along x and 10 along z for every 1 across, everywhere
+        let expected = Vector3::new(-5.0, 1.0, -10.0).normalize();
+        for (x, z) in [(1.0, -1.0), (2.0, 0.0), (3.0, 1.0)] {
+            assert_abs_diff_eq!(
+                map.normal_at(options, x, z).unwrap(),
+                expected,
+                epsilon = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the 3x2 height map")]
+    fn reading_outside_panics() {
+        height_map(3, &[0.0; 6]).get(3, 0);
+    }
+
+    #[test]
+    fn grid_faces_up() {
+        let map = height_map(3, &[0.0, 1.0, 0.0, 1.0, 2.0, 1.0, 0.0, 1.0, 0.0]);
+        let mesh = TriangleList::create(&map, TerrainOptions::default());
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(mesh.indices.len(), 2 * 2 * 6);
+        assert_eq!(mesh.vertices[4].position, Vector3::new(1.0, 2.0, 1.0));
+        assert_eq!(mesh.vertices[8].uv, [1.0, 1.0].into());
+
+        // Every triangle winds counterclockwise seen from above
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position);
+            assert!((b - a).cross(c - a).y > 0.0);
+        }
+    }
+
+    #[test]
+    fn border_normals_follow_the_slope() {
+        // A ramp rising 1 a sample along x, scaled up and spread out
+        let map = height_map(3, &[0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0, 1.0, 2.0]);
+        let options = TerrainOptions {
+            spacing: 2.0,
+            height_scale: 4.0,
+            ..Default::default()
+        };
+        let mesh = TriangleList::create(&map, options);
+
+        // Rising 2 for every 1 along x, at the edges as much as in between
+        let expected = Vector3::new(-2.0, 1.0, 0.0).normalize();
+        for vertex in &mesh.vertices {
+            assert_abs_diff_eq!(vertex.normal, expected, epsilon = 1e-6);
+        }
+    }
+}
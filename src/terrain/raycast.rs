@@ -0,0 +1,253 @@
+//! Casting rays against a [`HeightMap`]'s triangles, for picking points on
+//! the ground and probing it. The cells under the ray are walked in the
+//! order it crosses them, so the first hit is the nearest.
+
+use super::{sample_position, HeightMap, TerrainOptions};
+use crate::{math::Ray, model::Aabb};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+/// How far past a triangle's edges it's still hit, so rays along the edge
+/// between two don't slip through.
+const EDGE_TOLERANCE: f32 = 1e-5;
+
+/// Where a ray meets the ground.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub position: Point3<f32>,
+    /// The triangle's, facing up whichever side the ray came from.
+    pub normal: Vector3<f32>,
+    /// Along the ray from its origin.
+    pub distance: f32,
+}
+
+impl HeightMap {
+    /// Where `ray` first meets the map laid out by `options`, at the full
+    /// detail of [`TriangleList::create`](super::TriangleList::create).
+    /// Undersides are hit too, so rays starting below the ground find it.
+    pub fn raycast(&self, options: TerrainOptions, ray: &Ray) -> Option<RayHit> {
+        let cells = (self.width.checked_sub(1)?, self.depth.checked_sub(1)?);
+        if cells.0 == 0 || cells.1 == 0 {
+            return None;
+        }
+        let (near, far) = ray.clip_aabb(&self.surface_bounds(options))?;
+
+        let start = ray.at(near);
+        let mut x = Axis::new(
+            (start.x - options.origin.x) / options.spacing,
+            ray.direction.x / options.spacing,
+            near,
+            cells.0,
+        );
+        let mut z = Axis::new(
+            (start.z - options.origin.z) / options.spacing,
+            ray.direction.z / options.spacing,
+            near,
+            cells.1,
+        );
+        loop {
+            if let Some(hit) = self.cell_hit(options, ray, x.cell, z.cell) {
+                return Some(hit);
+            }
+            let axis = match x.next < z.next {
+                true => &mut x,
+                false => &mut z,
+            };
+            if axis.next > far || !axis.step() {
+                return None;
+            }
+        }
+    }
+
+    /// Everything the samples reach laid out by `options`.
+    fn surface_bounds(&self, options: TerrainOptions) -> Aabb {
+        let (low, high) = self.data.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(low, high), &height| (low.min(height), high.max(height)),
+        );
+        let (low, high) = match options.height_scale < 0.0 {
+            true => (high, low),
+            false => (low, high),
+        };
+        let far = |samples: usize| (samples - 1) as f32 * options.spacing;
+
+        Aabb::new(
+            options.origin + Vector3::new(0.0, low * options.height_scale, 0.0),
+            options.origin
+                + Vector3::new(
+                    far(self.width),
+                    high * options.height_scale,
+                    far(self.depth),
+                ),
+        )
+    }
+
+    /// The nearer of the cell's two triangles `ray` hits, if either.
+    fn cell_hit(&self, options: TerrainOptions, ray: &Ray, x: usize, z: usize) -> Option<RayHit> {
+        let corner = |x, z| Point3::from_vec(sample_position(self, options, x, z));
+        let [a, b, c, d] =
+            [(x, z), (x + 1, z), (x, z + 1), (x + 1, z + 1)].map(|(x, z)| corner(x, z));
+
+        // Split along the same diagonal as lod::grid_indices
+        [[a, c, b], [b, c, d]]
+            .into_iter()
+            .filter_map(|triangle| intersect_triangle(ray, triangle))
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+/// Where the ray is along one axis of the grid, stepping a cell at a time
+/// as in Amanatides and Woo's "A Fast Voxel Traversal Algorithm".
+struct Axis {
+    cell: usize,
+    cells: usize,
+    forward: bool,
+    /// How far along the ray it leaves the cell.
+    next: f32,
+    /// How far along the ray each cell is across.
+    delta: f32,
+}
+
+impl Axis {
+    /// `position` in cells `distance` along the ray, which moves `direction`
+    /// cells a unit.
+    fn new(position: f32, direction: f32, distance: f32, cells: usize) -> Self {
+        // Clamped, the ray having only just entered the bounds
+        let cell = (position.floor().max(0.0) as usize).min(cells - 1);
+        let forward = direction > 0.0;
+        let boundary = match forward {
+            true => cell + 1,
+            false => cell,
+        };
+        let next = match direction == 0.0 {
+            true => f32::INFINITY,
+            false => distance + (boundary as f32 - position) / direction,
+        };
+
+        Self {
+            cell,
+            cells,
+            forward,
+            next,
+            delta: direction.recip().abs(),
+        }
+    }
+
+    /// Moves on to the next cell, returning false when that's off the map.
+    fn step(&mut self) -> bool {
+        match self.forward {
+            true if self.cell + 1 < self.cells => self.cell += 1,
+            false if self.cell > 0 => self.cell -= 1,
+            _ => return false,
+        }
+        self.next += self.delta;
+
+        true
+    }
+}
+
+/// Möller and Trumbore's ray triangle intersection, from either side.
+fn intersect_triangle(ray: &Ray, [a, b, c]: [Point3<f32>; 3]) -> Option<RayHit> {
+    let (ab, ac) = (b - a, c - a);
+    let p = ray.direction.cross(ac);
+    let determinant = ab.dot(p);
+    // Parallel to the triangle, or too close to it to tell
+    if determinant.abs() <= f32::EPSILON * ab.magnitude() * ac.magnitude() {
+        return None;
+    }
+
+    let offset = ray.origin - a;
+    let u = offset.dot(p) / determinant;
+    let q = offset.cross(ab);
+    let v = ray.direction.dot(q) / determinant;
+    if u < -EDGE_TOLERANCE || v < -EDGE_TOLERANCE || u + v > 1.0 + EDGE_TOLERANCE {
+        return None;
+    }
+    let distance = ac.dot(q) / determinant;
+    if distance < 0.0 {
+        return None;
+    }
+    let normal = ab.cross(ac).normalize();
+
+    Some(RayHit {
+        position: ray.at(distance),
+        normal: match normal.y < 0.0 {
+            true => -normal,
+            false => normal,
+        },
+        distance,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        math::Ray,
+        terrain::{HeightMap, TerrainOptions},
+    };
+    use cgmath::{assert_abs_diff_eq, InnerSpace, Point3, Vector3};
+
+    #[test]
+    fn rays_straight_down_hit_the_heights() {
+        // A plateau rising to a peak in the middle
+        #[rustfmt::skip]
+        let heights = vec![
+            1.0, 1.0, 1.0, 1.0,
+            1.0, 3.0, 1.0, 1.0,
+            1.0, 1.0, 1.0, 1.0,
+        ];
+        let map = HeightMap::from_heights(4, 3, heights).unwrap();
+        let options = TerrainOptions {
+            origin: Vector3::new(-2.0, 5.0, 1.0),
+            spacing: 2.0,
+            height_scale: 0.5,
+            ..Default::default()
+        };
+        let down = |x: f32, y: f32, z: f32| {
+            map.raycast(options, &Ray::new(Point3::new(x, y, z), -Vector3::unit_y()))
+        };
+
+        let hit = down(0.0, 10.0, 3.0).unwrap();
+        assert_abs_diff_eq!(hit.position, Point3::new(0.0, 6.5, 3.0), epsilon = 1e-5);
+        assert_abs_diff_eq!(hit.distance, 3.5, epsilon = 1e-5);
+        // Out on the plateau, where the ground's flat
+        let hit = down(3.0, 10.0, 4.0).unwrap();
+        assert_abs_diff_eq!(hit.position.y, 5.5, epsilon = 1e-5);
+        assert_abs_diff_eq!(hit.normal, Vector3::unit_y(), epsilon = 1e-5);
+
+        // Nothing below a ray starting under the ground, or off the map
+        assert_eq!(down(3.0, 5.0, 4.0), None);
+        assert_eq!(down(-3.0, 10.0, 4.0), None);
+        // Though it's found above
+        let up = Ray::new(Point3::new(0.0, 0.0, 3.0), Vector3::unit_y());
+        let hit = map.raycast(options, &up).unwrap();
+        assert_abs_diff_eq!(hit.distance, 6.5, epsilon = 1e-5);
+        assert!(hit.normal.y > 0.0);
+    }
+
+    #[test]
+    fn rays_at_an_angle_cross_slopes() {
+        // Rising half a unit a sample along x
+        let heights = (0..33 * 9).map(|i| (i % 33) as f32 / 2.0).collect();
+        let map = HeightMap::from_heights(33, 9, heights).unwrap();
+        let options = TerrainOptions::default();
+
+        // 45° down towards the slope, meeting y = x / 2 at x = 4
+        let ray = Ray::new(Point3::new(0.0, 6.0, 3.5), Vector3::new(1.0, -1.0, 0.0));
+        let hit = map.raycast(options, &ray).unwrap();
+        assert_abs_diff_eq!(hit.position, Point3::new(4.0, 2.0, 3.5), epsilon = 1e-4);
+        assert_abs_diff_eq!(hit.distance, 32.0f32.sqrt(), epsilon = 1e-4);
+        let normal = Vector3::new(-0.5, 1.0, 0.0).normalize();
+        assert_abs_diff_eq!(hit.normal, normal, epsilon = 1e-5);
+
+        // Grazing it across cells and their diagonals, from just above
+        let ray = Ray::new(Point3::new(0.0, 0.01, 0.3), Vector3::new(1.0, 0.499, 0.2));
+        let hit = map.raycast(options, &ray).unwrap();
+        let ground = hit.position.x / 2.0;
+        assert_abs_diff_eq!(hit.position.y, ground, epsilon = 1e-4);
+        assert_abs_diff_eq!(hit.position.x, 10.0, epsilon = 1e-2);
+
+        // And back down the slope, away from it
+        let ray = Ray::new(Point3::new(30.0, 16.0, 4.0), Vector3::new(-1.0, -0.4, 0.0));
+        assert_eq!(map.raycast(options, &ray), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Layered 2d textures, so instances with different images can share one
+//! bind group and draw call.
+
+use super::{
+    cached_sampler, generate_mips, rows, SamplerOptions, Texture, TextureError, TextureResult,
+};
+use image::{DynamicImage, GenericImageView};
+use wgpu::{
+    Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+impl Texture {
+    /// Creates a srgb texture with one layer per image, each with its full
+    /// mip chain, viewed as [`TextureViewDimension::D2Array`].
+    pub fn array_from_images(
+        device: &Device,
+        queue: &Queue,
+        images: &[DynamicImage],
+        label: Option<&str>,
+        sampler: SamplerOptions,
+    ) -> TextureResult<Self> {
+        let (width, height) = array_dimensions(images)?;
+        let format = TextureFormat::Rgba8UnormSrgb;
+        let levels = images
+            .iter()
+            .map(|image| generate_mips(&image.to_rgba8(), u32::MAX))
+            .collect::<Vec<_>>();
+
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
+        };
+        let handle = device.create_texture(&TextureDescriptor {
+            label,
+            size,
+            mip_level_count: levels[0].len() as u32,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        for (layer, layer_levels) in levels.iter().enumerate() {
+            for (mip_level, data) in layer_levels.iter().enumerate() {
+                let (width, height) = data.dimensions();
+                queue.write_texture(
+                    ImageCopyTexture {
+                        aspect: TextureAspect::All,
+                        texture: &handle,
+                        mip_level: mip_level as u32,
+                        origin: Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                    },
+                    data,
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(rows::bytes_per_row(width, format)),
+                        rows_per_image: Some(height),
+                    },
+                    Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let view = handle.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        Ok(Self {
+            format: handle.format(),
+            handle,
+            view,
+            sampler: cached_sampler(device, sampler),
+            sampler_options: sampler,
+            depth_sampler: None,
+        })
+    }
+}
+
+/// The size every layer shares, erroring on none at all or any mismatch.
+fn array_dimensions(images: &[DynamicImage]) -> TextureResult<(u32, u32)> {
+    let dimensions = images
+        .first()
+        .ok_or_else(|| TextureError::Array("no layers".to_owned()))?
+        .dimensions();
+
+    match images
+        .iter()
+        .position(|image| image.dimensions() != dimensions)
+    {
+        Some(layer) => Err(TextureError::Array(format!(
+            "layer {layer} is {:?}, layer 0 is {dimensions:?}",
+            images[layer].dimensions()
+        ))),
+        None => Ok(dimensions),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::array_dimensions;
+    use image::DynamicImage;
+
+    #[test]
+    fn layers_share_dimensions() {
+        let layers = vec![DynamicImage::new_rgba8(4, 2); 3];
+        assert_eq!(array_dimensions(&layers).unwrap(), (4, 2));
+
+        let mut mismatched = layers.clone();
+        mismatched.push(DynamicImage::new_rgba8(2, 4));
+        assert!(array_dimensions(&mismatched).is_err());
+        assert!(array_dimensions(&[]).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Packs many small images into one texture, so decals and icons share a
+//! bind group.
+
+use super::{SamplerOptions, Texture, TextureError, TextureKind, TextureResult};
+use crate::model::ModelVertex;
+use image::{imageops, DynamicImage, GenericImageView, RgbaImage};
+use std::collections::HashMap;
+use wgpu::{Device, Queue};
+
+/// Where an image ended up in its atlas, in texture coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    /// Maps coordinates on the original image into the atlas.
+    pub fn map(&self, [u, v]: [f32; 2]) -> [f32; 2] {
+        [
+            self.min[0] + u * (self.max[0] - self.min[0]),
+            self.min[1] + v * (self.max[1] - self.min[1]),
+        ]
+    }
+
+    /// Remaps a mesh textured with the original image to sample the atlas
+    /// instead. Coordinates outside [0, 1] would reach the neighbours, so
+    /// repeating textures can't be atlased.
+    pub fn remap(&self, vertices: &mut [ModelVertex]) {
+        for vertex in vertices {
+            vertex.texture_coordinates = self.map(vertex.texture_coordinates);
+        }
+    }
+}
+
+/// Pixel placement of one image, see [`AtlasBuilder::pack`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtlasLayout {
+    pub width: u32,
+    pub height: u32,
+    pub rects: HashMap<String, AtlasRect>,
+}
+
+impl AtlasLayout {
+    pub fn uv_rects(&self) -> HashMap<String, UvRect> {
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        self.rects
+            .iter()
+            .map(|(name, rect)| {
+                let uv = UvRect {
+                    min: [rect.x as f32 / width, rect.y as f32 / height],
+                    max: [
+                        (rect.x + rect.width) as f32 / width,
+                        (rect.y + rect.height) as f32 / height,
+                    ],
+                };
+
+                (name.clone(), uv)
+            })
+            .collect()
+    }
+}
+
+/// Collects named images and shelf packs them, tallest first, into rows of
+/// a roughly square atlas.
+#[derive(Clone, Debug)]
+pub struct AtlasBuilder {
+    images: Vec<(String, DynamicImage)>,
+    padding: u32,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self {
+            images: vec![],
+            padding: 1,
+        }
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Empty pixels kept around every image so filtering doesn't bleed the
+    /// neighbours in, 1 by default.
+    pub fn with_padding(self, padding: u32) -> Self {
+        Self { padding, ..self }
+    }
+
+    /// Adds an image, replacing any earlier one of the same name.
+    pub fn add(&mut self, name: &str, image: DynamicImage) -> &mut Self {
+        self.images.retain(|(other, _)| other != name);
+        self.images.push((name.to_owned(), image));
+
+        self
+    }
+
+    pub fn pack(&self) -> AtlasLayout {
+        let padded = |image: &DynamicImage| {
+            let (width, height) = image.dimensions();
+            (width + 2 * self.padding, height + 2 * self.padding)
+        };
+
+        let area: u64 = self
+            .images
+            .iter()
+            .map(|(_, image)| {
+                let (width, height) = padded(image);
+                width as u64 * height as u64
+            })
+            .sum();
+        let widest = self
+            .images
+            .iter()
+            .map(|(_, image)| padded(image).0)
+            .max()
+            .unwrap_or(1);
+        let width = ((area as f64).sqrt().ceil() as u32)
+            .max(widest)
+            .next_power_of_two();
+
+        let mut order: Vec<_> = self.images.iter().collect();
+        order.sort_by_key(|(_, image)| std::cmp::Reverse(image.height()));
+
+        let mut rects = HashMap::new();
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for (name, image) in order {
+            let (padded_width, padded_height) = padded(image);
+            if x + padded_width > width {
+                (x, y, shelf_height) = (0, y + shelf_height, 0);
+            }
+
+            rects.insert(
+                name.clone(),
+                AtlasRect {
+                    x: x + self.padding,
+                    y: y + self.padding,
+                    width: image.width(),
+                    height: image.height(),
+                },
+            );
+            x += padded_width;
+            shelf_height = shelf_height.max(padded_height);
+        }
+
+        AtlasLayout {
+            width,
+            height: (y + shelf_height).max(1),
+            rects,
+        }
+    }
+
+    /// Packs and draws the atlas without a device.
+    pub fn build_image(&self) -> (RgbaImage, AtlasLayout) {
+        let layout = self.pack();
+        let mut atlas = RgbaImage::new(layout.width, layout.height);
+        for (name, image) in &self.images {
+            let rect = layout.rects[name];
+            imageops::replace(&mut atlas, &image.to_rgba8(), rect.x as i64, rect.y as i64);
+        }
+
+        (atlas, layout)
+    }
+
+    /// Uploads the atlas as a srgb color texture, the rects say where each
+    /// image went.
+    pub fn build(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        label: Option<&str>,
+    ) -> TextureResult<(Texture, HashMap<String, UvRect>)> {
+        let (atlas, layout) = self.build_image();
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        if layout.width > max_dimension || layout.height > max_dimension {
+            return Err(TextureError::Atlas(format!(
+                "{}x{} is over the device's limit of {max_dimension}",
+                layout.width, layout.height
+            )));
+        }
+
+        let texture = Texture::from_image(
+            device,
+            queue,
+            &DynamicImage::ImageRgba8(atlas),
+            label,
+            TextureKind::Color,
+            SamplerOptions::default(),
+        );
+
+        Ok((texture, layout.uv_rects()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AtlasBuilder, AtlasRect};
+    use crate::model::ModelVertex;
+    use bytemuck::Zeroable;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn overlaps(a: &AtlasRect, b: &AtlasRect) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn packed_rects_are_disjoint_and_inside() {
+        let mut builder = AtlasBuilder::new();
+        for index in 0..40u32 {
+            let (width, height) = (1 + index * 7 % 23, 1 + index * 5 % 17);
+            builder.add(
+                &format!("image {index}"),
+                DynamicImage::new_rgba8(width, height),
+            );
+        }
+
+        let layout = builder.pack();
+        assert_eq!(layout.rects.len(), 40);
+        let rects: Vec<_> = layout.rects.values().collect();
+        for (index, rect) in rects.iter().enumerate() {
+            assert!(rect.x + rect.width < layout.width);
+            assert!(rect.y + rect.height < layout.height);
+            assert!(rects[index + 1..]
+                .iter()
+                .all(|other| !overlaps(rect, other)));
+        }
+    }
+
+    #[test]
+    fn remapped_coordinates_land_on_the_image() {
+        let red = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(8, 2, Rgba([0, 0, 255, 255]));
+        let mut builder = AtlasBuilder::new();
+        builder.add("red", red.into()).add("blue", blue.into());
+
+        let (atlas, layout) = builder.build_image();
+        let uv_rects = layout.uv_rects();
+        let mut vertices = [0.0, 0.5, 0.99f32].map(|t| ModelVertex {
+            texture_coordinates: [t, t],
+            ..Zeroable::zeroed()
+        });
+        uv_rects["red"].remap(&mut vertices);
+
+        for vertex in vertices {
+            let [u, v] = vertex.texture_coordinates;
+            let pixel = atlas.get_pixel(
+                (u * atlas.width() as f32) as u32,
+                (v * atlas.height() as f32) as u32,
+            );
+            assert_eq!(*pixel, Rgba([255, 0, 0, 255]));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! CPU decoding of block compressed levels, for devices without
+//! [`Features::TEXTURE_COMPRESSION_BC`](wgpu::Features::TEXTURE_COMPRESSION_BC).
+
+use super::TextureLevels;
+use wgpu::{Extent3d, TextureDimension, TextureFormat};
+
+type BlockDecoder = fn(&[u8], usize, usize, &mut [u32]) -> Result<(), &'static str>;
+
+/// Decodes every level to rgba8 in the same color space, or `None` for
+/// formats that aren't block compressed or can't be decoded here. BC5 comes
+/// out as red and green with a zero blue channel, normal maps rebuild z in
+/// the shader either way.
+pub fn decompress(levels: &TextureLevels) -> Option<TextureLevels> {
+    let (decode, format): (BlockDecoder, _) = match levels.format {
+        TextureFormat::Bc1RgbaUnorm => (texture2ddecoder::decode_bc1a, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc1RgbaUnormSrgb => {
+            (texture2ddecoder::decode_bc1a, TextureFormat::Rgba8UnormSrgb)
+        }
+        TextureFormat::Bc3RgbaUnorm => (texture2ddecoder::decode_bc3, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc3RgbaUnormSrgb => {
+            (texture2ddecoder::decode_bc3, TextureFormat::Rgba8UnormSrgb)
+        }
+        TextureFormat::Bc5RgUnorm => (texture2ddecoder::decode_bc5, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc7RgbaUnorm => (texture2ddecoder::decode_bc7, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc7RgbaUnormSrgb => {
+            (texture2ddecoder::decode_bc7, TextureFormat::Rgba8UnormSrgb)
+        }
+        _ => return None,
+    };
+
+    let size = Extent3d {
+        width: levels.width,
+        height: levels.height,
+        depth_or_array_layers: 1,
+    };
+    let decoded = levels
+        .levels
+        .iter()
+        .enumerate()
+        .map(|(level, data)| {
+            let level_size = size.mip_level_size(level as u32, TextureDimension::D2);
+            let (width, height) = (level_size.width as usize, level_size.height as usize);
+            let mut pixels = vec![0; width * height];
+            decode(data, width, height, &mut pixels).ok()?;
+
+            // The decoder packs pixels as little endian bgra
+            Some(
+                pixels
+                    .into_iter()
+                    .flat_map(|pixel| {
+                        let [b, g, r, a] = pixel.to_le_bytes();
+                        [r, g, b, a]
+                    })
+                    .collect(),
+            )
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(TextureLevels {
+        format,
+        width: levels.width,
+        height: levels.height,
+        levels: decoded,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::decompress;
+    use crate::texture::TextureLevels;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn solid_blocks() {
+        // Pure red, 565 endpoints with every texel on the first
+        let bc1 = TextureLevels {
+            format: TextureFormat::Bc1RgbaUnormSrgb,
+            width: 4,
+            height: 4,
+            levels: vec![vec![0x00, 0xf8, 0x00, 0x00, 0, 0, 0, 0]],
+        };
+        let decoded = decompress(&bc1).unwrap();
+        assert_eq!(decoded.format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(decoded.levels[0], [255, 0, 0, 255].repeat(16));
+
+        // Red at full, green at zero
+        let bc5 = TextureLevels {
+            format: TextureFormat::Bc5RgUnorm,
+            width: 2,
+            height: 2,
+            levels: vec![[[255, 255, 0, 0, 0, 0, 0, 0], [0; 8]].concat()],
+        };
+        let decoded = decompress(&bc5).unwrap();
+        assert_eq!(decoded.levels[0], [255, 0, 0, 255].repeat(4));
+    }
+}
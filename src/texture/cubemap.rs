@@ -0,0 +1,176 @@
+//! Cube textures for skyboxes and reflections, from six face images or one
+//! equirectangular panorama.
+
+use super::{cached_sampler, rows, SamplerOptions, Texture, TextureError, TextureResult};
+use cgmath::{InnerSpace, Vector3};
+use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, Pixel};
+use std::f32::consts::{PI, TAU};
+use wgpu::{
+    Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+/// File stems of the faces in layer order, +x, -x, +y, -y, +z, -z.
+pub const CUBEMAP_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+impl Texture {
+    /// Creates a srgb cube texture from six square faces of the same size,
+    /// ordered like [`CUBEMAP_FACES`].
+    pub fn cubemap_from_images(
+        device: &Device,
+        queue: &Queue,
+        faces: [&DynamicImage; 6],
+        label: Option<&str>,
+    ) -> TextureResult<Self> {
+        let (width, height) = faces[0].dimensions();
+        if width != height
+            || faces
+                .iter()
+                .any(|face| face.dimensions() != (width, height))
+        {
+            return Err(TextureError::Cubemap(
+                "faces must be square and all the same size".to_owned(),
+            ));
+        }
+
+        let handle = device.create_texture(&TextureDescriptor {
+            label,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                ImageCopyTexture {
+                    aspect: TextureAspect::All,
+                    texture: &handle,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                },
+                &face.to_rgba8(),
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(rows::bytes_per_row(width, TextureFormat::Rgba8UnormSrgb)),
+                    rows_per_image: Some(height),
+                },
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = handle.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        // The default clamping keeps the seams between faces from bleeding
+        let sampler_options = SamplerOptions::default();
+
+        Ok(Self {
+            format: handle.format(),
+            handle,
+            view,
+            sampler: cached_sampler(device, sampler_options),
+            sampler_options,
+            depth_sampler: None,
+        })
+    }
+
+    /// Creates a cube texture from an equirectangular panorama, see
+    /// [`equirectangular_to_faces`].
+    pub fn cubemap_from_equirectangular(
+        device: &Device,
+        queue: &Queue,
+        image: &DynamicImage,
+        label: Option<&str>,
+    ) -> TextureResult<Self> {
+        let faces = equirectangular_to_faces(&image.to_rgba8(), image.width() / 4)
+            .map(DynamicImage::ImageRgba8);
+        let [px, nx, py, ny, pz, nz] = &faces;
+
+        Self::cubemap_from_images(device, queue, [px, nx, py, ny, pz, nz], label)
+    }
+}
+
+/// Resamples a panorama into six `face_size` faces. The middle of the image
+/// faces -z, the top edge is straight up.
+pub fn equirectangular_to_faces<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    face_size: u32,
+) -> [ImageBuffer<P, Vec<P::Subpixel>>; 6]
+where
+    P: Pixel,
+{
+    let face_size = face_size.max(1);
+
+    [0, 1, 2, 3, 4, 5].map(|face| {
+        ImageBuffer::from_fn(face_size, face_size, |x, y| {
+            let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+            let direction = face_direction(face, u, v).normalize();
+
+            let longitude = direction.x.atan2(-direction.z);
+            let latitude = direction.y.clamp(-1.0, 1.0).asin();
+            let (u, v) = (0.5 + longitude / TAU, 0.5 - latitude / PI);
+
+            imageops::sample_bilinear(image, u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
+                .unwrap_or_else(|| *image.get_pixel(0, 0))
+        })
+    })
+}
+
+/// Direction through a point on a face, `u` right and `v` down in [-1, 1],
+/// following the cube layout wgpu samples with.
+fn face_direction(face: usize, u: f32, v: f32) -> Vector3<f32> {
+    match face {
+        0 => Vector3::new(1.0, -v, -u),
+        1 => Vector3::new(-1.0, -v, u),
+        2 => Vector3::new(u, 1.0, v),
+        3 => Vector3::new(u, -1.0, -v),
+        4 => Vector3::new(u, -v, 1.0),
+        _ => Vector3::new(-u, -v, -1.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::equirectangular_to_faces;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn sky_and_ground_faces() {
+        // Red sky over a blue ground
+        let panorama = RgbaImage::from_fn(64, 32, |_, y| match y < 16 {
+            true => Rgba([255, 0, 0, 255]),
+            false => Rgba([0, 0, 255, 255]),
+        });
+
+        let faces = equirectangular_to_faces(&panorama, 8);
+        assert!(faces[2]
+            .pixels()
+            .all(|&pixel| pixel == Rgba([255, 0, 0, 255])));
+        assert!(faces[3]
+            .pixels()
+            .all(|&pixel| pixel == Rgba([0, 0, 255, 255])));
+
+        // The side faces have the horizon across their middle
+        assert_eq!(*faces[4].get_pixel(4, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*faces[4].get_pixel(4, 7), Rgba([0, 0, 255, 255]));
+    }
+}
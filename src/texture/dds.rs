@@ -0,0 +1,126 @@
+//! DDS reader for block compressed 2d textures, both the legacy fourCC
+//! headers and the DX10 extension.
+
+use super::{byte_size, TextureError, TextureLevels, TextureResult};
+use wgpu::{Extent3d, TextureDimension, TextureFormat};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+/// Magic plus the fixed header.
+const HEADER_SIZE: usize = 128;
+const DX10_HEADER_SIZE: usize = 20;
+const CUBEMAP_CAPS: u32 = 0x200;
+const CUBEMAP_MISC_FLAG: u32 = 0x4;
+
+pub fn parse_dds(bytes: &[u8]) -> TextureResult<TextureLevels> {
+    if !bytes.starts_with(MAGIC) || bytes.len() < HEADER_SIZE {
+        return Err(TextureError::Dds("not a dds file".to_owned()));
+    }
+
+    let word = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let [height, width, depth, mip_map_count] = [12, 16, 24, 28].map(word);
+    let four_cc = &bytes[84..88];
+    if word(112) & CUBEMAP_CAPS != 0 || depth > 1 {
+        return Err(TextureError::Dds(
+            "only 2d textures are supported".to_owned(),
+        ));
+    }
+
+    let (format, data_offset) = match four_cc {
+        b"DX10" => {
+            if bytes.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+                return Err(TextureError::Dds("truncated dx10 header".to_owned()));
+            }
+            let [dxgi_format, misc_flag, array_size] = [128, 136, 140].map(word);
+            if misc_flag & CUBEMAP_MISC_FLAG != 0 || array_size > 1 {
+                return Err(TextureError::Dds(
+                    "only 2d textures are supported".to_owned(),
+                ));
+            }
+
+            let format = dxgi_format_to_wgpu(dxgi_format).ok_or_else(|| {
+                TextureError::Dds(format!("unsupported dxgi format {dxgi_format}"))
+            })?;
+            (format, HEADER_SIZE + DX10_HEADER_SIZE)
+        }
+        // Legacy headers don't say which color space they're in, BC1 and BC3
+        // are nearly always color maps
+        b"DXT1" => (TextureFormat::Bc1RgbaUnormSrgb, HEADER_SIZE),
+        b"DXT5" => (TextureFormat::Bc3RgbaUnormSrgb, HEADER_SIZE),
+        b"ATI2" | b"BC5U" => (TextureFormat::Bc5RgUnorm, HEADER_SIZE),
+        _ => {
+            return Err(TextureError::Dds(format!(
+                "unsupported fourCC {}",
+                String::from_utf8_lossy(four_cc)
+            )))
+        }
+    };
+
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut offset = data_offset;
+    let levels = (0..mip_map_count.max(1))
+        .map(|level| {
+            let length =
+                byte_size(size.mip_level_size(level, TextureDimension::D2), format, 1) as usize;
+            let data = bytes
+                .get(offset..offset + length)
+                .ok_or_else(|| TextureError::Dds(format!("level {level} is out of bounds")))?;
+            offset += length;
+
+            Ok(data.to_vec())
+        })
+        .collect::<TextureResult<Vec<_>>>()?;
+
+    Ok(TextureLevels {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+fn dxgi_format_to_wgpu(dxgi_format: u32) -> Option<TextureFormat> {
+    Some(match dxgi_format {
+        28 => TextureFormat::Rgba8Unorm,
+        29 => TextureFormat::Rgba8UnormSrgb,
+        71 => TextureFormat::Bc1RgbaUnorm,
+        72 => TextureFormat::Bc1RgbaUnormSrgb,
+        77 => TextureFormat::Bc3RgbaUnorm,
+        78 => TextureFormat::Bc3RgbaUnormSrgb,
+        83 => TextureFormat::Bc5RgUnorm,
+        98 => TextureFormat::Bc7RgbaUnorm,
+        99 => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_dds, HEADER_SIZE, MAGIC};
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn legacy_dxt1_levels() {
+        let mut file = MAGIC.to_vec();
+        file.resize(HEADER_SIZE, 0);
+        file[12..16].copy_from_slice(&8u32.to_le_bytes());
+        file[16..20].copy_from_slice(&8u32.to_le_bytes());
+        file[28..32].copy_from_slice(&4u32.to_le_bytes());
+        file[84..88].copy_from_slice(b"DXT1");
+        // 8x8 is 2x2 blocks, every smaller level a single 8 byte block
+        file.extend([1; 32]);
+        file.extend([2; 8 * 3]);
+
+        let texture = parse_dds(&file).unwrap();
+        assert_eq!(texture.format, TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!(texture.levels.len(), 4);
+        assert_eq!(texture.levels[0], [1; 32]);
+        assert_eq!(texture.levels[3], [2; 8]);
+
+        file.truncate(file.len() - 1);
+        assert!(parse_dds(&file).is_err());
+    }
+}
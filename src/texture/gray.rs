@@ -0,0 +1,217 @@
+//! Single channel data textures, heightmaps, AO maps and masks, kept at one
+//! channel instead of being expanded to rgba8.
+
+use super::{SamplerOptions, Texture, TextureError, TextureLevels, TextureResult};
+use image::{DynamicImage, GenericImageView};
+use wgpu::{
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Device,
+    Extent3d, Features, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, SamplerBindingType,
+    ShaderStages, TextureAspect, TextureFormat, TextureSampleType, TextureViewDimension,
+};
+
+impl Texture {
+    /// Uploads the image's luminance as [`TextureFormat::R8Unorm`], 16 bit
+    /// images as [`TextureFormat::R16Unorm`] and floating point ones as
+    /// [`TextureFormat::R32Float`]. Data isn't mipped.
+    pub fn from_gray_image(
+        device: &Device,
+        queue: &Queue,
+        image: &DynamicImage,
+        label: Option<&str>,
+        sampler: SamplerOptions,
+    ) -> TextureResult<Self> {
+        let (width, height) = image.dimensions();
+        let (format, data) = match image {
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_)
+                if device
+                    .features()
+                    .contains(Features::TEXTURE_FORMAT_16BIT_NORM) =>
+            {
+                (
+                    TextureFormat::R16Unorm,
+                    bytemuck::cast_slice(image.to_luma16().as_raw()).to_vec(),
+                )
+            }
+            // Without 16 bit normalized formats floats keep the precision
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb32F(_)
+            | DynamicImage::ImageRgba32F(_) => {
+                return Self::from_gray_f32(
+                    device,
+                    queue,
+                    width,
+                    height,
+                    image.to_luma32f().as_raw(),
+                    label,
+                    sampler,
+                )
+            }
+            _ => (TextureFormat::R8Unorm, image.to_luma8().into_raw()),
+        };
+
+        Self::from_levels(
+            device,
+            queue,
+            &TextureLevels {
+                format,
+                width,
+                height,
+                levels: vec![data],
+            },
+            label,
+            sampler,
+        )
+    }
+
+    /// Uploads `width` by `height` values as [`TextureFormat::R32Float`],
+    /// which can't be filtered, so the sampler is made nearest.
+    pub fn from_gray_f32(
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        data: &[f32],
+        label: Option<&str>,
+        sampler: SamplerOptions,
+    ) -> TextureResult<Self> {
+        Self::from_levels(
+            device,
+            queue,
+            &TextureLevels {
+                format: TextureFormat::R32Float,
+                width,
+                height,
+                levels: vec![bytemuck::cast_slice(data).to_vec()],
+            },
+            label,
+            sampler.non_filtering(),
+        )
+    }
+
+    /// Replaces a `width` by `height` block of an `R32Float` texture's values
+    /// from `origin`, e.g. the part of a height map that was edited.
+    pub fn write_gray_f32(
+        &self,
+        queue: &Queue,
+        origin: (u32, u32),
+        width: u32,
+        height: u32,
+        data: &[f32],
+    ) -> TextureResult<()> {
+        if self.format != TextureFormat::R32Float {
+            return Err(TextureError::Write(format!(
+                "{:?} textures can't be written from f32s",
+                self.format
+            )));
+        }
+        let size = self.handle.size();
+        if origin.0 + width > size.width
+            || origin.1 + height > size.height
+            || data.len() != (width * height) as usize
+        {
+            return Err(TextureError::Write(format!(
+                "{} values for {width}x{height} at {origin:?} don't fit a {}x{} texture",
+                data.len(),
+                size.width,
+                size.height
+            )));
+        }
+
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.handle,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(data),
+            // Unlike copies from buffers, rows written from the CPU needn't
+            // be padded
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * std::mem::size_of::<f32>() as u32),
+                rows_per_image: Some(height),
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Whether the texture can be bound with
+    /// [`SamplerBindingType::Filtering`], which rules out `R32Float`.
+    pub fn is_filterable(&self) -> bool {
+        matches!(
+            self.format().sample_type(None),
+            Some(TextureSampleType::Float { filterable: true })
+        )
+    }
+}
+
+/// A layout of a single texture at binding 0 and its sampler at binding 1,
+/// non-filtering for data like `R32Float` heightmaps.
+pub fn data_bind_group_layout(
+    device: &Device,
+    label: Option<&str>,
+    visibility: ShaderStages,
+    filterable: bool,
+) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label,
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: TextureViewDimension::D2,
+                    sample_type: TextureSampleType::Float { filterable },
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility,
+                ty: BindingType::Sampler(match filterable {
+                    true => SamplerBindingType::Filtering,
+                    false => SamplerBindingType::NonFiltering,
+                }),
+                count: None,
+            },
+        ],
+    })
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use crate::texture::{test_device, SamplerOptions, Texture};
+    use image::{DynamicImage, GrayImage, Rgb32FImage};
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn single_channel_formats() {
+        let (device, queue) = test_device();
+        let upload = |image: DynamicImage| {
+            Texture::from_gray_image(&device, &queue, &image, None, SamplerOptions::default())
+                .unwrap()
+        };
+
+        // 3 texels wide needs 3 byte rows, not 12
+        let mask = upload(GrayImage::new(3, 3).into());
+        assert_eq!(mask.format(), TextureFormat::R8Unorm);
+        assert!(mask.is_filterable());
+
+        let heights = upload(Rgb32FImage::new(3, 3).into());
+        assert_eq!(heights.format(), TextureFormat::R32Float);
+        assert!(!heights.is_filterable());
+    }
+}
@@ -0,0 +1,149 @@
+//! Minimal KTX2 reader for pre-mipped 2d textures, enough for what an asset
+//! pipeline exports without supercompression. Block compressed formats are
+//! limited to the ones [`decompress`](super::bc::decompress) can fall back
+//! on.
+
+use super::{byte_size, TextureError, TextureLevels, TextureResult};
+use wgpu::{Extent3d, TextureFormat};
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&IDENTIFIER)
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> TextureResult<TextureLevels> {
+    if !is_ktx2(bytes) || bytes.len() < HEADER_SIZE {
+        return Err(TextureError::Ktx2("not a ktx2 file".to_owned()));
+    }
+
+    let word = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let [vk_format, _type_size, width, height, depth, layer_count, face_count, level_count, supercompression] =
+        [12, 16, 20, 24, 28, 32, 36, 40, 44].map(word);
+
+    let format = vk_format_to_wgpu(vk_format)
+        .ok_or_else(|| TextureError::Ktx2(format!("unsupported vkFormat {vk_format}")))?;
+    if supercompression != 0 {
+        return Err(TextureError::Ktx2(format!(
+            "unsupported supercompression scheme {supercompression}"
+        )));
+    }
+    if depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err(TextureError::Ktx2(
+            "only single layer 2d textures are supported".to_owned(),
+        ));
+    }
+
+    // A level count of 0 asks the loader to generate mips, which isn't worth
+    // it for compressed formats, so only the base level is uploaded
+    let level_count = level_count.max(1) as usize;
+    let index_end = HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE;
+    if bytes.len() < index_end {
+        return Err(TextureError::Ktx2("truncated level index".to_owned()));
+    }
+
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let levels = (0..level_count)
+        .map(|level| {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            let long = |offset: usize| {
+                u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+            };
+            let (offset, length) = (long(entry), long(entry + 8));
+
+            let level_size = size.mip_level_size(level as u32, wgpu::TextureDimension::D2);
+            if length as u64 != byte_size(level_size, format, 1) {
+                return Err(TextureError::Ktx2(format!(
+                    "level {level} is {length} bytes, expected {}",
+                    byte_size(level_size, format, 1)
+                )));
+            }
+
+            bytes
+                .get(offset..offset + length)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| TextureError::Ktx2(format!("level {level} is out of bounds")))
+        })
+        .collect::<TextureResult<Vec<_>>>()?;
+
+    Ok(TextureLevels {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+/// The Vulkan formats KTX2 files are tagged with that wgpu can sample.
+fn vk_format_to_wgpu(vk_format: u32) -> Option<TextureFormat> {
+    Some(match vk_format {
+        37 => TextureFormat::Rgba8Unorm,
+        43 => TextureFormat::Rgba8UnormSrgb,
+        // BC1 without alpha decodes the same, its blocks just never use the
+        // transparent mode
+        131 | 133 => TextureFormat::Bc1RgbaUnorm,
+        132 | 134 => TextureFormat::Bc1RgbaUnormSrgb,
+        137 => TextureFormat::Bc3RgbaUnorm,
+        138 => TextureFormat::Bc3RgbaUnormSrgb,
+        141 => TextureFormat::Bc5RgUnorm,
+        145 => TextureFormat::Bc7RgbaUnorm,
+        146 => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_ktx2, HEADER_SIZE, IDENTIFIER, LEVEL_INDEX_ENTRY_SIZE};
+    use wgpu::TextureFormat;
+
+    /// A KTX2 file with the given levels, stored smallest first like
+    /// exporters do.
+    fn ktx2_file(vk_format: u32, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        for word in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+            bytes.extend(word.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE, 0);
+
+        let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+        let mut index = vec![(0, 0); levels.len()];
+        for (level, data) in levels.iter().enumerate().rev() {
+            index[level] = (offset, data.len());
+            offset += data.len();
+        }
+        for (offset, length) in index {
+            bytes.extend((offset as u64).to_le_bytes());
+            bytes.extend((length as u64).to_le_bytes());
+            bytes.extend((length as u64).to_le_bytes());
+        }
+        for data in levels.iter().rev() {
+            bytes.extend(data);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn rgba8_levels() {
+        let levels = vec![vec![1; 4 * 4 * 4], vec![2; 2 * 2 * 4], vec![3; 4]];
+        let file = ktx2_file(43, 4, 4, &levels);
+
+        let texture = parse_ktx2(&file).unwrap();
+        assert_eq!(texture.format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!((texture.width, texture.height), (4, 4));
+        assert_eq!(texture.levels, levels);
+
+        // A level that doesn't match its size is rejected
+        let file = ktx2_file(43, 4, 4, &[vec![1; 4]]);
+        assert!(parse_ktx2(&file).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+use half::f16;
+use image::{
+    codecs::hdr::HdrDecoder,
+    imageops::{self, FilterType},
+    DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgb32FImage, Rgba, Rgba32FImage, RgbaImage,
+};
+use std::sync::Arc;
+use thiserror::Error;
+use wgpu::{
+    Device, Extent3d, ImageCopyTexture, ImageDataLayout, Queue, SurfaceConfiguration,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureViewDescriptor,
+};
+
+mod array;
+mod atlas;
+mod bc;
+mod cubemap;
+mod dds;
+mod gray;
+mod ktx2;
+pub mod readback;
+pub mod rows;
+mod sampler;
+mod target;
+mod write;
+
+pub use atlas::{AtlasBuilder, AtlasLayout, AtlasRect, UvRect};
+pub use cubemap::CUBEMAP_FACES;
+pub use dds::parse_dds;
+pub use gray::data_bind_group_layout;
+pub use ktx2::parse_ktx2;
+pub use sampler::{cached_sampler, SamplerOptions};
+
+/// A texture decoded off the render thread, ready to upload.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureData {
+    Image(DynamicImage),
+    Levels(TextureLevels),
+}
+
+impl TextureData {
+    /// What the upload will take, images counted as the rgba8 or half float
+    /// mip chain they're uploaded as.
+    pub fn byte_size(&self) -> u64 {
+        match self {
+            Self::Image(image) => byte_size(
+                Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                match image {
+                    DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                        TextureFormat::Rgba16Float
+                    }
+                    _ => TextureFormat::Rgba8Unorm,
+                },
+                mip_level_count(image.width(), image.height()),
+            ),
+            Self::Levels(levels) => levels.levels.iter().map(|level| level.len() as u64).sum(),
+        }
+    }
+}
+
+/// What a texture holds, deciding the color space images are uploaded in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    /// Colors to show, e.g. diffuse maps, stored as srgb.
+    Color,
+    NormalMap,
+    /// Anything else read as plain numbers, roughness, metalness or AO maps.
+    LinearData,
+}
+
+impl TextureKind {
+    /// The 8 bit format images of this kind are uploaded as.
+    pub fn rgba8_format(self) -> TextureFormat {
+        match self {
+            Self::Color => TextureFormat::Rgba8UnormSrgb,
+            Self::NormalMap | Self::LinearData => TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// Pre-mipped texture data from a container like KTX2, uploaded as is in
+/// its own format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureLevels {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rows of blocks per level, the base level first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub struct Texture {
+    handle: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    /// Shared with every texture created with the same options.
+    pub sampler: Arc<wgpu::Sampler>,
+    pub sampler_options: SamplerOptions,
+    /// A plain sampler next to the comparison one, for reading the values of
+    /// depth textures. `None` for everything else.
+    pub depth_sampler: Option<Arc<wgpu::Sampler>>,
+    format: TextureFormat,
+}
+
+impl Texture {
+    pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: Option<&str>,
+        kind: TextureKind,
+        sampler: SamplerOptions,
+    ) -> image::ImageResult<Self> {
+        let image = image::load_from_memory(bytes)?;
+
+        Ok(Self::from_image(
+            device, queue, &image, label, kind, sampler,
+        ))
+    }
+
+    /// Uploads an image with its full mip chain. Floating point images, e.g.
+    /// decoded from Radiance `.hdr` files, keep their range as
+    /// [`TextureFormat::Rgba16Float`].
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        label: Option<&str>,
+        kind: TextureKind,
+        sampler: SamplerOptions,
+    ) -> Self {
+        Self::from_image_with_max_mips(device, queue, image, label, kind, u32::MAX, sampler)
+    }
+
+    /// Uploads an image with at most `max_mip_levels` levels of its mip chain,
+    /// the base level included.
+    pub fn from_image_with_max_mips(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        label: Option<&str>,
+        kind: TextureKind,
+        max_mip_levels: u32,
+        sampler: SamplerOptions,
+    ) -> Self {
+        let (width, height) = image.dimensions();
+        let format = match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                TextureFormat::Rgba16Float
+            }
+            _ => kind.rgba8_format(),
+        };
+
+        Self::upload_levels(
+            device,
+            queue,
+            &TextureLevels {
+                format,
+                width,
+                height,
+                levels: image_levels(image, format, max_mip_levels),
+            },
+            label,
+            sampler,
+        )
+    }
+
+    /// Uploads KTX2 data, see [`Texture::from_levels`].
+    pub fn from_ktx2(
+        device: &Device,
+        queue: &Queue,
+        bytes: &[u8],
+        label: Option<&str>,
+        sampler: SamplerOptions,
+    ) -> TextureResult<Self> {
+        Self::from_levels(device, queue, &parse_ktx2(bytes)?, label, sampler)
+    }
+
+    /// Uploads pre-mipped data in its own format. Block compressed formats
+    /// the device can't sample are decoded to rgba8 on the CPU instead.
+    pub fn from_levels(
+        device: &Device,
+        queue: &Queue,
+        levels: &TextureLevels,
+        label: Option<&str>,
+        sampler: SamplerOptions,
+    ) -> TextureResult<Self> {
+        let format = levels.format;
+        if !device.features().contains(format.required_features()) {
+            return match bc::decompress(levels) {
+                Some(decoded) => Self::from_levels(device, queue, &decoded, label, sampler),
+                None => Err(TextureError::MissingFeatures(format)),
+            };
+        }
+
+        Ok(Self::upload_levels(device, queue, levels, label, sampler))
+    }
+
+    fn upload_levels(
+        device: &Device,
+        queue: &Queue,
+        levels: &TextureLevels,
+        label: Option<&str>,
+        sampler: SamplerOptions,
+    ) -> Self {
+        let format = levels.format;
+        let size = Extent3d {
+            width: levels.width,
+            height: levels.height,
+            depth_or_array_layers: 1,
+        };
+        let texture_handle = device.create_texture(&TextureDescriptor {
+            label,
+            size,
+            mip_level_count: levels.levels.len() as u32,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            // Copying back out is what readback and screenshots rely on
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        write_levels(queue, &texture_handle, &levels.levels);
+
+        Self::with_sampler(device, texture_handle, sampler)
+    }
+
+    /// Uploads whatever the loader decoded, `kind` only matters for images
+    /// since containers carry their own color space.
+    pub fn from_data(
+        device: &Device,
+        queue: &Queue,
+        data: &TextureData,
+        label: Option<&str>,
+        kind: TextureKind,
+        sampler: SamplerOptions,
+    ) -> TextureResult<Self> {
+        match data {
+            TextureData::Image(image) => {
+                Ok(Self::from_image(device, queue, image, label, kind, sampler))
+            }
+            TextureData::Levels(levels) => Self::from_levels(device, queue, levels, label, sampler),
+        }
+    }
+
+    fn with_sampler(device: &Device, handle: wgpu::Texture, options: SamplerOptions) -> Self {
+        let view = handle.create_view(&TextureViewDescriptor::default());
+
+        Self {
+            format: handle.format(),
+            handle,
+            view,
+            sampler: cached_sampler(device, options),
+            sampler_options: options,
+            depth_sampler: None,
+        }
+    }
+
+    /// Takes whether the image is a normal map like [`Texture::from_image`]
+    /// used to, color otherwise.
+    #[deprecated(note = "pass a `TextureKind` to `Texture::from_image`")]
+    pub fn from_image_with_normal_flag(
+        device: &Device,
+        queue: &Queue,
+        image: &DynamicImage,
+        label: Option<&str>,
+        is_normal_map: bool,
+    ) -> Self {
+        let kind = match is_normal_map {
+            true => TextureKind::NormalMap,
+            false => TextureKind::Color,
+        };
+
+        Self::from_image(device, queue, image, label, kind, SamplerOptions::default())
+    }
+
+    /// Creates a 1x1 texture of a single srgb color, used in place of a missing
+    /// diffuse map.
+    pub fn solid_color(device: &wgpu::Device, queue: &wgpu::Queue, color: [u8; 4]) -> Self {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba(color)));
+
+        Self::from_image(
+            device,
+            queue,
+            &image,
+            Some("Solid color texture"),
+            TextureKind::Color,
+            SamplerOptions::default(),
+        )
+    }
+
+    /// Creates a 1x1 normal map pointing straight out of the surface, used in
+    /// place of a missing normal map.
+    pub fn flat_normal(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let image =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([128, 128, 255, 255])));
+
+        Self::from_image(
+            device,
+            queue,
+            &image,
+            Some("Flat normal texture"),
+            TextureKind::NormalMap,
+            SamplerOptions::default(),
+        )
+    }
+
+    /// The format the texture was created with, for pipelines to check
+    /// they're binding something compatible.
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    /// The texture itself, e.g. for copies out of it.
+    pub fn handle(&self) -> &wgpu::Texture {
+        &self.handle
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        self.format.is_srgb()
+    }
+
+    /// Estimated GPU memory, mip levels included.
+    pub fn byte_size(&self) -> u64 {
+        byte_size(
+            self.handle.size(),
+            self.handle.format(),
+            self.handle.mip_level_count(),
+        )
+    }
+
+    /// Frees the GPU memory now instead of when the last handle drops, the
+    /// texture must not be used afterwards.
+    pub fn destroy(&self) {
+        self.handle.destroy();
+    }
+
+    pub fn create_depth_texture(device: &Device, config: &SurfaceConfiguration) -> Self {
+        Self::create_depth_target(device, config.width, config.height, 1)
+    }
+}
+
+/// Writes tightly packed levels of a 2d texture, the base level first.
+fn write_levels(queue: &Queue, handle: &wgpu::Texture, levels: &[Vec<u8>]) {
+    let format = handle.format();
+    let (_, block_height) = format.block_dimensions();
+    for (mip_level, data) in levels.iter().enumerate() {
+        let level_size = handle
+            .size()
+            .mip_level_size(mip_level as u32, TextureDimension::D2);
+        queue.write_texture(
+            ImageCopyTexture {
+                aspect: TextureAspect::All,
+                texture: handle,
+                mip_level: mip_level as u32,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(rows::bytes_per_row(level_size.width, format)),
+                rows_per_image: Some(level_size.height.div_ceil(block_height)),
+            },
+            // Copies of compressed levels cover whole blocks
+            level_size.physical_size(format),
+        );
+    }
+}
+
+/// The mip chain of an image converted to `format`, half floats for
+/// [`TextureFormat::Rgba16Float`] and rgba8 for anything else.
+fn image_levels(image: &DynamicImage, format: TextureFormat, max_mip_levels: u32) -> Vec<Vec<u8>> {
+    match format {
+        TextureFormat::Rgba16Float => generate_mips(&image.to_rgba32f(), max_mip_levels)
+            .iter()
+            .map(|level| bytemuck::cast_slice(&half_float_pixels(level)).to_vec())
+            .collect(),
+        _ => generate_mips(&image.to_rgba8(), max_mip_levels)
+            .into_iter()
+            .map(RgbaImage::into_raw)
+            .collect(),
+    }
+}
+
+/// Levels in a full mip chain down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Halves the image per level down to 1x1, or until there are
+/// `max_mip_levels` levels. Normal maps are filtered like any other image and
+/// renormalized by the shader.
+pub fn generate_mips<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    max_mip_levels: u32,
+) -> Vec<ImageBuffer<P, Vec<P::Subpixel>>>
+where
+    P: Pixel + 'static,
+{
+    let count = mip_level_count(image.width(), image.height()).min(max_mip_levels.max(1));
+    let mut levels = vec![image.clone()];
+    for _ in 1..count {
+        let previous = &levels[levels.len() - 1];
+        let (width, height) = (
+            (previous.width() / 2).max(1),
+            (previous.height() / 2).max(1),
+        );
+        levels.push(imageops::resize(
+            previous,
+            width,
+            height,
+            FilterType::Triangle,
+        ));
+    }
+
+    levels
+}
+
+/// Decodes a Radiance `.hdr` image, keeping values above 1 that the image
+/// crate's generic loader would clamp.
+pub fn decode_hdr(bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    let decoder = HdrDecoder::new(bytes)?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+
+    Ok(
+        Rgb32FImage::from_fn(metadata.width, metadata.height, |x, y| {
+            pixels[(y * metadata.width + x) as usize]
+        })
+        .into(),
+    )
+}
+
+/// Packs the channels as the bits of [`TextureFormat::Rgba16Float`].
+pub fn half_float_pixels(image: &Rgba32FImage) -> Vec<u16> {
+    image
+        .as_raw()
+        .iter()
+        .map(|&channel| f16::from_f32(channel).to_bits())
+        .collect()
+}
+
+/// Bytes taken by a 2d texture's mip chain, rounding partial compressed
+/// blocks up.
+pub fn byte_size(size: Extent3d, format: TextureFormat, mip_level_count: u32) -> u64 {
+    let (block_width, block_height) = format.block_dimensions();
+    // Combined depth/stencil formats have no single block size, 4 bytes is
+    // what most drivers use anyway
+    let block_size = format.block_size(None).unwrap_or(4) as u64;
+
+    (0..mip_level_count)
+        .map(|level| {
+            let size = size.mip_level_size(level, TextureDimension::D2);
+            let blocks_wide = size.width.div_ceil(block_width) as u64;
+            let blocks_high = size.height.div_ceil(block_height) as u64;
+
+            blocks_wide * blocks_high * size.depth_or_array_layers as u64 * block_size
+        })
+        .sum()
+}
+
+/// A device on whatever adapter is around, for tests behind the `gpu-tests`
+/// feature.
+#[cfg(all(test, feature = "gpu-tests"))]
+pub(crate) fn test_device() -> (Device, Queue) {
+    let instance = wgpu::Instance::new(Default::default());
+    let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+        .expect("gpu tests need an adapter");
+
+    pollster::block_on(adapter.request_device(&Default::default(), None))
+        .expect("gpu tests need a device")
+}
+
+pub type TextureResult<T> = Result<T, TextureError>;
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error("Failed to parse ktx2: {0}")]
+    Ktx2(String),
+    #[error("Failed to parse dds: {0}")]
+    Dds(String),
+    #[error("Invalid cubemap: {0}")]
+    Cubemap(String),
+    #[error("Invalid texture array: {0}")]
+    Array(String),
+    #[error("Invalid atlas: {0}")]
+    Atlas(String),
+    #[error("Can't write to texture: {0}")]
+    Write(String),
+    #[error("{0:?} textures aren't supported by this device")]
+    MissingFeatures(TextureFormat),
+    #[error("Reading back {0:?} textures isn't supported")]
+    UnsupportedReadback(TextureFormat),
+    #[error("Failed to map readback buffer: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_hdr, generate_mips, half_float_pixels, mip_level_count, TextureKind};
+    use half::f16;
+    use image::{codecs::hdr::HdrEncoder, Rgb, RgbaImage};
+
+    #[test]
+    fn full_mip_chain() {
+        let image = RgbaImage::new(1024, 1024);
+
+        let levels = generate_mips(&image, u32::MAX);
+        assert_eq!(levels.len(), 11);
+        assert_eq!(levels[1].dimensions(), (512, 512));
+        assert_eq!(levels[10].dimensions(), (1, 1));
+
+        assert_eq!(generate_mips(&image, 4).len(), 4);
+        assert_eq!(mip_level_count(300, 7), 9);
+    }
+
+    #[test]
+    fn only_color_is_srgb() {
+        assert!(TextureKind::Color.rgba8_format().is_srgb());
+        assert!(!TextureKind::NormalMap.rgba8_format().is_srgb());
+        assert!(!TextureKind::LinearData.rgba8_format().is_srgb());
+    }
+
+    #[test]
+    fn hdr_keeps_range() {
+        let pixels = [
+            [4.0, 2.0, 1.5],
+            [0.25, 0.5, 1.0],
+            [16.0, 8.0, 0.0],
+            [1.0; 3],
+        ]
+        .map(Rgb);
+        let mut file = vec![];
+        HdrEncoder::new(&mut file).encode(&pixels, 2, 2).unwrap();
+
+        let image = decode_hdr(&file).unwrap().to_rgba32f();
+        let halves = half_float_pixels(&image);
+        for (pixel, expected) in halves.chunks(4).zip(pixels) {
+            // RGBE keeps 8 bits of mantissa under an exponent shared with
+            // the brightest channel
+            let tolerance = expected.0.into_iter().fold(0.0, f32::max) / 64.0;
+            for (&channel, expected) in pixel.iter().zip(expected.0) {
+                let channel = f16::from_bits(channel).to_f32();
+                assert!((channel - expected).abs() <= tolerance);
+            }
+            assert_eq!(f16::from_bits(pixel[3]).to_f32(), 1.0);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Copies textures back to the CPU, for screenshots and golden image tests.
+
+use super::{rows, Texture, TextureError, TextureResult};
+use image::RgbaImage;
+use std::{
+    iter,
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+    CommandEncoderDescriptor, Device, Extent3d, ImageCopyBuffer, ImageDataLayout, Maintain,
+    MapMode, Queue, TextureFormat,
+};
+
+impl Texture {
+    /// See [`read_to_image`].
+    pub fn read_to_image(&self, device: &Device, queue: &Queue) -> TextureResult<RgbaImage> {
+        read_to_image(device, queue, &self.handle)
+    }
+}
+
+/// Reads back the base level of an rgba8 or bgra8 texture, e.g. a surface
+/// frame, which has to allow [`wgpu::TextureUsages::COPY_SRC`]. Blocks until
+/// the GPU has finished the copy, see [`PendingReadback`] for what doesn't.
+pub fn read_to_image(
+    device: &Device,
+    queue: &Queue,
+    texture: &wgpu::Texture,
+) -> TextureResult<RgbaImage> {
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Texture readback encoder"),
+    });
+    let mut readback = PendingReadback::new(device, &mut encoder, texture)?;
+    queue.submit(iter::once(encoder.finish()));
+    readback.map();
+    device.poll(Maintain::Wait);
+
+    readback
+        .poll()
+        // The callback is dropped without running if the device is lost
+        .unwrap_or(Err(TextureError::Readback(BufferAsyncError)))
+        .map(ReadbackRows::into_image)
+}
+
+/// A texture being copied back to the CPU without blocking on it. Recorded
+/// into a frame's encoder, mapped once that's submitted and polled on later
+/// frames.
+pub struct PendingReadback {
+    buffer: Buffer,
+    /// Gets the map's result, `None` until [`PendingReadback::map`].
+    mapped: Option<Receiver<Result<(), BufferAsyncError>>>,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+    is_bgra: bool,
+}
+
+impl PendingReadback {
+    /// Records copying the base level of `texture` into `encoder`, with the
+    /// same formats and usage as [`read_to_image`].
+    pub fn new(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> TextureResult<Self> {
+        let format = texture.format();
+        let is_bgra = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(TextureError::UnsupportedReadback(format)),
+        };
+
+        let (width, height) = (texture.width(), texture.height());
+        let bytes_per_row = rows::bytes_per_row(width, format);
+        let padded_bytes_per_row = rows::padded_bytes_per_row(bytes_per_row);
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Texture readback buffer"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(Self {
+            buffer,
+            mapped: None,
+            width,
+            height,
+            bytes_per_row,
+            is_bgra,
+        })
+    }
+
+    /// Starts mapping the copy, once the encoder it was recorded into has
+    /// been submitted.
+    pub fn map(&mut self) {
+        if self.mapped.is_some() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.mapped = Some(receiver);
+    }
+
+    /// The copied rows once they're mapped, which takes the device being
+    /// polled. `None` while they're still on their way.
+    pub fn poll(&self) -> Option<TextureResult<ReadbackRows>> {
+        let result = match self.mapped.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(BufferAsyncError),
+        };
+        if let Err(error) = result {
+            return Some(Err(error.into()));
+        }
+
+        let data = self.buffer.slice(..).get_mapped_range().to_vec();
+        self.buffer.unmap();
+
+        Some(Ok(ReadbackRows {
+            data,
+            width: self.width,
+            height: self.height,
+            bytes_per_row: self.bytes_per_row,
+            is_bgra: self.is_bgra,
+        }))
+    }
+}
+
+/// A mapped [`PendingReadback`]'s rows, still padded. Turning them into an
+/// image doesn't need the device, so it can happen on another thread.
+pub struct ReadbackRows {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+    is_bgra: bool,
+}
+
+impl ReadbackRows {
+    pub fn into_image(self) -> RgbaImage {
+        let mut pixels = rows::unpad_rows(&self.data, self.bytes_per_row).into_owned();
+        if self.is_bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.width, self.height, pixels).expect("readback rows are unpadded")
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use crate::texture::{test_device, SamplerOptions, Texture, TextureKind, TextureLevels};
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn checkerboard_round_trip() {
+        let (device, queue) = test_device();
+        // Odd sized so the rows need padding
+        let checkerboard = RgbaImage::from_fn(37, 5, |x, y| match (x + y) % 2 {
+            0 => Rgba([255, 0, 32, 255]),
+            _ => Rgba([0, 64, 255, 128]),
+        });
+
+        let texture = Texture::from_image(
+            &device,
+            &queue,
+            &DynamicImage::ImageRgba8(checkerboard.clone()),
+            None,
+            TextureKind::Color,
+            SamplerOptions::default(),
+        );
+        assert_eq!(
+            texture.read_to_image(&device, &queue).unwrap(),
+            checkerboard
+        );
+
+        // Surface-like bgra textures come back in rgba order
+        let bgra = checkerboard
+            .pixels()
+            .flat_map(|&Rgba([r, g, b, a])| [b, g, r, a])
+            .collect();
+        let texture = Texture::from_levels(
+            &device,
+            &queue,
+            &TextureLevels {
+                format: TextureFormat::Bgra8UnormSrgb,
+                width: 37,
+                height: 5,
+                levels: vec![bgra],
+            },
+            None,
+            SamplerOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            texture.read_to_image(&device, &queue).unwrap(),
+            checkerboard
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Row layouts for copies. Queue writes take tightly packed rows, but copies
+//! from textures into buffers need each row aligned to
+//! [`COPY_BYTES_PER_ROW_ALIGNMENT`].
+
+use std::borrow::Cow;
+use wgpu::{TextureFormat, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+/// Bytes in a tightly packed row of `width` texels, counting whole blocks for
+/// compressed formats.
+pub fn bytes_per_row(width: u32, format: TextureFormat) -> u32 {
+    let (block_width, _) = format.block_dimensions();
+    // Depth/stencil formats without a single block size are taken as 4 bytes
+    // like everywhere else
+    let block_size = format.block_size(None).unwrap_or(4);
+
+    width.div_ceil(block_width) * block_size
+}
+
+/// Rounds a row up to what buffer copies require.
+pub fn padded_bytes_per_row(bytes_per_row: u32) -> u32 {
+    bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+/// Repacks rows padded to [`padded_bytes_per_row`] tightly, e.g. from a
+/// mapped readback buffer. Borrows the data when the rows are already
+/// aligned.
+pub fn unpad_rows(data: &[u8], bytes_per_row: u32) -> Cow<'_, [u8]> {
+    let padded = padded_bytes_per_row(bytes_per_row) as usize;
+    let bytes_per_row = bytes_per_row as usize;
+    if padded == bytes_per_row || bytes_per_row == 0 {
+        return Cow::Borrowed(data);
+    }
+
+    Cow::Owned(
+        data.chunks(padded)
+            .flat_map(|row| &row[..bytes_per_row.min(row.len())])
+            .copied()
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bytes_per_row, padded_bytes_per_row, unpad_rows};
+    use std::borrow::Cow;
+    use wgpu::TextureFormat;
+
+    /// Tight rows of `bytes_per_row` padded the way a readback buffer is.
+    fn pad(data: &[u8], bytes_per_row: u32) -> Vec<u8> {
+        let padded = padded_bytes_per_row(bytes_per_row) as usize;
+        data.chunks(bytes_per_row as usize)
+            .flat_map(|row| row.iter().copied().chain(vec![0; padded - row.len()]))
+            .collect()
+    }
+
+    #[test]
+    fn one_texel_wide() {
+        let row = bytes_per_row(1, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(row, 4);
+        assert_eq!(padded_bytes_per_row(row), 256);
+
+        let data: Vec<u8> = (0..3 * 4).collect();
+        let padded = pad(&data, row);
+        assert_eq!(padded.len(), 3 * 256);
+        assert_eq!(unpad_rows(&padded, row), data);
+    }
+
+    #[test]
+    fn odd_widths() {
+        let row = bytes_per_row(255, TextureFormat::Rgba8Unorm);
+        assert_eq!(padded_bytes_per_row(row), 1024);
+
+        let data: Vec<u8> = (0..2 * row).map(|byte| byte as u8).collect();
+        assert_eq!(unpad_rows(&pad(&data, row), row), data);
+
+        // Aligned rows go through untouched
+        let data = vec![1; 2 * 256];
+        assert!(matches!(unpad_rows(&data, 256), Cow::Borrowed(_)));
+        assert_eq!(bytes_per_row(10, TextureFormat::Bc1RgbaUnorm), 3 * 8);
+    }
+}
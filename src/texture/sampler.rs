@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock, Weak},
+};
+use wgpu::{AddressMode, CompareFunction, Device, FilterMode, Sampler, SamplerDescriptor};
+
+/// How a texture is sampled. Textures created with equal options share one
+/// [`Sampler`], see [`cached_sampler`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerOptions {
+    /// Used on every axis, tiled materials and terrain want
+    /// [`AddressMode::Repeat`].
+    pub address_mode: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    /// Up to [`MAX_ANISOTROPY`](Self::MAX_ANISOTROPY), anything above 1
+    /// turns every filter linear as wgpu requires.
+    pub anisotropy_clamp: u16,
+    /// Makes it a comparison sampler, for depth textures.
+    pub compare: Option<CompareFunction>,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            address_mode: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            anisotropy_clamp: 1,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub const MAX_ANISOTROPY: u16 = 16;
+
+    pub fn repeat() -> Self {
+        Self {
+            address_mode: AddressMode::Repeat,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_anisotropy(self, anisotropy_clamp: u16) -> Self {
+        Self {
+            anisotropy_clamp,
+            ..self
+        }
+    }
+
+    /// Nearest filtering, which formats that can't be filtered like
+    /// `R32Float` need.
+    pub fn non_filtering(self) -> Self {
+        Self {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            anisotropy_clamp: 1,
+            ..self
+        }
+    }
+
+    /// The options wgpu accepts, the anisotropy clamped to 1..=16 and the
+    /// filters made linear when it's above 1.
+    pub fn corrected(self) -> Self {
+        let anisotropy_clamp = self.anisotropy_clamp.clamp(1, Self::MAX_ANISOTROPY);
+        match anisotropy_clamp {
+            1 => Self {
+                anisotropy_clamp,
+                ..self
+            },
+            _ => Self {
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                anisotropy_clamp,
+                ..self
+            },
+        }
+    }
+
+    /// Describes the [corrected](Self::corrected) options.
+    pub fn descriptor(&self) -> SamplerDescriptor<'static> {
+        let options = self.corrected();
+
+        SamplerDescriptor {
+            label: Some("Texture sampler"),
+            address_mode_u: options.address_mode,
+            address_mode_v: options.address_mode,
+            address_mode_w: options.address_mode,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_filter: options.mipmap_filter,
+            anisotropy_clamp: options.anisotropy_clamp,
+            compare: options.compare,
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the sampler for `options`, creating it only if no texture still
+/// holds one for the same device.
+pub fn cached_sampler(device: &Device, options: SamplerOptions) -> Arc<Sampler> {
+    type Cache = HashMap<(wgpu::Id<Device>, SamplerOptions), Weak<Sampler>>;
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+
+    let mut cache = CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Drop entries whose samplers are gone so the map doesn't grow forever
+    cache.retain(|_, sampler| sampler.strong_count() > 0);
+
+    // Options that correct to the same descriptor share a sampler
+    let options = options.corrected();
+    let key = (device.global_id(), options);
+    if let Some(sampler) = cache.get(&key).and_then(Weak::upgrade) {
+        return sampler;
+    }
+
+    let sampler = Arc::new(device.create_sampler(&options.descriptor()));
+    cache.insert(key, Arc::downgrade(&sampler));
+
+    sampler
+}
+
+#[cfg(test)]
+mod test {
+    use super::SamplerOptions;
+    use wgpu::{AddressMode, FilterMode};
+
+    #[test]
+    fn descriptor_covers_every_axis() {
+        let descriptor = SamplerOptions::repeat().descriptor();
+
+        assert_eq!(descriptor.address_mode_u, AddressMode::Repeat);
+        assert_eq!(descriptor.address_mode_v, AddressMode::Repeat);
+        assert_eq!(descriptor.address_mode_w, AddressMode::Repeat);
+        assert_eq!(descriptor.min_filter, FilterMode::Linear);
+        assert_eq!(descriptor.anisotropy_clamp, 1);
+    }
+
+    #[test]
+    fn anisotropy_forces_linear() {
+        let nearest = SamplerOptions {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        };
+
+        let descriptor = nearest.with_anisotropy(64).descriptor();
+        assert_eq!(descriptor.anisotropy_clamp, SamplerOptions::MAX_ANISOTROPY);
+        assert_eq!(descriptor.mag_filter, FilterMode::Linear);
+        assert_eq!(descriptor.min_filter, FilterMode::Linear);
+        assert_eq!(descriptor.mipmap_filter, FilterMode::Linear);
+
+        // Without anisotropy the filters are left alone
+        let descriptor = nearest.with_anisotropy(0).descriptor();
+        assert_eq!(descriptor.anisotropy_clamp, 1);
+        assert_eq!(descriptor.min_filter, FilterMode::Nearest);
+    }
+}
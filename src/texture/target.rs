@@ -0,0 +1,115 @@
+//! Textures the renderer draws into rather than uploads to, e.g. an
+//! offscreen scene for picking or post-processing.
+
+use super::{cached_sampler, SamplerOptions, Texture};
+use wgpu::{
+    CompareFunction, Device, Extent3d, FilterMode, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureViewDescriptor,
+};
+
+impl Texture {
+    /// A color attachment that can be sampled afterwards. Multisampled
+    /// targets have to be resolved or bound as multisampled textures.
+    pub fn create_render_target(
+        device: &Device,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        Self::create_target(
+            device,
+            Some("Render target"),
+            width,
+            height,
+            format,
+            sample_count,
+            SamplerOptions::default(),
+        )
+    }
+
+    /// A depth attachment with a comparison sampler, see
+    /// [`Texture::DEPTH_FORMAT`].
+    pub fn create_depth_target(
+        device: &Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        Self::create_target(
+            device,
+            Some("Depth target"),
+            width,
+            height,
+            Self::DEPTH_FORMAT,
+            sample_count,
+            SamplerOptions {
+                mipmap_filter: FilterMode::Nearest,
+                compare: Some(CompareFunction::LessEqual),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A square depth target for rendering from a light, sampled with
+    /// [`Texture::sampler`] to compare against it.
+    pub fn create_shadow_map(device: &Device, size: u32) -> Self {
+        Self::create_target(
+            device,
+            Some("Shadow map"),
+            size,
+            size,
+            Self::DEPTH_FORMAT,
+            1,
+            SamplerOptions {
+                mipmap_filter: FilterMode::Nearest,
+                compare: Some(CompareFunction::LessEqual),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn create_target(
+        device: &Device,
+        label: Option<&str>,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        sample_count: u32,
+        sampler: SamplerOptions,
+    ) -> Self {
+        let handle = device.create_texture(&TextureDescriptor {
+            label,
+            size: Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        Self {
+            format,
+            view: handle.create_view(&TextureViewDescriptor::default()),
+            handle,
+            sampler: cached_sampler(device, sampler),
+            sampler_options: sampler,
+            depth_sampler: sampler.compare.map(|_| {
+                cached_sampler(
+                    device,
+                    SamplerOptions {
+                        compare: None,
+                        ..sampler.non_filtering()
+                    },
+                )
+            }),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Replacing the pixels of existing textures, for hot reloading and video
+//! frames.
+
+use super::{image_levels, write_levels, Texture, TextureError, TextureKind, TextureResult};
+use image::{DynamicImage, GenericImageView};
+use wgpu::{Device, Queue, TextureFormat, TextureUsages};
+
+impl Texture {
+    /// Re-uploads every mip level from an image the size of the texture.
+    /// Only 2d textures uploaded from images can be written, the ones bind
+    /// groups are holding stay valid.
+    pub fn write(&self, queue: &Queue, image: &DynamicImage) -> TextureResult<()> {
+        let size = self.handle.size();
+        if size.depth_or_array_layers != 1 || !self.handle.usage().contains(TextureUsages::COPY_DST)
+        {
+            return Err(TextureError::Write(
+                "only single layer textures uploaded from the CPU can be written".to_owned(),
+            ));
+        }
+        if image.dimensions() != (size.width, size.height) {
+            return Err(TextureError::Write(format!(
+                "expected a {}x{} image, got {}x{}",
+                size.width,
+                size.height,
+                image.width(),
+                image.height()
+            )));
+        }
+
+        match self.format {
+            TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Rgba16Float => {}
+            format => {
+                return Err(TextureError::Write(format!(
+                    "{format:?} textures can't be written from images"
+                )))
+            }
+        }
+
+        let levels = image_levels(image, self.format, self.handle.mip_level_count());
+        write_levels(queue, &self.handle, &levels);
+
+        Ok(())
+    }
+
+    /// Writes the image like [`Texture::write`], or when that isn't possible
+    /// replaces the texture with a new one in the same color space and with
+    /// the same sampler options. Returns whether it was replaced, in which
+    /// case bind groups holding the old view have to be rebuilt, e.g. with
+    /// [`Material::refresh_bind_group`](crate::model::Material::refresh_bind_group).
+    pub fn recreate(&mut self, device: &Device, queue: &Queue, image: &DynamicImage) -> bool {
+        if self.write(queue, image).is_ok() {
+            return false;
+        }
+
+        let kind = match self.is_srgb() {
+            true => TextureKind::Color,
+            false => TextureKind::LinearData,
+        };
+        *self = Self::from_image(device, queue, image, None, kind, self.sampler_options);
+
+        true
+    }
+}
+
+#[cfg(all(test, feature = "gpu-tests"))]
+mod test {
+    use crate::texture::{test_device, SamplerOptions, Texture, TextureKind};
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    #[test]
+    fn write_then_recreate() {
+        let (device, queue) = test_device();
+        let red = RgbaImage::from_pixel(8, 4, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(8, 4, Rgba([0, 0, 255, 255]));
+        let mut texture = Texture::from_image(
+            &device,
+            &queue,
+            &DynamicImage::ImageRgba8(red),
+            None,
+            TextureKind::Color,
+            SamplerOptions::repeat(),
+        );
+
+        assert!(!texture.recreate(&device, &queue, &DynamicImage::ImageRgba8(blue.clone())));
+        assert_eq!(texture.read_to_image(&device, &queue).unwrap(), blue);
+
+        let larger = RgbaImage::from_pixel(16, 16, Rgba([0, 255, 0, 255]));
+        assert!(texture
+            .write(&queue, &DynamicImage::ImageRgba8(larger.clone()))
+            .is_err());
+        assert!(texture.recreate(&device, &queue, &DynamicImage::ImageRgba8(larger.clone())));
+        assert_eq!(texture.read_to_image(&device, &queue).unwrap(), larger);
+        assert!(texture.is_srgb());
+        assert_eq!(texture.sampler_options, SamplerOptions::repeat());
+    }
+}
@@ -0,0 +1,125 @@
+//! The text overlay drawn over the scene.
+
+use glyphon::{
+    Attrs, Buffer, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextBounds, TextRenderer,
+};
+use wgpu::{
+    CommandEncoder, Device, LoadOp, MultisampleState, Operations, Queue, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPassTimestampWrites, SurfaceConfiguration, TextureView,
+};
+
+pub struct TextManager {
+    font_system: FontSystem,
+    cache: SwashCache,
+    pub atlas: TextAtlas,
+    pub renderer: TextRenderer,
+    buffer: Buffer,
+}
+
+impl TextManager {
+    const SCALE: f32 = 0.5;
+
+    pub fn new(device: &Device, queue: &Queue, config: &SurfaceConfiguration) -> Self {
+        let mut font_system = FontSystem::new();
+        let cache = SwashCache::new();
+        let mut atlas = TextAtlas::new(device, queue, config.format);
+        let renderer = TextRenderer::new(&mut atlas, device, MultisampleState::default(), None);
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(30.0, 42.0));
+
+        let width = config.width as f32 * Self::SCALE;
+        let height = config.height as f32 * Self::SCALE;
+
+        buffer.set_size(&mut font_system, width, height);
+        buffer.set_text(
+            &mut font_system,
+            "ahoy there",
+            Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system);
+
+        Self {
+            font_system,
+            cache,
+            atlas,
+            renderer,
+            buffer,
+        }
+    }
+
+    pub fn update(&mut self, message: &str) {
+        self.buffer.set_text(
+            &mut self.font_system,
+            message,
+            Attrs::new().family(Family::SansSerif),
+            Shaping::Advanced,
+        );
+    }
+
+    pub fn resize(&mut self, config: &SurfaceConfiguration) {
+        self.buffer.set_size(
+            &mut self.font_system,
+            config.width as f32 * Self::SCALE,
+            config.height as f32 * Self::SCALE,
+        );
+    }
+
+    pub fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        config: &SurfaceConfiguration,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        timestamp_writes: Option<RenderPassTimestampWrites>,
+    ) {
+        self.renderer
+            .prepare(
+                device,
+                queue,
+                &mut self.font_system,
+                &mut self.atlas,
+                Resolution {
+                    width: config.width,
+                    height: config.height,
+                },
+                [TextArea {
+                    buffer: &self.buffer,
+                    left: 10.0,
+                    top: 10.0,
+                    scale: 1.0,
+                    bounds: TextBounds {
+                        left: 0,
+                        top: 0,
+                        right: config.width as i32,
+                        bottom: config.height as i32,
+                    },
+                    default_color: Color::rgb(255, 255, 255),
+                }],
+                &mut self.cache,
+            )
+            .unwrap();
+
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Text pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+            self.renderer.render(&self.atlas, &mut pass).unwrap();
+        }
+
+        self.atlas.trim();
+    }
+}